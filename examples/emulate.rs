@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::time::Duration;
+
+use bluest::emulator::{
+    Battery, CurrentTime, DeviceInformation, Emulator, HeartRate, LedButton, ValueSource,
+    BLINKY_LED_STATE_CHARACTERISTIC, NORDIC_LED_AND_BUTTON_SERVICE,
+};
+use bluest::Adapter;
+use futures_lite::StreamExt;
+use tracing::info;
+use tracing::metadata::LevelFilter;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::{fmt, EnvFilter};
+
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let adapter = Adapter::default().await.ok_or("Bluetooth adapter not found")?;
+    adapter.wait_available().await?;
+
+    // Run the `blinky` example on another machine to connect to the default emulator
+    let profile = std::env::args().nth(1).unwrap_or_else(|| "blinky".to_string());
+    let emulator = match profile.as_str() {
+        "blinky" => Emulator::new("Blinky").with_profile(LedButton::new(ValueSource::cycle(
+            [false, true],
+            Duration::from_secs(2),
+        ))),
+        "battery" => Emulator::new("Battery")
+            .with_profile(Battery::new(ValueSource::cycle(
+                (0..=100).rev(),
+                Duration::from_secs(1),
+            )))
+            .with_profile(DeviceInformation::new().with_manufacturer_name("Bluest")),
+        "heart-rate" => Emulator::new("Heart rate").with_profile(
+            HeartRate::new(ValueSource::cycle([60, 65, 72, 80, 72, 65], Duration::from_secs(1)))
+                .with_body_sensor_location(1),
+        ),
+        "clock" => Emulator::new("Clock").with_profile(CurrentTime::system_clock()),
+        _ => return Err("expected one of blinky, battery, heart-rate or clock".into()),
+    };
+
+    let emulator = emulator.start(&adapter).await?;
+    info!("emulating {}", profile);
+
+    let mut events = emulator.server().events();
+    let mut led = None;
+    loop {
+        tokio::select! {
+            Some(event) = events.next() => info!("{:?}", event),
+            _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+        }
+        let state = emulator.value(NORDIC_LED_AND_BUTTON_SERVICE, BLINKY_LED_STATE_CHARACTERISTIC);
+        if state.is_some() && state != led {
+            info!("LED state: {:?}", state);
+            led = state;
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::error::Error;
+
+use bluest::{Adapter, AdvertisementData, L2capChannel, Uuid};
+use futures_lite::StreamExt;
+use tracing::info;
+use tracing::metadata::LevelFilter;
+
+/// The service whose service data holds the PSM of the echo server
+const ECHO_SERVICE: Uuid = Uuid::from_u128(0x9f6c9e4a_8b21_4f6a_a3c5_2d6e0c8b1a70);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::{fmt, EnvFilter};
+
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let adapter = Adapter::default().await.ok_or("Bluetooth adapter not found")?;
+    adapter.wait_available().await?;
+
+    // Run `l2cap listen` on one machine and `l2cap` on another
+    if std::env::args().nth(1).as_deref() == Some("listen") {
+        listen(&adapter).await
+    } else {
+        connect(&adapter).await
+    }
+}
+
+async fn listen(adapter: &Adapter) -> Result<(), Box<dyn Error>> {
+    let (psm, mut channels) = adapter.listen_l2cap(None, false).await?;
+    info!("listening on PSM {:#x}", psm);
+
+    let _advertisement = adapter
+        .start_advertising(AdvertisementData {
+            service_data: [(ECHO_SERVICE, psm.to_le_bytes().to_vec())].into(),
+            is_connectable: Some(true),
+            ..Default::default()
+        })
+        .await?;
+
+    while let Some(res) = channels.next().await {
+        let (device, channel) = res?;
+        info!("accepted channel from {}", device.id());
+        tokio::spawn(echo(channel));
+    }
+
+    Ok(())
+}
+
+/// Sends every packet received on `channel` back to the remote device.
+async fn echo(mut channel: L2capChannel) -> bluest::Result<()> {
+    let mut buf = vec![0; channel.read_mtu()?];
+    loop {
+        let len = channel.read(&mut buf).await?;
+        channel.write(&buf[..len]).await?;
+    }
+}
+
+async fn connect(adapter: &Adapter) -> Result<(), Box<dyn Error>> {
+    info!("looking for an echo server");
+    let mut scan = adapter.scan(&[]).await?;
+    let (device, psm) = loop {
+        let found = scan.next().await.ok_or("scan terminated")?;
+        if let Some(&[a, b]) = found.adv_data.service_data.get(&ECHO_SERVICE).map(Vec::as_slice) {
+            break (found.device, u16::from_le_bytes([a, b]));
+        }
+    };
+    drop(scan);
+
+    adapter.connect_device(&device).await?;
+    let mut channel = device.open_l2cap_channel(psm, false).await?;
+    info!(
+        "opened channel to {} with MTU {}/{}",
+        device.id(),
+        channel.read_mtu()?,
+        channel.write_mtu()?
+    );
+
+    let mut buf = vec![0; channel.read_mtu()?];
+    for i in 0..10 {
+        let packet = format!("packet {i}");
+        channel.write(packet.as_bytes()).await?;
+        let len = channel.read(&mut buf).await?;
+        info!("echoed {:?}", String::from_utf8_lossy(&buf[..len]));
+    }
+
+    channel.close().await?;
+    Ok(())
+}
@@ -1,14 +1,30 @@
 #![allow(clippy::let_unit_value)]
 
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
 use futures_core::Stream;
+use futures_lite::{stream, StreamExt};
 
-use crate::{sys, AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, ConnectionEvent, Device, DeviceId, Result, Uuid};
+use crate::server::{self, RequestHandler, Server, ServiceDefinition};
+use crate::util::Timer;
+#[cfg(feature = "l2cap")]
+use crate::L2capChannel;
+use crate::{
+    sys, AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters, ConnectionEvent,
+    Device, DeviceId, DuplicatePolicy, MonitorEvent, MonitorOptions, MonitorPattern, Result, ScanEvent, ScanFilter,
+    ScanOptions, Uuid,
+};
 
 /// The system's Bluetooth adapter interface.
 ///
 /// The default adapter for the system may be accessed with the [`Adapter::default()`] method.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Adapter(sys::adapter::AdapterImpl);
+pub struct Adapter(pub(crate) sys::adapter::AdapterImpl);
 
 impl Adapter {
     /// Creates an interface to the default Bluetooth adapter for the system.
@@ -71,7 +87,8 @@ impl Adapter {
     ///
     /// Returns a stream of [`AdvertisingDevice`] structs which contain the data from the advertising packet and the
     /// [`Device`] which sent it. Scanning is automatically stopped when the stream is dropped. Inclusion of duplicate
-    /// packets is a platform-specific implementation detail.
+    /// packets is a platform-specific implementation detail, use [`Adapter::scan_with_options`] to choose how they are
+    /// reported.
     ///
     /// If `services` is not empty, returns advertisements including at least one GATT service with a UUID in
     /// `services`. Otherwise returns all advertisements.
@@ -80,7 +97,209 @@ impl Adapter {
         &'a self,
         services: &'a [Uuid],
     ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + 'a> {
-        self.0.scan(services).await
+        self.0
+            .scan(ScanFilter::from_services(services), &ScanOptions::default())
+            .await
+    }
+
+    /// Starts scanning for Bluetooth advertising packets matching `filter`.
+    ///
+    /// Returns a stream of [`AdvertisingDevice`] structs like [`Adapter::scan`], but only for advertisements which
+    /// match `filter`. The platform applies the parts of `filter` it supports while scanning and the rest is applied to
+    /// the received advertisements.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// BlueZ filters by services, minimum RSSI and name prefix.
+    ///
+    /// ## MacOS/iOS
+    ///
+    /// CoreBluetooth filters by services.
+    ///
+    /// ## Windows
+    ///
+    /// Windows filters by minimum RSSI and manufacturer data.
+    ///
+    /// ## Android
+    ///
+    /// The whole filter is applied to the received advertisements.
+    #[inline]
+    pub async fn scan_with_filter(
+        &self,
+        filter: ScanFilter,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
+        self.0.scan(filter, &ScanOptions::default()).await
+    }
+
+    /// Starts scanning for Bluetooth advertising packets matching `filter`, with the given scan `options`.
+    ///
+    /// Returns a stream of [`AdvertisingDevice`] structs like [`Adapter::scan_with_filter`]. The stream ends when the
+    /// scan has run for [`ScanOptions::duration`] or returned [`ScanOptions::max_results`] advertisements, whichever
+    /// comes first. Duplicates are handled the same way on every platform unless [`ScanOptions::duplicates`] is
+    /// [`DuplicatePolicy::PlatformDefault`]. The scan interval and window are hints which platforms may ignore.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// Passive scans are not supported. With [`DuplicatePolicy::ReportAll`], BlueZ reports an advertisement again when
+    /// its signal strength, TX power level, manufacturer data or service data changes; repeated advertisements which
+    /// change none of these, and changes to other device properties such as the name, are not reported. The scan
+    /// interval and window are ignored.
+    ///
+    /// ## MacOS/iOS
+    ///
+    /// Passive scans are not supported. The scan interval and window are ignored.
+    ///
+    /// ## Windows
+    ///
+    /// The scan interval and window are ignored.
+    ///
+    /// ## Android
+    ///
+    /// Passive scans are not supported. The ratio of the scan window to the scan interval selects the closest Android
+    /// scan mode.
+    pub async fn scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
+        let options = ScanOptions {
+            lost_timeout: None,
+            ..options
+        };
+        Ok(self
+            .scan_events(filter, options)
+            .await?
+            .filter_map(|event| match event {
+                ScanEvent::Advertisement(adv) => Some(adv),
+                ScanEvent::DeviceLost(_) => None,
+            }))
+    }
+
+    /// Starts scanning for Bluetooth advertising packets matching `filter`, and reports when devices are lost.
+    ///
+    /// Returns a stream of [`ScanEvent`]s with the advertisements returned by [`Adapter::scan_with_options`]. A
+    /// [`ScanEvent::DeviceLost`] is reported when a device which has been reported is not heard for
+    /// [`ScanOptions::lost_timeout`], or when the platform forgets it. If the device is heard again, its next
+    /// advertisement is reported whatever the [`ScanOptions::duplicates`] policy is.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// Devices are also lost when BlueZ removes them, which it does for devices which are not paired or connected
+    /// after it has not heard them for a while (30 seconds by default).
+    ///
+    /// ## MacOS/iOS, Windows and Android
+    ///
+    /// Devices are only lost when [`ScanOptions::lost_timeout`] is set.
+    pub async fn scan_events(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<impl Stream<Item = ScanEvent> + Send + Unpin + '_> {
+        Ok(self.try_scan_events(filter, options).await?.filter_map(Result::ok))
+    }
+
+    /// Starts scanning for Bluetooth advertising packets matching `filter`, and reports errors as they occur.
+    ///
+    /// Returns the same events as [`Adapter::scan_events`], together with the errors the other scan methods skip. An
+    /// advertisement which cannot be converted to an [`AdvertisingDevice`] is reported as an error and the scan goes
+    /// on. When the scan dies, the last item is an error giving the reason: an error with
+    /// [`ErrorKind::AdapterUnavailable`] if the adapter is turned off or removed, or with [`ErrorKind::ScanStopped`] if
+    /// the system stopped scanning. The stream then ends and a new scan can be started once the adapter is available
+    /// again. The stream ends without an error when the scan has run for [`ScanOptions::duration`] or returned
+    /// [`ScanOptions::max_results`] advertisements.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// BlueZ stops discovery for a few seconds while the controller is busy. The scan is only considered stopped when
+    /// discovery does not restart within 10 seconds.
+    ///
+    /// ## MacOS/iOS and Android
+    ///
+    /// No errors are reported.
+    ///
+    /// ## Windows
+    ///
+    /// An error with [`ErrorKind::ScanStopped`] is reported when Windows stops the advertisement watcher. Other errors
+    /// are not reported.
+    ///
+    /// [`ErrorKind::AdapterUnavailable`]: crate::error::ErrorKind::AdapterUnavailable
+    /// [`ErrorKind::ScanStopped`]: crate::error::ErrorKind::ScanStopped
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        let events = self.0.try_scan_events(filter, &options).await?;
+        // Lost devices are tracked before duplicates are removed, so devices repeating themselves are not lost
+        let mut is_reported = duplicate_filter(options.duplicates);
+        let mut stream = LostDevices::new(events, options.lost_timeout).filter(move |x| match x {
+            Ok(event) => is_reported(event),
+            Err(_) => true,
+        });
+
+        let mut remaining = options.max_results.unwrap_or(usize::MAX);
+        let mut timer = options.duration.map(Timer::after);
+        Ok(stream::poll_fn(move |cx| {
+            if remaining == 0 {
+                return Poll::Ready(None);
+            }
+            if let Some(timer) = &mut timer {
+                if Pin::new(timer).poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+            }
+            let event = ready!(stream.poll_next(cx));
+            if let Some(Ok(ScanEvent::Advertisement(_))) = event {
+                remaining -= 1;
+            }
+            Poll::Ready(event)
+        }))
+    }
+
+    /// Starts monitoring for devices advertising data matching any of `patterns`.
+    ///
+    /// Returns a stream of [`MonitorEvent`]s reporting when a matching device comes into range and when it goes out of
+    /// range again. Unlike a scan, the patterns are matched by the Bluetooth controller where it supports this, which
+    /// uses much less power. Monitoring is automatically stopped when the stream is dropped.
+    ///
+    /// # Platform specifics
+    ///
+    /// Only Linux supports advertisement monitors, other platforms return an error with
+    /// [`ErrorKind::NotSupported`][crate::error::ErrorKind::NotSupported].
+    ///
+    /// ## Linux
+    ///
+    /// BlueZ must support the `AdvertisementMonitorManager1` interface, which older versions only enable with the
+    /// `--experimental` option. Without a controller supporting advertisement monitor offload, BlueZ matches the
+    /// patterns itself while scanning passively.
+    #[inline]
+    pub async fn monitor_advertisements(
+        &self,
+        patterns: &[MonitorPattern],
+    ) -> Result<impl Stream<Item = MonitorEvent> + Send + Unpin + '_> {
+        self.0
+            .monitor_advertisements(patterns, &MonitorOptions::default())
+            .await
+    }
+
+    /// Starts monitoring for devices advertising data matching any of `patterns`, with the given monitor `options`.
+    ///
+    /// See [`Adapter::monitor_advertisements`] for details.
+    #[inline]
+    pub async fn monitor_advertisements_with_options(
+        &self,
+        patterns: &[MonitorPattern],
+        options: MonitorOptions,
+    ) -> Result<impl Stream<Item = MonitorEvent> + Send + Unpin + '_> {
+        self.0.monitor_advertisements(patterns, &options).await
     }
 
     /// Finds Bluetooth devices providing any service in `services`.
@@ -166,11 +385,256 @@ impl Adapter {
         self.0.device_connection_events(device).await
     }
 
+    /// Starts advertising `data` from this adapter.
+    ///
+    /// The advertisement continues until the returned [`AdvertisingGuard`] is dropped.
+    ///
+    /// [`AdvertisementData::is_connectable`] selects connectable or non-connectable (broadcast) advertising. The
+    /// discoverable mode is taken from the [`LE_LIMITED_DISCOVERABLE`][crate::adv::flags::LE_LIMITED_DISCOVERABLE]
+    /// and [`LE_GENERAL_DISCOVERABLE`][crate::adv::flags::LE_GENERAL_DISCOVERABLE] bits of
+    /// [`AdvertisementData::flags`]. If `flags` is `None`, connectable advertisements are general discoverable and
+    /// broadcast advertisements are not discoverable. The remaining flags are set by the platform.
+    ///
+    /// [`AdvertisementData::advertising_interval`] sets the interval the advertisement is sent at, and
+    /// [`AdvertisementData::tx_power_level`] requests a transmit power and includes the power actually used in the
+    /// advertisement. All other fields are advertised as they are.
+    ///
+    /// Returns an error with [`ErrorKind::NotSupported`][crate::error::ErrorKind::NotSupported] if the platform cannot
+    /// advertise some part of `data`.
+    ///
+    /// Several advertisements may be active at the same time, each in its own advertising set. Dropping an
+    /// [`AdvertisingGuard`] stops only its own set. Use [`Adapter::start_advertising_with_parameters`] to set the
+    /// interval range, transmit power, duration, timeout, secondary PHY or scan response of a set.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// BlueZ does not support limited discoverable or discoverable broadcast advertisements, and allows a single
+    /// manufacturer data entry per company ID. Advertising a URI, the LE supported features or
+    /// [`other_structures`][AdvertisementData::other_structures] requires `bluetoothd` to run with experimental
+    /// features enabled.
+    ///
+    /// ## MacOS/iOS
+    ///
+    /// Advertisements are always connectable and general discoverable, whatever `is_connectable` and `flags` are set
+    /// to. Only the local name, services and a single manufacturer data entry can be advertised, and iOS does not
+    /// advertise manufacturer data. Returns an error with
+    /// [`ErrorKind::AdapterUnavailable`][crate::error::ErrorKind::AdapterUnavailable] if Bluetooth is powered off.
+    ///
+    /// ## Windows
+    ///
+    /// Only non-connectable, non-discoverable advertisements are supported and the advertising interval cannot be
+    /// set.
+    ///
+    /// ## Android
+    ///
+    /// Advertising is not supported.
+    #[inline]
+    pub async fn start_advertising(&self, data: AdvertisementData) -> Result<AdvertisingGuard> {
+        self.0.start_advertising(data, AdvertisingParameters::default()).await
+    }
+
+    /// Starts advertising `data` from this adapter in a new advertising set with the given `parameters`.
+    ///
+    /// See [`Adapter::start_advertising`] for how `data` is advertised.
+    ///
+    /// # Platform specifics
+    ///
+    /// ## Linux
+    ///
+    /// A separate scan response is not supported: BlueZ decides itself which data goes in the scan response. Setting
+    /// the secondary PHY requires an adapter which supports extended advertising.
+    ///
+    /// ## MacOS/iOS
+    ///
+    /// None of the parameters are supported.
+    ///
+    /// ## Windows
+    ///
+    /// Only the transmit power is supported.
+    ///
+    /// ## Android
+    ///
+    /// Advertising is not supported.
+    #[inline]
+    pub async fn start_advertising_with_parameters(
+        &self,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        self.0.start_advertising(data, parameters).await
+    }
 
-    /// Advertise.
+    /// The number of further advertising sets that can be started on this adapter.
     ///
-    pub async fn start_advertising(self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        self.0.start_advertising(data).await
+    /// # Platform specifics
+    ///
+    /// The number of advertising sets is not available on MacOS/iOS, Windows or Android, where this method returns an
+    /// error with [`ErrorKind::NotSupported`][crate::error::ErrorKind::NotSupported].
+    #[inline]
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        self.0.available_advertising_sets().await
     }
 
+    /// Listens for incoming L2CAP connection-oriented channels (CoC).
+    ///
+    /// Channels are accepted on `psm`, or on a dynamically assigned PSM in the LE range if `psm` is `None`. Returns the
+    /// PSM listened on, which can be shared with other devices e.g. in service data, and a stream of the accepted
+    /// channels, each with the remote device which opened it. Incoming channels are refused once the stream is dropped.
+    ///
+    /// If `secure` is true, channels require an encrypted link and remote devices will be paired if necessary.
+    ///
+    /// An error accepting a single channel, e.g. because the remote device aborted the connection, is yielded and the
+    /// stream keeps listening. The stream ends after any other error.
+    ///
+    /// # Platform specifics
+    ///
+    /// Only Linux supports listening for L2CAP channels, other platforms return an error with
+    /// [`ErrorKind::NotSupported`][crate::error::ErrorKind::NotSupported].
+    #[inline]
+    #[cfg(feature = "l2cap")]
+    pub async fn listen_l2cap(
+        &self,
+        psm: Option<u16>,
+        secure: bool,
+    ) -> Result<(
+        u16,
+        impl Stream<Item = Result<(Device, L2capChannel)>> + Send + Unpin + '_,
+    )> {
+        self.0.listen_l2cap(psm, secure).await
+    }
+
+    /// Starts a GATT server publishing `services`, whose reads and writes are answered by `handler`.
+    ///
+    /// The services are removed when the returned [`Server`] is dropped. See the [`server`][crate::server] module for
+    /// how to declare services.
+    ///
+    /// Returns an error with [`ErrorKind::InvalidParameter`][crate::error::ErrorKind::InvalidParameter] if the
+    /// permissions of a characteristic do not match its properties, or if a characteristic declares its own client
+    /// characteristic configuration descriptor.
+    ///
+    /// # Platform specifics
+    ///
+    /// Only Linux and the `mock` backend support GATT servers, other platforms return an error with
+    /// [`ErrorKind::NotSupported`][crate::error::ErrorKind::NotSupported].
+    ///
+    /// ## Linux
+    ///
+    /// The services are registered with BlueZ as a GATT application. BlueZ adds its own GAP and GATT services to the
+    /// database.
+    ///
+    /// ## Mock
+    ///
+    /// The services are served by the peripherals advertised with [`Adapter::start_advertising`] on the same
+    /// `VirtualRadio`.
+    #[inline]
+    pub async fn start_server(&self, services: Vec<ServiceDefinition>, handler: impl RequestHandler) -> Result<Server> {
+        server::validate(&services)?;
+        self.0.start_server(services, Arc::new(handler)).await
+    }
+}
+
+/// Returns a predicate which keeps the advertisements reported under `policy`.
+///
+/// The advertisements and scan responses of a device are compared separately, since platforms which report scan
+/// responses on their own alternate between the two. Lost devices are forgotten, so they are reported again.
+fn duplicate_filter(policy: DuplicatePolicy) -> impl FnMut(&ScanEvent) -> bool + Send {
+    let mut reported = HashMap::new();
+    move |event| match event {
+        ScanEvent::Advertisement(x) => {
+            policy != DuplicatePolicy::ReportChanges
+                || reported
+                    .insert((x.device.id(), x.is_scan_response == Some(true)), x.adv_data.clone())
+                    .as_ref()
+                    != Some(&x.adv_data)
+        }
+        ScanEvent::DeviceLost(id) => {
+            reported.retain(|(x, _), _| x != id);
+            true
+        }
+    }
+}
+
+/// Reports devices lost when they have not been heard for a timeout, and drops the platform's reports of lost devices
+/// which were never heard.
+struct LostDevices<S> {
+    events: S,
+    timeout: Option<Duration>,
+    last_seen: HashMap<DeviceId, Instant>,
+    /// Devices found lost but not yet reported
+    lost: Vec<DeviceId>,
+    timer: Option<Timer>,
+}
+
+impl<S> LostDevices<S> {
+    fn new(events: S, timeout: Option<Duration>) -> Self {
+        LostDevices {
+            events,
+            timeout,
+            last_seen: HashMap::new(),
+            lost: Vec::new(),
+            timer: None,
+        }
+    }
+
+    /// Moves the devices which have timed out to `lost` and starts a timer for the next timeout.
+    fn expire(&mut self) {
+        let Some(timeout) = self.timeout else { return };
+        let now = Instant::now();
+        let (lost, last_seen): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(&mut self.last_seen)
+            .into_iter()
+            .partition(|(_, seen)| now.saturating_duration_since(*seen) >= timeout);
+        self.last_seen = last_seen;
+        self.lost.extend(lost.into_keys());
+
+        self.timer = self
+            .last_seen
+            .values()
+            .min()
+            .map(|seen| Timer::after((*seen + timeout).saturating_duration_since(now)));
+    }
+}
+
+impl<S: Stream<Item = Result<ScanEvent>> + Unpin> Stream for LostDevices<S> {
+    type Item = Result<ScanEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(id) = this.lost.pop() {
+                return Poll::Ready(Some(Ok(ScanEvent::DeviceLost(id))));
+            }
+
+            // Advertisements which have already arrived are handled first, so devices are not lost only because the
+            // stream was not polled for a while
+            let Poll::Ready(event) = Pin::new(&mut this.events).poll_next(cx) else {
+                let Some(timer) = &mut this.timer else {
+                    return Poll::Pending;
+                };
+                ready!(Pin::new(timer).poll(cx));
+                this.timer = None;
+                this.expire();
+                continue;
+            };
+            match event {
+                Some(Ok(ScanEvent::Advertisement(adv))) => {
+                    let now = Instant::now();
+                    this.last_seen.insert(adv.device.id(), now);
+                    // Every other device was heard earlier, so a running timer already fires before this deadline
+                    if let (Some(timeout), None) = (this.timeout, &this.timer) {
+                        this.timer = Some(Timer::after(timeout));
+                    }
+                    return Poll::Ready(Some(Ok(ScanEvent::Advertisement(adv))));
+                }
+                Some(Ok(ScanEvent::DeviceLost(id))) => {
+                    if this.last_seen.remove(&id).is_some() {
+                        return Poll::Ready(Some(Ok(ScanEvent::DeviceLost(id))));
+                    }
+                }
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
 }
@@ -0,0 +1,476 @@
+//! Encoding and decoding of raw advertising data
+//!
+//! Advertising and scan response payloads are a sequence of AD structures, each made of a length octet, an AD type
+//! octet and the AD data (Bluetooth Core Specification, Vol 3, Part C, §11). The AD types are defined in the Core
+//! Specification Supplement (CSS), Part A.
+//!
+//! [`decode`] converts a payload, e.g. one received from a gateway or read from an HCI log, into an
+//! [`AdvertisementData`], and [`encode`] converts an [`AdvertisementData`] back into a payload.
+//!
+//! ```rust
+//! use bluest::adv::{self, flags, PayloadFormat};
+//! use bluest::AdvertisementData;
+//!
+//! let data = AdvertisementData {
+//!     local_name: Some("Sensor".to_string()),
+//!     flags: Some(flags::LE_GENERAL_DISCOVERABLE | flags::BR_EDR_NOT_SUPPORTED),
+//!     appearance: Some(0x0540),
+//!     ..Default::default()
+//! };
+//!
+//! let payload = adv::encode(&data, PayloadFormat::Legacy)?;
+//! assert_eq!(payload, b"\x02\x01\x06\x03\x19\x40\x05\x07\x09Sensor");
+//! assert_eq!(adv::decode(&payload)?, data);
+//!# Ok::<(), bluest::Error>(())
+//! ```
+
+use std::time::Duration;
+
+use crate::error::ErrorKind;
+use crate::{AdvertisementData, BluetoothUuidExt, Error, ManufacturerData, Result, Uuid};
+
+/// Assigned numbers of the AD types supported by this module
+pub mod ad_types {
+    #![allow(missing_docs)]
+
+    pub const FLAGS: u8 = 0x01;
+    pub const INCOMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS: u8 = 0x02;
+    pub const COMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS: u8 = 0x03;
+    pub const INCOMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS: u8 = 0x04;
+    pub const COMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS: u8 = 0x05;
+    pub const INCOMPLETE_LIST_OF_128_BIT_SERVICE_UUIDS: u8 = 0x06;
+    pub const COMPLETE_LIST_OF_128_BIT_SERVICE_UUIDS: u8 = 0x07;
+    pub const SHORTENED_LOCAL_NAME: u8 = 0x08;
+    pub const COMPLETE_LOCAL_NAME: u8 = 0x09;
+    pub const TX_POWER_LEVEL: u8 = 0x0a;
+    pub const LIST_OF_16_BIT_SERVICE_SOLICITATION_UUIDS: u8 = 0x14;
+    pub const LIST_OF_128_BIT_SERVICE_SOLICITATION_UUIDS: u8 = 0x15;
+    pub const SERVICE_DATA_16_BIT_UUID: u8 = 0x16;
+    pub const APPEARANCE: u8 = 0x19;
+    pub const ADVERTISING_INTERVAL: u8 = 0x1a;
+    pub const LIST_OF_32_BIT_SERVICE_SOLICITATION_UUIDS: u8 = 0x1f;
+    pub const SERVICE_DATA_32_BIT_UUID: u8 = 0x20;
+    pub const SERVICE_DATA_128_BIT_UUID: u8 = 0x21;
+    pub const URI: u8 = 0x24;
+    pub const LE_SUPPORTED_FEATURES: u8 = 0x27;
+    pub const ADVERTISING_INTERVAL_LONG: u8 = 0x2f;
+    pub const MANUFACTURER_SPECIFIC_DATA: u8 = 0xff;
+}
+
+/// Bits of the Flags AD type (CSS §A.1.3)
+pub mod flags {
+    /// LE Limited Discoverable Mode
+    pub const LE_LIMITED_DISCOVERABLE: u8 = 1 << 0;
+    /// LE General Discoverable Mode
+    pub const LE_GENERAL_DISCOVERABLE: u8 = 1 << 1;
+    /// BR/EDR Not Supported
+    pub const BR_EDR_NOT_SUPPORTED: u8 = 1 << 2;
+    /// Simultaneous LE and BR/EDR to Same Device Capable (Controller)
+    pub const LE_BR_EDR_CONTROLLER: u8 = 1 << 3;
+}
+
+/// A single AD structure
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdStructure {
+    /// The AD type (see [`ad_types`])
+    pub ad_type: u8,
+    /// The AD data
+    pub data: Vec<u8>,
+}
+
+/// The kind of advertising payload to encode, which determines the space available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PayloadFormat {
+    /// A legacy advertising or scan response payload
+    Legacy,
+    /// The advertising or scan response data of an extended advertising set
+    Extended,
+}
+
+impl PayloadFormat {
+    /// The maximum length of a payload in this format in octets
+    pub fn max_len(self) -> usize {
+        match self {
+            PayloadFormat::Legacy => 31,
+            PayloadFormat::Extended => 1650,
+        }
+    }
+}
+
+/// The length of the AD data of a single AD structure is encoded in one octet together with the AD type
+const MAX_AD_DATA_LEN: usize = 254;
+
+/// The duration of one unit of the advertising interval
+const ADVERTISING_INTERVAL_UNIT: Duration = Duration::from_micros(625);
+
+/// URI scheme name string mappings (Bluetooth Assigned Numbers, §2.7) supported when encoding or decoding URIs
+///
+/// The code point `0x01` stands for an empty scheme, i.e. the URI follows unabbreviated.
+const URI_SCHEMES: &[(char, &str)] = &[('\u{16}', "http:"), ('\u{17}', "https:"), ('\u{01}', "")];
+
+/// Splits `payload` into its AD structures.
+///
+/// Parsing stops at the first zero length octet, which marks the start of the padding of a payload.
+pub fn parse_structures(payload: &[u8]) -> Result<Vec<AdStructure>> {
+    let mut structures = Vec::new();
+    let mut rest = payload;
+    while let Some((&len, tail)) = rest.split_first() {
+        let len = usize::from(len);
+        if len == 0 {
+            break;
+        }
+        if len > tail.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!(
+                    "AD structure at offset {} overruns the payload",
+                    payload.len() - rest.len()
+                ),
+            ));
+        }
+        structures.push(AdStructure {
+            ad_type: tail[0],
+            data: tail[1..len].to_vec(),
+        });
+        rest = &tail[len..];
+    }
+    Ok(structures)
+}
+
+/// Decodes an advertising or scan response payload.
+///
+/// [`AdvertisementData::is_connectable`] depends on the advertising PDU type rather than the payload, and is always
+/// `None`.
+pub fn decode(payload: &[u8]) -> Result<AdvertisementData> {
+    let mut data = AdvertisementData::default();
+    decode_into(&mut data, payload)?;
+    Ok(data)
+}
+
+/// Decodes an advertising or scan response payload into `data`.
+///
+/// Fields present in `payload` overwrite or are added to the corresponding fields of `data`. This can be used to
+/// combine an advertisement with its scan response.
+pub fn decode_into(data: &mut AdvertisementData, payload: &[u8]) -> Result<()> {
+    for structure in parse_structures(payload)? {
+        apply_structure(data, structure.ad_type, &structure.data);
+    }
+    Ok(())
+}
+
+/// Adds the AD structure of type `ad_type` with data `value` to `data`.
+///
+/// Structures which can not be represented exactly by the fields of [`AdvertisementData`] are added to
+/// [`AdvertisementData::other_structures`].
+pub(crate) fn apply_structure(data: &mut AdvertisementData, ad_type: u8, value: &[u8]) {
+    let applied = match ad_type {
+        ad_types::FLAGS => match value {
+            &[flags] => {
+                data.flags = Some(flags);
+                true
+            }
+            _ => false,
+        },
+        ad_types::INCOMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS
+        | ad_types::COMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS
+        | ad_types::INCOMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS
+        | ad_types::COMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS
+        | ad_types::INCOMPLETE_LIST_OF_128_BIT_SERVICE_UUIDS
+        | ad_types::COMPLETE_LIST_OF_128_BIT_SERVICE_UUIDS => {
+            let size =
+                match ad_type {
+                    ad_types::INCOMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS
+                    | ad_types::COMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS => 2,
+                    ad_types::INCOMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS
+                    | ad_types::COMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS => 4,
+                    _ => 16,
+                };
+            extend_uuids(&mut data.services, value, size)
+        }
+        ad_types::LIST_OF_16_BIT_SERVICE_SOLICITATION_UUIDS => extend_uuids(&mut data.solicited_services, value, 2),
+        ad_types::LIST_OF_32_BIT_SERVICE_SOLICITATION_UUIDS => extend_uuids(&mut data.solicited_services, value, 4),
+        ad_types::LIST_OF_128_BIT_SERVICE_SOLICITATION_UUIDS => extend_uuids(&mut data.solicited_services, value, 16),
+        ad_types::SHORTENED_LOCAL_NAME | ad_types::COMPLETE_LOCAL_NAME => match std::str::from_utf8(value) {
+            Ok(name) => {
+                data.local_name = Some(name.to_string());
+                true
+            }
+            Err(_) => false,
+        },
+        ad_types::TX_POWER_LEVEL => match value {
+            &[tx_power_level] => {
+                data.tx_power_level = Some(i16::from(tx_power_level as i8));
+                true
+            }
+            _ => false,
+        },
+        ad_types::SERVICE_DATA_16_BIT_UUID
+        | ad_types::SERVICE_DATA_32_BIT_UUID
+        | ad_types::SERVICE_DATA_128_BIT_UUID => {
+            let size = match ad_type {
+                ad_types::SERVICE_DATA_16_BIT_UUID => 2,
+                ad_types::SERVICE_DATA_32_BIT_UUID => 4,
+                _ => 16,
+            };
+            if value.len() >= size {
+                let (uuid, service_data) = value.split_at(size);
+                data.service_data
+                    .insert(uuid_from_le_bytes(uuid), service_data.to_vec());
+                true
+            } else {
+                false
+            }
+        }
+        ad_types::APPEARANCE => match value {
+            &[a, b] => {
+                data.appearance = Some(u16::from_le_bytes([a, b]));
+                true
+            }
+            _ => false,
+        },
+        ad_types::ADVERTISING_INTERVAL | ad_types::ADVERTISING_INTERVAL_LONG => {
+            let units = match (ad_type, value) {
+                (ad_types::ADVERTISING_INTERVAL, &[a, b]) => Some(u32::from_le_bytes([a, b, 0, 0])),
+                (ad_types::ADVERTISING_INTERVAL_LONG, &[a, b, c]) => Some(u32::from_le_bytes([a, b, c, 0])),
+                (ad_types::ADVERTISING_INTERVAL_LONG, &[a, b, c, d]) => Some(u32::from_le_bytes([a, b, c, d])),
+                _ => None,
+            };
+            if let Some(units) = units {
+                data.advertising_interval = Some(ADVERTISING_INTERVAL_UNIT * units);
+            }
+            units.is_some()
+        }
+        ad_types::URI => match decode_uri(value) {
+            Some(uri) => {
+                data.uri = Some(uri);
+                true
+            }
+            None => false,
+        },
+        ad_types::LE_SUPPORTED_FEATURES => {
+            data.le_supported_features = Some(value.to_vec());
+            true
+        }
+        ad_types::MANUFACTURER_SPECIFIC_DATA if value.len() >= 2 => {
+            data.manufacturer_data.push(ManufacturerData {
+                company_id: u16::from_le_bytes([value[0], value[1]]),
+                data: value[2..].to_vec(),
+            });
+            true
+        }
+        _ => false,
+    };
+
+    if !applied {
+        data.other_structures.push(AdStructure {
+            ad_type,
+            data: value.to_vec(),
+        });
+    }
+}
+
+/// Encodes `data` as an advertising payload.
+///
+/// UUIDs are encoded in their shortest form and the local name is encoded as a complete local name.
+/// [`AdvertisementData::is_connectable`] is not part of the payload and is ignored.
+///
+/// Returns an error with [`ErrorKind::InvalidParameter`] if a field can not be encoded, or if the payload does not fit
+/// in `format`.
+pub fn encode(data: &AdvertisementData, format: PayloadFormat) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    for structure in encode_structures(data)? {
+        if structure.data.len() > MAX_AD_DATA_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!(
+                    "AD structure of type {:#04x} has {} octets of data but can hold at most {MAX_AD_DATA_LEN}",
+                    structure.ad_type,
+                    structure.data.len()
+                ),
+            ));
+        }
+        payload.push(structure.data.len() as u8 + 1);
+        payload.push(structure.ad_type);
+        payload.extend_from_slice(&structure.data);
+    }
+
+    if payload.len() > format.max_len() {
+        return Err(Error::new(
+            ErrorKind::InvalidParameter,
+            None,
+            format!(
+                "advertising data needs {} octets but a {:?} payload holds at most {}",
+                payload.len(),
+                format,
+                format.max_len()
+            ),
+        ));
+    }
+
+    Ok(payload)
+}
+
+/// Converts `data` to the AD structures [`encode`] writes, in the same order.
+pub(crate) fn encode_structures(data: &AdvertisementData) -> Result<Vec<AdStructure>> {
+    let mut structures = Vec::new();
+    let mut push = |ad_type: u8, data: Vec<u8>| structures.push(AdStructure { ad_type, data });
+
+    if let Some(flags) = data.flags {
+        push(ad_types::FLAGS, vec![flags]);
+    }
+
+    let [services16, services32, services128] = encode_uuids(&data.services);
+    if !services16.is_empty() {
+        push(ad_types::COMPLETE_LIST_OF_16_BIT_SERVICE_UUIDS, services16);
+    }
+    if !services32.is_empty() {
+        push(ad_types::COMPLETE_LIST_OF_32_BIT_SERVICE_UUIDS, services32);
+    }
+    if !services128.is_empty() {
+        push(ad_types::COMPLETE_LIST_OF_128_BIT_SERVICE_UUIDS, services128);
+    }
+
+    let [solicited16, solicited32, solicited128] = encode_uuids(&data.solicited_services);
+    if !solicited16.is_empty() {
+        push(ad_types::LIST_OF_16_BIT_SERVICE_SOLICITATION_UUIDS, solicited16);
+    }
+    if !solicited32.is_empty() {
+        push(ad_types::LIST_OF_32_BIT_SERVICE_SOLICITATION_UUIDS, solicited32);
+    }
+    if !solicited128.is_empty() {
+        push(ad_types::LIST_OF_128_BIT_SERVICE_SOLICITATION_UUIDS, solicited128);
+    }
+
+    let mut service_data = data.service_data.iter().collect::<Vec<_>>();
+    service_data.sort();
+    for (uuid, value) in service_data {
+        let uuid = uuid_to_le_bytes(uuid);
+        let ad_type = match uuid.len() {
+            2 => ad_types::SERVICE_DATA_16_BIT_UUID,
+            4 => ad_types::SERVICE_DATA_32_BIT_UUID,
+            _ => ad_types::SERVICE_DATA_128_BIT_UUID,
+        };
+        push(ad_type, [uuid.as_slice(), value].concat());
+    }
+
+    for manufacturer_data in &data.manufacturer_data {
+        push(
+            ad_types::MANUFACTURER_SPECIFIC_DATA,
+            [&manufacturer_data.company_id.to_le_bytes()[..], &manufacturer_data.data].concat(),
+        );
+    }
+
+    if let Some(appearance) = data.appearance {
+        push(ad_types::APPEARANCE, appearance.to_le_bytes().to_vec());
+    }
+
+    if let Some(tx_power_level) = data.tx_power_level {
+        let tx_power_level = i8::try_from(tx_power_level).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!("TX power level {tx_power_level} dBm is out of range"),
+            )
+        })?;
+        push(ad_types::TX_POWER_LEVEL, vec![tx_power_level as u8]);
+    }
+
+    if let Some(interval) = data.advertising_interval {
+        let units = u32::try_from(interval.as_nanos() / ADVERTISING_INTERVAL_UNIT.as_nanos()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!("advertising interval {interval:?} is out of range"),
+            )
+        })?;
+        match u16::try_from(units) {
+            Ok(units) => push(ad_types::ADVERTISING_INTERVAL, units.to_le_bytes().to_vec()),
+            Err(_) => {
+                let bytes = units.to_le_bytes();
+                let len = if bytes[3] == 0 { 3 } else { 4 };
+                push(ad_types::ADVERTISING_INTERVAL_LONG, bytes[..len].to_vec());
+            }
+        }
+    }
+
+    if let Some(uri) = &data.uri {
+        push(ad_types::URI, encode_uri(uri));
+    }
+
+    if let Some(features) = &data.le_supported_features {
+        push(ad_types::LE_SUPPORTED_FEATURES, features.clone());
+    }
+
+    if let Some(local_name) = &data.local_name {
+        push(ad_types::COMPLETE_LOCAL_NAME, local_name.as_bytes().to_vec());
+    }
+
+    for structure in &data.other_structures {
+        push(structure.ad_type, structure.data.clone());
+    }
+
+    Ok(structures)
+}
+
+fn uuid_from_le_bytes(bytes: &[u8]) -> Uuid {
+    let mut bytes = bytes.to_vec();
+    bytes.reverse();
+    Uuid::from_bluetooth_bytes(&bytes)
+}
+
+fn uuid_to_le_bytes(uuid: &Uuid) -> Vec<u8> {
+    let mut bytes = uuid.as_bluetooth_bytes().to_vec();
+    bytes.reverse();
+    bytes
+}
+
+/// Appends the UUIDs of `size` octets in `value` to `uuids`, skipping duplicates.
+///
+/// Returns `false`, leaving `uuids` untouched, if `value` is not a list of such UUIDs.
+fn extend_uuids(uuids: &mut Vec<Uuid>, value: &[u8], size: usize) -> bool {
+    if !value.len().is_multiple_of(size) {
+        return false;
+    }
+
+    for uuid in value.chunks_exact(size).map(uuid_from_le_bytes) {
+        if !uuids.contains(&uuid) {
+            uuids.push(uuid);
+        }
+    }
+    true
+}
+
+/// Encodes `uuids` into lists of 16-bit, 32-bit and 128-bit UUIDs
+fn encode_uuids(uuids: &[Uuid]) -> [Vec<u8>; 3] {
+    let mut lists: [Vec<u8>; 3] = Default::default();
+    for uuid in uuids {
+        let bytes = uuid_to_le_bytes(uuid);
+        let list = match bytes.len() {
+            2 => &mut lists[0],
+            4 => &mut lists[1],
+            _ => &mut lists[2],
+        };
+        list.extend_from_slice(&bytes);
+    }
+    lists
+}
+
+fn decode_uri(value: &[u8]) -> Option<String> {
+    let uri = std::str::from_utf8(value).ok()?;
+    let mut chars = uri.chars();
+    let code = chars.next()?;
+    let (_, scheme) = URI_SCHEMES.iter().find(|(x, _)| *x == code)?;
+    Some(format!("{scheme}{}", chars.as_str()))
+}
+
+pub(crate) fn encode_uri(uri: &str) -> Vec<u8> {
+    let (code, rest) = URI_SCHEMES
+        .iter()
+        .find_map(|(code, scheme)| uri.strip_prefix(scheme).map(|rest| (*code, rest)))
+        .unwrap_or(('\u{01}', uri));
+    let mut value = code.to_string().into_bytes();
+    value.extend_from_slice(rest.as_bytes());
+    value
+}
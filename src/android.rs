@@ -4,10 +4,12 @@ use self::bindings::java::lang::Throwable;
 use crate::error::ErrorKind;
 
 pub mod adapter;
+pub mod advertisement;
 pub mod characteristic;
 pub mod descriptor;
 pub mod device;
 pub mod l2cap_channel;
+pub mod server;
 pub mod service;
 
 pub(crate) mod bindings;
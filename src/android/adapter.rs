@@ -3,6 +3,7 @@ use std::pin::Pin;
 use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use async_channel::{Receiver, Sender};
 use futures_core::Stream;
@@ -18,9 +19,13 @@ use super::bindings::com::github::alexmoon::bluest::android::BluestScanCallback;
 use super::device::DeviceImpl;
 use super::{JavaIterator, OptionExt};
 use crate::android::bindings::java::util::Map_Entry;
+use crate::error::ErrorKind;
+use crate::server::{RequestHandler, Server, ServiceDefinition};
 use crate::util::defer;
 use crate::{
-    AdapterEvent, AdvertisementData, AdvertisingDevice, ConnectionEvent, Device, DeviceId, ManufacturerData, Result,
+    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters, ConnectionEvent,
+    Device, DeviceId, Error, ManufacturerData, ManufacturerDataList, MonitorEvent, MonitorOptions, MonitorPattern, Phy,
+    Result, ScanEvent, ScanFilter, ScanMode, ScanOptions,
 };
 
 struct AdapterInner {
@@ -74,17 +79,26 @@ impl AdapterImpl {
         todo!()
     }
 
-    pub async fn scan<'a>(
-        &'a self,
-        _services: &'a [Uuid],
-    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + 'a> {
+    pub async fn scan(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
+        if options.mode == ScanMode::Passive {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "Android does not support passive scanning",
+            ));
+        }
+
         self.inner.manager.vm().with_env(|env| {
             let receiver = SCAN_CALLBACKS.allocate();
             let callback = BluestScanCallback::new(env, receiver.id)?;
             let callback_global = callback.as_global();
             let scanner = self.inner.le_scanner.as_ref(env);
             let settings = ScanSettings_Builder::new(env)?;
-            settings.setScanMode(ScanSettings::SCAN_MODE_LOW_LATENCY)?;
+            settings.setScanMode(scan_mode(options))?;
             let settings = settings.build()?.non_null()?;
             scanner.startScan_List_ScanSettings_ScanCallback(Null, settings, callback)?;
 
@@ -99,13 +113,30 @@ impl AdapterImpl {
                 });
             });
 
-            Ok(Box::pin(receiver).map(move |x| {
-                let _guard = &guard;
-                x
-            }))
+            // The bindings cannot build a list of platform scan filters, so the filter is applied here
+            Ok(Box::pin(receiver)
+                .map(move |x| {
+                    let _guard = &guard;
+                    x
+                })
+                .filter(move |x| filter.matches(x)))
         })
     }
 
+    /// Starts scanning for Bluetooth advertising packets.
+    ///
+    /// Android never reports devices lost or scan errors, so only advertisements are returned.
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        Ok(self
+            .scan(filter, options)
+            .await?
+            .map(|adv| Ok(ScanEvent::Advertisement(adv))))
+    }
+
     pub async fn discover_devices<'a>(
         &'a self,
         services: &'a [Uuid],
@@ -116,7 +147,10 @@ impl AdapterImpl {
         let advertising = Box::pin(stream::try_unfold(None, |state| async {
             let mut stream = match state {
                 Some(stream) => stream,
-                None => self.scan(services).await?,
+                None => {
+                    self.scan(ScanFilter::from_services(services), &ScanOptions::default())
+                        .await?
+                }
             };
             Ok(stream.next().await.map(|x| (x.device, Some(stream))))
         }));
@@ -140,6 +174,63 @@ impl AdapterImpl {
     ) -> Result<impl Stream<Item = ConnectionEvent> + Send + Unpin + 'a> {
         Ok(stream::empty()) // TODO
     }
+
+    pub async fn start_advertising(
+        &self,
+        _data: AdvertisementData,
+        _parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "advertising is not supported on Android",
+        ))
+    }
+
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "advertising is not supported on Android",
+        ))
+    }
+
+    pub async fn monitor_advertisements(
+        &self,
+        _patterns: &[MonitorPattern],
+        _options: &MonitorOptions,
+    ) -> Result<stream::Empty<MonitorEvent>> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "advertisement monitors are not supported on Android",
+        ))
+    }
+
+    #[cfg(feature = "l2cap")]
+    pub async fn listen_l2cap(
+        &self,
+        _psm: Option<u16>,
+        _secure: bool,
+    ) -> Result<(u16, stream::Empty<Result<(Device, crate::L2capChannel)>>)> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "listening for L2CAP channels is not supported on Android",
+        ))
+    }
+
+    pub async fn start_server(
+        &self,
+        _services: Vec<ServiceDefinition>,
+        _handler: Arc<dyn RequestHandler>,
+    ) -> Result<Server> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "GATT servers are not supported on Android",
+        ))
+    }
 }
 
 impl PartialEq for AdapterImpl {
@@ -242,6 +333,34 @@ fn convert_uuid(uuid: Local<'_, ParcelUuid>) -> Result<Uuid> {
     Ok(Uuid::from_u64_pair(msb, lsb))
 }
 
+/// Converts one of the `BluetoothDevice.PHY_LE_*` constants
+fn convert_phy(phy: i32) -> Option<Phy> {
+    match phy {
+        1 => Some(Phy::Le1M),
+        2 => Some(Phy::Le2M),
+        3 => Some(Phy::LeCoded),
+        _ => None,
+    }
+}
+
+/// The `ScanSettings.SCAN_MODE_*` constant closest to the scan interval and window hints in `options`
+fn scan_mode(options: &ScanOptions) -> i32 {
+    match (options.interval, options.window) {
+        (Some(interval), Some(window)) if !interval.is_zero() => {
+            // Android scans 10% of the time in low power mode, 25% in balanced mode and continuously in low latency mode
+            let duty_cycle = window.as_secs_f64() / interval.as_secs_f64();
+            if duty_cycle < 0.175 {
+                ScanSettings::SCAN_MODE_LOW_POWER
+            } else if duty_cycle < 0.625 {
+                ScanSettings::SCAN_MODE_BALANCED
+            } else {
+                ScanSettings::SCAN_MODE_LOW_LATENCY
+            }
+        }
+        _ => ScanSettings::SCAN_MODE_LOW_LATENCY,
+    }
+}
+
 #[no_mangle]
 fn on_scan_result(env: Env<'_>, id: i32, callback_type: i32, scan_result: Arg<ScanResult>) -> Result<()> {
     let scan_result = unsafe { scan_result.into_ref(env) }.non_null()?;
@@ -253,7 +372,15 @@ fn on_scan_result(env: Env<'_>, id: i32, callback_type: i32, scan_result: Arg<Sc
 
     let address = device.getAddress()?.non_null()?.to_string_lossy();
     let rssi = scan_result.getRssi()?;
-    let is_connectable = scan_result.isConnectable()?;
+    // ScanResult::getTimestampNanos is relative to boot, which we have no way to map to wall-clock time
+    let timestamp = SystemTime::now();
+    let is_connectable = Some(scan_result.isConnectable()?);
+    let primary_phy = convert_phy(scan_result.getPrimaryPhy()?);
+    let secondary_phy = convert_phy(scan_result.getSecondaryPhy()?);
+    // ScanResult.SID_NOT_PRESENT is 0xff
+    let advertising_sid = u8::try_from(scan_result.getAdvertisingSid()?)
+        .ok()
+        .filter(|&x| x != 0xff);
     let local_name = scan_record.getDeviceName()?.map(|s| s.to_string_lossy());
     let tx_power_level = scan_record.getTxPowerLevel()?;
 
@@ -277,13 +404,12 @@ fn on_scan_result(env: Env<'_>, id: i32, callback_type: i32, scan_result: Arg<Sc
     }
 
     // Manufacturer data
-    let mut manufacturer_data = None;
+    let mut manufacturer_data = ManufacturerDataList::new();
     let msd = scan_record.getManufacturerSpecificData()?.non_null()?;
-    // TODO there can be multiple manufacturer data entries, but the bluest API only supports one. So grab just the first.
-    if msd.size()? != 0 {
-        let val: Local<'_, ByteArray> = msd.valueAt(0)?.non_null()?.cast()?;
-        manufacturer_data = Some(ManufacturerData {
-            company_id: msd.keyAt(0)? as _,
+    for index in 0..msd.size()? {
+        let val: Local<'_, ByteArray> = msd.valueAt(index)?.non_null()?.cast()?;
+        manufacturer_data.push(ManufacturerData {
+            company_id: msd.keyAt(index)? as _,
             data: val.as_vec().into_iter().map(|i| i as u8).collect(),
         });
     }
@@ -298,12 +424,19 @@ fn on_scan_result(env: Env<'_>, id: i32, callback_type: i32, scan_result: Arg<Sc
         adv_data: AdvertisementData {
             is_connectable,
             local_name,
-            manufacturer_data, // TODO, SparseArray is cursed.
+            manufacturer_data,
             service_data,
             services,
             tx_power_level: Some(tx_power_level as _),
+            ..Default::default()
         },
         rssi: Some(rssi as _),
+        timestamp: Some(timestamp),
+        address_type: None,
+        primary_phy,
+        secondary_phy,
+        advertising_sid,
+        is_scan_response: None,
     };
     SCAN_CALLBACKS.callback(id, d);
 
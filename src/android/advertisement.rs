@@ -1,34 +1,11 @@
-use std::error::Error;
-use std::time::Duration;
-use async_trait::async_trait;
+use crate::Result;
 
-pub struct AdvertisementImpl {
-    company_id: u16,
-}
+/// Advertising is not supported on Android, so there are no advertisements.
+#[derive(Debug)]
+pub enum AdvertisementImpl {}
 
 impl AdvertisementImpl {
-    pub fn new(company_id: u16) -> Self {
-        AdvertisementImpl { company_id }
-    }
-}
-
-#[async_trait]
-impl Advertisement for AdvertisementImpl {
-    async fn advertise(&self, data: &Vec<u8>, advertise_duration: Option<Duration>) -> Result<(), Box<dyn Error>> {
-        // Android-specific Bluetooth advertisement setup
-        println!("Android advertisement started with company ID: {:X}.", self.company_id);
-
-        if let Some(duration) = advertise_duration {
-            tokio::time::sleep(duration).await;
-            self.stop()?;
-            println!("Android advertisement stopped after {:?}", duration);
-        }
-
-        Ok(())
-    }
-
-    fn stop(&self) -> Result<(), Box<dyn Error>> {
-        println!("Android advertisement manually stopped.");
-        Ok(())
+    pub fn stop_advertising(&mut self) -> Result<()> {
+        match *self {}
     }
 }
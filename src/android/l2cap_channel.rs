@@ -1,9 +1,13 @@
 #![cfg(feature = "l2cap")]
 
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 use std::{fmt, slice, thread};
 
 use async_channel::{Receiver, Sender, TryRecvError, TrySendError};
+use futures_core::Stream;
 use java_spaghetti::{ByteArray, Global, Local, PrimitiveArray};
 use tracing::{debug, warn};
 
@@ -28,6 +32,9 @@ pub fn open_l2cap_channel(
 
         channel.connect()?;
 
+        let read_mtu = channel.getMaxReceivePacketSize()? as usize;
+        let write_mtu = channel.getMaxTransmitPacketSize()? as usize;
+
         // The L2capCloser closes the l2cap channel when dropped.
         // We put it in an Arc held by both the reader and writer, so it gets dropped
         // when
@@ -109,10 +116,13 @@ pub fn open_l2cap_channel(
             L2capChannelReader {
                 closer: closer.clone(),
                 stream: read_receiver,
+                mtu: read_mtu,
             },
             L2capChannelWriter {
                 closer,
                 stream: write_sender,
+                sending: None,
+                mtu: write_mtu,
             },
         ))
     })
@@ -144,6 +154,7 @@ impl Drop for L2capCloser {
 pub struct L2capChannelReader {
     stream: Receiver<Vec<u8>>,
     closer: Arc<L2capCloser>,
+    mtu: usize,
 }
 
 impl L2capChannelReader {
@@ -180,6 +191,29 @@ impl L2capChannelReader {
         Ok(packet.len())
     }
 
+    /// Like the socket on Linux, reports the end of the channel as an empty packet.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        let Some(packet) = ready!(Pin::new(&mut self.stream).poll_next(cx)) else {
+            return Poll::Ready(Ok(0));
+        };
+
+        if packet.len() > buf.len() {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                "Buffer is too small",
+            )));
+        }
+
+        buf[..packet.len()].copy_from_slice(&packet);
+
+        Poll::Ready(Ok(packet.len()))
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(self.mtu)
+    }
+
     pub async fn close(&mut self) -> Result<()> {
         self.closer.close();
         Ok(())
@@ -195,6 +229,9 @@ impl fmt::Debug for L2capChannelReader {
 pub struct L2capChannelWriter {
     stream: Sender<Vec<u8>>,
     closer: Arc<L2capCloser>,
+    /// The packet being enqueued by `poll_write`
+    sending: Option<Sending>,
+    mtu: usize,
 }
 
 impl L2capChannelWriter {
@@ -212,12 +249,56 @@ impl L2capChannelWriter {
         })
     }
 
+    /// Enqueues `packet`.
+    ///
+    /// The shared writer only polls this with its pending packet, which doesn't change until the write completes. A
+    /// write in progress for a different packet is abandoned, so that stale data is never sent.
+    pub fn poll_write(&mut self, cx: &mut Context<'_>, packet: &[u8]) -> Poll<Result<()>> {
+        if self.sending.as_ref().is_some_and(|x| x.packet != packet) {
+            self.sending = None;
+        }
+
+        let sending = self.sending.get_or_insert_with(|| {
+            let stream = self.stream.clone();
+            let packet = packet.to_vec();
+            let queued = packet.clone();
+            Sending {
+                packet,
+                future: Box::pin(async move {
+                    stream
+                        .send(queued)
+                        .await
+                        .map_err(|_| Error::new(ErrorKind::ConnectionFailed, None, "channel is closed"))
+                }),
+            }
+        });
+
+        let res = ready!(sending.future.as_mut().poll(cx));
+        self.sending = None;
+        Poll::Ready(res)
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(self.mtu)
+    }
+
+    pub fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.closer.close();
+        Poll::Ready(Ok(()))
+    }
+
     pub async fn close(&mut self) -> Result<()> {
         self.closer.close();
         Ok(())
     }
 }
 
+/// A packet being enqueued, which is only sent once the future completes.
+struct Sending {
+    packet: Vec<u8>,
+    future: Pin<Box<dyn Future<Output = Result<()>> + Send>>,
+}
+
 impl fmt::Debug for L2capChannelWriter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("L2capChannelWriter")
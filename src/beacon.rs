@@ -0,0 +1,396 @@
+//! Decoding and encoding of common beacon formats
+//!
+//! [`Beacon::from_device`] recognises iBeacon and AltBeacon advertisements, which are carried in manufacturer specific
+//! data, and Eddystone advertisements, which are carried in service data for the [`EDDYSTONE_SERVICE`] UUID. Each
+//! beacon type can also be converted into an [`AdvertisementData`] to broadcast it with
+//! [`Adapter::start_advertising`][crate::Adapter::start_advertising].
+//!
+//! Multi-octet fields of all these formats are big-endian, unlike the rest of the advertising data.
+//!
+//! ```rust
+//! use bluest::beacon::{Beacon, Eddystone};
+//!
+//! let beacon = Eddystone::Url {
+//!     tx_power: -20,
+//!     url: "https://www.example.com/".to_string(),
+//! };
+//! let data = beacon.to_advertisement()?;
+//! assert_eq!(Beacon::from_advertisement(&data), Some(Beacon::Eddystone(beacon)));
+//!# Ok::<(), bluest::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::error::ErrorKind;
+use crate::{AdvertisementData, AdvertisingDevice, Error, ManufacturerData, Result, Uuid};
+
+/// The company identifier of Apple, Inc., used by iBeacon advertisements
+pub const APPLE_COMPANY_ID: u16 = 0x004c;
+
+/// The 16-bit service UUID of Eddystone advertisements
+pub const EDDYSTONE_SERVICE: Uuid = Uuid::from_u128(0x0000feaa_0000_1000_8000_00805f9b34fb);
+
+const IBEACON_PREFIX: [u8; 2] = [0x02, 0x15];
+const ALTBEACON_PREFIX: [u8; 2] = [0xbe, 0xac];
+
+const EDDYSTONE_UID: u8 = 0x00;
+const EDDYSTONE_URL: u8 = 0x10;
+const EDDYSTONE_TLM: u8 = 0x20;
+const EDDYSTONE_EID: u8 = 0x30;
+
+/// The longest encoded URL, excluding the scheme prefix, that fits in an Eddystone-URL frame
+const MAX_ENCODED_URL_LEN: usize = 17;
+
+/// Eddystone-URL scheme prefixes, indexed by their code. Prefixes ending in `www.` come first so they are preferred.
+const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+/// Eddystone-URL expansions, indexed by their code. Expansions ending in `/` come first so they are preferred.
+const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net", ".info", ".biz",
+    ".gov",
+];
+
+/// A TLM temperature which indicates that the beacon has no temperature sensor
+const TLM_NO_TEMPERATURE: i16 = i16::MIN;
+
+/// A beacon decoded from an advertisement
+#[derive(Debug, Clone, PartialEq)]
+pub enum Beacon {
+    /// An Apple iBeacon
+    IBeacon(IBeacon),
+    /// An AltBeacon
+    AltBeacon(AltBeacon),
+    /// An Eddystone frame
+    Eddystone(Eddystone),
+}
+
+impl Beacon {
+    /// Decodes the beacon advertised by `device`, if it is advertising one of the supported formats.
+    pub fn from_device(device: &AdvertisingDevice) -> Option<Self> {
+        Self::from_advertisement(&device.adv_data)
+    }
+
+    /// Decodes the beacon in `data`, if it contains one of the supported formats.
+    ///
+    /// If `data` contains several beacons, an iBeacon is returned first, then an AltBeacon, then an Eddystone frame.
+    pub fn from_advertisement(data: &AdvertisementData) -> Option<Self> {
+        IBeacon::from_advertisement(data)
+            .map(Beacon::IBeacon)
+            .or_else(|| AltBeacon::from_advertisement(data).map(Beacon::AltBeacon))
+            .or_else(|| Eddystone::from_advertisement(data).map(Beacon::Eddystone))
+    }
+
+    /// Builds the advertisement data which broadcasts this beacon.
+    ///
+    /// Returns an error with [`ErrorKind::InvalidParameter`] if the beacon cannot be encoded.
+    pub fn to_advertisement(&self) -> Result<AdvertisementData> {
+        match self {
+            Beacon::IBeacon(beacon) => Ok(beacon.to_advertisement()),
+            Beacon::AltBeacon(beacon) => Ok(beacon.to_advertisement()),
+            Beacon::Eddystone(beacon) => beacon.to_advertisement(),
+        }
+    }
+}
+
+/// An Apple iBeacon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IBeacon {
+    /// The proximity UUID identifying the beacons of an organisation or deployment
+    pub uuid: Uuid,
+    /// The major number, typically identifying a group of beacons
+    pub major: u16,
+    /// The minor number, typically identifying a beacon within its group
+    pub minor: u16,
+    /// The signal strength in dBm measured 1 m from the beacon
+    pub measured_power: i8,
+}
+
+impl IBeacon {
+    /// Decodes the iBeacon in the manufacturer specific data of `data`, if there is one.
+    pub fn from_advertisement(data: &AdvertisementData) -> Option<Self> {
+        data.manufacturer_data
+            .get_all(APPLE_COMPANY_ID)
+            .find_map(Self::from_manufacturer_data)
+    }
+
+    fn from_manufacturer_data(value: &[u8]) -> Option<Self> {
+        let value: &[u8; 23] = value.try_into().ok()?;
+        if value[..2] != IBEACON_PREFIX {
+            return None;
+        }
+        Some(IBeacon {
+            uuid: Uuid::from_bytes(value[2..18].try_into().unwrap()),
+            major: u16::from_be_bytes([value[18], value[19]]),
+            minor: u16::from_be_bytes([value[20], value[21]]),
+            measured_power: value[22] as i8,
+        })
+    }
+
+    /// Builds the non-connectable advertisement data which broadcasts this iBeacon.
+    pub fn to_advertisement(&self) -> AdvertisementData {
+        let mut value = IBEACON_PREFIX.to_vec();
+        value.extend_from_slice(self.uuid.as_bytes());
+        value.extend_from_slice(&self.major.to_be_bytes());
+        value.extend_from_slice(&self.minor.to_be_bytes());
+        value.push(self.measured_power as u8);
+        AdvertisementData {
+            manufacturer_data: ManufacturerData {
+                company_id: APPLE_COMPANY_ID,
+                data: value,
+            }
+            .into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An AltBeacon (see the [AltBeacon specification](https://github.com/AltBeacon/spec))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AltBeacon {
+    /// The company identifier of the beacon's manufacturer
+    pub company_id: u16,
+    /// The beacon identifier. Its first 16 octets are usually an organisational unit UUID.
+    pub beacon_id: [u8; 20],
+    /// The signal strength in dBm measured 1 m from the beacon
+    pub reference_rssi: i8,
+    /// The value reserved for use by the manufacturer
+    pub mfg_reserved: u8,
+}
+
+impl AltBeacon {
+    /// Decodes the AltBeacon in the manufacturer specific data of `data`, if there is one.
+    pub fn from_advertisement(data: &AdvertisementData) -> Option<Self> {
+        data.manufacturer_data.iter().find_map(|entry| {
+            let value: &[u8; 24] = entry.data.as_slice().try_into().ok()?;
+            if value[..2] != ALTBEACON_PREFIX {
+                return None;
+            }
+            Some(AltBeacon {
+                company_id: entry.company_id,
+                beacon_id: value[2..22].try_into().unwrap(),
+                reference_rssi: value[22] as i8,
+                mfg_reserved: value[23],
+            })
+        })
+    }
+
+    /// Builds the non-connectable advertisement data which broadcasts this AltBeacon.
+    pub fn to_advertisement(&self) -> AdvertisementData {
+        let mut value = ALTBEACON_PREFIX.to_vec();
+        value.extend_from_slice(&self.beacon_id);
+        value.push(self.reference_rssi as u8);
+        value.push(self.mfg_reserved);
+        AdvertisementData {
+            manufacturer_data: ManufacturerData {
+                company_id: self.company_id,
+                data: value,
+            }
+            .into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An Eddystone frame (see the [Eddystone specification](https://github.com/google/eddystone/blob/master/protocol-specification.md))
+#[derive(Debug, Clone, PartialEq)]
+pub enum Eddystone {
+    /// An Eddystone-UID frame, broadcasting a static identifier
+    Uid {
+        /// The signal strength in dBm at 0 m from the beacon
+        tx_power: i8,
+        /// The namespace of the identifier
+        namespace: [u8; 10],
+        /// The instance within the namespace
+        instance: [u8; 6],
+    },
+    /// An Eddystone-URL frame, broadcasting a URL
+    Url {
+        /// The signal strength in dBm at 0 m from the beacon
+        tx_power: i8,
+        /// The URL
+        url: String,
+    },
+    /// An unencrypted Eddystone-TLM frame, broadcasting telemetry
+    Tlm(EddystoneTlm),
+    /// An Eddystone-EID frame, broadcasting an encrypted ephemeral identifier
+    Eid {
+        /// The signal strength in dBm at 0 m from the beacon
+        tx_power: i8,
+        /// The ephemeral identifier
+        eid: [u8; 8],
+    },
+}
+
+/// The telemetry of an unencrypted Eddystone-TLM frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EddystoneTlm {
+    /// The battery voltage in mV, or `None` if the beacon does not measure it
+    pub battery_voltage: Option<u16>,
+    /// The temperature in °C, with a resolution of 1/256 °C, or `None` if the beacon has no temperature sensor
+    pub temperature: Option<f32>,
+    /// The number of advertising packets sent since the beacon was powered on or rebooted
+    pub advertising_count: u32,
+    /// The time since the beacon was powered on or rebooted, with a resolution of 0.1 s
+    pub uptime: Duration,
+}
+
+impl Eddystone {
+    /// Decodes the Eddystone frame in the service data of `data`, if there is one.
+    ///
+    /// Encrypted Eddystone-TLM frames and frames of unknown types are ignored.
+    pub fn from_advertisement(data: &AdvertisementData) -> Option<Self> {
+        Self::from_frame(data.service_data.get(&EDDYSTONE_SERVICE)?)
+    }
+
+    fn from_frame(frame: &[u8]) -> Option<Self> {
+        let (&frame_type, value) = frame.split_first()?;
+        match frame_type {
+            EDDYSTONE_UID if value.len() == 17 || value.len() == 19 => Some(Eddystone::Uid {
+                tx_power: value[0] as i8,
+                namespace: value[1..11].try_into().unwrap(),
+                instance: value[11..17].try_into().unwrap(),
+            }),
+            EDDYSTONE_URL if value.len() >= 2 => Some(Eddystone::Url {
+                tx_power: value[0] as i8,
+                url: decode_url(value[1], &value[2..])?,
+            }),
+            EDDYSTONE_TLM if value.len() == 13 && value[0] == 0x00 => {
+                let battery_voltage = u16::from_be_bytes([value[1], value[2]]);
+                let temperature = i16::from_be_bytes([value[3], value[4]]);
+                let uptime = u32::from_be_bytes(value[9..13].try_into().unwrap());
+                Some(Eddystone::Tlm(EddystoneTlm {
+                    battery_voltage: (battery_voltage != 0).then_some(battery_voltage),
+                    temperature: (temperature != TLM_NO_TEMPERATURE).then(|| f32::from(temperature) / 256.0),
+                    advertising_count: u32::from_be_bytes(value[5..9].try_into().unwrap()),
+                    uptime: Duration::from_millis(u64::from(uptime) * 100),
+                }))
+            }
+            EDDYSTONE_EID if value.len() == 9 => Some(Eddystone::Eid {
+                tx_power: value[0] as i8,
+                eid: value[1..9].try_into().unwrap(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Builds the non-connectable advertisement data which broadcasts this frame.
+    ///
+    /// Returns an error with [`ErrorKind::InvalidParameter`] if a URL does not start with `http://` or `https://`,
+    /// contains characters other than printable ASCII or is too long once compressed, or if the TLM temperature or
+    /// uptime is out of range.
+    pub fn to_advertisement(&self) -> Result<AdvertisementData> {
+        let frame = match self {
+            Eddystone::Uid {
+                tx_power,
+                namespace,
+                instance,
+            } => {
+                let mut frame = vec![EDDYSTONE_UID, *tx_power as u8];
+                frame.extend_from_slice(namespace);
+                frame.extend_from_slice(instance);
+                // Reserved for future use
+                frame.extend_from_slice(&[0, 0]);
+                frame
+            }
+            Eddystone::Url { tx_power, url } => {
+                let mut frame = vec![EDDYSTONE_URL, *tx_power as u8];
+                frame.extend(encode_url(url)?);
+                frame
+            }
+            Eddystone::Tlm(tlm) => {
+                let temperature = match tlm.temperature {
+                    Some(temperature) => {
+                        let fixed = (temperature * 256.0).round();
+                        if !(f32::from(i16::MIN + 1)..=f32::from(i16::MAX)).contains(&fixed) {
+                            return Err(Error::new(
+                                ErrorKind::InvalidParameter,
+                                None,
+                                format!("TLM temperature {temperature} °C is out of range"),
+                            ));
+                        }
+                        fixed as i16
+                    }
+                    None => TLM_NO_TEMPERATURE,
+                };
+                let uptime = u32::try_from(tlm.uptime.as_millis() / 100).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidParameter,
+                        None,
+                        format!("TLM uptime {:?} is out of range", tlm.uptime),
+                    )
+                })?;
+
+                let mut frame = vec![EDDYSTONE_TLM, 0x00];
+                frame.extend_from_slice(&tlm.battery_voltage.unwrap_or(0).to_be_bytes());
+                frame.extend_from_slice(&temperature.to_be_bytes());
+                frame.extend_from_slice(&tlm.advertising_count.to_be_bytes());
+                frame.extend_from_slice(&uptime.to_be_bytes());
+                frame
+            }
+            Eddystone::Eid { tx_power, eid } => {
+                let mut frame = vec![EDDYSTONE_EID, *tx_power as u8];
+                frame.extend_from_slice(eid);
+                frame
+            }
+        };
+
+        Ok(AdvertisementData {
+            services: vec![EDDYSTONE_SERVICE],
+            service_data: HashMap::from([(EDDYSTONE_SERVICE, frame)]),
+            ..Default::default()
+        })
+    }
+}
+
+fn decode_url(scheme: u8, encoded: &[u8]) -> Option<String> {
+    let mut url = URL_SCHEMES.get(usize::from(scheme))?.to_string();
+    for &code in encoded {
+        match URL_EXPANSIONS.get(usize::from(code)) {
+            Some(expansion) => url.push_str(expansion),
+            None if code.is_ascii_graphic() => url.push(char::from(code)),
+            None => return None,
+        }
+    }
+    Some(url)
+}
+
+/// Compresses `url` with the Eddystone-URL scheme prefixes and expansions.
+fn encode_url(url: &str) -> Result<Vec<u8>> {
+    let invalid = |reason: &str| {
+        Error::new(
+            ErrorKind::InvalidParameter,
+            None,
+            format!("cannot encode {url:?} as an Eddystone-URL: {reason}"),
+        )
+    };
+
+    let (scheme, mut rest) = find_prefix(&URL_SCHEMES, url).ok_or_else(|| invalid("unsupported scheme"))?;
+    let mut encoded = vec![scheme];
+    while !rest.is_empty() {
+        if let Some((code, tail)) = find_prefix(&URL_EXPANSIONS, rest) {
+            encoded.push(code);
+            rest = tail;
+        } else {
+            let c = rest.as_bytes()[0];
+            if !c.is_ascii_graphic() {
+                return Err(invalid("only printable ASCII characters are allowed"));
+            }
+            encoded.push(c);
+            rest = &rest[1..];
+        }
+    }
+
+    if encoded.len() - 1 > MAX_ENCODED_URL_LEN {
+        return Err(invalid("the URL is too long"));
+    }
+    Ok(encoded)
+}
+
+/// Finds the first of `prefixes` that `s` starts with, returning its index and the rest of `s`.
+fn find_prefix<'a>(prefixes: &[&str], s: &'a str) -> Option<(u8, &'a str)> {
+    prefixes
+        .iter()
+        .enumerate()
+        .find_map(|(code, prefix)| s.strip_prefix(prefix).map(|rest| (code as u8, rest)))
+}
@@ -1,10 +1,11 @@
 pub mod adapter;
+pub mod advertisement;
 pub mod characteristic;
 pub mod descriptor;
 pub mod device;
 pub mod l2cap_channel;
+pub mod server;
 pub mod service;
-pub mod advertisement;
 
 mod error;
 
@@ -13,6 +14,13 @@ mod error;
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceId(bluer::Address);
 
+impl DeviceId {
+    /// The Bluetooth address of the device, most significant byte first
+    pub fn address(&self) -> [u8; 6] {
+        self.0 .0
+    }
+}
+
 impl std::fmt::Display for DeviceId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Display::fmt(&self.0, f)
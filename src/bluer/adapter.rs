@@ -1,13 +1,29 @@
+use std::future::poll_fn;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
-use bluer::AdapterProperty;
+use bluer::monitor::{self, Monitor, Pattern, RssiSamplingPeriod};
+use bluer::{AdapterProperty, DeviceEvent, DeviceProperty};
 use futures_core::Stream;
-use futures_lite::StreamExt;
+use futures_lite::{stream, StreamExt};
+use tracing::debug;
 
+use super::advertisement::AdvertisementImpl;
+#[cfg(feature = "l2cap")]
+use super::l2cap_channel;
+use super::server::ServerImpl;
 use crate::error::ErrorKind;
-use crate::{AdapterEvent, Advertisement, AdvertisementData, AdvertisingDevice, AdvertisingGuard, ConnectionEvent, Device, DeviceId, Error, Result, Uuid};
-#[cfg(target_os = "linux")]
-use crate::AdvertisementImpl;
+use crate::server::{RequestHandler, Server, ServiceDefinition};
+#[cfg(feature = "l2cap")]
+use crate::L2capChannel;
+use crate::{
+    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters, ConnectionEvent,
+    Device, DeviceId, DuplicatePolicy, Error, MonitorEvent, MonitorOptions, MonitorPattern, Result, ScanEvent,
+    ScanFilter, ScanMode, ScanOptions, Uuid,
+};
+
 /// The system's Bluetooth adapter interface.
 ///
 /// The default adapter for the system may be accessed with the [`Adapter::default()`] method.
@@ -119,37 +135,126 @@ impl AdapterImpl {
     /// [`Device`] which sent it. Scanning is automatically stopped when the stream is dropped. Inclusion of duplicate
     /// packets is a platform-specific implementation detail.
     ///
-    /// If `services` is not empty, returns advertisements including at least one GATT service with a UUID in
-    /// `services`. Otherwise returns all advertisements.
-    pub async fn scan<'a>(
-        &'a self,
-        services: &'a [Uuid],
-    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + 'a> {
+    /// Only advertisements matching `filter` are returned. BlueZ only scans actively and does not take a scan interval
+    /// or window.
+    pub async fn scan(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
         Ok(self
-            .inner
-            .discover_devices()
+            .try_scan_events(filter, options)
+            .await?
+            .filter_map(|event| match event {
+                Ok(ScanEvent::Advertisement(adv)) => Some(adv),
+                Ok(ScanEvent::DeviceLost(_)) | Err(_) => None,
+            }))
+    }
+
+    /// Starts scanning for Bluetooth advertising packets, and reports devices removed by BlueZ as lost.
+    ///
+    /// Removals are reported for every device, whether or not its advertisements matched `filter`. Devices whose
+    /// advertisement cannot be read are reported as errors. The stream ends after an error when the adapter is powered
+    /// off or removed, or when discovery stops and BlueZ does not restart it.
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        if options.mode == ScanMode::Passive {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "BlueZ does not support passive scanning",
+            ));
+        }
+
+        self.set_discovery_filter(&filter, options).await?;
+
+        Ok(self
+            .advertisements()
             .await?
             .then(move |event| {
                 Box::pin(async move {
                     match event {
-                        bluer::AdapterEvent::DeviceAdded(addr) => {
-                            let device = Device::new(self.session.clone(), &self.inner, addr).ok()?;
-                            if !device.is_connected().await {
-                                let adv_data = device.0.adv_data().await;
-                                let rssi = device.rssi().await.ok();
-                                Some(AdvertisingDevice { device, adv_data, rssi })
-                            } else {
-                                None
-                            }
-                        }
-                        _ => None,
+                        Ok(DiscoveryEvent::Advertised(addr, timestamp)) => self
+                            .advertisement(addr, timestamp)
+                            .await
+                            .transpose()
+                            .map(|x| x.map(ScanEvent::Advertisement)),
+                        Ok(DiscoveryEvent::Removed(addr)) => Some(Ok(ScanEvent::DeviceLost(super::DeviceId(addr)))),
+                        Err(err) => Some(Err(err)),
                     }
                 })
             })
+            .filter_map(|x| x)
+            // BlueZ merges the filters of all discovery sessions, so it may still report devices we did not ask for
+            .filter(move |x: &Result<ScanEvent>| match x {
+                Ok(ScanEvent::Advertisement(adv)) => filter.matches(adv),
+                Ok(ScanEvent::DeviceLost(_)) | Err(_) => true,
+            }))
+    }
+
+    /// Starts monitoring for devices advertising data matching any of `patterns`.
+    ///
+    /// Registers an advertisement monitor with BlueZ, which is unregistered when the returned stream is dropped.
+    pub async fn monitor_advertisements(
+        &self,
+        patterns: &[MonitorPattern],
+        options: &MonitorOptions,
+    ) -> Result<impl Stream<Item = MonitorEvent> + Send + Unpin + '_> {
+        if patterns.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                "an advertisement monitor needs at least one pattern",
+            ));
+        }
+
+        let manager = self.inner.monitor().await?;
+        let monitor = Monitor {
+            monitor_type: monitor::Type::OrPatterns,
+            rssi_low_threshold: options.rssi_low_threshold,
+            rssi_high_threshold: options.rssi_high_threshold,
+            rssi_low_timeout: options.rssi_low_timeout,
+            rssi_high_timeout: options.rssi_high_timeout,
+            rssi_sampling_period: options.rssi_sampling_period.map(|period| {
+                if period.is_zero() {
+                    RssiSamplingPeriod::All
+                } else {
+                    RssiSamplingPeriod::Period(period)
+                }
+            }),
+            patterns: Some(
+                patterns
+                    .iter()
+                    .map(|x| Pattern::new(x.ad_type, x.start_position, &x.content))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let handle = manager.register(monitor).await?;
+
+        Ok(handle
+            .then(move |event| {
+                // Dropping the manager unregisters its monitors, so it has to live as long as the stream
+                let _manager = &manager;
+                Box::pin(async move {
+                    let (found, addr) = match event {
+                        monitor::MonitorEvent::DeviceFound(id) => (true, id.device),
+                        monitor::MonitorEvent::DeviceLost(id) => (false, id.device),
+                        _ => return None,
+                    };
+                    let device = Device::new(self.session.clone(), &self.inner, addr).ok()?;
+                    let adv = advertising_device(device, SystemTime::now()).await;
+                    Some(if found {
+                        MonitorEvent::DeviceFound(adv)
+                    } else {
+                        MonitorEvent::DeviceLost(adv)
+                    })
+                })
+            })
             .filter_map(|x| x))
-            // .filter(|x: &AdvertisingDevice| {
-            //     services.is_empty() || x.adv_data.services.iter().any(|y| services.contains(y))
-            // }))
     }
 
     /// Finds Bluetooth devices providing any service in `services`.
@@ -162,6 +267,9 @@ impl AdapterImpl {
         &'a self,
         services: &'a [Uuid],
     ) -> Result<impl Stream<Item = Result<Device>> + Send + Unpin + 'a> {
+        self.set_discovery_filter(&ScanFilter::from_services(services), &ScanOptions::default())
+            .await?;
+
         Ok(self
             .inner
             .discover_devices()
@@ -226,9 +334,281 @@ impl AdapterImpl {
         }))
     }
 
-    pub async fn start_advertising(self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        println!("START ADVERTISING");
-        let advertisement_impl = AdvertisementImpl::new();
-        advertisement_impl.start_advertising(data).await
+    /// Starts advertising `data` from this adapter in a new advertising set
+    pub async fn start_advertising(
+        &self,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        let advertisement = AdvertisementImpl::start(&self.inner, data, parameters).await?;
+        Ok(AdvertisingGuard { advertisement })
+    }
+
+    /// The number of advertising instances BlueZ has left on this adapter
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        Ok(self.inner.supported_advertising_instances().await?.into())
+    }
+
+    pub async fn start_server(
+        &self,
+        services: Vec<ServiceDefinition>,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Result<Server> {
+        Ok(Server(ServerImpl::start(&self.inner, services, handler).await?))
+    }
+
+    /// Reads the advertisement of the device with `addr`, which was received at `timestamp`.
+    ///
+    /// Returns `None` if the device is connected, since BlueZ keeps reporting changes to the properties of connected
+    /// devices which are not advertisements.
+    async fn advertisement(&self, addr: bluer::Address, timestamp: SystemTime) -> Result<Option<AdvertisingDevice>> {
+        let device = Device::new(self.session.clone(), &self.inner, addr)?;
+        if device.0.inner.is_connected().await? {
+            return Ok(None);
+        }
+        Ok(Some(advertising_device(device, timestamp).await))
+    }
+
+    /// Starts a discovery session and returns the address of a device, and the time the event was received, every time
+    /// it is discovered or one of its advertised properties changes, and the address of every device BlueZ removes.
+    ///
+    /// The stream ends after an error if the adapter goes away or discovery stops for good. The discovery session ends
+    /// when the returned stream is dropped.
+    async fn advertisements(&self) -> Result<impl Stream<Item = Result<DiscoveryEvent>> + Send + Unpin + 'static> {
+        let advertisements = Advertisements {
+            session_events: Box::pin(self.session.events().await?),
+            adapter_events: Box::pin(self.inner.events().await?),
+            discovery: Box::pin(self.inner.discover_devices().await?),
+            devices: Vec::new(),
+            adapter: self.inner.clone(),
+            paused_until: None,
+            stopped: false,
+        };
+
+        // The stream owns the discovery session, so discovery stops as soon as the stream is dropped
+        Ok(Box::pin(stream::unfold(advertisements, |mut state| async move {
+            state.next().await.map(|event| (event, state))
+        })))
+    }
+
+    /// Listens for incoming L2CAP channels, returning the PSM listened on and a stream of accepted channels.
+    #[cfg(feature = "l2cap")]
+    pub async fn listen_l2cap(
+        &self,
+        psm: Option<u16>,
+        secure: bool,
+    ) -> Result<(
+        u16,
+        impl Stream<Item = Result<(Device, L2capChannel)>> + Send + Unpin + '_,
+    )> {
+        let (psm, listener) = l2cap_channel::listen_l2cap(&self.inner, psm, secure).await?;
+
+        // The stream owns the listener, so incoming channels are refused as soon as the stream is dropped. It is taken
+        // out of the state after a fatal error, which ends the stream.
+        let channels = stream::unfold(Some(listener), move |listener| async move {
+            let listener = listener?;
+            match l2cap_channel::accept(&listener).await {
+                Ok((addr, reader, writer)) => {
+                    let res = Device::new(self.session.clone(), &self.inner, addr)
+                        .map(|device| (device, L2capChannel::new(reader, writer)));
+                    Some((res, Some(listener)))
+                }
+                Err(err) if l2cap_channel::is_connection_error(&err) => Some((Err(err), Some(listener))),
+                Err(err) => Some((Err(err), None)),
+            }
+        });
+        Ok((psm, Box::pin(channels)))
+    }
+
+    /// Sets the discovery filter of the discovery sessions started by this adapter.
+    ///
+    /// Discovery is restricted to LE devices. The services, minimum RSSI and name prefix which every alternative of
+    /// `filter` requires, and the duplicate policy of `options`, are pushed down to BlueZ. BlueZ only narrows the
+    /// advertisements it reports, so callers still apply [`ScanFilter::matches`] to every advertisement. If another
+    /// discovery session of this process is already running, its filter is kept.
+    async fn set_discovery_filter(&self, filter: &ScanFilter, options: &ScanOptions) -> Result<()> {
+        let filter = bluer::DiscoveryFilter {
+            uuids: filter.services().into_iter().collect(),
+            rssi: filter.min_rssi(),
+            transport: bluer::DiscoveryTransport::Le,
+            // Without duplicate data BlueZ only signals changed data, which is what is reported for `ReportChanges`
+            duplicate_data: options.duplicates != DuplicatePolicy::ReportChanges,
+            pattern: filter.name_prefix().map(str::to_owned),
+            ..Default::default()
+        };
+
+        match self.inner.set_discovery_filter(filter).await {
+            // bluer waits for the discovery session of a dropped stream to stop, so this only fails while another scan
+            // on this adapter is running. It keeps its own filter and we filter locally instead.
+            Err(err) if matches!(err.kind, bluer::ErrorKind::DiscoveryActive) => {
+                debug!(
+                    "Discovery filter not applied, since another scan of {} is running",
+                    self.inner.name()
+                );
+                Ok(())
+            }
+            res => res.map_err(Into::into),
+        }
+    }
+}
+
+/// Reads the advertised properties of `device`, which were received at `timestamp`.
+async fn advertising_device(device: Device, timestamp: SystemTime) -> AdvertisingDevice {
+    let adv_data = device.0.adv_data().await;
+    let rssi = device.rssi().await.ok();
+    let address_type = device.0.address_type().await;
+    AdvertisingDevice {
+        device,
+        adv_data,
+        rssi,
+        timestamp: Some(timestamp),
+        address_type,
+        primary_phy: None,
+        secondary_phy: None,
+        advertising_sid: None,
+        is_scan_response: None,
+    }
+}
+
+/// An event of a discovery session
+enum DiscoveryEvent {
+    /// The device was discovered, or one of its advertised properties changed, at the given time
+    Advertised(bluer::Address, SystemTime),
+    /// BlueZ removed the device
+    Removed(bluer::Address),
+}
+
+/// How long discovery may stay stopped before a scan is considered dead.
+///
+/// BlueZ stops discovery while the controller is busy, for example connecting to a device, and restarts it 5 seconds
+/// after the controller reports it stopped.
+const DISCOVERY_RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The devices discovered by a discovery session and the changes to their advertised properties.
+struct Advertisements {
+    session_events: Pin<Box<dyn Stream<Item = bluer::SessionEvent> + Send>>,
+    adapter_events: Pin<Box<dyn Stream<Item = bluer::AdapterEvent> + Send>>,
+    discovery: Pin<Box<dyn Stream<Item = bluer::AdapterEvent> + Send>>,
+    /// The property changes of every discovered device, which report the device again when an advertised property
+    /// changes
+    devices: Vec<(bluer::Address, DeviceEvents)>,
+    adapter: bluer::Adapter,
+    /// Set while discovery is stopped, to the time after which it is not expected to restart
+    paused_until: Option<tokio::time::Instant>,
+    /// Set once the error which ended the discovery session has been returned
+    stopped: bool,
+}
+
+impl Advertisements {
+    async fn next(&mut self) -> Option<Result<DiscoveryEvent>> {
+        if self.stopped {
+            return None;
+        }
+        let event = self.next_event().await;
+        self.stopped = event.is_err();
+        Some(event)
+    }
+
+    async fn next_event(&mut self) -> Result<DiscoveryEvent> {
+        loop {
+            let paused_until = self.paused_until;
+            tokio::select! {
+                event = self.discovery.next() => match event {
+                    Some(bluer::AdapterEvent::DeviceAdded(addr)) => {
+                        let timestamp = SystemTime::now();
+                        self.follow(addr).await;
+                        return Ok(DiscoveryEvent::Advertised(addr, timestamp));
+                    }
+                    Some(bluer::AdapterEvent::DeviceRemoved(addr)) => {
+                        self.devices.retain(|(x, _)| *x != addr);
+                        return Ok(DiscoveryEvent::Removed(addr));
+                    }
+                    Some(_) => (),
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::AdapterUnavailable,
+                            None,
+                            "the connection to BlueZ was lost",
+                        ))
+                    }
+                },
+                addr = poll_fn(|cx| poll_advertised_changes(&mut self.devices, cx)) => {
+                    return Ok(DiscoveryEvent::Advertised(addr, SystemTime::now()));
+                }
+                Some(event) = self.adapter_events.next() => match event {
+                    bluer::AdapterEvent::PropertyChanged(AdapterProperty::Powered(false)) => {
+                        return Err(Error::new(ErrorKind::AdapterUnavailable, None, "the adapter was powered off"));
+                    }
+                    bluer::AdapterEvent::PropertyChanged(AdapterProperty::Discovering(discovering)) => {
+                        self.paused_until =
+                            (!discovering).then(|| tokio::time::Instant::now() + DISCOVERY_RESTART_TIMEOUT);
+                    }
+                    _ => (),
+                },
+                Some(event) = self.session_events.next() => {
+                    if matches!(event, bluer::SessionEvent::AdapterRemoved(name) if name == self.adapter.name()) {
+                        return Err(Error::new(ErrorKind::AdapterUnavailable, None, "the adapter was removed"));
+                    }
+                }
+                () = tokio::time::sleep_until(paused_until.unwrap_or_else(tokio::time::Instant::now)),
+                    if paused_until.is_some() =>
+                {
+                    return Err(Error::new(ErrorKind::ScanStopped, None, "BlueZ stopped discovery"));
+                }
+            }
+        }
+    }
+
+    /// Follows the property changes of the device with `addr`, unless they are already followed.
+    ///
+    /// A device whose property changes cannot be followed is still reported when it is discovered again.
+    async fn follow(&mut self, addr: bluer::Address) {
+        if self.devices.iter().any(|(x, _)| *x == addr) {
+            return;
+        }
+        let events = match self.adapter.device(addr) {
+            Ok(device) => device.events().await,
+            Err(err) => Err(err),
+        };
+        match events {
+            Ok(events) => self.devices.push((addr, Box::pin(events))),
+            Err(err) => debug!("Cannot follow the property changes of {}: {}", addr, err),
+        }
+    }
+}
+
+/// The property changes of a discovered device
+type DeviceEvents = Pin<Box<dyn Stream<Item = DeviceEvent> + Send>>;
+
+/// Polls the property changes of `devices` for the next device whose advertised properties changed.
+///
+/// Only the properties which BlueZ updates from received advertisements count. Other properties, such as the name or
+/// the connection state, also change without receiving an advertisement. The streams of removed devices are dropped.
+fn poll_advertised_changes(
+    devices: &mut Vec<(bluer::Address, DeviceEvents)>,
+    cx: &mut Context<'_>,
+) -> Poll<bluer::Address> {
+    let mut i = 0;
+    while i < devices.len() {
+        let (addr, events) = &mut devices[i];
+        match events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(DeviceEvent::PropertyChanged(
+                DeviceProperty::Rssi(_)
+                | DeviceProperty::ManufacturerData(_)
+                | DeviceProperty::ServiceData(_)
+                | DeviceProperty::TxPower(_),
+            ))) => {
+                let addr = *addr;
+                // Poll the other devices first next time, so that a busy device does not starve them
+                devices.rotate_left(i + 1);
+                return Poll::Ready(addr);
+            }
+            Poll::Ready(Some(_)) => (),
+            Poll::Ready(None) => {
+                drop(devices.swap_remove(i));
+            }
+            Poll::Pending => i += 1,
+        }
     }
+    Poll::Pending
 }
@@ -1,106 +1,138 @@
-#[cfg(target_os = "linux")]
-use bluer::{Session, adv::{Advertisement, AdvertisementHandle, Type}};
-use std::{collections::BTreeMap, time::Duration};
-
-use crate::{AdvertisementData, AdvertisingGuard};
-
-use super::adapter::AdapterImpl;
-
-#[cfg(target_os = "linux")]
-#[derive(Debug)]
-pub struct AdvertisementImpl {
-    advertisement_handle: Option<AdvertisementHandle>,
-}
-
-impl AdvertisementImpl {
-    /// Creates a new `PlatformAdvertisementImpl` instance with the specified adapter.
-    pub fn new() -> Self {
-        Self {
-            advertisement_handle: None,
-        }
-    }
-
-    // /// Start advertising on Linux using `bluer`.
-    // pub async fn advertise(&mut self, data: &Vec<u8>, advertise_duration: Option<Duration>) -> bluer::Result<()> {
-    //     // Stop any existing advertisement
-    //     self.stop_advertising()?;
-
-    //     // Configure the advertisement
-    //     let le_advertisement = Advertisement {
-    //         advertisement_type: Type::Peripheral,
-    //         service_uuids: vec![]
-    //             .into_iter()
-    //             .collect(),
-    //         local_name: None,
-    //         discoverable: Some(true),
-    //         manufacturer_data: data.manufacturer_data.map(|m| {
-    //             let mut map = BTreeMap::new();
-    //             map.insert(m.company_id, m.data);
-    //             map}),
-    //         ..Default::default()
-    //     };
-
-    //     // Start advertising
-    //     let handle = self.adapter.advertise(le_advertisement).await?;
-    //     self.advertisement_handle = Some(handle);
-
-    //     if let Some(duration) = advertise_duration {
-    //         sleep(duration).await;
-    //         self.stop_advertising()?; // Stop the advertisement after the duration
-    //         println!("Linux advertisement stopped after {:?}", duration);
-    //     }
-
-    //     Ok(())
-    // }
-
-    /// Stop advertising if an advertisement is active
-    pub fn stop_advertising(&mut self) -> bluer::Result<()> {
-        if let Some(handle) = self.advertisement_handle.take() {
-            println!("Linux advertisement manually stopped.");
-            drop(handle); // Dropping the handle stops the advertisement
-        }
-        Ok(())
-    }
-
-    /// Start advertising and return an AdvertisingGuard
-    pub async fn start_advertising(mut self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        println!("START ADVERTISOMG ***");
-    // Convert manufacturer_data to the expected BTreeMap format
-        let manufacturer_data: BTreeMap<u16, Vec<u8>> = data.manufacturer_data
-        .map(|manufacturer_data| {
-            let mut map = BTreeMap::new();
-            map.insert(manufacturer_data.company_id, manufacturer_data.data.clone());
-            map
-        })
-        .unwrap_or_default();
-
-        let le_advertisement = Advertisement {
-            advertisement_type: Type::Broadcast,
-            service_uuids: vec![]
-                .into_iter()
-                .collect(),
-            local_name: Some("le_advertise".to_string()),
-            discoverable: Some(true),
-            manufacturer_data: manufacturer_data,
-            ..Default::default()
-        };
-        let adapter = AdapterImpl::default().await;
-        match adapter {
-            Some(adapter) => {
-                let handle = adapter.inner.advertise(le_advertisement).await.map_err(|e| format!("Failed to start advertising: {:?}", e))?;
-                self.advertisement_handle = Some(handle);        
-            },
-            None=>{}
-        }
-        
-        Ok(AdvertisingGuard { advertisement: self })
-    }
-}
-
-/// Struct to handle advertisement cleanup on drop for Linux
-#[cfg(target_os = "linux")]
-impl Drop for AdvertisementImpl {
-    fn drop(&mut self) {
-        let _ = self.stop_advertising();
-    }
-}
\ No newline at end of file
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+use bluer::adv::{Advertisement, AdvertisementHandle, Feature, SecondaryChannel, Type};
+
+use crate::adv::{self, ad_types, flags};
+use crate::error::ErrorKind;
+use crate::{AdvertisementData, AdvertisingParameters, Error, Phy, Result};
+
+/// An advertisement registered with BlueZ. It is unregistered when dropped.
+#[derive(Debug)]
+pub struct AdvertisementImpl {
+    handle: Option<AdvertisementHandle>,
+}
+
+impl AdvertisementImpl {
+    /// Registers an advertisement of `data` with `adapter`. BlueZ gives each registered advertisement its own
+    /// advertising instance.
+    pub(super) async fn start(
+        adapter: &bluer::Adapter,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<Self> {
+        let handle = adapter.advertise(to_advertisement(data, parameters)?).await?;
+        Ok(AdvertisementImpl { handle: Some(handle) })
+    }
+
+    /// Stop advertising if an advertisement is active
+    pub fn stop_advertising(&mut self) -> Result<()> {
+        // Dropping the handle unregisters the advertisement
+        self.handle.take();
+        Ok(())
+    }
+}
+
+fn to_advertisement(data: AdvertisementData, parameters: AdvertisingParameters) -> Result<Advertisement> {
+    if parameters.scan_response.is_some() {
+        return Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "BlueZ does not support setting the scan response",
+        ));
+    }
+
+    let discoverable = data
+        .flags
+        .map(|x| x & (flags::LE_LIMITED_DISCOVERABLE | flags::LE_GENERAL_DISCOVERABLE));
+    if discoverable.is_some_and(|x| x & flags::LE_LIMITED_DISCOVERABLE != 0) {
+        return Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "BlueZ does not support limited discoverable advertisements",
+        ));
+    }
+
+    let (advertisement_type, discoverable) = if data.is_connectable == Some(true) {
+        (Type::Peripheral, Some(discoverable != Some(0)))
+    } else if discoverable.is_some_and(|x| x != 0) {
+        return Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "BlueZ does not support discoverable broadcast advertisements",
+        ));
+    } else {
+        (Type::Broadcast, None)
+    };
+
+    let mut manufacturer_data = BTreeMap::new();
+    for entry in data.manufacturer_data {
+        if manufacturer_data.insert(entry.company_id, entry.data).is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!(
+                    "BlueZ supports one manufacturer data entry per company ID but {:#06x} has several",
+                    entry.company_id
+                ),
+            ));
+        }
+    }
+
+    // AD types BlueZ has no property for
+    let mut advertising_data = BTreeMap::new();
+    let structures = data
+        .uri
+        .map(|uri| (ad_types::URI, adv::encode_uri(&uri)))
+        .into_iter()
+        .chain(data.le_supported_features.map(|x| (ad_types::LE_SUPPORTED_FEATURES, x)))
+        .chain(data.other_structures.into_iter().map(|x| (x.ad_type, x.data)));
+    for (ad_type, value) in structures {
+        match advertising_data.entry(ad_type) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidParameter,
+                    None,
+                    format!("BlueZ supports one AD structure of each type but {ad_type:#04x} is repeated"),
+                ))
+            }
+        }
+    }
+
+    let mut advertisement = Advertisement {
+        advertisement_type,
+        service_uuids: data.services.into_iter().collect(),
+        manufacturer_data,
+        solicit_uuids: data.solicited_services.into_iter().collect(),
+        service_data: data.service_data.into_iter().collect(),
+        advertising_data,
+        discoverable,
+        local_name: data.local_name,
+        appearance: data.appearance,
+        min_interval: parameters
+            .interval
+            .as_ref()
+            .map(|x| *x.start())
+            .or(data.advertising_interval),
+        max_interval: parameters.interval.map(|x| *x.end()).or(data.advertising_interval),
+        tx_power: parameters.tx_power.or(data.tx_power_level),
+        duration: parameters.duration,
+        timeout: parameters.timeout,
+        secondary_channel: parameters.secondary_phy.map(|x| match x {
+            Phy::Le1M => SecondaryChannel::OneM,
+            Phy::Le2M => SecondaryChannel::TwoM,
+            Phy::LeCoded => SecondaryChannel::Coded,
+        }),
+        ..Default::default()
+    };
+
+    if data.tx_power_level.is_some() {
+        // Include the power level the controller actually uses
+        advertisement.system_includes.insert(Feature::TxPower);
+    }
+
+    Ok(advertisement)
+}
@@ -9,7 +9,7 @@ use super::DeviceId;
 use crate::device::ServicesChanged;
 use crate::error::ErrorKind;
 use crate::pairing::PairingAgent;
-use crate::{btuuid, AdvertisementData, Device, Error, ManufacturerData, Result, Service, Uuid};
+use crate::{adv, btuuid, AddressType, AdvertisementData, Device, Error, ManufacturerData, Result, Service, Uuid};
 
 /// A Bluetooth LE device
 #[derive(Debug, Clone)]
@@ -251,27 +251,38 @@ impl DeviceImpl {
 
     /// Get the current signal strength from the device in dBm.
     ///
-    /// # Platform specific
-    ///
-    /// Returns [ErrorKind::NotSupported].
+    /// Returns the RSSI of the last advertisement BlueZ received from this device, or [`ErrorKind::NotReady`] if the
+    /// device has not been seen by the current discovery session.
     pub async fn rssi(&self) -> Result<i16> {
-        Err(ErrorKind::NotSupported.into())
+        self.inner.rssi().await?.ok_or_else(|| ErrorKind::NotReady.into())
     }
 
     pub(super) async fn adv_data(&self) -> AdvertisementData {
         let device = &self.inner;
 
-        let is_connectable = true;
+        let flags = device
+            .advertising_flags()
+            .await
+            .ok()
+            .flatten()
+            .and_then(|flags| flags.first().copied());
 
         let local_name = device.alias().await.unwrap_or_default();
         let local_name = (!local_name.is_empty()).then_some(local_name);
 
-        let manufacturer_data = device
+        // BlueZ keeps the most recent data for each company, so at most one entry per company ID is reported
+        let mut manufacturer_data = device
             .manufacturer_data()
             .await
             .unwrap_or_default()
-            .and_then(|data| data.into_iter().next())
-            .map(|(company_id, data)| ManufacturerData { company_id, data });
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>();
+        manufacturer_data.sort();
+        let manufacturer_data = manufacturer_data
+            .into_iter()
+            .map(|(company_id, data)| ManufacturerData { company_id, data })
+            .collect();
 
         let tx_power_level = device.tx_power().await.unwrap_or_default();
 
@@ -283,23 +294,51 @@ impl DeviceImpl {
             .unwrap_or_default()
             .map_or(Vec::new(), |x| x.into_iter().collect());
 
-        AdvertisementData {
+        let appearance = device.appearance().await.unwrap_or_default();
+
+        let mut adv_data = AdvertisementData {
             local_name,
             manufacturer_data,
             service_data,
             services,
             tx_power_level,
-            is_connectable,
+            // BlueZ does not expose the advertising PDU type
+            is_connectable: None,
+            flags,
+            appearance,
+            ..Default::default()
+        };
+
+        // AD structures BlueZ does not parse itself
+        let mut advertising_data = device
+            .advertising_data()
+            .await
+            .unwrap_or_default()
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<Vec<_>>();
+        advertising_data.sort();
+        for (ad_type, value) in advertising_data {
+            adv::apply_structure(&mut adv_data, ad_type, &value);
+        }
+
+        adv_data
+    }
+
+    pub(super) async fn address_type(&self) -> Option<AddressType> {
+        match self.inner.address_type().await.ok()? {
+            bluer::AddressType::LeRandom => Some(AddressType::Random),
+            bluer::AddressType::LePublic | bluer::AddressType::BrEdr => Some(AddressType::Public),
         }
     }
 
     #[cfg(feature = "l2cap")]
     pub async fn open_l2cap_channel(
         &self,
-        _psm: u16,
-        _secure: bool,
+        psm: u16,
+        secure: bool,
     ) -> std::prelude::v1::Result<(L2capChannelReader, L2capChannelWriter), crate::Error> {
-        Err(ErrorKind::NotSupported.into())
+        super::l2cap_channel::open_l2cap_channel(&self.inner, psm, secure).await
     }
 }
 
@@ -21,6 +21,14 @@ fn kind_from_bluer(err: &bluer::Error) -> ErrorKind {
         bluer::ErrorKind::InvalidName(_) => ErrorKind::InvalidParameter,
         bluer::ErrorKind::ServicesUnresolved => ErrorKind::NotReady,
         bluer::ErrorKind::NotFound => ErrorKind::NotFound,
+        bluer::ErrorKind::AdvertisementMonitorRejected => ErrorKind::InvalidParameter,
+        // BlueZ does not provide the interface, e.g. because it is experimental and not enabled
+        bluer::ErrorKind::Internal(bluer::InternalErrorKind::DBus(ref name))
+            if name == "org.freedesktop.DBus.Error.UnknownMethod"
+                || name == "org.freedesktop.DBus.Error.UnknownInterface" =>
+        {
+            ErrorKind::NotSupported
+        }
         _ => ErrorKind::Other,
     }
 }
@@ -1,25 +1,172 @@
 #![cfg(feature = "l2cap")]
 
 use std::fmt;
+use std::future::poll_fn;
+use std::net::Shutdown;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
 
-use crate::Result;
+use bluer::l2cap::{SecurityLevel, SeqPacket, SeqPacketListener, Socket, SocketAddr};
+use tokio::io::ReadBuf;
+
+use crate::error::ErrorKind;
+use crate::{Error, Result};
+
+pub(super) async fn open_l2cap_channel(
+    device: &bluer::Device,
+    psm: u16,
+    secure: bool,
+) -> Result<(L2capChannelReader, L2capChannelWriter)> {
+    let addr_type = device.address_type().await?;
+    let socket = Socket::<SeqPacket>::new_seq_packet().map_err(io_error)?;
+    socket.bind(SocketAddr::any_le()).map_err(io_error)?;
+    set_security(&socket, secure)?;
+
+    let channel = socket
+        .connect(SocketAddr::new(device.address(), addr_type, psm))
+        .await
+        .map_err(io_error)?;
+    split(channel)
+}
+
+/// Listens for L2CAP channels on `psm` of the adapter, or on a dynamically assigned PSM if `psm` is `None`.
+pub(super) async fn listen_l2cap(
+    adapter: &bluer::Adapter,
+    psm: Option<u16>,
+    secure: bool,
+) -> Result<(u16, SeqPacketListener)> {
+    let addr = SocketAddr::new(
+        adapter.address().await?,
+        adapter.address_type().await?,
+        psm.unwrap_or(0),
+    );
+    let socket = Socket::<SeqPacket>::new_seq_packet().map_err(io_error)?;
+    socket.bind(addr).map_err(io_error)?;
+    set_security(&socket, secure)?;
+
+    let listener = socket.listen(1).map_err(io_error)?;
+    let psm = listener.as_ref().local_addr().map_err(io_error)?.psm;
+    Ok((psm, listener))
+}
+
+/// Accepts the next L2CAP channel on `listener`, returning the channel and the address of the remote device.
+pub(super) async fn accept(
+    listener: &SeqPacketListener,
+) -> Result<(bluer::Address, L2capChannelReader, L2capChannelWriter)> {
+    let (channel, addr) = listener.accept().await.map_err(io_error)?;
+    let (reader, writer) = split(channel)?;
+    Ok((addr.addr, reader, writer))
+}
+
+/// Whether an error from [`accept`] only concerns the channel being accepted, so the listener can keep accepting.
+pub(super) fn is_connection_error(err: &Error) -> bool {
+    matches!(err.kind(), ErrorKind::ConnectionFailed | ErrorKind::NotConnected)
+}
+
+/// A secure channel requires an encrypted link, pairing with the device if necessary.
+fn set_security(socket: &Socket<SeqPacket>, secure: bool) -> Result<()> {
+    let mut security = socket.security().map_err(io_error)?;
+    security.level = if secure {
+        SecurityLevel::Medium
+    } else {
+        SecurityLevel::Low
+    };
+    socket.set_security(security).map_err(io_error)
+}
+
+fn split(channel: SeqPacket) -> Result<(L2capChannelReader, L2capChannelWriter)> {
+    let channel = Arc::new(channel);
+    let recv_mtu = channel.recv_mtu().map_err(io_error)?;
+
+    Ok((
+        L2capChannelReader {
+            channel: channel.clone(),
+            buf: vec![0; recv_mtu],
+        },
+        L2capChannelWriter { channel },
+    ))
+}
+
+/// Converts an error from an L2CAP socket.
+fn io_error(err: std::io::Error) -> Error {
+    use std::io::ErrorKind as IoErrorKind;
+
+    let kind = match err.kind() {
+        IoErrorKind::WouldBlock => ErrorKind::NotReady,
+        IoErrorKind::NotConnected | IoErrorKind::BrokenPipe => ErrorKind::NotConnected,
+        IoErrorKind::ConnectionRefused | IoErrorKind::ConnectionReset | IoErrorKind::ConnectionAborted => {
+            ErrorKind::ConnectionFailed
+        }
+        IoErrorKind::PermissionDenied => ErrorKind::NotAuthorized,
+        IoErrorKind::TimedOut => ErrorKind::Timeout,
+        IoErrorKind::InvalidInput => ErrorKind::InvalidParameter,
+        _ => ErrorKind::Other,
+    };
+    Error::new(kind, Some(Box::new(err)), String::new())
+}
+
+/// Polls `f` once without waiting, mapping [`Poll::Pending`] to a `NotReady` error.
+fn poll_now<T>(message: &str, f: impl FnOnce(&mut Context<'_>) -> Poll<Result<T>>) -> Result<T> {
+    match f(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(res) => res,
+        Poll::Pending => Err(Error::new(ErrorKind::NotReady, None, message)),
+    }
+}
+
+fn close(channel: &SeqPacket) -> Result<()> {
+    shutdown_result(channel.shutdown(Shutdown::Both))
+}
+
+/// Converts the result of shutting down a channel.
+fn shutdown_result(res: std::io::Result<()>) -> Result<()> {
+    match res {
+        // The channel has already been closed, either by us or by the remote device
+        Err(err) if err.kind() == std::io::ErrorKind::NotConnected => Ok(()),
+        res => res.map_err(io_error),
+    }
+}
 
 pub struct L2capChannelReader {
-    _private: (),
+    channel: Arc<SeqPacket>,
+    buf: Vec<u8>,
 }
 
 impl L2capChannelReader {
     #[inline]
-    pub async fn read(&mut self, _buf: &mut [u8]) -> Result<usize> {
-        todo!()
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        poll_now("no received packet in queue", |cx| self.poll_read(cx, buf))
+    }
+
+    /// Receives a packet into the internal buffer, which is large enough for any packet, and copies it to `buf`.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        let mut packet = ReadBuf::new(&mut self.buf);
+        if let Err(err) = std::task::ready!(self.channel.poll_recv(cx, &mut packet)) {
+            return Poll::Ready(Err(io_error(err)));
+        }
+
+        let packet = packet.filled();
+        if packet.len() > buf.len() {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                "Buffer is too small",
+            )));
+        }
+
+        buf[..packet.len()].copy_from_slice(packet);
+        Poll::Ready(Ok(packet.len()))
     }
 
-    pub fn try_read(&mut self, _buf: &mut [u8]) -> Result<usize> {
-        todo!()
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(self.buf.len())
     }
 
     pub async fn close(&mut self) -> Result<()> {
-        todo!()
+        close(&self.channel)
     }
 }
 
@@ -30,20 +177,34 @@ impl fmt::Debug for L2capChannelReader {
 }
 
 pub struct L2capChannelWriter {
-    _private: (),
+    channel: Arc<SeqPacket>,
 }
 
 impl L2capChannelWriter {
-    pub async fn write(&mut self, _packet: &[u8]) -> Result<()> {
-        todo!()
+    pub async fn write(&mut self, packet: &[u8]) -> Result<()> {
+        poll_fn(|cx| self.poll_write(cx, packet)).await
+    }
+
+    pub fn try_write(&mut self, packet: &[u8]) -> Result<()> {
+        poll_now("No buffer space for write", |cx| self.poll_write(cx, packet))
+    }
+
+    pub fn poll_write(&mut self, cx: &mut Context<'_>, packet: &[u8]) -> Poll<Result<()>> {
+        self.channel
+            .poll_send(cx, packet)
+            .map(|res| res.map(drop).map_err(io_error))
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        self.channel.send_mtu().map_err(io_error)
     }
 
-    pub fn try_write(&mut self, _packet: &[u8]) -> Result<()> {
-        todo!()
+    pub fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(close(&self.channel))
     }
 
     pub async fn close(&mut self) -> Result<()> {
-        todo!()
+        close(&self.channel)
     }
 }
 
@@ -52,3 +213,60 @@ impl fmt::Debug for L2capChannelWriter {
         f.write_str("L2capChannelWriter")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn io_error_kinds() {
+        let cases = [
+            (io::ErrorKind::WouldBlock, ErrorKind::NotReady),
+            (io::ErrorKind::NotConnected, ErrorKind::NotConnected),
+            (io::ErrorKind::BrokenPipe, ErrorKind::NotConnected),
+            (io::ErrorKind::ConnectionRefused, ErrorKind::ConnectionFailed),
+            (io::ErrorKind::ConnectionReset, ErrorKind::ConnectionFailed),
+            (io::ErrorKind::ConnectionAborted, ErrorKind::ConnectionFailed),
+            (io::ErrorKind::PermissionDenied, ErrorKind::NotAuthorized),
+            (io::ErrorKind::TimedOut, ErrorKind::Timeout),
+            (io::ErrorKind::InvalidInput, ErrorKind::InvalidParameter),
+            (io::ErrorKind::AddrInUse, ErrorKind::Other),
+        ];
+        for (io_kind, kind) in cases {
+            assert_eq!(io_error(io_kind.into()).kind(), kind, "{io_kind:?}");
+        }
+    }
+
+    #[test]
+    fn io_error_keeps_source() {
+        let err = io_error(io::Error::new(io::ErrorKind::TimedOut, "host is down"));
+        let source = err.source().and_then(|x| x.downcast_ref::<io::Error>()).unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(source.to_string(), "host is down");
+    }
+
+    #[test]
+    fn accept_errors() {
+        let is_connection_error = |kind: io::ErrorKind| is_connection_error(&io_error(kind.into()));
+        assert!(is_connection_error(io::ErrorKind::ConnectionAborted));
+        assert!(is_connection_error(io::ErrorKind::ConnectionReset));
+        assert!(is_connection_error(io::ErrorKind::NotConnected));
+        assert!(!is_connection_error(io::ErrorKind::InvalidInput));
+        assert!(!is_connection_error(io::ErrorKind::PermissionDenied));
+        assert!(!is_connection_error(io::ErrorKind::Other));
+    }
+
+    #[test]
+    fn close_ignores_closed_channel() {
+        assert!(shutdown_result(Ok(())).is_ok());
+        assert!(shutdown_result(Err(io::ErrorKind::NotConnected.into())).is_ok());
+
+        let err = shutdown_result(Err(io::ErrorKind::BrokenPipe.into())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+        let err = shutdown_result(Err(io::ErrorKind::PermissionDenied.into())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+    }
+}
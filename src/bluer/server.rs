@@ -0,0 +1,456 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use bluer::gatt::local::{
+    Application, ApplicationHandle, Characteristic, CharacteristicNotifier, CharacteristicNotify,
+    CharacteristicNotifyMethod, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, Descriptor,
+    DescriptorRead, DescriptorWrite, ReqError, Service,
+};
+use bluer::gatt::WriteOp;
+use bluer::{DeviceEvent, DeviceProperty};
+use futures_core::Stream;
+use futures_lite::StreamExt;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::DeviceId;
+use crate::error::{AttError, ErrorKind};
+use crate::server::{
+    AttributeId, Central, CharacteristicDefinition, DescriptorDefinition, ReadRequest, RequestHandler, SecurityLevel,
+    ServerEvent, ServiceDefinition, Subscription, WriteKind, WriteRequest,
+};
+use crate::{Error, Result, Uuid};
+
+/// The ATT MTU of a connection before it is exchanged (Core Specification, Vol 3, Part G, §5.2.1)
+const DEFAULT_MTU: u16 = 23;
+
+/// A GATT application registered with BlueZ. It is unregistered when dropped.
+pub struct ServerImpl {
+    state: Arc<State>,
+    handle: ApplicationHandle,
+}
+
+struct State {
+    adapter: bluer::Adapter,
+    characteristics: Vec<CharacteristicState>,
+    centrals: Mutex<HashMap<bluer::Address, CentralState>>,
+    events: broadcast::Sender<ServerEvent>,
+}
+
+struct CharacteristicState {
+    attribute: AttributeId,
+    notifies: bool,
+    /// The notification session BlueZ opens while any central is subscribed
+    notifier: tokio::sync::Mutex<Option<CharacteristicNotifier>>,
+    subscription: Mutex<Option<Subscription>>,
+}
+
+struct CentralState {
+    mtu: u16,
+    /// Watches for the central disconnecting
+    watcher: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for ServerImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerImpl")
+            .field("handle", &self.handle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ServerImpl {
+    fn drop(&mut self) {
+        for central in self.state.centrals.lock().unwrap().values() {
+            central.watcher.abort();
+        }
+    }
+}
+
+impl ServerImpl {
+    /// Registers `services` with `adapter` as a GATT application whose requests are answered by `handler`.
+    pub(super) async fn start(
+        adapter: &bluer::Adapter,
+        services: Vec<ServiceDefinition>,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Result<Self> {
+        let characteristics = services
+            .iter()
+            .flat_map(|service| {
+                service
+                    .characteristics
+                    .iter()
+                    .map(|characteristic| CharacteristicState {
+                        attribute: AttributeId {
+                            service: service.uuid,
+                            characteristic: characteristic.uuid,
+                            descriptor: None,
+                        },
+                        notifies: characteristic.properties.notify || characteristic.properties.indicate,
+                        notifier: tokio::sync::Mutex::new(None),
+                        subscription: Mutex::new(None),
+                    })
+            })
+            .collect();
+        let state = Arc::new(State {
+            adapter: adapter.clone(),
+            characteristics,
+            centrals: Mutex::new(HashMap::new()),
+            events: broadcast::channel(16).0,
+        });
+
+        let mut index = 0;
+        let services = services
+            .into_iter()
+            .map(|service| Service {
+                uuid: service.uuid,
+                primary: service.primary,
+                characteristics: service
+                    .characteristics
+                    .into_iter()
+                    .map(|characteristic| {
+                        index += 1;
+                        to_characteristic(&state, &handler, index - 1, service.uuid, characteristic)
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        let handle = adapter
+            .serve_gatt_application(Application {
+                services,
+                ..Default::default()
+            })
+            .await?;
+        Ok(ServerImpl { state, handle })
+    }
+
+    pub fn centrals(&self) -> Vec<Central> {
+        let centrals = self.state.centrals.lock().unwrap();
+        centrals
+            .iter()
+            .map(|(address, central)| Central {
+                id: DeviceId(*address),
+                mtu: central.mtu,
+            })
+            .collect()
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.state
+            .characteristics
+            .iter()
+            .filter_map(|x| x.subscription.lock().unwrap().clone())
+            .collect()
+    }
+
+    pub fn events(&self) -> impl Stream<Item = ServerEvent> + Send + Unpin {
+        let receiver = self.state.events.subscribe();
+        Box::pin(futures_lite::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(x) => return Some((x, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+
+    pub async fn notify(&self, service: Uuid, characteristic: Uuid, value: &[u8]) -> Result<bool> {
+        let Some(state) = self
+            .state
+            .characteristics
+            .iter()
+            .find(|x| x.attribute.service == service && x.attribute.characteristic == characteristic)
+        else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                None,
+                format!("the server has no characteristic {characteristic} in service {service}"),
+            ));
+        };
+        if !state.notifies {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                format!("characteristic {characteristic} has neither the notify nor the indicate property"),
+            ));
+        }
+
+        let mut notifier = state.notifier.lock().await;
+        match notifier.as_mut() {
+            Some(notifier) if !notifier.is_stopped() => {
+                notifier.notify(value.to_vec()).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl State {
+    /// Records a request from the central with `address`, over a connection with `mtu` if the request reports it.
+    fn track(self: &Arc<Self>, address: bluer::Address, mtu: Option<u16>) -> DeviceId {
+        let id = DeviceId(address);
+        let mut centrals = self.centrals.lock().unwrap();
+        match centrals.entry(address) {
+            Entry::Occupied(mut entry) => {
+                if let Some(mtu) = mtu.filter(|x| *x != entry.get().mtu) {
+                    entry.get_mut().mtu = mtu;
+                    let _ = self
+                        .events
+                        .send(ServerEvent::MtuChanged(Central { id: id.clone(), mtu }));
+                }
+            }
+            Entry::Vacant(entry) => {
+                let mtu = mtu.unwrap_or(DEFAULT_MTU);
+                let watcher = tokio::spawn(watch_disconnect(Arc::downgrade(self), self.adapter.clone(), address));
+                entry.insert(CentralState { mtu, watcher });
+                let _ = self
+                    .events
+                    .send(ServerEvent::Connected(Central { id: id.clone(), mtu }));
+            }
+        }
+        id
+    }
+
+    /// The MTU of the connection to the central with `address`.
+    fn mtu(&self, address: bluer::Address) -> u16 {
+        let centrals = self.centrals.lock().unwrap();
+        centrals.get(&address).map_or(DEFAULT_MTU, |x| x.mtu)
+    }
+
+    fn forget(&self, address: bluer::Address) {
+        if self.centrals.lock().unwrap().remove(&address).is_some() {
+            let _ = self.events.send(ServerEvent::Disconnected(DeviceId(address)));
+        }
+    }
+
+    /// Stores the notification session BlueZ opened for characteristic `index`.
+    async fn subscribe(self: Arc<Self>, index: usize, notifier: CharacteristicNotifier) {
+        let characteristic = &self.characteristics[index];
+        let subscription = Subscription {
+            attribute: characteristic.attribute,
+            central: None,
+            indications: notifier.confirming(),
+        };
+        let stopped = notifier.stopped();
+        *characteristic.notifier.lock().await = Some(notifier);
+        *characteristic.subscription.lock().unwrap() = Some(subscription.clone());
+        let _ = self.events.send(ServerEvent::Subscribed(subscription));
+
+        // BlueZ awaits this function before answering the central, so the end of the session is awaited separately
+        tokio::spawn(async move {
+            stopped.await;
+            let characteristic = &self.characteristics[index];
+            let mut notifier = characteristic.notifier.lock().await;
+            // A new session may have replaced this one in the meantime
+            if notifier.as_ref().is_some_and(CharacteristicNotifier::is_stopped) {
+                *notifier = None;
+                if let Some(subscription) = characteristic.subscription.lock().unwrap().take() {
+                    let _ = self.events.send(ServerEvent::Unsubscribed(subscription));
+                }
+            }
+        });
+    }
+}
+
+/// Forgets the central with `address` once it disconnects.
+async fn watch_disconnect(state: Weak<State>, adapter: bluer::Adapter, address: bluer::Address) {
+    if let Ok(device) = adapter.device(address) {
+        if let Ok(mut events) = device.events().await {
+            // The central may have disconnected before its events were subscribed to
+            if device.is_connected().await.unwrap_or(false) {
+                while let Some(event) = events.next().await {
+                    if let DeviceEvent::PropertyChanged(DeviceProperty::Connected(false)) = event {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(state) = state.upgrade() {
+        state.forget(address);
+    }
+}
+
+fn to_characteristic(
+    state: &Arc<State>,
+    handler: &Arc<dyn RequestHandler>,
+    index: usize,
+    service: Uuid,
+    characteristic: CharacteristicDefinition,
+) -> Characteristic {
+    let attribute = AttributeId {
+        service,
+        characteristic: characteristic.uuid,
+        descriptor: None,
+    };
+    let properties = characteristic.properties;
+
+    let read = characteristic.permissions.read.map(|level| {
+        let (state, handler) = (state.clone(), handler.clone());
+        CharacteristicRead {
+            read: true,
+            encrypt_read: level == SecurityLevel::Encrypted,
+            encrypt_authenticated_read: level == SecurityLevel::Authenticated,
+            secure_read: level == SecurityLevel::Secure,
+            fun: Box::new(move |req| {
+                let (state, handler) = (state.clone(), handler.clone());
+                Box::pin(async move {
+                    let request = ReadRequest {
+                        central: state.track(req.device_address, Some(req.mtu)),
+                        attribute,
+                        offset: req.offset,
+                        mtu: req.mtu,
+                    };
+                    handler.read(request).await.map_err(to_req_error)
+                })
+            }),
+            ..Default::default()
+        }
+    });
+
+    let write = characteristic.permissions.write.map(|level| {
+        let (state, handler) = (state.clone(), handler.clone());
+        CharacteristicWrite {
+            write: properties.write,
+            write_without_response: properties.write_without_response,
+            reliable_write: properties.reliable_write,
+            authenticated_signed_writes: properties.authenticated_signed_writes,
+            encrypt_write: level == SecurityLevel::Encrypted,
+            encrypt_authenticated_write: level == SecurityLevel::Authenticated,
+            secure_write: level == SecurityLevel::Secure,
+            method: CharacteristicWriteMethod::Fun(Box::new(move |value, req| {
+                let (state, handler) = (state.clone(), handler.clone());
+                Box::pin(async move {
+                    let request = WriteRequest {
+                        central: state.track(req.device_address, Some(req.mtu)),
+                        attribute,
+                        offset: req.offset,
+                        mtu: req.mtu,
+                        value,
+                        kind: match req.op_type {
+                            WriteOp::Command => WriteKind::WithoutResponse,
+                            WriteOp::Request => WriteKind::WithResponse,
+                            WriteOp::Reliable => WriteKind::Reliable,
+                        },
+                    };
+                    handler.write(request).await.map_err(to_req_error)
+                })
+            })),
+            ..Default::default()
+        }
+    });
+
+    let notify = (properties.notify || properties.indicate).then(|| {
+        let state = state.clone();
+        CharacteristicNotify {
+            notify: properties.notify,
+            indicate: properties.indicate,
+            method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
+                Box::pin(state.clone().subscribe(index, notifier))
+            })),
+            ..Default::default()
+        }
+    });
+
+    Characteristic {
+        uuid: characteristic.uuid,
+        broadcast: properties.broadcast,
+        writable_auxiliaries: properties.writable_auxiliaries,
+        descriptors: characteristic
+            .descriptors
+            .into_iter()
+            .map(|descriptor| to_descriptor(state, handler, attribute, descriptor))
+            .collect(),
+        read,
+        write,
+        notify,
+        ..Default::default()
+    }
+}
+
+fn to_descriptor(
+    state: &Arc<State>,
+    handler: &Arc<dyn RequestHandler>,
+    characteristic: AttributeId,
+    descriptor: DescriptorDefinition,
+) -> Descriptor {
+    let attribute = AttributeId {
+        descriptor: Some(descriptor.uuid),
+        ..characteristic
+    };
+
+    let read = descriptor.permissions.read.map(|level| {
+        let (state, handler) = (state.clone(), handler.clone());
+        DescriptorRead {
+            read: true,
+            encrypt_read: level == SecurityLevel::Encrypted,
+            encrypt_authenticated_read: level == SecurityLevel::Authenticated,
+            secure_read: level == SecurityLevel::Secure,
+            fun: Box::new(move |req| {
+                let (state, handler) = (state.clone(), handler.clone());
+                Box::pin(async move {
+                    let request = ReadRequest {
+                        central: state.track(req.device_address, None),
+                        attribute,
+                        offset: req.offset,
+                        mtu: state.mtu(req.device_address),
+                    };
+                    handler.read(request).await.map_err(to_req_error)
+                })
+            }),
+            ..Default::default()
+        }
+    });
+
+    let write = descriptor.permissions.write.map(|level| {
+        let (state, handler) = (state.clone(), handler.clone());
+        DescriptorWrite {
+            write: true,
+            encrypt_write: level == SecurityLevel::Encrypted,
+            encrypt_authenticated_write: level == SecurityLevel::Authenticated,
+            secure_write: level == SecurityLevel::Secure,
+            fun: Box::new(move |value, req| {
+                let (state, handler) = (state.clone(), handler.clone());
+                Box::pin(async move {
+                    let request = WriteRequest {
+                        central: state.track(req.device_address, None),
+                        attribute,
+                        offset: req.offset,
+                        mtu: state.mtu(req.device_address),
+                        value,
+                        kind: WriteKind::WithResponse,
+                    };
+                    handler.write(request).await.map_err(to_req_error)
+                })
+            }),
+            ..Default::default()
+        }
+    });
+
+    Descriptor {
+        uuid: descriptor.uuid,
+        read,
+        write,
+        ..Default::default()
+    }
+}
+
+/// Converts `err` to the closest of the errors BlueZ can send to the central.
+fn to_req_error(err: AttError) -> ReqError {
+    match err {
+        AttError::INVALID_OFFSET => ReqError::InvalidOffset,
+        AttError::INVALID_ATTRIBUTE_VALUE_LENGTH => ReqError::InvalidValueLength,
+        AttError::READ_NOT_PERMITTED | AttError::WRITE_NOT_PERMITTED => ReqError::NotPermitted,
+        AttError::INSUFFICIENT_AUTHORIZATION => ReqError::NotAuthorized,
+        AttError::REQUEST_NOT_SUPPORTED => ReqError::NotSupported,
+        AttError::PROCEDURE_ALREADY_IN_PROGRESS | AttError::PREPARE_QUEUE_FULL => ReqError::InProgress,
+        _ => ReqError::Failed,
+    }
+}
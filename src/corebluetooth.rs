@@ -1,13 +1,14 @@
 use crate::Uuid;
 
 pub mod adapter;
+pub mod advertisement;
 pub mod characteristic;
 pub mod descriptor;
 pub mod device;
 pub mod error;
 pub mod l2cap_channel;
+pub mod server;
 pub mod service;
-pub mod advertisement;
 
 mod delegates;
 mod types;
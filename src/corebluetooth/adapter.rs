@@ -11,13 +11,17 @@ use tracing::{debug, error, info, warn};
 
 use super::advertisement::AdvertisementImpl;
 use super::delegates::{self, CentralDelegate};
-use super::types::{CBCentralManager, CBManagerAuthorization, CBManagerState, CBUUID, NSUUID};
+use super::types::{
+    scan_options_allow_duplicates, CBCentralManager, CBManagerAuthorization, CBManagerState, CBUUID, NSUUID,
+};
 use crate::corebluetooth::types::{dispatch_get_global_queue, QOS_CLASS_UTILITY};
 use crate::error::ErrorKind;
+use crate::server::{RequestHandler, Server, ServiceDefinition};
 use crate::util::defer;
 use crate::{
-    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, ConnectionEvent, Device, DeviceId, Error,
-    Result, Uuid,
+    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters, ConnectionEvent,
+    Device, DeviceId, DuplicatePolicy, Error, MonitorEvent, MonitorOptions, MonitorPattern, Result, ScanEvent,
+    ScanFilter, ScanMode, ScanOptions, Uuid,
 };
 
 /// The system's Bluetooth adapter interface.
@@ -155,22 +159,32 @@ impl AdapterImpl {
     /// [`Device`] which sent it. Scanning is automatically stopped when the stream is dropped. Inclusion of duplicate
     /// packets is a platform-specific implementation detail.
     ///
-    /// If `services` is not empty, returns advertisements including at least one GATT service with a UUID in
-    /// `services`. Otherwise returns all advertisements.
-    pub async fn scan<'a>(
-        &'a self,
-        services: &'a [Uuid],
-    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + 'a> {
+    /// Only advertisements matching `filter` are returned. CoreBluetooth only scans actively and does not take a scan
+    /// interval or window.
+    pub async fn scan(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
         if self.central.state() != CBManagerState::POWERED_ON {
             return Err(ErrorKind::AdapterUnavailable.into());
         }
 
+        if options.mode == ScanMode::Passive {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "CoreBluetooth does not support passive scanning",
+            ));
+        }
+
         if self.scanning.swap(true, Ordering::Acquire) {
             return Err(ErrorKind::AlreadyScanning.into());
         }
 
+        let services = filter.services();
         let services = (!services.is_empty()).then(|| {
-            let vec = services.iter().copied().map(CBUUID::from_uuid).collect::<Vec<_>>();
+            let vec = services.into_iter().map(CBUUID::from_uuid).collect::<Vec<_>>();
             NSArray::from_vec(vec)
         });
 
@@ -191,21 +205,45 @@ impl AdapterImpl {
                         peripheral,
                         adv_data,
                         rssi,
+                        timestamp,
                     } => Some(AdvertisingDevice {
                         device: Device::new(peripheral),
                         adv_data: AdvertisementData::from_nsdictionary(&adv_data),
                         rssi: Some(rssi),
+                        timestamp: Some(timestamp),
+                        address_type: None,
+                        primary_phy: None,
+                        secondary_phy: None,
+                        advertising_sid: None,
+                        is_scan_response: None,
                     }),
                     _ => None,
                 }
-            });
+            })
+            .filter(move |x| filter.matches(x));
 
+        // Changed advertisements are only seen reliably if every advertisement is reported
+        let scan_options = (options.duplicates != DuplicatePolicy::PlatformDefault).then(scan_options_allow_duplicates);
         self.central
-            .scan_for_peripherals_with_services(services.as_deref(), None);
+            .scan_for_peripherals_with_services(services.as_deref(), scan_options.as_deref());
 
         Ok(events)
     }
 
+    /// Starts scanning for Bluetooth advertising packets.
+    ///
+    /// CoreBluetooth never reports devices lost or scan errors, so only advertisements are returned.
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        Ok(self
+            .scan(filter, options)
+            .await?
+            .map(|adv| Ok(ScanEvent::Advertisement(adv))))
+    }
+
     /// Finds Bluetooth devices providing any service in `services`.
     ///
     /// Returns a stream of [`Device`] structs with matching connected devices returned first. If the stream is not
@@ -222,7 +260,10 @@ impl AdapterImpl {
         let advertising = Box::pin(stream::try_unfold(None, |state| async {
             let mut stream = match state {
                 Some(stream) => stream,
-                None => self.scan(services).await?,
+                None => {
+                    self.scan(ScanFilter::from_services(services), &ScanOptions::default())
+                        .await?
+                }
             };
             Ok(stream.next().await.map(|x| (x.device, Some(stream))))
         }));
@@ -400,8 +441,46 @@ impl AdapterImpl {
             }))
     }
 
-    pub async fn start_advertising(&self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        let advertisement_impl = AdvertisementImpl::new();
-        advertisement_impl.start_advertising(data).await
+    /// Starts advertising `data` from this adapter with a new `CBPeripheralManager`
+    pub async fn start_advertising(
+        &self,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        let advertisement = AdvertisementImpl::start(data, parameters).await?;
+        Ok(AdvertisingGuard { advertisement })
+    }
+
+    /// CoreBluetooth does not report the number of advertising sets
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        Err(ErrorKind::NotSupported.into())
+    }
+
+    /// Advertisement monitors are not supported on MacOS/iOS
+    pub async fn monitor_advertisements(
+        &self,
+        _patterns: &[MonitorPattern],
+        _options: &MonitorOptions,
+    ) -> Result<stream::Empty<MonitorEvent>> {
+        Err(ErrorKind::NotSupported.into())
+    }
+
+    /// Listening for L2CAP channels is not supported on MacOS/iOS
+    #[cfg(feature = "l2cap")]
+    pub async fn listen_l2cap(
+        &self,
+        _psm: Option<u16>,
+        _secure: bool,
+    ) -> Result<(u16, stream::Empty<Result<(Device, crate::L2capChannel)>>)> {
+        Err(ErrorKind::NotSupported.into())
+    }
+
+    /// GATT servers are not supported on MacOS/iOS
+    pub async fn start_server(
+        &self,
+        _services: Vec<ServiceDefinition>,
+        _handler: Arc<dyn RequestHandler>,
+    ) -> Result<Server> {
+        Err(ErrorKind::NotSupported.into())
     }
 }
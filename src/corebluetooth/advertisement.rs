@@ -1,135 +1,161 @@
-use core::fmt;
-use std::io;
-
-use objc::class;
-use objc::rc::StrongPtr;
-use objc::runtime::{Class, Object};
-use objc::{msg_send, sel, sel_impl};
-use tracing::debug;
-
-use crate::{AdvertisementData, AdvertisingGuard, Result};
-
-#[derive(Clone)]
-pub struct AdvertisementImpl {
-    peripheral_manager: Option<StrongPtr>,
-}
-
-impl fmt::Debug for AdvertisementImpl {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "AdvertisementImpl {{ peripheral_manager: ... }}")
-    }
-}
-
-impl AdvertisementImpl {
-    pub fn new() -> Self {
-        Self {
-            peripheral_manager: None,
-        }
-    }
-
-    pub fn stop_advertising(&mut self) -> Result<(), io::Error> {
-        if let Some(ref peripheral_manager) = self.peripheral_manager {
-            unsafe {
-                let _: () = msg_send![**peripheral_manager, stopAdvertising];
-                debug!("Stopped CoreBluetooth advertisement");
-            }
-        }
-        self.peripheral_manager = None;
-        Ok(())
-    }
-
-    pub async fn start_advertising(mut self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        //self.stop_advertising();
-
-        // Initialize CBPeripheralManager if not already created
-        if self.peripheral_manager.is_none() {
-            println!("creating new peripheral_manager");
-            let peripheral_manager: *mut Object = unsafe {
-                let manager: *mut Object = msg_send![class!(CBPeripheralManager), alloc];
-                msg_send![manager, init]
-            };
-            self.peripheral_manager = Some(unsafe { StrongPtr::new(peripheral_manager) });
-        }
-
-        if let Some(ref peripheral_manager) = self.peripheral_manager {
-            // debug!("Starting CoreBluetooth advertisement");
-            // let is_advertising: bool = unsafe { msg_send![**peripheral_manager, isAdvertising] };
-            // debug!("Peripheral Manager is advertising: {}", is_advertising);
-
-            // Create an NSMutableDictionary and add manufacturer data
-            let advertisement_data = create_mutable_dictionary();
-            if let Some(manufacturer_data) = data.manufacturer_data {
-                // Combine the company ID with the manufacturer data
-                let mut combined_data = Vec::with_capacity(2 + manufacturer_data.data.len());
-                let c = manufacturer_data.company_id.to_le_bytes();
-                combined_data.extend_from_slice(&[c[1], c[0]]);
-                //combined_data.extend_from_slice(&[0x69u8,0x69u8]);
-                combined_data.extend_from_slice(&manufacturer_data.data);
-                debug!("Final Manufacturer Data: {:x?}", combined_data);
-                add_data_to_dict(advertisement_data, "kCBAdvDataManufacturerData", &combined_data);
-                debug!("Setting kCBAdvDataManufacturerData: {:x?}", combined_data);
-            }
-            debug!("starting ADVERT");
-            unsafe {
-                let description: *mut Object = msg_send![advertisement_data, description];
-                let c_str: *const i8 = msg_send![description, UTF8String];
-                if !c_str.is_null() {
-                    let ad_description = std::ffi::CStr::from_ptr(c_str).to_string_lossy().into_owned();
-                    debug!("Advertisement Dictionary Description: {}", ad_description);
-                } else {
-                    debug!("Failed to get Advertisement Dictionary Description");
-                }
-            }
-
-            // Start advertising
-            unsafe {
-                let _: () = msg_send![**peripheral_manager, startAdvertising: advertisement_data];
-            }
-            debug!("done ADVERT");
-
-            return Ok(AdvertisingGuard { advertisement: self });
-        }
-        Err("Failed to start CoreBluetooth advertising".to_owned())
-    }
-}
-
-impl Drop for AdvertisementImpl {
-    fn drop(&mut self) {
-        println!("AdvertisementImpl dropped.");
-    }
-}
-
-fn create_mutable_dictionary() -> *mut Object {
-    let dict_class = Class::get("NSMutableDictionary").expect("NSMutableDictionary class not found");
-    unsafe { msg_send![dict_class, dictionary] }
-}
-
-fn add_data_to_dict(dict: *mut Object, key: &str, value: &[u8]) {
-    debug!("Adding to Dictionary - Key: {}, Value: {:x?}", key, value);
-    let ns_key = NSString::from_str(key);
-    debug!("Adding to Dictionary - Key: {}, Value: {:x?}", key, value);
-    let ns_value = NSData::from_vec(value);
-    debug!("Adding to Dictionary - Key: {}, Value: {:x?}", key, value);
-    unsafe {
-        let _: () = msg_send![dict, setObject: ns_value forKey: ns_key];
-    }
-}
-
-// Helper function to convert Rust string to NSString
-pub struct NSString;
-impl NSString {
-    pub fn from_str(s: &str) -> *mut Object {
-        let ns_string_class = Class::get("NSString").expect("NSString class not found");
-        let ns_string: *mut Object = unsafe { msg_send![ns_string_class, alloc] };
-        unsafe { msg_send![ns_string, initWithUTF8String: s.as_ptr()] }
-    }
-}
-
-// Helper function to convert Vec<u8> to NSData
-pub struct NSData;
-impl NSData {
-    pub fn from_vec(data: &[u8]) -> *mut Object {
-        let data_class = Class::get("NSData").expect("NSData class not found");
-        unsafe { msg_send![data_class, dataWithBytes: data.as_ptr() length: data.len()] }
-    }
-}
+use core::fmt;
+
+use objc::runtime::Object;
+use objc::{class, msg_send, sel, sel_impl};
+use objc_foundation::{INSArray, INSData, INSString, NSArray, NSData, NSString};
+use objc_id::{Id, ShareId};
+
+use super::delegates::{PeripheralManagerDelegate, PeripheralManagerEvent};
+use super::types::{dispatch_get_global_queue, CBManagerState, CBUUID, QOS_CLASS_UTILITY};
+use crate::error::ErrorKind;
+use crate::{AdvertisementData, AdvertisingParameters, Error, Result};
+
+pub struct AdvertisementImpl {
+    peripheral_manager: Option<ShareId<Object>>,
+    // The peripheral manager only holds a weak reference to its delegate
+    _delegate: ShareId<PeripheralManagerDelegate>,
+}
+
+impl fmt::Debug for AdvertisementImpl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AdvertisementImpl {{ peripheral_manager: ... }}")
+    }
+}
+
+impl AdvertisementImpl {
+    /// Starts advertising `data` with a new `CBPeripheralManager`
+    ///
+    /// Waits for the peripheral manager to be powered on and for CoreBluetooth to confirm that advertising started.
+    pub(super) async fn start(data: AdvertisementData, parameters: AdvertisingParameters) -> Result<Self> {
+        if parameters != AdvertisingParameters::default() {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "CoreBluetooth does not support advertising parameters",
+            ));
+        }
+
+        let unsupported = [
+            ("service data", !data.service_data.is_empty()),
+            ("a TX power level", data.tx_power_level.is_some()),
+            ("solicited services", !data.solicited_services.is_empty()),
+            ("an appearance", data.appearance.is_some()),
+            ("an advertising interval", data.advertising_interval.is_some()),
+            ("a URI", data.uri.is_some()),
+            ("LE supported features", data.le_supported_features.is_some()),
+            ("other AD structures", !data.other_structures.is_empty()),
+            ("several manufacturer data entries", data.manufacturer_data.len() > 1),
+        ];
+        if let Some((field, _)) = unsupported.iter().find(|(_, present)| *present) {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                format!("CoreBluetooth cannot advertise {field}"),
+            ));
+        }
+
+        let delegate = PeripheralManagerDelegate::new().share();
+        let mut receiver = delegate.sender().new_receiver();
+        let peripheral_manager = unsafe {
+            let queue = dispatch_get_global_queue(QOS_CLASS_UTILITY, 0);
+            if queue.is_null() {
+                return Err(Error::new(ErrorKind::Internal, None, "getting a dispatch queue"));
+            }
+            let manager: *mut Object = msg_send![class!(CBPeripheralManager), alloc];
+            let manager: *mut Object = msg_send![manager, initWithDelegate: &*delegate queue: queue];
+            ShareId::from_retained_ptr(manager)
+        };
+
+        // The peripheral manager starts in the unknown state and ignores advertising requests until it is powered on
+        while check_state(&peripheral_manager)? != CBManagerState::POWERED_ON {
+            receiver.recv().await.map_err(Error::from_recv_error)?;
+        }
+
+        start_advertising(&peripheral_manager, &data);
+        loop {
+            match receiver.recv().await.map_err(Error::from_recv_error)? {
+                PeripheralManagerEvent::StartedAdvertising { error: None } => break,
+                PeripheralManagerEvent::StartedAdvertising { error: Some(err) } => {
+                    return Err(Error::from_nserror(err))
+                }
+                PeripheralManagerEvent::StateChanged => {
+                    check_state(&peripheral_manager)?;
+                }
+            }
+        }
+
+        Ok(AdvertisementImpl {
+            peripheral_manager: Some(peripheral_manager),
+            _delegate: delegate,
+        })
+    }
+
+    /// Stop advertising if an advertisement is active
+    pub fn stop_advertising(&mut self) -> Result<()> {
+        if let Some(peripheral_manager) = self.peripheral_manager.take() {
+            unsafe {
+                let _: () = msg_send![&*peripheral_manager, stopAdvertising];
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the state of `peripheral_manager`, or an error if it cannot become powered on by itself
+fn check_state(peripheral_manager: &Object) -> Result<CBManagerState> {
+    let state = CBManagerState(unsafe { msg_send![peripheral_manager, state] });
+    match state {
+        CBManagerState::UNSUPPORTED => Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "this device does not support the peripheral role",
+        )),
+        CBManagerState::UNAUTHORIZED => Err(Error::new(
+            ErrorKind::NotAuthorized,
+            None,
+            "the app is not authorized to advertise",
+        )),
+        CBManagerState::POWERED_OFF => Err(ErrorKind::AdapterUnavailable.into()),
+        state => Ok(state),
+    }
+}
+
+/// Asks `peripheral_manager` to advertise `data`, which is confirmed through its delegate
+fn start_advertising(peripheral_manager: &Object, data: &AdvertisementData) {
+    let advertisement_data: *mut Object = unsafe { msg_send![class!(NSMutableDictionary), dictionary] };
+
+    if let Some(local_name) = &data.local_name {
+        insert(
+            advertisement_data,
+            "kCBAdvDataLocalName",
+            &*NSString::from_str(local_name),
+        );
+    }
+
+    if !data.services.is_empty() {
+        let services = data.services.iter().copied().map(CBUUID::from_uuid).collect::<Vec<_>>();
+        let services: Id<NSArray<CBUUID>> = NSArray::from_vec(services);
+        insert(advertisement_data, "kCBAdvDataServiceUUIDs", &*services);
+    }
+
+    if let Some(manufacturer_data) = data.manufacturer_data.first() {
+        let mut value = manufacturer_data.company_id.to_le_bytes().to_vec();
+        value.extend_from_slice(&manufacturer_data.data);
+        insert(
+            advertisement_data,
+            "kCBAdvDataManufacturerData",
+            &*NSData::from_vec(value),
+        );
+    }
+
+    unsafe {
+        let _: () = msg_send![peripheral_manager, startAdvertising: advertisement_data];
+    }
+}
+
+fn insert<T: objc::Message>(dict: *mut Object, key: &str, value: &T) {
+    let key = NSString::from_str(key);
+    unsafe {
+        let _: () = msg_send![dict, setObject: value forKey: &*key];
+    }
+}
@@ -1,5 +1,6 @@
 use std::os::raw::c_void;
 use std::sync::Once;
+use std::time::SystemTime;
 
 use objc::declare::ClassDecl;
 use objc::runtime::{Class, Object, Protocol, Sel};
@@ -32,6 +33,7 @@ pub enum CentralEvent {
         peripheral: ShareId<CBPeripheral>,
         adv_data: ShareId<NSDictionary<NSString, NSObject>>,
         rssi: i16,
+        timestamp: SystemTime,
     },
     StateChanged,
 }
@@ -122,6 +124,12 @@ pub enum PeripheralEvent {
     },
 }
 
+#[derive(Debug, Clone)]
+pub enum PeripheralManagerEvent {
+    StateChanged,
+    StartedAdvertising { error: Option<ShareId<NSError>> },
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CBConnectionEvent {
     Disconnected,
@@ -194,6 +202,21 @@ macro_rules! delegate_method {
             }
         }
     };
+
+    ($name:ident < $event:ident > ( peripheral_manager $(, $param:ident: $ty:ident)*)) => {
+        extern "C" fn $name(this: &mut Object, _sel: Sel, _peripheral_manager: id, $($param: id),*) {
+            unsafe {
+                let ptr = (*this.get_ivar::<*mut c_void>("sender")).cast::<async_broadcast::Sender<PeripheralManagerEvent>>();
+                if !ptr.is_null() {
+                    let event = PeripheralManagerEvent::$event {
+                        $($param: delegate_method!(@value $param: $ty)),*
+                    };
+                    debug!("PeripheralManagerDelegate received {:?}", event);
+                    let _res = (*ptr).try_broadcast(event);
+                }
+            }
+        }
+    };
 }
 
 pub struct CentralDelegate {
@@ -264,6 +287,40 @@ impl ::std::fmt::Debug for PeripheralDelegate {
     }
 }
 
+pub struct PeripheralManagerDelegate {
+    _private: (),
+}
+unsafe impl objc::Message for PeripheralManagerDelegate {}
+
+impl objc_foundation::INSObject for PeripheralManagerDelegate {
+    fn class() -> &'static ::objc::runtime::Class {
+        PeripheralManagerDelegate::class()
+    }
+}
+impl PartialEq for PeripheralManagerDelegate {
+    fn eq(&self, other: &Self) -> bool {
+        use objc_foundation::INSObject;
+        self.is_equal(other)
+    }
+}
+impl Eq for PeripheralManagerDelegate {}
+
+impl std::hash::Hash for PeripheralManagerDelegate {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: std::hash::Hasher,
+    {
+        use objc_foundation::INSObject;
+        self.hash_code().hash(state);
+    }
+}
+impl ::std::fmt::Debug for PeripheralManagerDelegate {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        use objc_foundation::{INSObject, INSString};
+        ::std::fmt::Debug::fmt(self.description().as_str(), f)
+    }
+}
+
 impl CentralDelegate {
     pub fn new() -> Option<Id<CentralDelegate>> {
         let (mut sender, receiver) = async_broadcast::broadcast::<CentralEvent>(16);
@@ -370,6 +427,7 @@ impl CentralDelegate {
                     peripheral: ShareId::from_ptr(peripheral.cast()),
                     adv_data: ShareId::from_ptr(adv_data.cast()),
                     rssi,
+                    timestamp: SystemTime::now(),
                 };
                 // debug!("CentralDelegate received {:?}", event);
                 let _res = (*ptr).try_broadcast(event);
@@ -643,3 +701,112 @@ impl PeripheralDelegate {
         class!(BluestPeripheralDelegate)
     }
 }
+
+impl PeripheralManagerDelegate {
+    pub fn new() -> Id<PeripheralManagerDelegate> {
+        let (mut sender, receiver) = async_broadcast::broadcast::<PeripheralManagerEvent>(16);
+        sender.set_overflow(true);
+        let receiver = receiver.deactivate();
+
+        unsafe {
+            let obj: *mut Self = msg_send![Self::class(), alloc];
+            let obj: *mut Self = msg_send![obj, initWithSender: Box::into_raw(Box::new(sender)).cast::<c_void>() receiver: Box::into_raw(Box::new(receiver)).cast::<c_void>()];
+            Id::from_retained_ptr(obj)
+        }
+    }
+
+    pub fn sender(&self) -> &async_broadcast::Sender<PeripheralManagerEvent> {
+        unsafe {
+            let sender: *const c_void = msg_send![self, sender];
+            assert!(!sender.is_null());
+            &*(sender.cast::<async_broadcast::Sender<PeripheralManagerEvent>>())
+        }
+    }
+
+    extern "C" fn init(this: &mut Object, _sel: Sel, sender: *mut c_void, receiver: *mut c_void) -> id {
+        let this: &mut Object = unsafe { msg_send![super(this, class!(NSObject)), init] };
+        unsafe { this.set_ivar("sender", sender) };
+        unsafe { this.set_ivar("receiver", receiver) };
+        this
+    }
+
+    extern "C" fn dealloc(this: &mut Object, _sel: Sel) {
+        unsafe {
+            let sender: *mut c_void = *this.get_ivar("sender");
+            let receiver: *mut c_void = *this.get_ivar("receiver");
+            this.set_ivar("sender", std::ptr::null_mut::<c_void>());
+            this.set_ivar("receiver", std::ptr::null_mut::<c_void>());
+            if !sender.is_null() {
+                drop(Box::from_raw(
+                    sender.cast::<async_broadcast::Sender<PeripheralManagerEvent>>(),
+                ));
+            }
+            if !receiver.is_null() {
+                drop(Box::from_raw(
+                    receiver.cast::<async_broadcast::InactiveReceiver<PeripheralManagerEvent>>(),
+                ));
+            }
+            let _: () = msg_send![super(this, class!(NSObject)), dealloc];
+        };
+    }
+
+    extern "C" fn sender_getter(this: &mut Object, _sel: Sel) -> *const c_void {
+        unsafe { *this.get_ivar("sender") }
+    }
+
+    extern "C" fn receiver_getter(this: &mut Object, _sel: Sel) -> *const c_void {
+        unsafe { *this.get_ivar("receiver") }
+    }
+
+    delegate_method!(did_update_state<StateChanged>(peripheral_manager));
+    delegate_method!(did_start_advertising<StartedAdvertising>(peripheral_manager, error: Option));
+
+    fn class() -> &'static Class {
+        static DELEGATE_CLASS_INIT: Once = Once::new();
+        DELEGATE_CLASS_INIT.call_once(|| {
+            let mut cls = ClassDecl::new("BluestPeripheralManagerDelegate", class!(NSObject)).unwrap();
+            cls.add_ivar::<*mut c_void>("sender");
+            cls.add_ivar::<*mut c_void>("receiver");
+            cls.add_protocol(Protocol::get("CBPeripheralManagerDelegate").unwrap());
+
+            unsafe {
+                // Initialization
+                cls.add_method(
+                    sel!(initWithSender:receiver:),
+                    Self::init as extern "C" fn(&mut Object, Sel, *mut c_void, *mut c_void) -> id,
+                );
+
+                // Cleanup
+                cls.add_method(sel!(dealloc), Self::dealloc as extern "C" fn(&mut Object, Sel));
+
+                // Sender property
+                cls.add_method(
+                    sel!(sender),
+                    Self::sender_getter as extern "C" fn(&mut Object, Sel) -> *const c_void,
+                );
+
+                // Receiver property
+                cls.add_method(
+                    sel!(receiver),
+                    Self::receiver_getter as extern "C" fn(&mut Object, Sel) -> *const c_void,
+                );
+
+                // CBPeripheralManagerDelegate
+                // Monitoring the Peripheral Manager's State
+                cls.add_method(
+                    sel!(peripheralManagerDidUpdateState:),
+                    Self::did_update_state as extern "C" fn(&mut Object, Sel, id),
+                );
+                // Advertising Peripheral Data
+                cls.add_method(
+                    sel!(peripheralManagerDidStartAdvertising:error:),
+                    Self::did_start_advertising as extern "C" fn(&mut Object, Sel, id, id),
+                );
+            }
+
+            cls.register();
+        });
+
+        class!(BluestPeripheralManagerDelegate)
+    }
+}
@@ -1,6 +1,7 @@
 #![cfg(feature = "l2cap")]
 
 use std::fmt;
+use std::task::{Context, Poll};
 
 use crate::Result;
 
@@ -18,6 +19,14 @@ impl L2capChannelReader {
         todo!()
     }
 
+    pub fn poll_read(&mut self, _cx: &mut Context<'_>, _buf: &mut [u8]) -> Poll<Result<usize>> {
+        todo!()
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        todo!()
+    }
+
     pub async fn close(&mut self) -> Result<()> {
         todo!()
     }
@@ -42,6 +51,18 @@ impl L2capChannelWriter {
         todo!()
     }
 
+    pub fn poll_write(&mut self, _cx: &mut Context<'_>, _packet: &[u8]) -> Poll<Result<()>> {
+        todo!()
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        todo!()
+    }
+
+    pub fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        todo!()
+    }
+
     pub async fn close(&mut self) -> Result<()> {
         todo!()
     }
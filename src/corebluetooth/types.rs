@@ -7,8 +7,8 @@ use std::ffi::c_ulong;
 use std::os::raw::{c_char, c_void};
 
 use objc::rc::autoreleasepool;
-use objc::runtime::{Object, BOOL, NO};
-use objc::{msg_send, sel, sel_impl};
+use objc::runtime::{Object, BOOL, NO, YES};
+use objc::{class, msg_send, sel, sel_impl};
 use objc_foundation::{
     object_struct, INSData, INSDictionary, INSFastEnumeration, INSObject, INSString, NSArray, NSData, NSDictionary,
     NSObject, NSString,
@@ -148,7 +148,7 @@ impl AdvertisementData {
     pub(super) fn from_nsdictionary(adv_data: &ShareId<NSDictionary<NSString, NSObject>>) -> Self {
         let is_connectable = adv_data
             .object_for(unsafe { extern_nsstring(CBAdvertisementDataIsConnectable) })
-            .map_or(false, |val| unsafe {
+            .map(|val| unsafe {
                 let n: BOOL = msg_send![val, boolValue];
                 n != NO
             });
@@ -157,6 +157,7 @@ impl AdvertisementData {
             .object_for(unsafe { extern_nsstring(CBAdvertisementDataLocalNameKey) })
             .map(|val| unsafe { (*(val as *const NSObject).cast::<NSString>()).as_str().to_string() });
 
+        // CoreBluetooth only reports a single manufacturer specific data entry
         let manufacturer_data = adv_data
             .object_for(unsafe { extern_nsstring(CBAdvertisementDataManufacturerDataKey) })
             .map(|val| unsafe { (*(val as *const NSObject).cast::<NSData>()).bytes() })
@@ -165,7 +166,8 @@ impl AdvertisementData {
                     company_id: u16::from_le_bytes(val[0..2].try_into().unwrap()),
                     data: val[2..].to_vec(),
                 })
-            });
+            })
+            .into();
 
         let tx_power_level: Option<i16> = adv_data
             .object_for(unsafe { extern_nsstring(CBAdvertisementDataTxPowerLevelKey) })
@@ -203,6 +205,7 @@ impl AdvertisementData {
             service_data,
             tx_power_level,
             is_connectable,
+            ..Default::default()
         }
     }
 }
@@ -228,6 +231,9 @@ extern "C" {
     // CBConnectionEventMatchingOption
     static CBConnectionEventMatchingOptionPeripheralUUIDs: id;
     static CBConnectionEventMatchingOptionServiceUUIDs: id;
+
+    // CBCentralManagerScanOption keys
+    static CBCentralManagerScanOptionAllowDuplicatesKey: id;
 }
 
 pub const QOS_CLASS_USER_INTERACTIVE: isize = 0x21;
@@ -260,6 +266,16 @@ pub fn connection_event_matching_option_service_uuids() -> &'static NSString {
     unsafe { extern_nsstring(CBConnectionEventMatchingOptionServiceUUIDs) }
 }
 
+/// Scan options which report every advertisement received from a peripheral.
+pub fn scan_options_allow_duplicates() -> Id<NSDictionary<NSString, NSObject>> {
+    let key = unsafe { extern_nsstring(CBCentralManagerScanOptionAllowDuplicatesKey) };
+    let value: Id<NSObject> = unsafe {
+        let number: *mut NSObject = msg_send![class!(NSNumber), numberWithBool: YES];
+        Id::from_ptr(number)
+    };
+    NSDictionary::from_keys_and_objects(&[key], vec![value])
+}
+
 object_struct!(NSError);
 object_struct!(NSUUID);
 object_struct!(CBUUID);
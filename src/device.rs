@@ -149,7 +149,8 @@ impl Device {
     ///
     /// # Platform specific
     ///
-    /// Returns [`NotSupported`][crate::error::ErrorKind::NotSupported] on Windows and Linux.
+    /// Returns [`NotSupported`][crate::error::ErrorKind::NotSupported] on Windows. On Linux, returns the RSSI of the last
+    /// advertisement received while scanning.
     #[inline]
     pub async fn rssi(&self) -> Result<i16> {
         self.0.rssi().await
@@ -157,15 +158,17 @@ impl Device {
 
     /// Open an L2CAP connection-oriented channel (CoC) to this device.
     ///
+    /// If `secure` is true, the channel requires an encrypted link and the device will be paired if necessary.
+    ///
     /// # Platform specific
     ///
-    /// Returns [`NotSupported`][crate::error::ErrorKind::NotSupported] on iOS/MacOS and Linux.
+    /// Returns [`NotSupported`][crate::error::ErrorKind::NotSupported] on iOS/MacOS.
     /// The `l2cap` feature is not available on Windows.
     #[inline]
     #[cfg(feature = "l2cap")]
     pub async fn open_l2cap_channel(&self, psm: u16, secure: bool) -> Result<L2capChannel> {
         let (reader, writer) = self.0.open_l2cap_channel(psm, secure).await?;
-        Ok(L2capChannel { reader, writer })
+        Ok(L2capChannel::new(reader, writer))
     }
 }
 
@@ -0,0 +1,332 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+use crate::{AddressType, AdvertisementData, AdvertisingDevice, Device, DeviceId};
+
+/// The default weight of a new signal strength sample in [`DeviceEntry::smoothed_rssi`].
+const DEFAULT_RSSI_SMOOTHING: f64 = 0.25;
+
+/// A table of the devices seen by a scan, with statistics for each device.
+///
+/// The table is updated with [`DeviceTable::track`], which consumes a stream such as the one returned by
+/// [`Adapter::scan`][crate::Adapter::scan], or with [`DeviceTable::record`] for individual advertisements. Clones of a
+/// table share the same entries, so one task can update the table while others query it.
+///
+/// Devices which have not been seen for longer than the eviction timeout are removed from the table.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// use bluest::{Adapter, DeviceTable};
+///
+/// # async fn example(adapter: Adapter) -> bluest::Result<()> {
+/// let table = DeviceTable::new().with_eviction_timeout(Duration::from_secs(30));
+/// let scan = adapter.scan(&[]).await?;
+/// let tracker = table.track(scan);
+/// # let _ = tracker;
+/// // Poll `tracker` while other tasks read the table:
+/// for entry in table.sorted_by_rssi() {
+///     println!("{:?}: {:?} dBm", entry.adv_data.local_name, entry.smoothed_rssi);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeviceTable {
+    inner: Arc<Mutex<TableState>>,
+}
+
+#[derive(Debug)]
+struct TableState {
+    devices: HashMap<DeviceId, DeviceEntry>,
+    eviction_timeout: Option<Duration>,
+    rssi_smoothing: f64,
+}
+
+impl Default for TableState {
+    fn default() -> Self {
+        TableState {
+            devices: HashMap::new(),
+            eviction_timeout: None,
+            rssi_smoothing: DEFAULT_RSSI_SMOOTHING,
+        }
+    }
+}
+
+/// What a [`DeviceTable`] knows about a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceEntry {
+    /// The device
+    pub device: Device,
+    /// The type of the address the device last advertised with
+    pub address_type: Option<AddressType>,
+    /// The advertisement data received from the device, with the most recently received value of each field
+    pub adv_data: AdvertisementData,
+    /// When the first advertisement from the device was received
+    pub first_seen: SystemTime,
+    /// When the latest advertisement from the device was received
+    pub last_seen: SystemTime,
+    /// The number of advertising packets and scan responses received from the device
+    pub packets: u64,
+    /// The signal strength in dBm of the latest packet received with a signal strength
+    pub rssi: Option<i16>,
+    /// The weakest signal strength in dBm received
+    pub min_rssi: Option<i16>,
+    /// The strongest signal strength in dBm received
+    pub max_rssi: Option<i16>,
+    /// The mean of every signal strength in dBm received
+    pub mean_rssi: Option<f64>,
+    /// The exponential moving average of the signal strength in dBm
+    pub smoothed_rssi: Option<f64>,
+    /// The shortest time between two advertising packets, the best estimate of the advertising interval
+    pub min_interval: Option<Duration>,
+    /// The mean time between advertising packets, which is longer than the advertising interval if packets were missed
+    pub mean_interval: Option<Duration>,
+    rssi_sum: i64,
+    rssi_samples: u64,
+    advertisements: u64,
+    first_advertisement: Option<SystemTime>,
+    last_advertisement: Option<SystemTime>,
+}
+
+impl DeviceTable {
+    /// Creates an empty table which keeps devices until they are removed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes devices which have not been seen for longer than `timeout`.
+    pub fn with_eviction_timeout(self, timeout: Duration) -> Self {
+        self.inner.lock().unwrap().eviction_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the weight, between 0 and 1, of each new sample in [`DeviceEntry::smoothed_rssi`]. The default is 0.25.
+    ///
+    /// Larger weights follow changes in signal strength more quickly but smooth out less noise.
+    pub fn with_rssi_smoothing(self, weight: f64) -> Self {
+        self.inner.lock().unwrap().rssi_smoothing = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Records every advertisement from `scan` until it ends.
+    pub async fn track(&self, mut scan: impl Stream<Item = AdvertisingDevice> + Unpin) {
+        while let Some(device) = scan.next().await {
+            self.record(&device);
+        }
+    }
+
+    /// Records one advertisement.
+    ///
+    /// Advertisements without a timestamp are recorded as received now.
+    pub fn record(&self, adv: &AdvertisingDevice) {
+        let timestamp = adv.timestamp.unwrap_or_else(SystemTime::now);
+        let mut state = self.inner.lock().unwrap();
+        let smoothing = state.rssi_smoothing;
+        let entry = state.devices.entry(adv.device.id()).or_insert_with(|| DeviceEntry {
+            device: adv.device.clone(),
+            address_type: None,
+            adv_data: AdvertisementData::default(),
+            first_seen: timestamp,
+            last_seen: timestamp,
+            packets: 0,
+            rssi: None,
+            min_rssi: None,
+            max_rssi: None,
+            mean_rssi: None,
+            smoothed_rssi: None,
+            min_interval: None,
+            mean_interval: None,
+            rssi_sum: 0,
+            rssi_samples: 0,
+            advertisements: 0,
+            first_advertisement: None,
+            last_advertisement: None,
+        });
+        entry.update(adv, timestamp, smoothing);
+        state.evict(timestamp);
+    }
+
+    /// The entry for the device with `id`, if it is in the table.
+    pub fn get(&self, id: &DeviceId) -> Option<DeviceEntry> {
+        self.inner.lock().unwrap().devices.get(id).cloned()
+    }
+
+    /// Removes the entry for the device with `id` from the table.
+    pub fn remove(&self, id: &DeviceId) -> Option<DeviceEntry> {
+        self.inner.lock().unwrap().devices.remove(id)
+    }
+
+    /// Removes every entry from the table.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().devices.clear();
+    }
+
+    /// The number of devices in the table.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().devices.len()
+    }
+
+    /// Returns `true` if the table has no devices.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the devices which have not been seen for longer than the eviction timeout.
+    ///
+    /// Devices are also evicted when an advertisement is recorded and when the table is queried.
+    pub fn evict(&self) -> Vec<DeviceEntry> {
+        self.inner.lock().unwrap().evict(SystemTime::now())
+    }
+
+    /// The devices in the table, with the strongest smoothed signal strength first.
+    ///
+    /// Devices without a signal strength are last.
+    pub fn sorted_by_rssi(&self) -> Vec<DeviceEntry> {
+        let mut devices = self.snapshot();
+        devices.sort_by(|a, b| match (a.smoothed_rssi, b.smoothed_rssi) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        devices
+    }
+
+    /// The devices in the table, with the most recently seen first.
+    pub fn sorted_by_recency(&self) -> Vec<DeviceEntry> {
+        let mut devices = self.snapshot();
+        devices.sort_by_key(|x| Reverse(x.last_seen));
+        devices
+    }
+
+    fn snapshot(&self) -> Vec<DeviceEntry> {
+        let mut state = self.inner.lock().unwrap();
+        state.evict(SystemTime::now());
+        state.devices.values().cloned().collect()
+    }
+}
+
+impl TableState {
+    fn evict(&mut self, now: SystemTime) -> Vec<DeviceEntry> {
+        let Some(timeout) = self.eviction_timeout else {
+            return Vec::new();
+        };
+        let mut evicted = Vec::new();
+        self.devices.retain(|_, entry| {
+            let expired = now.duration_since(entry.last_seen).is_ok_and(|x| x > timeout);
+            if expired {
+                evicted.push(entry.clone());
+            }
+            !expired
+        });
+        evicted
+    }
+}
+
+impl DeviceEntry {
+    fn update(&mut self, adv: &AdvertisingDevice, timestamp: SystemTime, smoothing: f64) {
+        self.device = adv.device.clone();
+        self.address_type = adv.address_type.or(self.address_type);
+        merge(&mut self.adv_data, &adv.adv_data, adv.is_scan_response == Some(true));
+        self.first_seen = self.first_seen.min(timestamp);
+        self.last_seen = self.last_seen.max(timestamp);
+        self.packets += 1;
+
+        if let Some(rssi) = adv.rssi {
+            self.rssi = Some(rssi);
+            self.min_rssi = Some(self.min_rssi.map_or(rssi, |x| x.min(rssi)));
+            self.max_rssi = Some(self.max_rssi.map_or(rssi, |x| x.max(rssi)));
+            self.rssi_sum += i64::from(rssi);
+            self.rssi_samples += 1;
+            self.mean_rssi = Some(self.rssi_sum as f64 / self.rssi_samples as f64);
+            let rssi = f64::from(rssi);
+            self.smoothed_rssi = Some(self.smoothed_rssi.map_or(rssi, |x| x + smoothing * (rssi - x)));
+        }
+
+        // Scan responses follow their advertisement immediately, so they would distort the interval estimates
+        if adv.is_scan_response != Some(true) {
+            if let Some(interval) = self
+                .last_advertisement
+                .and_then(|x| timestamp.duration_since(x).ok())
+                .filter(|x| !x.is_zero())
+            {
+                self.min_interval = Some(self.min_interval.map_or(interval, |x| x.min(interval)));
+            }
+            self.advertisements += 1;
+            let first = *self.first_advertisement.get_or_insert(timestamp);
+            self.last_advertisement = Some(timestamp);
+            if self.advertisements > 1 {
+                self.mean_interval = timestamp.duration_since(first).ok().map(|x| {
+                    let nanos = x.as_nanos() / u128::from(self.advertisements - 1);
+                    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+                });
+            }
+        }
+    }
+}
+
+/// Updates `data` with the fields present in `update`.
+///
+/// Only advertising packets, not scan responses, say whether the device is connectable.
+fn merge(data: &mut AdvertisementData, update: &AdvertisementData, is_scan_response: bool) {
+    if update.local_name.is_some() {
+        data.local_name.clone_from(&update.local_name);
+    }
+
+    let updated_companies = update
+        .manufacturer_data
+        .iter()
+        .map(|x| x.company_id)
+        .collect::<Vec<_>>();
+    if !updated_companies.is_empty() {
+        data.manufacturer_data = data
+            .manufacturer_data
+            .iter()
+            .filter(|x| !updated_companies.contains(&x.company_id))
+            .chain(update.manufacturer_data.iter())
+            .cloned()
+            .collect();
+    }
+
+    for uuid in &update.services {
+        if !data.services.contains(uuid) {
+            data.services.push(*uuid);
+        }
+    }
+    for uuid in &update.solicited_services {
+        if !data.solicited_services.contains(uuid) {
+            data.solicited_services.push(*uuid);
+        }
+    }
+    data.service_data
+        .extend(update.service_data.iter().map(|(k, v)| (*k, v.clone())));
+
+    data.tx_power_level = update.tx_power_level.or(data.tx_power_level);
+    if !is_scan_response {
+        data.is_connectable = update.is_connectable;
+    }
+    data.flags = update.flags.or(data.flags);
+    data.appearance = update.appearance.or(data.appearance);
+    data.advertising_interval = update.advertising_interval.or(data.advertising_interval);
+    if update.uri.is_some() {
+        data.uri.clone_from(&update.uri);
+    }
+    if update.le_supported_features.is_some() {
+        data.le_supported_features.clone_from(&update.le_supported_features);
+    }
+
+    for structure in &update.other_structures {
+        match data
+            .other_structures
+            .iter_mut()
+            .find(|x| x.ad_type == structure.ad_type)
+        {
+            Some(x) => x.data.clone_from(&structure.data),
+            None => data.other_structures.push(structure.clone()),
+        }
+    }
+}
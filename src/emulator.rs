@@ -0,0 +1,636 @@
+//! Emulated peripherals for standard profiles
+//!
+//! An [`Emulator`] is a known-good peripheral to test clients against. It serves one or more [`Profile`]s from a GATT
+//! server started with [`Adapter::start_server`], and makes itself discoverable with a connectable advertisement
+//! started with [`Adapter::start_advertising`]. The profiles are:
+//!
+//! - [`Battery`]: the Battery service
+//! - [`DeviceInformation`]: the Device Information service
+//! - [`HeartRate`]: the Heart Rate service
+//! - [`CurrentTime`]: the Current Time service
+//! - [`LedButton`]: the Nordic LED Button service used by the `blinky` example
+//!
+//! The changing values of a profile, such as the battery level, are taken from a [`ValueSource`]. Each value is stored
+//! as the value of its characteristic and sent to the centrals subscribed to it.
+//!
+//! Emulators run wherever GATT servers are supported, which is Linux and the `mock` backend. With the mock backend, a
+//! client scanning on the same virtual radio finds the emulator and can connect to it in-process.
+//!
+//! Value sources work with any asynchronous runtime, but a running emulator updates its values from Tokio tasks, so
+//! [`Emulator::start`] must be called from within a Tokio runtime.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use bluest::emulator::{Battery, DeviceInformation, Emulator, ValueSource};
+//! use bluest::Adapter;
+//!
+//! # async fn example(adapter: Adapter) -> bluest::Result<()> {
+//! let emulator = Emulator::new("Emulated sensor")
+//!     .with_profile(Battery::new(ValueSource::cycle([100, 75, 50, 25], Duration::from_secs(10))))
+//!     .with_profile(DeviceInformation::new().with_manufacturer_name("Bluest"))
+//!     .start(&adapter)
+//!     .await?;
+//!
+//! // The emulator advertises and serves its profiles until it is dropped
+//! tokio::time::sleep(Duration::from_secs(60)).await;
+//! drop(emulator);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use futures_core::Stream;
+use futures_lite::{stream, StreamExt};
+use tokio::task::JoinHandle;
+
+use crate::btuuid::{characteristics, services};
+use crate::error::{AttError, ErrorKind};
+use crate::server::{
+    AttributeId, CharacteristicDefinition, ReadRequest, RequestHandler, Server, ServiceDefinition, WriteRequest,
+};
+use crate::util::Timer;
+use crate::{Adapter, AdvertisementData, AdvertisingGuard, CharacteristicProperties, Error, Result, Uuid};
+
+/// The Nordic LED Button service
+pub const NORDIC_LED_AND_BUTTON_SERVICE: Uuid = Uuid::from_u128(0x00001523_1212_efde_1523_785feabcd123);
+/// The button state characteristic of the Nordic LED Button service
+pub const BLINKY_BUTTON_STATE_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00001524_1212_efde_1523_785feabcd123);
+/// The LED state characteristic of the Nordic LED Button service
+pub const BLINKY_LED_STATE_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00001525_1212_efde_1523_785feabcd123);
+
+/// Characteristic property bits (Core Specification, Vol 3, Part G, §3.3.1.1)
+const READ: u32 = 0x02;
+const WRITE: u32 = 0x08;
+const NOTIFY: u32 = 0x10;
+
+type Updates = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+/// The values of a characteristic of a [`Profile`]
+///
+/// A source has an initial value, followed by a stream of updates. Scripted sources are created with
+/// [`steps`][Self::steps], [`cycle`][Self::cycle] and [`sample`][Self::sample], and any other stream of values, such as
+/// the receiver of a channel, can be used with [`new`][Self::new].
+pub struct ValueSource<T> {
+    initial: T,
+    updates: Pin<Box<dyn Stream<Item = T> + Send>>,
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for ValueSource<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValueSource")
+            .field("initial", &self.initial)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> ValueSource<T> {
+    /// Creates a source which starts with `initial` and then takes each value of `updates`.
+    ///
+    /// The last value is kept once `updates` ends.
+    pub fn new(initial: T, updates: impl Stream<Item = T> + Send + 'static) -> Self {
+        ValueSource {
+            initial,
+            updates: Box::pin(updates),
+        }
+    }
+
+    /// Creates a source which always has `value`.
+    pub fn constant(value: T) -> Self {
+        Self::new(value, stream::empty())
+    }
+
+    /// Creates a source which starts with `initial` and then takes each of `values` in turn, `interval` apart.
+    pub fn steps<I>(initial: T, interval: Duration, values: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::new(initial, delayed(stream::iter(values), interval))
+    }
+
+    /// Creates a source which samples `f` now and then every `interval`.
+    pub fn sample(interval: Duration, mut f: impl FnMut() -> T + Send + 'static) -> Self {
+        let initial = f();
+        let updates = stream::unfold(f, move |mut f| async move {
+            Timer::after(interval).await;
+            Some((f(), f))
+        });
+        Self::new(initial, updates)
+    }
+
+    /// The initial value, and the updates encoded with `encode`.
+    fn encode(self, encode: fn(&T) -> Vec<u8>) -> (Vec<u8>, Updates) {
+        (encode(&self.initial), Box::pin(self.updates.map(move |x| encode(&x))))
+    }
+}
+
+impl<T: Clone + Send + 'static> ValueSource<T> {
+    /// Creates a source which repeats `values` forever, moving to the next value every `interval`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` is empty.
+    pub fn cycle(values: impl IntoIterator<Item = T>, interval: Duration) -> Self {
+        let values = values.into_iter().collect::<Vec<_>>();
+        assert!(!values.is_empty(), "a cycle needs at least one value");
+        let initial = values[0].clone();
+        Self::new(
+            initial,
+            delayed(stream::iter(values.into_iter().cycle().skip(1)), interval),
+        )
+    }
+}
+
+/// Delays each item of `stream` by `interval`.
+fn delayed<S>(stream: S, interval: Duration) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+    S::Item: Send,
+{
+    stream.then(move |x| async move {
+        Timer::after(interval).await;
+        x
+    })
+}
+
+/// A characteristic value of a [`Profile`]
+struct Value {
+    characteristic: Uuid,
+    initial: Vec<u8>,
+    updates: Option<Updates>,
+}
+
+/// A service of an [`Emulator`], with the values of its characteristics
+///
+/// Profiles are created from [`Battery`], [`DeviceInformation`], [`HeartRate`], [`CurrentTime`] and [`LedButton`].
+pub struct Profile {
+    service: ServiceDefinition,
+    values: Vec<Value>,
+}
+
+impl std::fmt::Debug for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Profile")
+            .field("service", &self.service)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Profile {
+    fn new(service: Uuid) -> Self {
+        Profile {
+            service: ServiceDefinition::new(service),
+            values: Vec::new(),
+        }
+    }
+
+    /// Adds a characteristic with a fixed initial value.
+    fn with_value(self, characteristic: Uuid, properties: u32, value: Vec<u8>) -> Self {
+        self.with_characteristic(characteristic, properties, value, None)
+    }
+
+    /// Adds a characteristic whose value is taken from `source`.
+    fn with_source<T: Send + 'static>(
+        self,
+        characteristic: Uuid,
+        properties: u32,
+        source: ValueSource<T>,
+        encode: fn(&T) -> Vec<u8>,
+    ) -> Self {
+        let (initial, updates) = source.encode(encode);
+        self.with_characteristic(characteristic, properties, initial, Some(updates))
+    }
+
+    fn with_characteristic(
+        mut self,
+        characteristic: Uuid,
+        properties: u32,
+        initial: Vec<u8>,
+        updates: Option<Updates>,
+    ) -> Self {
+        let properties = CharacteristicProperties::from_bits(properties);
+        self.service = self
+            .service
+            .with_characteristic(CharacteristicDefinition::new(characteristic, properties));
+        self.values.push(Value {
+            characteristic,
+            initial,
+            updates,
+        });
+        self
+    }
+}
+
+/// The Battery service, whose battery level is taken from a [`ValueSource`]
+#[derive(Debug)]
+pub struct Battery {
+    level: ValueSource<u8>,
+}
+
+impl Battery {
+    /// Creates the service with the battery level in percent from `level`.
+    pub fn new(level: ValueSource<u8>) -> Self {
+        Battery { level }
+    }
+}
+
+impl From<Battery> for Profile {
+    fn from(battery: Battery) -> Self {
+        Profile::new(services::BATTERY).with_source(
+            characteristics::BATTERY_LEVEL,
+            READ | NOTIFY,
+            battery.level,
+            |level| vec![*level],
+        )
+    }
+}
+
+/// The Device Information service, with fixed strings
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeviceInformation {
+    strings: Vec<(Uuid, String)>,
+}
+
+impl DeviceInformation {
+    /// Creates the service without any characteristics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the manufacturer name string.
+    pub fn with_manufacturer_name(self, name: impl Into<String>) -> Self {
+        self.with_string(characteristics::MANUFACTURER_NAME_STRING, name.into())
+    }
+
+    /// Sets the model number string.
+    pub fn with_model_number(self, model: impl Into<String>) -> Self {
+        self.with_string(characteristics::MODEL_NUMBER_STRING, model.into())
+    }
+
+    /// Sets the serial number string.
+    pub fn with_serial_number(self, serial: impl Into<String>) -> Self {
+        self.with_string(characteristics::SERIAL_NUMBER_STRING, serial.into())
+    }
+
+    /// Sets the hardware revision string.
+    pub fn with_hardware_revision(self, revision: impl Into<String>) -> Self {
+        self.with_string(characteristics::HARDWARE_REVISION_STRING, revision.into())
+    }
+
+    /// Sets the firmware revision string.
+    pub fn with_firmware_revision(self, revision: impl Into<String>) -> Self {
+        self.with_string(characteristics::FIRMWARE_REVISION_STRING, revision.into())
+    }
+
+    /// Sets the software revision string.
+    pub fn with_software_revision(self, revision: impl Into<String>) -> Self {
+        self.with_string(characteristics::SOFTWARE_REVISION_STRING, revision.into())
+    }
+
+    fn with_string(mut self, characteristic: Uuid, value: String) -> Self {
+        self.strings.retain(|(x, _)| *x != characteristic);
+        self.strings.push((characteristic, value));
+        self
+    }
+}
+
+impl From<DeviceInformation> for Profile {
+    fn from(information: DeviceInformation) -> Self {
+        information
+            .strings
+            .into_iter()
+            .fold(Profile::new(services::DEVICE_INFORMATION), |profile, (uuid, value)| {
+                profile.with_value(uuid, READ, value.into_bytes())
+            })
+    }
+}
+
+/// The Heart Rate service, whose heart rate measurements are taken from a [`ValueSource`]
+#[derive(Debug)]
+pub struct HeartRate {
+    heart_rate: ValueSource<u16>,
+    body_sensor_location: Option<u8>,
+}
+
+impl HeartRate {
+    /// Creates the service with the heart rate in beats per minute from `heart_rate`.
+    ///
+    /// Measurements are sent in the 8-bit format up to 255 beats per minute and in the 16-bit format above.
+    pub fn new(heart_rate: ValueSource<u16>) -> Self {
+        HeartRate {
+            heart_rate,
+            body_sensor_location: None,
+        }
+    }
+
+    /// Adds the body sensor location characteristic with `location`, which is one of 0 (other), 1 (chest), 2 (wrist),
+    /// 3 (finger), 4 (hand), 5 (ear lobe) and 6 (foot).
+    pub fn with_body_sensor_location(mut self, location: u8) -> Self {
+        self.body_sensor_location = Some(location);
+        self
+    }
+}
+
+impl From<HeartRate> for Profile {
+    fn from(heart_rate: HeartRate) -> Self {
+        let profile = Profile::new(services::HEART_RATE).with_source(
+            characteristics::HEART_RATE_MEASUREMENT,
+            NOTIFY,
+            heart_rate.heart_rate,
+            |&bpm| match u8::try_from(bpm) {
+                Ok(bpm) => vec![0x00, bpm],
+                Err(_) => {
+                    let [a, b] = bpm.to_le_bytes();
+                    vec![0x01, a, b]
+                }
+            },
+        );
+        match heart_rate.body_sensor_location {
+            Some(location) => profile.with_value(characteristics::BODY_SENSOR_LOCATION, READ, vec![location]),
+            None => profile,
+        }
+    }
+}
+
+/// The Current Time service, whose time is taken from a [`ValueSource`]
+#[derive(Debug)]
+pub struct CurrentTime {
+    time: ValueSource<SystemTime>,
+}
+
+impl CurrentTime {
+    /// Creates the service with the time from `time`, which is sent as UTC.
+    pub fn new(time: ValueSource<SystemTime>) -> Self {
+        CurrentTime { time }
+    }
+
+    /// Creates the service with the time of the system clock, which is sent to subscribed centrals every second.
+    pub fn system_clock() -> Self {
+        Self::new(ValueSource::sample(Duration::from_secs(1), SystemTime::now))
+    }
+}
+
+impl From<CurrentTime> for Profile {
+    fn from(current_time: CurrentTime) -> Self {
+        Profile::new(services::CURRENT_TIME).with_source(
+            characteristics::CURRENT_TIME,
+            READ | NOTIFY,
+            current_time.time,
+            exact_time_256,
+        )
+    }
+}
+
+/// Encodes `time` as an Exact Time 256 in UTC, followed by an adjust reason of 0 (GATT Specification Supplement,
+/// §3.62).
+fn exact_time_256(time: &SystemTime) -> Vec<u8> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let days = since_epoch.as_secs() / 86400;
+    let seconds = since_epoch.as_secs() % 86400;
+
+    // Converts days since 1970-01-01 to a civil date (https://howardhinnant.github.io/date_algorithms.html)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    // 1970-01-01 was a Thursday, and Monday is 1
+    let day_of_week = (days + 3) % 7 + 1;
+    let fractions = u64::from(since_epoch.subsec_nanos()) * 256 / 1_000_000_000;
+
+    let [y0, y1] = (year as u16).to_le_bytes();
+    vec![
+        y0,
+        y1,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+        day_of_week as u8,
+        fractions as u8,
+        0,
+    ]
+}
+
+/// The Nordic LED Button service, whose button state is taken from a [`ValueSource`]
+///
+/// Centrals can write the LED state, which is read with [`EmulatorHandle::value`].
+#[derive(Debug)]
+pub struct LedButton {
+    button: ValueSource<bool>,
+    led: bool,
+}
+
+impl LedButton {
+    /// Creates the service with the button state, which is `true` while pressed, from `button`. The LED is off.
+    pub fn new(button: ValueSource<bool>) -> Self {
+        LedButton { button, led: false }
+    }
+
+    /// Sets the initial LED state.
+    pub fn with_led(mut self, on: bool) -> Self {
+        self.led = on;
+        self
+    }
+}
+
+impl From<LedButton> for Profile {
+    fn from(led_button: LedButton) -> Self {
+        Profile::new(NORDIC_LED_AND_BUTTON_SERVICE)
+            .with_source(
+                BLINKY_BUTTON_STATE_CHARACTERISTIC,
+                READ | NOTIFY,
+                led_button.button,
+                |&x| vec![u8::from(x)],
+            )
+            .with_value(
+                BLINKY_LED_STATE_CHARACTERISTIC,
+                READ | WRITE,
+                vec![u8::from(led_button.led)],
+            )
+    }
+}
+
+/// An emulated peripheral, which advertises with a name and serves a set of [`Profile`]s
+#[derive(Debug)]
+pub struct Emulator {
+    name: String,
+    appearance: Option<u16>,
+    profiles: Vec<Profile>,
+}
+
+impl Emulator {
+    /// Creates an emulator advertising with the local name `name` and without any profiles.
+    pub fn new(name: impl Into<String>) -> Self {
+        Emulator {
+            name: name.into(),
+            appearance: None,
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Adds `profile`. Its service is included in the advertisement.
+    pub fn with_profile(mut self, profile: impl Into<Profile>) -> Self {
+        self.profiles.push(profile.into());
+        self
+    }
+
+    /// Sets the appearance included in the advertisement.
+    pub fn with_appearance(mut self, appearance: u16) -> Self {
+        self.appearance = Some(appearance);
+        self
+    }
+
+    /// Starts serving the profiles from `adapter` and advertising them.
+    ///
+    /// The values of the profiles are updated from their sources until the returned handle is dropped.
+    ///
+    /// Returns an error with [`ErrorKind::NotSupported`] if not called from within a Tokio runtime.
+    pub async fn start(self, adapter: &Adapter) -> Result<EmulatorHandle> {
+        let runtime = tokio::runtime::Handle::try_current().map_err(|err| {
+            Error::new(
+                ErrorKind::NotSupported,
+                Some(Box::new(err)),
+                "the emulator must be started from within a Tokio runtime",
+            )
+        })?;
+
+        let database = Database::default();
+        let mut services = Vec::with_capacity(self.profiles.len());
+        let mut updates = Vec::new();
+        for profile in self.profiles {
+            for value in profile.values {
+                let attribute = AttributeId {
+                    service: profile.service.uuid(),
+                    characteristic: value.characteristic,
+                    descriptor: None,
+                };
+                database.set(attribute, value.initial);
+                updates.extend(value.updates.map(|x| (attribute, x)));
+            }
+            services.push(profile.service);
+        }
+
+        let advertisement = AdvertisementData {
+            local_name: Some(self.name),
+            services: services.iter().map(ServiceDefinition::uuid).collect(),
+            is_connectable: Some(true),
+            appearance: self.appearance,
+            ..Default::default()
+        };
+        let server = Arc::new(adapter.start_server(services, database.clone()).await?);
+        let advertising = adapter.start_advertising(advertisement).await?;
+
+        let tasks = updates
+            .into_iter()
+            .map(|(attribute, updates)| {
+                runtime.spawn(update(database.clone(), Arc::downgrade(&server), attribute, updates))
+            })
+            .collect();
+
+        Ok(EmulatorHandle {
+            database,
+            server,
+            _advertising: advertising,
+            tasks,
+        })
+    }
+}
+
+/// Stores each value of `updates` and sends it to the centrals subscribed to `attribute`.
+async fn update(database: Database, server: Weak<Server>, attribute: AttributeId, mut updates: Updates) {
+    while let Some(value) = updates.next().await {
+        database.set(attribute, value.clone());
+        let Some(server) = server.upgrade() else { return };
+        if let Err(err) = server.notify(attribute.service, attribute.characteristic, &value).await {
+            tracing::warn!("failed to notify {}: {:?}", attribute.characteristic, err);
+        }
+    }
+}
+
+/// A running [`Emulator`], which stops when dropped
+#[derive(Debug)]
+pub struct EmulatorHandle {
+    database: Database,
+    server: Arc<Server>,
+    _advertising: AdvertisingGuard,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl EmulatorHandle {
+    /// The current value of `characteristic` of `service`, including values written by centrals.
+    pub fn value(&self, service: Uuid, characteristic: Uuid) -> Option<Vec<u8>> {
+        self.database.get(AttributeId {
+            service,
+            characteristic,
+            descriptor: None,
+        })
+    }
+
+    /// The GATT server of the emulator, which reports the centrals using it.
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+}
+
+/// The characteristic values of an emulator, which answers the requests of centrals
+#[derive(Debug, Default, Clone)]
+struct Database(Arc<Mutex<HashMap<AttributeId, Vec<u8>>>>);
+
+impl Database {
+    fn get(&self, attribute: AttributeId) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(&attribute).cloned()
+    }
+
+    fn set(&self, attribute: AttributeId, value: Vec<u8>) {
+        self.0.lock().unwrap().insert(attribute, value);
+    }
+}
+
+#[async_trait]
+impl RequestHandler for Database {
+    async fn read(&self, request: ReadRequest) -> Result<Vec<u8>, AttError> {
+        let values = self.0.lock().unwrap();
+        let value = values.get(&request.attribute).ok_or(AttError::ATTRIBUTE_NOT_FOUND)?;
+        value
+            .get(usize::from(request.offset)..)
+            .map(<[u8]>::to_vec)
+            .ok_or(AttError::INVALID_OFFSET)
+    }
+
+    /// Replaces the value of the attribute, whose length is fixed.
+    async fn write(&self, request: WriteRequest) -> Result<(), AttError> {
+        let mut values = self.0.lock().unwrap();
+        let value = values
+            .get_mut(&request.attribute)
+            .ok_or(AttError::ATTRIBUTE_NOT_FOUND)?;
+        if request.offset != 0 {
+            Err(AttError::INVALID_OFFSET)
+        } else if request.value.len() != value.len() {
+            Err(AttError::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        } else {
+            *value = request.value;
+            Ok(())
+        }
+    }
+}
@@ -60,6 +60,8 @@ pub enum ErrorKind {
     AdapterUnavailable,
     /// the Bluetooth adapter is already scanning
     AlreadyScanning,
+    /// the scan was stopped by the system
+    ScanStopped,
     /// connection failed
     ConnectionFailed,
     /// the Bluetooth device isn't connected
@@ -91,6 +93,7 @@ impl std::fmt::Display for ErrorKind {
         match self {
             ErrorKind::AdapterUnavailable => f.write_str("the Bluetooth adapter is not available"),
             ErrorKind::AlreadyScanning => f.write_str("the Bluetooth adapter is already scanning"),
+            ErrorKind::ScanStopped => f.write_str("the scan was stopped by the system"),
             ErrorKind::ConnectionFailed => f.write_str("connection failed"),
             ErrorKind::NotConnected => f.write_str("the Bluetooth device isn't connected"),
             ErrorKind::NotSupported => f.write_str("the Bluetooth operation is unsupported"),
@@ -117,6 +120,25 @@ impl From<ErrorKind> for Error {
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        use std::io::ErrorKind as IoErrorKind;
+
+        let kind = match err.kind {
+            ErrorKind::ConnectionFailed => IoErrorKind::ConnectionAborted,
+            ErrorKind::NotConnected => IoErrorKind::NotConnected,
+            ErrorKind::NotSupported => IoErrorKind::Unsupported,
+            ErrorKind::NotAuthorized => IoErrorKind::PermissionDenied,
+            ErrorKind::NotReady => IoErrorKind::WouldBlock,
+            ErrorKind::NotFound => IoErrorKind::NotFound,
+            ErrorKind::InvalidParameter => IoErrorKind::InvalidInput,
+            ErrorKind::Timeout => IoErrorKind::TimedOut,
+            _ => IoErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
 /// Bluetooth Attribute Protocol error. See the Bluetooth Core Specification, Vol 3, Part F, §3.4.1.1
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AttError(u8);
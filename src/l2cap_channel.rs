@@ -1,27 +1,60 @@
 #![cfg(feature = "l2cap")]
 
-use crate::{sys, Result};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll, Waker};
+use std::{fmt, io};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_sink::Sink;
+
+use crate::error::ErrorKind;
+use crate::{sys, Error, Result};
 
 /// A Bluetooth LE L2CAP Connection-oriented Channel (CoC)
+///
+/// Besides reading and writing individual packets, the channel can be used as a byte stream with [`AsyncRead`] and
+/// [`AsyncWrite`], or as a [`Stream`] and [`Sink`] of packets. With the `tokio` feature, it also implements the
+/// `AsyncRead` and `AsyncWrite` traits of tokio.
 #[derive(Debug)]
 pub struct L2capChannel {
-    pub(crate) reader: sys::l2cap_channel::L2capChannelReader,
-    pub(crate) writer: sys::l2cap_channel::L2capChannelWriter,
+    reader: L2capChannelReader,
+    writer: L2capChannelWriter,
 }
 
 /// Reader half of a L2CAP Connection-oriented Channel (CoC)
-#[derive(Debug)]
 pub struct L2capChannelReader {
     reader: sys::l2cap_channel::L2capChannelReader,
+    /// The last packet received by [`AsyncRead::poll_read`], of which `buf[start..end]` has not been read yet
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
 }
 
 /// Writerhalf of a L2CAP Connection-oriented Channel (CoC)
-#[derive(Debug)]
 pub struct L2capChannelWriter {
     writer: sys::l2cap_channel::L2capChannelWriter,
+    /// The packet passed to [`Sink::start_send`] or [`AsyncWrite::poll_write`] which has not been written yet
+    pending: Option<Vec<u8>>,
 }
 
 impl L2capChannel {
+    pub(crate) fn new(
+        reader: sys::l2cap_channel::L2capChannelReader,
+        writer: sys::l2cap_channel::L2capChannelWriter,
+    ) -> Self {
+        L2capChannel {
+            reader: L2capChannelReader {
+                reader,
+                buf: Vec::new(),
+                start: 0,
+                end: 0,
+            },
+            writer: L2capChannelWriter { writer, pending: None },
+        }
+    }
+
     /// Read a packet from the L2CAP channel.
     ///
     /// The packet is written to the start of `buf`, and the packet length is returned.
@@ -36,6 +69,18 @@ impl L2capChannel {
         self.writer.write(packet).await
     }
 
+    /// The maximum size of a packet which can be read from the L2CAP channel.
+    #[inline]
+    pub fn read_mtu(&self) -> Result<usize> {
+        self.reader.mtu()
+    }
+
+    /// The maximum size of a packet which can be written to the L2CAP channel.
+    #[inline]
+    pub fn write_mtu(&self) -> Result<usize> {
+        self.writer.mtu()
+    }
+
     /// Close the L2CAP channel.
     ///
     /// This closes the entire channel, in both directions (reading and writing).
@@ -50,18 +95,21 @@ impl L2capChannel {
     /// Split the channel into read and write halves.
     #[inline]
     pub fn split(self) -> (L2capChannelReader, L2capChannelWriter) {
-        let Self { reader, writer } = self;
-        (L2capChannelReader { reader }, L2capChannelWriter { writer })
+        (self.reader, self.writer)
     }
 }
 
 impl L2capChannelReader {
     /// Read a packet from the L2CAP channel.
     ///
-    /// The packet is written to the start of `buf`, and the packet length is returned.
+    /// The packet is written to the start of `buf`, and the packet length is returned. If a packet was partially read
+    /// with [`AsyncRead`], the rest of that packet is returned first.
     #[inline]
     pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.reader.read(buf).await
+        match self.take_pending(buf) {
+            Some(res) => res,
+            None => self.reader.read(buf).await,
+        }
     }
 
     /// Try reading a packet from the L2CAP channel.
@@ -71,7 +119,16 @@ impl L2capChannelReader {
     /// If no packet is immediately available for reading, this returns an error with kind `NotReady`.
     #[inline]
     pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.reader.try_read(buf)
+        match self.take_pending(buf) {
+            Some(res) => res,
+            None => self.reader.try_read(buf),
+        }
+    }
+
+    /// The maximum size of a packet which can be read from the L2CAP channel.
+    #[inline]
+    pub fn mtu(&self) -> Result<usize> {
+        self.reader.mtu()
     }
 
     /// Close the L2CAP channel.
@@ -84,6 +141,85 @@ impl L2capChannelReader {
     pub async fn close(&mut self) -> Result<()> {
         self.reader.close().await
     }
+
+    /// Copies the unread part of the last packet to `buf`, if there is one.
+    fn take_pending(&mut self, buf: &mut [u8]) -> Option<Result<usize>> {
+        let len = self.end - self.start;
+        if len == 0 {
+            None
+        } else if len > buf.len() {
+            Some(Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                "Buffer is too small",
+            )))
+        } else {
+            buf[..len].copy_from_slice(&self.buf[self.start..self.end]);
+            self.start = self.end;
+            Some(Ok(len))
+        }
+    }
+
+    /// Returns the unread part of the last packet, receiving a new packet if it has been read completely.
+    ///
+    /// An empty packet is returned at the end of the channel.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        if self.start == self.end {
+            if self.buf.is_empty() {
+                self.buf = vec![0; self.reader.mtu()?];
+            }
+            self.end = ready!(self.reader.poll_read(cx, &mut self.buf))?;
+            self.start = 0;
+        }
+        Poll::Ready(Ok(&self.buf[self.start..self.end]))
+    }
+}
+
+impl fmt::Debug for L2capChannelReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("L2capChannelReader").field(&self.reader).finish()
+    }
+}
+
+impl AsyncRead for L2capChannelReader {
+    /// Reads bytes from the channel, ignoring packet boundaries.
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let packet = ready!(this.poll_fill(cx))?;
+        let len = packet.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet[..len]);
+        this.start += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl Stream for L2capChannelReader {
+    type Item = Result<Vec<u8>>;
+
+    /// Receives the next packet, ending at the end of the channel.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let packet = match ready!(this.poll_fill(cx)) {
+            Ok([]) => return Poll::Ready(None),
+            Ok(packet) => packet.to_vec(),
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        };
+        this.start = this.end;
+        Poll::Ready(Some(Ok(packet)))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for L2capChannelReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let len = ready!(AsyncRead::poll_read(self, cx, buf.initialize_unfilled()))?;
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
 }
 
 impl L2capChannelWriter {
@@ -92,6 +228,7 @@ impl L2capChannelWriter {
     /// If the buffer is full, this will wait until there's buffer space for the packet.
     #[inline]
     pub async fn write(&mut self, packet: &[u8]) -> Result<()> {
+        poll_fn(|cx| self.poll_write_pending(cx)).await?;
         self.writer.write(packet).await
     }
 
@@ -100,9 +237,19 @@ impl L2capChannelWriter {
     /// If there's no buffer space, this returns an error with kind `NotReady`.
     #[inline]
     pub fn try_write(&mut self, packet: &[u8]) -> Result<()> {
+        match self.poll_write_pending(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(res) => res?,
+            Poll::Pending => return Err(Error::new(ErrorKind::NotReady, None, "No buffer space for write")),
+        }
         self.writer.try_write(packet)
     }
 
+    /// The maximum size of a packet which can be written to the L2CAP channel.
+    #[inline]
+    pub fn mtu(&self) -> Result<usize> {
+        self.writer.mtu()
+    }
+
     /// Close the L2CAP channel.
     ///
     /// This closes the entire channel, not just the write half.
@@ -113,4 +260,173 @@ impl L2capChannelWriter {
     pub async fn close(&mut self) -> Result<()> {
         self.writer.close().await
     }
+
+    /// Writes the pending packet, if there is one, so that packets are written in order.
+    ///
+    /// The backends are only polled with the pending packet, which doesn't change until it has been written.
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(packet) = &self.pending {
+            let res = ready!(self.writer.poll_write(cx, packet));
+            self.pending = None;
+            res?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for L2capChannelWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("L2capChannelWriter").field(&self.writer).finish()
+    }
+}
+
+impl AsyncWrite for L2capChannelWriter {
+    /// Writes a packet of up to [`mtu`][Self::mtu] bytes of `buf` to the channel.
+    ///
+    /// The packet may still be pending when this returns, in which case it is written by the next write or flush.
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        ready!(this.poll_write_pending(cx))?;
+        let len = buf.len().min(this.writer.mtu()?);
+        this.pending = Some(buf[..len].to_vec());
+        if let Poll::Ready(Err(err)) = this.poll_write_pending(cx) {
+            return Poll::Ready(Err(err.into()));
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_write_pending(cx).map_err(Into::into)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        this.writer.poll_close(cx).map_err(Into::into)
+    }
+}
+
+impl<T: AsRef<[u8]>> Sink<T> for L2capChannelWriter {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_write_pending(cx)
+    }
+
+    /// Queues a packet to be written to the channel.
+    ///
+    /// Returns an error with kind `InvalidParameter` if the packet is larger than the [`mtu`][Self::mtu].
+    fn start_send(self: Pin<&mut Self>, packet: T) -> Result<()> {
+        let this = self.get_mut();
+        let packet = packet.as_ref();
+        let mtu = this.writer.mtu()?;
+        if packet.len() > mtu {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!("packet of {} bytes is larger than the MTU of {mtu} bytes", packet.len()),
+            ));
+        }
+        this.pending = Some(packet.to_vec());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_write_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        this.writer.poll_close(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for L2capChannelWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(self, cx)
+    }
+}
+
+impl AsyncRead for L2capChannel {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        AsyncRead::poll_read(Pin::new(&mut self.get_mut().reader), cx, buf)
+    }
+}
+
+impl AsyncWrite for L2capChannel {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.get_mut().writer), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().writer), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(Pin::new(&mut self.get_mut().writer), cx)
+    }
+}
+
+impl Stream for L2capChannel {
+    type Item = Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().reader).poll_next(cx)
+    }
+}
+
+impl<T: AsRef<[u8]>> Sink<T> for L2capChannel {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<T>::poll_ready(Pin::new(&mut self.get_mut().writer), cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, packet: T) -> Result<()> {
+        Pin::new(&mut self.get_mut().writer).start_send(packet)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<T>::poll_flush(Pin::new(&mut self.get_mut().writer), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Sink::<T>::poll_close(Pin::new(&mut self.get_mut().writer), cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for L2capChannel {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncRead::poll_read(Pin::new(&mut self.get_mut().reader), cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for L2capChannel {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(self, cx)
+    }
 }
@@ -5,7 +5,8 @@
 //!
 //! The goal of Bluest is to create a *thin* abstraction on top of the platform-specific Bluetooth APIs in order to
 //! provide safe, cross-platform access to Bluetooth LE devices. The crate currently supports the GAP Central and
-//! GATT Client roles. Peripheral and Server roles are not supported.
+//! GATT Client roles on all platforms. The GAP Peripheral role is supported by advertising, and the GATT Server role
+//! on Linux.
 //!
 //! [Rust]: https://www.rust-lang.org/
 //! [Bluetooth Low Energy]: https://www.bluetooth.com/specifications/specs/
@@ -45,6 +46,11 @@
 //!
 //! - Device discovery:
 //!   - [Scanning][Adapter::scan] for devices and receiving advertisements
+//!   - Detecting [lost devices][Adapter::scan_events] which are no longer heard
+//!   - [Sharing][ScanBroker] one scan between several subscribers
+//!   - [Aggregating][DeviceTable] scan results with per-device statistics
+//!   - [Monitoring][presence] devices entering and leaving regions
+//!   - [Offloading][Adapter::monitor_advertisements] advertisement matching to the controller on Linux
 //!   - Finding [connected devices][Adapter::connected_devices]
 //!   - [Opening][Adapter::open_device] previously found devices
 //!   - [Connecting][Adapter::connect_device] to discovered devices
@@ -57,6 +63,13 @@
 //!     [write without response][Characteristic::write_without_response]), and
 //!     [notify/indicate][Characteristic::notify] operations on remote characteristics
 //!   - [Read][Descriptor::read] and [write][Descriptor::write] operations on characteristic descriptors
+//! - [Advertising][Adapter::start_advertising] as a connectable peripheral or a broadcaster
+//! - Serving a local GATT database to connected centrals with a [GATT server][server] on Linux
+//! - [Emulating][emulator] Battery, Device Information, Heart Rate, Current Time and Nordic LED Button peripherals to
+//!   test clients against
+//! - Decoding and broadcasting iBeacon, AltBeacon and Eddystone [beacons][beacon]
+//! - Estimating the [distance][proximity] of devices from their signal strength
+//! - Resolving the identities of devices advertising with resolvable private addresses (with the `privacy` feature)
 //!
 //! # Asynchronous runtimes
 //!
@@ -79,12 +92,14 @@
 //!|----------------------------------------------------------|:---------:|:-------:|:-----:|
 //!| [`Adapter::connect_device`][Adapter::connect_device]                     | ✅ | ✨ | ✅ |
 //!| [`Adapter::disconnect_device`][Adapter::disconnect_device]               | ✅ | ✨ | ✅ |
+//!| [`Adapter::monitor_advertisements`][Adapter::monitor_advertisements]     | ❌ | ❌ | ✅ |
+//!| [`Adapter::start_server`][Adapter::start_server]                         | ❌ | ❌ | ✅ |
 //!| [`Device::name`][Device::name]                                           | ✅ | ✅ | ⌛️ |
 //!| [`Device::is_paired`][Device::is_paired]                                 | ❌ | ✅ | ✅ |
 //!| [`Device::pair`][Device::pair]                                           | ✨ | ✅ | ✅ |
 //!| [`Device::pair_with_agent`][Device::pair_with_agent]                     | ✨ | ✅ | ✅ |
 //!| [`Device::unpair`][Device::unpair]                                       | ❌ | ✅ | ✅ |
-//!| [`Device::rssi`][Device::rssi]                                           | ✅ | ❌ | ❌ |
+//!| [`Device::rssi`][Device::rssi]                                           | ✅ | ❌ | ✅ |
 //!| [`Service::uuid`][Service::uuid]                                         | ✅ | ✅ | ⌛️ |
 //!| [`Service::is_primary`][Service::is_primary]                             | ✅ | ❌ | ✅ |
 //!| [`Characteristic::uuid`][Characteristic::uuid]                           | ✅ | ✅ | ⌛️ |
@@ -107,6 +122,22 @@
 //! The `serde` feature is available to enable serializing/deserializing device
 //! identifiers.
 //!
+//! The `regex` feature enables matching device names with regular expressions in a [`ScanFilter`].
+//!
+//! The `l2cap` feature enables L2CAP connection-oriented channels with `Device::open_l2cap_channel`. It is stable
+//! on Linux; on Android and iOS/MacOS it also requires the `unstable` feature, and it is not available on Windows.
+//! Channels implement the `AsyncRead` and `AsyncWrite` traits of the `futures` crate, and with the `tokio` feature also
+//! those of tokio.
+//!
+//! The `mock` feature replaces the platform backend with an in-process virtual radio. Peripherals, their
+//! advertisements and their GATT databases are declared with the types in the `mock` module and are then accessed
+//! through the normal [`Adapter`] APIs. This allows code built on Bluest to be tested without Bluetooth hardware.
+//! GATT servers and [emulators][emulator] are served by the peripherals which adapters on the same radio advertise, so
+//! clients and servers can be tested against each other in-process.
+//!
+//! The `privacy` feature enables the `privacy` module, which resolves resolvable private addresses to the identities
+//! of bonded devices. It pulls in the `aes` crate.
+//!
 //! # Examples
 //!
 //! Examples demonstrating basic usage are available in the [examples folder].
@@ -114,13 +145,24 @@
 //! [examples folder]: https://github.com/alexmoon/bluest/tree/master/bluest/examples
 
 mod adapter;
+pub mod adv;
+pub mod beacon;
 pub mod btuuid;
 mod characteristic;
 mod descriptor;
 mod device;
+mod device_table;
+pub mod emulator;
 pub mod error;
 mod l2cap_channel;
 pub mod pairing;
+pub mod presence;
+#[cfg(feature = "privacy")]
+pub mod privacy;
+pub mod proximity;
+mod scan_broker;
+mod scan_filter;
+pub mod server;
 mod service;
 mod util;
 
@@ -130,39 +172,26 @@ compile_error!("Android support is unstable and requires the 'unstable' feature
 #[cfg(all(windows, feature = "l2cap"))]
 compile_error!("L2CAP support is not available on Windows");
 
-#[cfg(all(feature = "l2cap", not(feature = "unstable")))]
+#[cfg(all(feature = "l2cap", not(feature = "unstable"), not(target_os = "linux")))]
 compile_error!("L2CAP support is unstable and requires the 'unstable' feature to be enabled");
 
-mod advertisement; // Ensure advertisement.rs is part of the module tree
-pub use advertisement::Advertisement;
-#[cfg(target_os = "linux")]
-use bluer::adapter::AdapterImpl;
-#[cfg(target_os = "linux")]
-use bluer::advertisement::AdvertisementImpl;
-#[cfg(target_os = "windows")]
-use windows::adapter::AdapterImpl;
-#[cfg(target_os = "windows")]
-use windows_advertisement::AdvertisementImpl; // Re-export Advertisement for project-wide access
-// Conditionally include platform-specific modules
-
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", not(feature = "mock")))]
 mod android;
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
 mod bluer;
 
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "mock")))]
 mod corebluetooth;
 
-#[cfg(target_os = "windows")]
-mod windows;
-
-#[cfg(target_os = "windows")]
-#[path = "windows/advertisement.rs"]
-mod windows_advertisement;
-
+#[cfg(feature = "mock")]
+pub mod mock;
 
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
+mod windows;
 
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::{Duration, SystemTime};
 
 #[cfg(target_os = "linux")]
 pub use ::bluer::Uuid;
@@ -171,29 +200,37 @@ pub use btuuid::BluetoothUuidExt;
 pub use characteristic::Characteristic;
 pub use descriptor::Descriptor;
 pub use device::{Device, ServicesChanged};
+pub use device_table::{DeviceEntry, DeviceTable};
 pub use error::Error;
 #[cfg(feature = "l2cap")]
 pub use l2cap_channel::{L2capChannel, L2capChannelReader, L2capChannelWriter};
+pub use scan_broker::{ScanBroker, ScanSubscription};
+pub use scan_filter::ScanFilter;
 pub use service::Service;
 pub use sys::DeviceId;
 #[cfg(not(target_os = "linux"))]
 pub use uuid::Uuid;
-#[cfg(target_os = "android")]
-pub use crate::android::advertisement::AdvertisementImpl;
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", not(feature = "mock")))]
 use crate::android as sys;
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "android", not(feature = "mock")))]
+use crate::android::advertisement::AdvertisementImpl;
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
 use crate::bluer as sys;
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
+use crate::bluer::advertisement::AdvertisementImpl;
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "mock")))]
 use crate::corebluetooth as sys;
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "mock")))]
 use crate::corebluetooth::advertisement::AdvertisementImpl;
-#[cfg(target_os = "android")]
-pub use android::advertisement::AdvertisementImpl;
-
-#[cfg(target_os = "windows")]
+#[cfg(feature = "mock")]
+use crate::mock as sys;
+#[cfg(feature = "mock")]
+use crate::mock::advertisement::AdvertisementImpl;
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
 use crate::windows as sys;
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
+use crate::windows::advertisement::AdvertisementImpl;
 
 /// Convenience alias for a result with [`Error`]
 pub type Result<T, E = Error> = core::result::Result<T, E>;
@@ -225,23 +262,185 @@ pub struct AdvertisingDevice {
     pub adv_data: AdvertisementData,
     /// The signal strength in dBm of the received advertisement packet
     pub rssi: Option<i16>,
+    /// The time at which the advertisement packet was received
+    pub timestamp: Option<SystemTime>,
+    /// The type of the address the device is advertising with
+    pub address_type: Option<AddressType>,
+    /// The PHY on which the primary advertising channel packet was received
+    pub primary_phy: Option<Phy>,
+    /// The PHY of the auxiliary packets of an extended advertisement
+    pub secondary_phy: Option<Phy>,
+    /// The advertising set identifier (SID) of an extended advertisement
+    pub advertising_sid: Option<u8>,
+    /// Set to true if the data was received in a scan response rather than an advertising packet
+    pub is_scan_response: Option<bool>,
 }
 
-/// Data included in a Bluetooth advertisement or scan reponse.
+/// Options of a scan started with [`Adapter::scan_with_options`].
+///
+/// The default options match [`Adapter::scan`]: an active scan using the platform's duplicate handling and timing,
+/// which runs until the stream is dropped.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Whether scan requests are sent to scannable advertisers
+    pub mode: ScanMode,
+    /// How repeated advertisements from the same device are reported
+    pub duplicates: DuplicatePolicy,
+    /// A hint for the time from the start of one scan window to the start of the next
+    pub interval: Option<Duration>,
+    /// A hint for how long the radio listens in each scan interval
+    pub window: Option<Duration>,
+    /// How long until the scan stops and the stream ends
+    pub duration: Option<Duration>,
+    /// How many advertisements are returned before the scan stops and the stream ends
+    pub max_results: Option<usize>,
+    /// How long a device can go unheard before [`Adapter::scan_events`] reports it lost
+    pub lost_timeout: Option<Duration>,
+}
+
+/// Events generated by [`Adapter::scan_events`]
+// Almost every event is an advertisement, so boxing it would only add an allocation per event
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanEvent {
+    /// An advertisement was received
+    Advertisement(AdvertisingDevice),
+    /// A device which was reported has not been heard for [`ScanOptions::lost_timeout`], or the platform has forgotten
+    /// it. It is reported again when it is next heard.
+    DeviceLost(DeviceId),
+}
+
+/// Whether a scan requests additional data from advertisers.
+#[non_exhaustive]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScanMode {
+    /// Send scan requests to scannable advertisers and report their scan responses
+    #[default]
+    Active,
+    /// Only listen for advertisements without sending scan requests
+    Passive,
+}
+
+/// Which advertisements a scan reports when a device advertises repeatedly.
+#[non_exhaustive]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DuplicatePolicy {
+    /// Leave it to the platform, which may report only some of the repeated advertisements
+    #[default]
+    PlatformDefault,
+    /// Report every advertisement received
+    ReportAll,
+    /// Report an advertisement only if its data differs from the last one reported for the device
+    ReportChanges,
+}
+
+/// A pattern matched against the AD structures of advertisements by [`Adapter::monitor_advertisements`].
+///
+/// An AD structure matches if its AD type is `ad_type` and its data contains `content` at `start_position`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MonitorPattern {
+    /// The AD type of the structures to match, see [`adv::ad_types`]
+    pub ad_type: u8,
+    /// The offset into the AD data at which `content` must appear
+    pub start_position: u8,
+    /// The bytes to match, at most 31
+    pub content: Vec<u8>,
+}
+
+impl MonitorPattern {
+    /// Creates a pattern matching `content` at `start_position` in AD structures of type `ad_type`.
+    pub fn new(ad_type: u8, start_position: u8, content: impl Into<Vec<u8>>) -> Self {
+        MonitorPattern {
+            ad_type,
+            start_position,
+            content: content.into(),
+        }
+    }
+}
+
+/// Options of an advertisement monitor started with [`Adapter::monitor_advertisements`].
+///
+/// A device is found once its signal has been at least `rssi_high_threshold` for `rssi_high_timeout`, and lost once
+/// it has been weaker than `rssi_low_threshold` for `rssi_low_timeout`. The platform defaults are used for the options
+/// which are not set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MonitorOptions {
+    /// The signal strength in dBm above which a device is in range, from -127 to 20
+    pub rssi_high_threshold: Option<i16>,
+    /// The signal strength in dBm below which a device is out of range, from -127 to 20
+    pub rssi_low_threshold: Option<i16>,
+    /// How long the signal must stay above the high threshold before a device is found, from 1 to 300 seconds
+    pub rssi_high_timeout: Option<Duration>,
+    /// How long the signal must stay below the low threshold before a device is lost, from 1 to 300 seconds
+    pub rssi_low_timeout: Option<Duration>,
+    /// How often the advertisements of devices in range update their properties, in multiples of 100ms. Zero updates
+    /// them on every advertisement.
+    pub rssi_sampling_period: Option<Duration>,
+}
+
+/// Events generated by [`Adapter::monitor_advertisements`]
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorEvent {
+    /// A device advertising data which matches the patterns has come into range
+    DeviceFound(AdvertisingDevice),
+    /// A device which was found has gone out of range, with the last advertisement received from it
+    DeviceLost(AdvertisingDevice),
+}
+
+/// The type of a Bluetooth LE device address. See the Bluetooth Core Specification, Vol 6, Part B, §1.3 for details.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressType {
+    /// A public device address
+    Public,
+    /// A random device address (static, resolvable private or non-resolvable private)
+    Random,
+}
+
+/// A Bluetooth LE physical layer. See the Bluetooth Core Specification, Vol 6, Part A, §2 for details.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phy {
+    /// The LE 1M PHY
+    Le1M,
+    /// The LE 2M PHY
+    Le2M,
+    /// The LE Coded PHY
+    LeCoded,
+}
+
+/// Data included in a Bluetooth advertisement or scan reponse.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct AdvertisementData {
     /// The (possibly shortened) local name of the device (CSS §A.1.2)
     pub local_name: Option<String>,
-    /// Manufacturer specific data (CSS §A.1.4)
-    pub manufacturer_data: Option<ManufacturerData>,
+    /// Manufacturer specific data (CSS §A.1.4), one entry for each AD structure
+    pub manufacturer_data: ManufacturerDataList,
     /// Advertised GATT service UUIDs (CSS §A.1.1)
     pub services: Vec<Uuid>,
     /// Service associated data (CSS §A.1.11)
     pub service_data: HashMap<Uuid, Vec<u8>>,
     /// Transmitted power level (CSS §A.1.5)
     pub tx_power_level: Option<i16>,
-    /// Set to true for connectable advertising packets
-    pub is_connectable: bool,
+    /// Whether the advertising packets are connectable, or `None` if the platform does not report it
+    ///
+    /// BlueZ does not report the advertising packet type, so this is always `None` for advertisements received on Linux.
+    /// When advertising, only `Some(true)` selects connectable advertising.
+    pub is_connectable: Option<bool>,
+    /// Flags (CSS §A.1.3). See [`adv::flags`] for the defined bits.
+    pub flags: Option<u8>,
+    /// GATT service UUIDs the device solicits (CSS §A.1.10)
+    pub solicited_services: Vec<Uuid>,
+    /// The external appearance of the device (CSS §A.1.12)
+    pub appearance: Option<u16>,
+    /// The interval between advertisements (CSS §A.1.15)
+    pub advertising_interval: Option<Duration>,
+    /// A URI (CSS §A.1.18)
+    pub uri: Option<String>,
+    /// LE supported features, as a bit mask in little-endian order (CSS §A.1.19)
+    pub le_supported_features: Option<Vec<u8>>,
+    /// AD structures which are not represented by any other field, in the order they were received
+    pub other_structures: Vec<adv::AdStructure>,
 }
 
 /// Manufacturer specific data included in Bluetooth advertisements. See the Bluetooth Core Specification Supplement
@@ -254,6 +453,95 @@ pub struct ManufacturerData {
     pub data: Vec<u8>,
 }
 
+/// The manufacturer specific data entries of an advertisement, in the order they were received
+///
+/// An advertisement may contain manufacturer specific data for several companies, and data for the same company may be
+/// split between the advertisement and its scan response. Use [`get`][Self::get] to look up the data for a single company
+/// and [`first`][slice::first] for the first entry regardless of company.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ManufacturerDataList(Vec<ManufacturerData>);
+
+impl ManufacturerDataList {
+    /// Creates an empty list
+    pub const fn new() -> Self {
+        ManufacturerDataList(Vec::new())
+    }
+
+    /// The data of the first entry for `company_id`, if any
+    pub fn get(&self, company_id: u16) -> Option<&[u8]> {
+        self.get_all(company_id).next()
+    }
+
+    /// The data of every entry for `company_id`
+    pub fn get_all(&self, company_id: u16) -> impl Iterator<Item = &[u8]> + '_ {
+        self.0
+            .iter()
+            .filter(move |x| x.company_id == company_id)
+            .map(|x| x.data.as_slice())
+    }
+
+    /// Appends an entry to the list
+    pub fn push(&mut self, data: ManufacturerData) {
+        self.0.push(data);
+    }
+}
+
+impl std::ops::Deref for ManufacturerDataList {
+    type Target = [ManufacturerData];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<ManufacturerData> for ManufacturerDataList {
+    fn from(data: ManufacturerData) -> Self {
+        ManufacturerDataList(vec![data])
+    }
+}
+
+impl From<Option<ManufacturerData>> for ManufacturerDataList {
+    fn from(data: Option<ManufacturerData>) -> Self {
+        data.into_iter().collect()
+    }
+}
+
+impl From<Vec<ManufacturerData>> for ManufacturerDataList {
+    fn from(data: Vec<ManufacturerData>) -> Self {
+        ManufacturerDataList(data)
+    }
+}
+
+impl FromIterator<ManufacturerData> for ManufacturerDataList {
+    fn from_iter<T: IntoIterator<Item = ManufacturerData>>(iter: T) -> Self {
+        ManufacturerDataList(iter.into_iter().collect())
+    }
+}
+
+impl Extend<ManufacturerData> for ManufacturerDataList {
+    fn extend<T: IntoIterator<Item = ManufacturerData>>(&mut self, iter: T) {
+        self.0.extend(iter);
+    }
+}
+
+impl IntoIterator for ManufacturerDataList {
+    type Item = ManufacturerData;
+    type IntoIter = std::vec::IntoIter<ManufacturerData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ManufacturerDataList {
+    type Item = &'a ManufacturerData;
+    type IntoIter = std::slice::Iter<'a, ManufacturerData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 /// GATT characteristic properties as defined in the Bluetooth Core Specification, Vol 3, Part G, §3.3.1.1.
 /// Extended properties are also included as defined in §3.3.3.1.
 #[allow(missing_docs)]
@@ -308,27 +596,39 @@ impl CharacteristicProperties {
     }
 }
 
-/// Represents a guard for advertisements that stops advertisements when dropped.
-// pub struct AdvertisingGuard {
-//     /// the actual advertisment
-//     pub advertisement: AdvertisementImpl,
-// }
-
-// impl Drop for AdvertisingGuard {
-//     fn drop(&mut self) {
-//         let _ = self.advertisement.stop_advertising();
-//     }
-// }
+/// Per-set parameters of an advertisement started with [`Adapter::start_advertising_with_parameters`].
+///
+/// Fields left as `None` use the platform defaults.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AdvertisingParameters {
+    /// The range of intervals the controller may send the advertisement at.
+    ///
+    /// Overrides [`AdvertisementData::advertising_interval`].
+    pub interval: Option<RangeInclusive<Duration>>,
+    /// The requested transmit power in dBm.
+    ///
+    /// Overrides [`AdvertisementData::tx_power_level`] as the requested power, which is still used to include the
+    /// power actually used in the advertisement.
+    pub tx_power: Option<i16>,
+    /// How long this set is advertised for each time the controller rotates between advertising sets
+    pub duration: Option<Duration>,
+    /// How long until this set stops advertising
+    pub timeout: Option<Duration>,
+    /// The PHY of the secondary advertising channel used by extended advertisements
+    pub secondary_phy: Option<Phy>,
+    /// Data sent in response to scan requests
+    pub scan_response: Option<AdvertisementData>,
+}
 
+/// Represents a guard for advertisements that stops advertisements when dropped.
 #[derive(Debug)]
 pub struct AdvertisingGuard {
     /// The owned advertisement
     advertisement: AdvertisementImpl,
 }
 
-impl<'a> Drop for AdvertisingGuard {
+impl Drop for AdvertisingGuard {
     fn drop(&mut self) {
-        println!("AdvertisingGuard dropped. Stopping advertisement.");
         let _ = self.advertisement.stop_advertising();
     }
 }
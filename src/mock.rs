@@ -0,0 +1,168 @@
+//! In-process mock Bluetooth backend.
+//!
+//! When the `mock` feature is enabled, this module replaces the platform-specific backend. Instead of talking to the
+//! operating system, an [`Adapter`][crate::Adapter] talks to a [`VirtualRadio`] populated with
+//! [`VirtualPeripheral`]s. Each peripheral has advertisement data, an RSSI and a GATT database built from
+//! [`VirtualService`]s, [`VirtualCharacteristic`]s and [`VirtualDescriptor`]s. Failures can be scripted with the
+//! `fail_next` methods so that error handling paths can be exercised as well.
+//!
+//! Adapters can also act as peripherals: [`Adapter::start_advertising`][crate::Adapter::start_advertising] adds a
+//! peripheral to the radio, which serves the services of the GATT servers started with
+//! [`Adapter::start_server`][crate::Adapter::start_server] on the same radio.
+//!
+//! With the `l2cap` feature, peripherals accept L2CAP channels opened with `Device::open_l2cap_channel` on the PSMs
+//! they listen on with `VirtualPeripheral::listen_l2cap`, and adapters listening with `Adapter::listen_l2cap` accept
+//! the channels which peripherals open with `VirtualPeripheral::open_l2cap_channel`. Both ends of a channel are
+//! in-memory queues of packets.
+//!
+//! # Example
+//!
+//! ```rust
+//!# #[cfg(feature = "mock")]
+//!# #[tokio::main]
+//!# async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use bluest::mock::{VirtualCharacteristic, VirtualPeripheral, VirtualRadio, VirtualService};
+//! use bluest::{btuuid, AdvertisementData, CharacteristicProperties};
+//! use futures_lite::StreamExt;
+//!
+//! let battery_level = VirtualCharacteristic::new(
+//!     btuuid::characteristics::BATTERY_LEVEL,
+//!     CharacteristicProperties::from_bits(0x12), // read + notify
+//! )
+//! .with_value([87]);
+//!
+//! let radio = VirtualRadio::new();
+//! let peripheral = VirtualPeripheral::new()
+//!     .with_name("sensor")
+//!     .with_rssi(-52)
+//!     .with_advertisement(AdvertisementData {
+//!         services: vec![btuuid::services::BATTERY],
+//!         ..Default::default()
+//!     })
+//!     .with_service(VirtualService::new(btuuid::services::BATTERY).with_characteristic(battery_level.clone()));
+//! radio.add_peripheral(&peripheral);
+//!
+//! let adapter = radio.adapter();
+//! let found = adapter.scan(&[btuuid::services::BATTERY]).await?.next().await.unwrap();
+//! assert_eq!(found.device.id(), peripheral.id());
+//!
+//! adapter.connect_device(&found.device).await?;
+//! let service = &found.device.discover_services().await?[0];
+//! let characteristic = &service.discover_characteristics().await?[0];
+//! assert_eq!(characteristic.read().await?, vec![87]);
+//!
+//! let mut updates = characteristic.notify().await?;
+//! battery_level.notify([86]);
+//! assert_eq!(updates.next().await.unwrap()?, vec![86]);
+//!#
+//!#    Ok(())
+//!# }
+//!# #[cfg(not(feature = "mock"))]
+//!# fn main() {}
+//! ```
+
+pub(crate) mod adapter;
+pub(crate) mod advertisement;
+pub(crate) mod characteristic;
+pub(crate) mod descriptor;
+pub(crate) mod device;
+pub(crate) mod l2cap_channel;
+pub(crate) mod server;
+pub(crate) mod service;
+
+mod gatt;
+mod peripheral;
+mod radio;
+
+use std::sync::Mutex;
+
+use futures_core::Stream;
+use tokio::sync::broadcast;
+
+pub use self::gatt::{VirtualCharacteristic, VirtualDescriptor, VirtualService};
+pub use self::peripheral::VirtualPeripheral;
+pub use self::radio::VirtualRadio;
+use crate::error::ErrorKind;
+use crate::{Error, Result};
+
+/// A platform-specific device identifier.
+///
+/// For the mock backend this is the (virtual) Bluetooth address of the peripheral.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId(pub(crate) [u8; 6]);
+
+impl DeviceId {
+    /// The Bluetooth address of the virtual peripheral, most significant byte first
+    pub fn address(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl std::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{g:02X}")
+    }
+}
+
+/// An operation on a virtual object which can be scripted to fail with the `fail_next` methods.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    /// [`Adapter::connect_device`][crate::Adapter::connect_device]
+    Connect,
+    /// [`Adapter::disconnect_device`][crate::Adapter::disconnect_device]
+    Disconnect,
+    /// [`Device::pair`][crate::Device::pair] and [`Device::pair_with_agent`][crate::Device::pair_with_agent]
+    Pair,
+    /// [`Device::discover_services`][crate::Device::discover_services] and related methods
+    DiscoverServices,
+    /// [`Device::rssi`][crate::Device::rssi]
+    Rssi,
+    /// [`Characteristic::read`][crate::Characteristic::read] and [`Descriptor::read`][crate::Descriptor::read]
+    Read,
+    /// [`Characteristic::write`][crate::Characteristic::write] and [`Descriptor::write`][crate::Descriptor::write]
+    Write,
+    /// [`Characteristic::write_without_response`][crate::Characteristic::write_without_response]
+    WriteWithoutResponse,
+    /// [`Characteristic::notify`][crate::Characteristic::notify]
+    Notify,
+}
+
+/// A queue of scripted failures.
+#[derive(Debug, Default)]
+pub(crate) struct Faults(Mutex<Vec<(Operation, ErrorKind)>>);
+
+impl Faults {
+    pub(crate) fn push(&self, op: Operation, kind: ErrorKind) {
+        self.0.lock().unwrap().push((op, kind));
+    }
+
+    /// Returns the first scripted failure for `op`, if any, removing it from the queue.
+    pub(crate) fn check(&self, op: Operation) -> Result<()> {
+        let mut faults = self.0.lock().unwrap();
+        match faults.iter().position(|(x, _)| *x == op) {
+            Some(idx) => {
+                let (_, kind) = faults.remove(idx);
+                Err(Error::new(kind, None, format!("scripted failure of {op:?}")))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Converts a broadcast receiver into a stream, skipping over any lagged messages.
+pub(crate) fn broadcast_stream<T: Clone + Send + 'static>(
+    receiver: broadcast::Receiver<T>,
+) -> impl Stream<Item = T> + Send + Unpin {
+    Box::pin(futures_lite::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(x) => return Some((x, receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
@@ -0,0 +1,326 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures_core::Stream;
+use futures_lite::{stream, StreamExt};
+
+use super::advertisement::AdvertisementImpl;
+use super::device::DeviceImpl;
+use super::peripheral::{PeripheralEvent, VirtualPeripheral};
+use super::radio::{RadioEvent, VirtualRadio};
+use super::server::ServerImpl;
+use super::{broadcast_stream, Operation};
+use crate::error::ErrorKind;
+use crate::server::{RequestHandler, Server, ServiceDefinition};
+use crate::{
+    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters, ConnectionEvent,
+    Device, DeviceId, Error, MonitorEvent, MonitorOptions, MonitorPattern, Phy, Result, ScanEvent, ScanFilter,
+    ScanMode, ScanOptions, Uuid,
+};
+
+/// The system's Bluetooth adapter interface.
+///
+/// The default adapter for the system may be accessed with the [`Adapter::default()`] method.
+#[derive(Debug, Clone)]
+pub struct AdapterImpl {
+    radio: VirtualRadio,
+}
+
+impl PartialEq for AdapterImpl {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.radio.inner, &other.radio.inner)
+    }
+}
+
+impl Eq for AdapterImpl {}
+
+impl std::hash::Hash for AdapterImpl {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.radio.inner).hash(state);
+    }
+}
+
+impl AdapterImpl {
+    pub(super) fn new(radio: VirtualRadio) -> Self {
+        AdapterImpl { radio }
+    }
+
+    /// Creates an interface to the adapter of the process-wide [`VirtualRadio`]
+    pub async fn default() -> Option<Self> {
+        Some(AdapterImpl::new(VirtualRadio::global()))
+    }
+
+    /// A stream of [`AdapterEvent`] which allows the application to identify when the adapter is enabled or disabled.
+    pub async fn events(&self) -> Result<impl Stream<Item = Result<AdapterEvent>> + Send + Unpin + '_> {
+        let receiver = self.radio.inner.events.subscribe();
+        Ok(broadcast_stream(receiver).filter_map(|event| match event {
+            RadioEvent::Powered(true) => Some(Ok(AdapterEvent::Available)),
+            RadioEvent::Powered(false) => Some(Ok(AdapterEvent::Unavailable)),
+            _ => None,
+        }))
+    }
+
+    /// Asynchronously blocks until the adapter is available
+    pub async fn wait_available(&self) -> Result<()> {
+        let events = self.events().await?;
+        if !self.radio.is_powered() {
+            events
+                .skip_while(|x| x.is_ok() && !matches!(x, Ok(AdapterEvent::Available)))
+                .next()
+                .await
+                .ok_or_else(|| Error::new(ErrorKind::Internal, None, "adapter event stream closed unexpectedly"))??;
+        }
+        Ok(())
+    }
+
+    /// Attempts to create the device identified by `id`
+    pub async fn open_device(&self, id: &DeviceId) -> Result<Device> {
+        self.radio
+            .peripheral(id)
+            .map(DeviceImpl::device)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, None, "opening device"))
+    }
+
+    /// Finds all connected Bluetooth LE devices
+    pub async fn connected_devices(&self) -> Result<Vec<Device>> {
+        self.check_powered()?;
+        Ok(self
+            .radio
+            .peripherals()
+            .into_iter()
+            .filter(VirtualPeripheral::is_connected)
+            .map(DeviceImpl::device)
+            .collect())
+    }
+
+    /// Finds all connected devices providing any service in `services`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `services` is empty.
+    pub async fn connected_devices_with_services(&self, services: &[Uuid]) -> Result<Vec<Device>> {
+        assert!(!services.is_empty());
+
+        self.check_powered()?;
+        Ok(self
+            .radio
+            .peripherals()
+            .into_iter()
+            .filter(|x| x.is_connected() && x.services().iter().any(|s| services.contains(&s.uuid())))
+            .map(DeviceImpl::device)
+            .collect())
+    }
+
+    /// Starts scanning for Bluetooth advertising packets.
+    ///
+    /// Returns a stream of [`AdvertisingDevice`] structs which contain the data from the advertising packet and the
+    /// [`Device`] which sent it. Scanning is automatically stopped when the stream is dropped or the radio is powered
+    /// off. Every call to [`VirtualPeripheral::advertise`] which matches `filter` produces an item, followed by the
+    /// scan response of the peripheral unless the scan is passive.
+    pub async fn scan(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
+        Ok(self
+            .try_scan_events(filter, options)
+            .await?
+            .filter_map(|event| match event {
+                Ok(ScanEvent::Advertisement(adv)) => Some(adv),
+                Ok(ScanEvent::DeviceLost(_)) | Err(_) => None,
+            }))
+    }
+
+    /// Starts scanning for Bluetooth advertising packets like [`AdapterImpl::scan`], and reports the peripherals
+    /// removed from the radio as lost.
+    ///
+    /// When the radio is powered off the stream ends with an error.
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        self.check_powered()?;
+        let passive = options.mode == ScanMode::Passive;
+
+        // Subscribe before taking the snapshot so no advertisement falls between the two
+        let receiver = self.radio.inner.events.subscribe();
+        let timestamp = SystemTime::now();
+        let current = self
+            .radio
+            .peripherals()
+            .into_iter()
+            .flat_map(|peripheral| peripheral.advertising_events(timestamp))
+            .collect::<Vec<_>>();
+
+        Ok(stream::iter(current)
+            .chain(broadcast_stream(receiver))
+            .take_while(|event| !matches!(event, RadioEvent::Powered(false)))
+            .filter_map(move |event| match event {
+                RadioEvent::Advertisement {
+                    peripheral,
+                    adv_data,
+                    is_scan_response,
+                    rssi,
+                    timestamp,
+                } if !(passive && is_scan_response) => Some(Ok(ScanEvent::Advertisement(AdvertisingDevice {
+                    address_type: Some(peripheral.address_type()),
+                    device: DeviceImpl::device(peripheral),
+                    adv_data: *adv_data,
+                    rssi,
+                    timestamp: Some(timestamp),
+                    primary_phy: Some(Phy::Le1M),
+                    secondary_phy: None,
+                    advertising_sid: None,
+                    is_scan_response: Some(is_scan_response),
+                }))),
+                RadioEvent::Removed(id) => Some(Ok(ScanEvent::DeviceLost(id))),
+                _ => None,
+            })
+            .chain(stream::once(Err(Error::new(
+                ErrorKind::AdapterUnavailable,
+                None,
+                "the radio was powered off",
+            ))))
+            .filter(move |x| match x {
+                Ok(ScanEvent::Advertisement(adv)) => filter.matches(adv),
+                Ok(ScanEvent::DeviceLost(_)) | Err(_) => true,
+            }))
+    }
+
+    /// Finds Bluetooth devices providing any service in `services`.
+    ///
+    /// Returns a stream of [`Device`] structs with matching connected devices returned first. If the stream is not
+    /// dropped before all matching connected devices are consumed then scanning will begin for devices advertising any
+    /// of the `services`. Scanning will continue until the stream is dropped.
+    pub async fn discover_devices<'a>(
+        &'a self,
+        services: &'a [Uuid],
+    ) -> Result<impl Stream<Item = Result<Device>> + Send + Unpin + 'a> {
+        let connected = if services.is_empty() {
+            self.connected_devices().await?
+        } else {
+            self.connected_devices_with_services(services).await?
+        };
+        let connected = stream::iter(connected).map(Ok);
+
+        // try_unfold is used to ensure we do not start scanning until the connected devices have been consumed
+        let advertising = Box::pin(stream::try_unfold(None, |state| async {
+            let mut stream = match state {
+                Some(stream) => stream,
+                None => {
+                    self.scan(ScanFilter::from_services(services), &ScanOptions::default())
+                        .await?
+                }
+            };
+            Ok(stream.next().await.map(|x| (x.device, Some(stream))))
+        }));
+
+        Ok(connected.chain(advertising))
+    }
+
+    /// Connects to the [`Device`]
+    pub async fn connect_device(&self, device: &Device) -> Result<()> {
+        self.check_powered()?;
+        let peripheral = &device.0.peripheral;
+        if peripheral.radio().is_none() {
+            return Err(Error::new(
+                ErrorKind::ConnectionFailed,
+                None,
+                "peripheral is out of range",
+            ));
+        }
+        peripheral.inner.faults.check(Operation::Connect)?;
+        peripheral.set_connected(true);
+        Ok(())
+    }
+
+    /// Disconnects from the [`Device`]
+    pub async fn disconnect_device(&self, device: &Device) -> Result<()> {
+        self.check_powered()?;
+        device.0.peripheral.inner.faults.check(Operation::Disconnect)?;
+        device.0.peripheral.disconnect();
+        Ok(())
+    }
+
+    /// Monitors a device for connection/disconnection events.
+    pub async fn device_connection_events<'a>(
+        &'a self,
+        device: &'a Device,
+    ) -> Result<impl Stream<Item = ConnectionEvent> + Send + Unpin + 'a> {
+        let receiver = device.0.peripheral.inner.events.subscribe();
+        Ok(broadcast_stream(receiver).filter_map(|event| match event {
+            PeripheralEvent::Connection(event) => Some(event),
+            _ => None,
+        }))
+    }
+
+    /// Starts advertising `data` as a new virtual peripheral on this adapter's radio.
+    ///
+    /// Each advertisement uses one of the radio's advertising sets, see [`VirtualRadio::set_max_advertising_sets`].
+    /// The scan response in `parameters` is sent after each advertisement; the other parameters are accepted but
+    /// have no effect.
+    pub async fn start_advertising(
+        &self,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        self.check_powered()?;
+        let advertisement = AdvertisementImpl::start(self.radio.clone(), data, parameters)?;
+        Ok(AdvertisingGuard { advertisement })
+    }
+
+    /// The number of further advertising sets this adapter's radio can start.
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        Ok(self.radio.available_advertising_sets())
+    }
+
+    /// Advertisement monitors are not supported by the virtual radio.
+    pub async fn monitor_advertisements(
+        &self,
+        _patterns: &[MonitorPattern],
+        _options: &MonitorOptions,
+    ) -> Result<stream::Empty<MonitorEvent>> {
+        Err(Error::new(
+            ErrorKind::NotSupported,
+            None,
+            "advertisement monitors are not supported by the virtual radio",
+        ))
+    }
+
+    /// Listens for L2CAP channels opened to the radio with [`VirtualPeripheral::open_l2cap_channel`].
+    #[cfg(feature = "l2cap")]
+    pub async fn listen_l2cap(
+        &self,
+        psm: Option<u16>,
+        secure: bool,
+    ) -> Result<(
+        u16,
+        impl Stream<Item = Result<(Device, crate::L2capChannel)>> + Send + Unpin + '_,
+    )> {
+        self.check_powered()?;
+        let (psm, channels) = self.radio.inner.l2cap_listeners.listen(psm, secure)?;
+        Ok((psm, channels.map(Ok)))
+    }
+
+    /// Starts a GATT server on this adapter's radio.
+    ///
+    /// The services are served by the peripherals advertised with [`AdapterImpl::start_advertising`], including those
+    /// already advertised, until the server is dropped.
+    pub async fn start_server(
+        &self,
+        services: Vec<ServiceDefinition>,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Result<Server> {
+        self.check_powered()?;
+        Ok(Server(ServerImpl::start(&self.radio, services, handler)))
+    }
+
+    fn check_powered(&self) -> Result<()> {
+        if self.radio.is_powered() {
+            Ok(())
+        } else {
+            Err(ErrorKind::AdapterUnavailable.into())
+        }
+    }
+}
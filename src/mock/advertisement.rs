@@ -0,0 +1,62 @@
+use super::peripheral::VirtualPeripheral;
+use super::radio::VirtualRadio;
+use crate::error::ErrorKind;
+use crate::{AdvertisementData, AdvertisingParameters, Error, Result};
+
+#[derive(Debug)]
+pub struct AdvertisementImpl {
+    radio: VirtualRadio,
+    peripheral: Option<VirtualPeripheral>,
+}
+
+impl AdvertisementImpl {
+    /// Starts advertising `data` as a new virtual peripheral on `radio`, using one of its advertising sets.
+    ///
+    /// The peripheral serves the services of the GATT servers running on `radio`. Only the scan response of
+    /// `parameters` has an effect.
+    pub(super) fn start(
+        radio: VirtualRadio,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<Self> {
+        if !radio.acquire_advertising_set() {
+            return Err(Error::new(
+                ErrorKind::NotAuthorized,
+                None,
+                "the maximum number of advertising sets is active",
+            ));
+        }
+
+        let mut peripheral = VirtualPeripheral::new().into_local();
+        for service in radio.local_services() {
+            peripheral = peripheral.with_service(service);
+        }
+        if let Some(name) = &data.local_name {
+            peripheral = peripheral.with_name(name.clone());
+        }
+        if let Some(scan_response) = parameters.scan_response {
+            peripheral = peripheral.with_scan_response(scan_response);
+        }
+        let peripheral = peripheral.with_advertisement(data);
+        radio.add_peripheral(&peripheral);
+        Ok(AdvertisementImpl {
+            radio,
+            peripheral: Some(peripheral),
+        })
+    }
+
+    /// Stop advertising if an advertisement is active
+    pub fn stop_advertising(&mut self) -> Result<()> {
+        if let Some(peripheral) = self.peripheral.take() {
+            self.radio.remove_peripheral(&peripheral);
+            self.radio.release_advertising_set();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AdvertisementImpl {
+    fn drop(&mut self) {
+        let _ = self.stop_advertising();
+    }
+}
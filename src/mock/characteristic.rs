@@ -0,0 +1,182 @@
+use std::sync::atomic::Ordering;
+
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+use super::gatt::VirtualCharacteristic;
+use super::peripheral::VirtualPeripheral;
+use super::{broadcast_stream, Operation};
+use crate::error::{AttError, ErrorKind};
+use crate::server::WriteKind;
+use crate::util::defer;
+use crate::{Characteristic, CharacteristicProperties, Descriptor, Error, Result, Uuid};
+
+/// A Bluetooth GATT characteristic
+#[derive(Debug, Clone)]
+pub struct CharacteristicImpl {
+    inner: VirtualCharacteristic,
+    peripheral: VirtualPeripheral,
+}
+
+impl PartialEq for CharacteristicImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for CharacteristicImpl {}
+
+impl std::hash::Hash for CharacteristicImpl {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::sync::Arc::as_ptr(&self.inner.inner).hash(state);
+    }
+}
+
+impl Characteristic {
+    pub(super) fn new(peripheral: VirtualPeripheral, inner: VirtualCharacteristic) -> Characteristic {
+        Characteristic(CharacteristicImpl { inner, peripheral })
+    }
+}
+
+impl CharacteristicImpl {
+    /// The [`Uuid`] identifying the type of this GATT characteristic
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid()
+    }
+
+    /// The [`Uuid`] identifying the type of this GATT characteristic
+    pub async fn uuid_async(&self) -> Result<Uuid> {
+        Ok(self.inner.uuid())
+    }
+
+    /// The properties of this this GATT characteristic.
+    pub async fn properties(&self) -> Result<CharacteristicProperties> {
+        Ok(self.inner.properties())
+    }
+
+    /// The cached value of this characteristic
+    pub async fn value(&self) -> Result<Vec<u8>> {
+        Ok(self.inner.value())
+    }
+
+    /// Read the value of this characteristic from the device
+    pub async fn read(&self) -> Result<Vec<u8>> {
+        self.check(Operation::Read, |x| x.read, AttError::READ_NOT_PERMITTED)?;
+        if let Some(binding) = self.inner.inner.binding.get() {
+            let value = binding.read(&self.peripheral).await?;
+            self.inner.set_value(value);
+        }
+        Ok(self.inner.value())
+    }
+
+    /// Write the value of this descriptor on the device to `value` and request the device return a response indicating
+    /// a successful write.
+    pub async fn write(&self, value: &[u8]) -> Result<()> {
+        self.check(Operation::Write, |x| x.write, AttError::WRITE_NOT_PERMITTED)?;
+        self.write_value(value, WriteKind::WithResponse).await
+    }
+
+    /// Write the value of this descriptor on the device to `value` without requesting a response.
+    pub async fn write_without_response(&self, value: &[u8]) -> Result<()> {
+        self.check(
+            Operation::WriteWithoutResponse,
+            |x| x.write_without_response,
+            AttError::WRITE_NOT_PERMITTED,
+        )?;
+        self.write_value(value, WriteKind::WithoutResponse).await
+    }
+
+    /// Get the maximum amount of data that can be written in a single packet for this characteristic.
+    pub fn max_write_len(&self) -> Result<usize> {
+        // GATT characteristic writes have 3 bytes of overhead (opcode + handle id)
+        Ok(usize::from(self.peripheral.mtu()) - 3)
+    }
+
+    /// Get the maximum amount of data that can be written in a single packet for this characteristic.
+    pub async fn max_write_len_async(&self) -> Result<usize> {
+        self.max_write_len()
+    }
+
+    /// Enables notification of value changes for this GATT characteristic.
+    ///
+    /// Returns a stream of values for the characteristic sent from the device. The stream ends when the device
+    /// disconnects.
+    pub async fn notify(&self) -> Result<impl Stream<Item = Result<Vec<u8>>> + Send + Unpin + '_> {
+        self.peripheral.check_connected()?;
+        let properties = self.inner.properties();
+        if !(properties.notify || properties.indicate) {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "characteristic does not support notifications or indications",
+            ));
+        }
+        self.inner.inner.faults.check(Operation::Notify)?;
+
+        // A local GATT server sees one subscription for all the streams of the central
+        let binding = self.inner.inner.binding.get();
+        let receiver = self.inner.inner.notifications.subscribe();
+        if self.inner.inner.subscribers.fetch_add(1, Ordering::AcqRel) == 0 {
+            if let Some(binding) = binding {
+                binding.subscribed(!properties.notify);
+            }
+        }
+        let guard = defer(move || {
+            if self.inner.inner.subscribers.fetch_sub(1, Ordering::AcqRel) == 1 {
+                if let Some(binding) = binding {
+                    binding.unsubscribed();
+                }
+            }
+        });
+
+        Ok(broadcast_stream(receiver)
+            .take_while(Option::is_some)
+            .filter_map(move |x| {
+                let _guard = &guard;
+                x.map(Ok)
+            }))
+    }
+
+    /// Is the device currently sending notifications for this characteristic?
+    pub async fn is_notifying(&self) -> Result<bool> {
+        Ok(self.inner.is_notifying())
+    }
+
+    /// Discover the descriptors associated with this characteristic.
+    pub async fn discover_descriptors(&self) -> Result<Vec<Descriptor>> {
+        self.descriptors().await
+    }
+
+    /// Get previously discovered descriptors.
+    ///
+    /// If no descriptors have been discovered yet, this method will perform descriptor discovery.
+    pub async fn descriptors(&self) -> Result<Vec<Descriptor>> {
+        self.peripheral.check_connected()?;
+        self.peripheral.inner.faults.check(Operation::DiscoverServices)?;
+        Ok(self
+            .inner
+            .descriptors()
+            .into_iter()
+            .map(|x| Descriptor::new(self.peripheral.clone(), x))
+            .collect())
+    }
+
+    /// Passes `value` to the local GATT server serving the characteristic, if any, and stores it.
+    async fn write_value(&self, value: &[u8], kind: WriteKind) -> Result<()> {
+        if let Some(binding) = self.inner.inner.binding.get() {
+            binding.write(&self.peripheral, value, kind).await?;
+        }
+        self.inner.set_value(value);
+        Ok(())
+    }
+
+    fn check(&self, op: Operation, permitted: fn(&CharacteristicProperties) -> bool, err: AttError) -> Result<()> {
+        self.peripheral.check_connected()?;
+        self.inner.inner.faults.check(op)?;
+        if permitted(&self.inner.properties()) {
+            Ok(())
+        } else {
+            Err(ErrorKind::Protocol(err).into())
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::gatt::VirtualDescriptor;
+use super::peripheral::VirtualPeripheral;
+use super::Operation;
+use crate::server::WriteKind;
+use crate::{Descriptor, Result, Uuid};
+
+/// A Bluetooth GATT descriptor
+#[derive(Debug, Clone)]
+pub struct DescriptorImpl {
+    inner: VirtualDescriptor,
+    peripheral: VirtualPeripheral,
+}
+
+impl PartialEq for DescriptorImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for DescriptorImpl {}
+
+impl Descriptor {
+    pub(super) fn new(peripheral: VirtualPeripheral, inner: VirtualDescriptor) -> Descriptor {
+        Descriptor(DescriptorImpl { inner, peripheral })
+    }
+}
+
+impl DescriptorImpl {
+    /// The [`Uuid`] identifying the type of this GATT descriptor
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid()
+    }
+
+    /// The [`Uuid`] identifying the type of this GATT descriptor
+    pub async fn uuid_async(&self) -> Result<Uuid> {
+        Ok(self.inner.uuid())
+    }
+
+    /// The cached value of this descriptor
+    pub async fn value(&self) -> Result<Vec<u8>> {
+        Ok(self.inner.value())
+    }
+
+    /// Read the value of this descriptor from the device
+    pub async fn read(&self) -> Result<Vec<u8>> {
+        self.peripheral.check_connected()?;
+        self.inner.inner.faults.check(Operation::Read)?;
+        if let Some(binding) = self.inner.inner.binding.get() {
+            let value = binding.read(&self.peripheral).await?;
+            self.inner.set_value(value);
+        }
+        Ok(self.inner.value())
+    }
+
+    /// Write the value of this descriptor on the device to `value`
+    pub async fn write(&self, value: &[u8]) -> Result<()> {
+        self.peripheral.check_connected()?;
+        self.inner.inner.faults.check(Operation::Write)?;
+        if let Some(binding) = self.inner.inner.binding.get() {
+            binding.write(&self.peripheral, value, WriteKind::WithResponse).await?;
+        }
+        self.inner.set_value(value);
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+#[cfg(feature = "l2cap")]
+use super::l2cap_channel::{L2capChannelReader, L2capChannelWriter};
+use super::peripheral::{PeripheralEvent, VirtualPeripheral};
+use super::{broadcast_stream, DeviceId, Operation};
+use crate::device::ServicesChanged;
+use crate::error::ErrorKind;
+use crate::pairing::{IoCapability, PairingAgent, PairingRejected};
+use crate::{Device, Error, Result, Service, Uuid};
+
+/// A Bluetooth LE device
+#[derive(Debug, Clone)]
+pub struct DeviceImpl {
+    pub(super) peripheral: VirtualPeripheral,
+}
+
+impl PartialEq for DeviceImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.peripheral.id() == other.peripheral.id()
+    }
+}
+
+impl Eq for DeviceImpl {}
+
+impl std::hash::Hash for DeviceImpl {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.peripheral.id().hash(state);
+    }
+}
+
+impl std::fmt::Display for DeviceImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.peripheral.name().as_deref().unwrap_or("(Unknown)"))
+    }
+}
+
+impl DeviceImpl {
+    pub(super) fn device(peripheral: VirtualPeripheral) -> Device {
+        Device(DeviceImpl { peripheral })
+    }
+
+    /// This device's unique identifier
+    pub fn id(&self) -> DeviceId {
+        self.peripheral.id()
+    }
+
+    /// The local name for this device, if available
+    pub fn name(&self) -> Result<String> {
+        self.peripheral
+            .name()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, None, "device has no name"))
+    }
+
+    /// The local name for this device, if available
+    pub async fn name_async(&self) -> Result<String> {
+        self.name()
+    }
+
+    /// The connection status for this device
+    pub async fn is_connected(&self) -> bool {
+        self.peripheral.is_connected()
+    }
+
+    /// The pairing status for this device
+    pub async fn is_paired(&self) -> Result<bool> {
+        Ok(self.peripheral.is_paired())
+    }
+
+    /// Attempt to pair this device using the system default pairing UI
+    pub async fn pair(&self) -> Result<()> {
+        if self.peripheral.is_paired() {
+            return Ok(());
+        }
+
+        self.peripheral.inner.faults.check(Operation::Pair)?;
+        self.peripheral.set_paired(true);
+        Ok(())
+    }
+
+    /// Attempt to pair this device using a custom pairing agent
+    ///
+    /// If the virtual peripheral has a passkey, the agent is asked to display, confirm or enter it according to its
+    /// I/O capabilities. Otherwise, the agent is asked to confirm the pairing.
+    pub async fn pair_with_agent<T: PairingAgent + 'static>(&self, agent: &T) -> Result<()> {
+        if self.peripheral.is_paired() {
+            return Ok(());
+        }
+
+        self.peripheral.inner.faults.check(Operation::Pair)?;
+
+        let device = Device(self.clone());
+        let res = match (agent.io_capability(), self.peripheral.passkey()) {
+            (IoCapability::NoInputNoOutput, _) | (_, None) => agent.confirm(&device).await,
+            (IoCapability::DisplayOnly, Some(passkey)) => {
+                agent.display_passkey(&device, passkey);
+                Ok(())
+            }
+            (IoCapability::DisplayYesNo, Some(passkey)) => agent.confirm_passkey(&device, passkey).await,
+            (IoCapability::KeyboardOnly | IoCapability::KeyboardDisplay, Some(passkey)) => {
+                match agent.request_passkey(&device).await {
+                    Ok(entered) if entered == passkey => Ok(()),
+                    _ => Err(PairingRejected),
+                }
+            }
+        };
+
+        res.map_err(|err| Error::new(ErrorKind::NotAuthorized, Some(Box::new(err)), "pairing"))?;
+        self.peripheral.set_paired(true);
+        Ok(())
+    }
+
+    /// Disconnect and unpair this device from the system
+    pub async fn unpair(&self) -> Result<()> {
+        self.peripheral.disconnect();
+        self.peripheral.set_paired(false);
+        Ok(())
+    }
+
+    /// Discover the primary services of this device.
+    pub async fn discover_services(&self) -> Result<Vec<Service>> {
+        self.services().await
+    }
+
+    /// Discover the primary service(s) of this device with the given [`Uuid`].
+    pub async fn discover_services_with_uuid(&self, uuid: Uuid) -> Result<Vec<Service>> {
+        Ok(self
+            .services()
+            .await?
+            .into_iter()
+            .filter(|x| x.uuid() == uuid)
+            .collect())
+    }
+
+    /// Get previously discovered services.
+    ///
+    /// If no services have been discovered yet, this method will perform service discovery.
+    pub async fn services(&self) -> Result<Vec<Service>> {
+        self.peripheral.check_connected()?;
+        self.peripheral.inner.faults.check(Operation::DiscoverServices)?;
+        Ok(self
+            .peripheral
+            .services()
+            .into_iter()
+            .filter(|x| x.is_primary())
+            .map(|x| Service::new(self.peripheral.clone(), x))
+            .collect())
+    }
+
+    /// Monitors the device for services changed events.
+    pub async fn service_changed_indications(
+        &self,
+    ) -> Result<impl Stream<Item = Result<ServicesChanged>> + Send + Unpin + '_> {
+        self.peripheral.check_connected()?;
+        let receiver = self.peripheral.inner.events.subscribe();
+        Ok(broadcast_stream(receiver)
+            .take_while(|event| !matches!(event, PeripheralEvent::Connection(crate::ConnectionEvent::Disconnected)))
+            .filter_map(|event| match event {
+                PeripheralEvent::ServicesChanged(range) => Some(Ok(ServicesChanged(ServicesChangedImpl(range)))),
+                _ => None,
+            }))
+    }
+
+    /// Get the current signal strength from the device in dBm.
+    ///
+    /// Returns [`ErrorKind::NotReady`] if the virtual peripheral has no RSSI.
+    pub async fn rssi(&self) -> Result<i16> {
+        self.peripheral.inner.faults.check(Operation::Rssi)?;
+        self.peripheral.rssi().ok_or_else(|| ErrorKind::NotReady.into())
+    }
+
+    /// Opens an L2CAP channel to a listener of the virtual peripheral.
+    ///
+    /// See [`VirtualPeripheral::listen_l2cap`].
+    #[cfg(feature = "l2cap")]
+    pub async fn open_l2cap_channel(&self, psm: u16, secure: bool) -> Result<(L2capChannelReader, L2capChannelWriter)> {
+        self.peripheral.check_connected()?;
+        self.peripheral
+            .inner
+            .l2cap_listeners
+            .connect(psm, secure, self.peripheral.is_paired(), |channel| channel)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServicesChangedImpl(std::ops::RangeInclusive<u16>);
+
+impl ServicesChangedImpl {
+    pub fn was_invalidated(&self, service: &Service) -> bool {
+        let range = service.0.inner.handle_range();
+        self.0.contains(range.start()) || range.contains(self.0.start())
+    }
+}
@@ -0,0 +1,296 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tokio::sync::broadcast;
+
+use super::server::Binding;
+use super::{Faults, Operation};
+use crate::error::ErrorKind;
+use crate::{CharacteristicProperties, Uuid};
+
+/// A GATT service in the database of a [`VirtualPeripheral`][super::VirtualPeripheral].
+///
+/// This is a cheaply cloneable handle: all clones refer to the same service.
+#[derive(Debug, Clone)]
+pub struct VirtualService {
+    pub(super) inner: Arc<ServiceInner>,
+}
+
+#[derive(Debug)]
+pub(super) struct ServiceInner {
+    uuid: Uuid,
+    state: Mutex<ServiceState>,
+}
+
+#[derive(Debug)]
+struct ServiceState {
+    primary: bool,
+    handles: RangeInclusive<u16>,
+    characteristics: Vec<VirtualCharacteristic>,
+    included: Vec<VirtualService>,
+}
+
+impl PartialEq for VirtualService {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for VirtualService {}
+
+impl VirtualService {
+    /// Creates a new primary service.
+    pub fn new(uuid: Uuid) -> Self {
+        VirtualService {
+            inner: Arc::new(ServiceInner {
+                uuid,
+                state: Mutex::new(ServiceState {
+                    primary: true,
+                    handles: 0..=0,
+                    characteristics: Vec::new(),
+                    included: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Marks this service as a secondary service.
+    pub fn secondary(self) -> Self {
+        self.inner.state.lock().unwrap().primary = false;
+        self
+    }
+
+    /// Adds a characteristic to this service.
+    pub fn with_characteristic(self, characteristic: VirtualCharacteristic) -> Self {
+        self.inner.state.lock().unwrap().characteristics.push(characteristic);
+        self
+    }
+
+    /// Adds an included service to this service.
+    pub fn with_included_service(self, service: VirtualService) -> Self {
+        self.inner.state.lock().unwrap().included.push(service);
+        self
+    }
+
+    /// The [`Uuid`] identifying the type of this service.
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid
+    }
+
+    /// Whether this is a primary service.
+    pub fn is_primary(&self) -> bool {
+        self.inner.state.lock().unwrap().primary
+    }
+
+    /// The characteristics of this service.
+    pub fn characteristics(&self) -> Vec<VirtualCharacteristic> {
+        self.inner.state.lock().unwrap().characteristics.clone()
+    }
+
+    /// The included services of this service.
+    pub fn included_services(&self) -> Vec<VirtualService> {
+        self.inner.state.lock().unwrap().included.clone()
+    }
+
+    /// The attribute handle range occupied by this service.
+    pub(super) fn handle_range(&self) -> RangeInclusive<u16> {
+        self.inner.state.lock().unwrap().handles.clone()
+    }
+
+    /// Lays out the attributes of this service starting at `start`, returning the occupied handle range.
+    pub(super) fn assign_handles(&self, start: u16) -> RangeInclusive<u16> {
+        let mut state = self.inner.state.lock().unwrap();
+        // One handle for the service declaration, two for each characteristic (declaration and value) and one for
+        // each descriptor.
+        let len = state
+            .characteristics
+            .iter()
+            .map(|x| 2 + x.descriptors().len())
+            .sum::<usize>();
+        let end = start.saturating_add(len as u16);
+        state.handles = start..=end;
+        start..=end
+    }
+
+    pub(super) fn disconnected(&self) {
+        for characteristic in self.characteristics() {
+            let _ = characteristic.inner.notifications.send(None);
+        }
+    }
+}
+
+/// A GATT characteristic in the database of a [`VirtualPeripheral`][super::VirtualPeripheral].
+///
+/// This is a cheaply cloneable handle: all clones refer to the same characteristic.
+#[derive(Debug, Clone)]
+pub struct VirtualCharacteristic {
+    pub(super) inner: Arc<CharacteristicInner>,
+}
+
+#[derive(Debug)]
+pub(super) struct CharacteristicInner {
+    uuid: Uuid,
+    state: Mutex<CharacteristicState>,
+    pub(super) notifications: broadcast::Sender<Option<Vec<u8>>>,
+    pub(super) subscribers: AtomicUsize,
+    pub(super) faults: Faults,
+    /// The local GATT server which answers requests, if the characteristic is part of one
+    pub(super) binding: OnceLock<Binding>,
+}
+
+#[derive(Debug)]
+struct CharacteristicState {
+    properties: CharacteristicProperties,
+    value: Vec<u8>,
+    descriptors: Vec<VirtualDescriptor>,
+}
+
+impl PartialEq for VirtualCharacteristic {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for VirtualCharacteristic {}
+
+impl VirtualCharacteristic {
+    /// Creates a new characteristic with an empty value.
+    pub fn new(uuid: Uuid, properties: CharacteristicProperties) -> Self {
+        VirtualCharacteristic {
+            inner: Arc::new(CharacteristicInner {
+                uuid,
+                state: Mutex::new(CharacteristicState {
+                    properties,
+                    value: Vec::new(),
+                    descriptors: Vec::new(),
+                }),
+                notifications: broadcast::channel(64).0,
+                subscribers: AtomicUsize::new(0),
+                faults: Faults::default(),
+                binding: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Sets the initial value of this characteristic.
+    pub fn with_value(self, value: impl Into<Vec<u8>>) -> Self {
+        self.set_value(value);
+        self
+    }
+
+    /// Adds a descriptor to this characteristic.
+    pub fn with_descriptor(self, descriptor: VirtualDescriptor) -> Self {
+        self.inner.state.lock().unwrap().descriptors.push(descriptor);
+        self
+    }
+
+    /// The [`Uuid`] identifying the type of this characteristic.
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid
+    }
+
+    /// The properties of this characteristic.
+    pub fn properties(&self) -> CharacteristicProperties {
+        self.inner.state.lock().unwrap().properties
+    }
+
+    /// The current value of this characteristic, including any value written by a central.
+    pub fn value(&self) -> Vec<u8> {
+        self.inner.state.lock().unwrap().value.clone()
+    }
+
+    /// Replaces the value of this characteristic without notifying subscribers.
+    pub fn set_value(&self, value: impl Into<Vec<u8>>) {
+        self.inner.state.lock().unwrap().value = value.into();
+    }
+
+    /// The descriptors of this characteristic.
+    pub fn descriptors(&self) -> Vec<VirtualDescriptor> {
+        self.inner.state.lock().unwrap().descriptors.clone()
+    }
+
+    /// Whether any central is subscribed to notifications or indications of this characteristic.
+    pub fn is_notifying(&self) -> bool {
+        self.inner.subscribers.load(Ordering::Acquire) > 0
+    }
+
+    /// Updates the value of this characteristic and sends it to all subscribed centrals.
+    ///
+    /// Returns the number of subscriptions the value was delivered to.
+    pub fn notify(&self, value: impl Into<Vec<u8>>) -> usize {
+        let value = value.into();
+        self.set_value(value.clone());
+        self.inner.notifications.send(Some(value)).unwrap_or(0)
+    }
+
+    /// Causes the next `op` performed on this characteristic to fail with `kind`.
+    pub fn fail_next(&self, op: Operation, kind: ErrorKind) {
+        self.inner.faults.push(op, kind);
+    }
+}
+
+/// A GATT descriptor in the database of a [`VirtualPeripheral`][super::VirtualPeripheral].
+///
+/// This is a cheaply cloneable handle: all clones refer to the same descriptor.
+#[derive(Debug, Clone)]
+pub struct VirtualDescriptor {
+    pub(super) inner: Arc<DescriptorInner>,
+}
+
+#[derive(Debug)]
+pub(super) struct DescriptorInner {
+    uuid: Uuid,
+    value: Mutex<Vec<u8>>,
+    pub(super) faults: Faults,
+    /// The local GATT server which answers requests, if the descriptor is part of one
+    pub(super) binding: OnceLock<Binding>,
+}
+
+impl PartialEq for VirtualDescriptor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for VirtualDescriptor {}
+
+impl VirtualDescriptor {
+    /// Creates a new descriptor with an empty value.
+    pub fn new(uuid: Uuid) -> Self {
+        VirtualDescriptor {
+            inner: Arc::new(DescriptorInner {
+                uuid,
+                value: Mutex::new(Vec::new()),
+                faults: Faults::default(),
+                binding: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Sets the initial value of this descriptor.
+    pub fn with_value(self, value: impl Into<Vec<u8>>) -> Self {
+        self.set_value(value);
+        self
+    }
+
+    /// The [`Uuid`] identifying the type of this descriptor.
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid
+    }
+
+    /// The current value of this descriptor, including any value written by a central.
+    pub fn value(&self) -> Vec<u8> {
+        self.inner.value.lock().unwrap().clone()
+    }
+
+    /// Replaces the value of this descriptor.
+    pub fn set_value(&self, value: impl Into<Vec<u8>>) {
+        *self.inner.value.lock().unwrap() = value.into();
+    }
+
+    /// Causes the next `op` performed on this descriptor to fail with `kind`.
+    pub fn fail_next(&self, op: Operation, kind: ErrorKind) {
+        self.inner.faults.push(op, kind);
+    }
+}
@@ -0,0 +1,247 @@
+#![cfg(feature = "l2cap")]
+
+use std::fmt;
+use std::future::poll_fn;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use futures_lite::stream;
+use tokio::sync::mpsc;
+
+use crate::error::ErrorKind;
+use crate::{Error, L2capChannel, Result};
+
+/// The MTU of both directions of virtual L2CAP channels.
+const MTU: usize = 512;
+
+/// The PSMs assigned to listeners which don't ask for a specific PSM.
+const DYNAMIC_PSMS: RangeInclusive<u16> = 0x80..=0xff;
+
+/// The L2CAP listeners of a virtual peripheral or radio, which receive the channels opened to them as `T`.
+#[derive(Debug)]
+pub(super) struct Listeners<T> {
+    listeners: Mutex<Vec<Listener<T>>>,
+}
+
+#[derive(Debug)]
+struct Listener<T> {
+    psm: u16,
+    secure: bool,
+    sender: mpsc::UnboundedSender<T>,
+}
+
+impl<T> Default for Listeners<T> {
+    fn default() -> Self {
+        Listeners {
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T: Send + 'static> Listeners<T> {
+    /// Listens on `psm`, or on a free dynamic PSM if `psm` is `None`, until the returned stream is dropped.
+    pub(super) fn listen(&self, psm: Option<u16>, secure: bool) -> Result<(u16, impl Stream<Item = T> + Send + Unpin)> {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|x| !x.sender.is_closed());
+
+        let in_use = |psm: u16| listeners.iter().any(|x| x.psm == psm);
+        let psm = match psm {
+            Some(psm) if in_use(psm) => return Err(Error::new(ErrorKind::Other, None, "PSM is already in use")),
+            Some(psm) => psm,
+            None => DYNAMIC_PSMS
+                .into_iter()
+                .find(|x| !in_use(*x))
+                .ok_or_else(|| Error::new(ErrorKind::Other, None, "no dynamic PSM is free"))?,
+        };
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        listeners.push(Listener { psm, secure, sender });
+        let channels = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|x| (x, receiver))
+        });
+        Ok((psm, Box::pin(channels)))
+    }
+
+    /// Opens a channel to the listener on `psm`, which receives its end of the channel converted by `accept`.
+    ///
+    /// A channel is secure if either end asks for it, which requires the devices to be paired.
+    pub(super) fn connect(
+        &self,
+        psm: u16,
+        secure: bool,
+        paired: bool,
+        accept: impl FnOnce(L2capChannel) -> T,
+    ) -> Result<(L2capChannelReader, L2capChannelWriter)> {
+        let listeners = self.listeners.lock().unwrap();
+        let listener = listeners
+            .iter()
+            .find(|x| x.psm == psm && !x.sender.is_closed())
+            .ok_or_else(|| no_listener(psm))?;
+        if (secure || listener.secure) && !paired {
+            return Err(Error::new(
+                ErrorKind::NotAuthorized,
+                None,
+                "a secure channel requires pairing",
+            ));
+        }
+
+        let (local, (reader, writer)) = pair();
+        listener
+            .sender
+            .send(accept(L2capChannel::new(reader, writer)))
+            .map_err(|_| no_listener(psm))?;
+        Ok(local)
+    }
+}
+
+fn no_listener(psm: u16) -> Error {
+    Error::new(
+        ErrorKind::ConnectionFailed,
+        None,
+        format!("no listener on PSM {psm:#x}"),
+    )
+}
+
+/// Creates the two ends of an in-memory L2CAP channel.
+fn pair() -> (
+    (L2capChannelReader, L2capChannelWriter),
+    (L2capChannelReader, L2capChannelWriter),
+) {
+    let (a_sender, a_receiver) = mpsc::unbounded_channel();
+    let (b_sender, b_receiver) = mpsc::unbounded_channel();
+    (end(a_sender, b_receiver), end(b_sender, a_receiver))
+}
+
+fn end(
+    sender: mpsc::UnboundedSender<Vec<u8>>,
+    receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+) -> (L2capChannelReader, L2capChannelWriter) {
+    let end = Arc::new(End {
+        sender: Mutex::new(Some(sender)),
+        receiver: Mutex::new(receiver),
+    });
+    (L2capChannelReader { end: end.clone() }, L2capChannelWriter { end })
+}
+
+/// One end of a channel, shared by its reader and writer so that either can close the entire channel.
+///
+/// The other end reads the end of the channel once the sender is dropped, and its writes fail once the receiver is
+/// closed. Both happen when the channel is closed or when both the reader and the writer are dropped.
+#[derive(Debug)]
+struct End {
+    sender: Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>,
+    receiver: Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+}
+
+impl End {
+    fn close(&self) {
+        self.sender.lock().unwrap().take();
+        self.receiver.lock().unwrap().close();
+    }
+}
+
+fn closed() -> Error {
+    Error::new(ErrorKind::NotConnected, None, "channel is closed")
+}
+
+pub struct L2capChannelReader {
+    end: Arc<End>,
+}
+
+impl L2capChannelReader {
+    #[inline]
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self.poll_read(&mut Context::from_waker(Waker::noop()), buf) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(Error::new(ErrorKind::NotReady, None, "no received packet in queue")),
+        }
+    }
+
+    /// Like the socket on Linux, reports the end of the channel as an empty packet.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        let Some(packet) = std::task::ready!(self.end.receiver.lock().unwrap().poll_recv(cx)) else {
+            return Poll::Ready(Ok(0));
+        };
+
+        if packet.len() > buf.len() {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                "Buffer is too small",
+            )));
+        }
+
+        buf[..packet.len()].copy_from_slice(&packet);
+        Poll::Ready(Ok(packet.len()))
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(MTU)
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.end.close();
+        Ok(())
+    }
+}
+
+impl fmt::Debug for L2capChannelReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("L2capChannelReader")
+    }
+}
+
+pub struct L2capChannelWriter {
+    end: Arc<End>,
+}
+
+impl L2capChannelWriter {
+    pub async fn write(&mut self, packet: &[u8]) -> Result<()> {
+        self.try_write(packet)
+    }
+
+    /// Packets are queued without limit, so writes never wait for buffer space.
+    pub fn try_write(&mut self, packet: &[u8]) -> Result<()> {
+        if packet.len() > MTU {
+            return Err(Error::new(
+                ErrorKind::InvalidParameter,
+                None,
+                format!("packet of {} bytes is larger than the MTU of {MTU} bytes", packet.len()),
+            ));
+        }
+
+        let sender = self.end.sender.lock().unwrap();
+        let sender = sender.as_ref().ok_or_else(closed)?;
+        sender.send(packet.to_vec()).map_err(|_| closed())
+    }
+
+    pub fn poll_write(&mut self, _cx: &mut Context<'_>, packet: &[u8]) -> Poll<Result<()>> {
+        Poll::Ready(self.try_write(packet))
+    }
+
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(MTU)
+    }
+
+    pub fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.end.close();
+        Poll::Ready(Ok(()))
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.end.close();
+        Ok(())
+    }
+}
+
+impl fmt::Debug for L2capChannelWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("L2capChannelWriter")
+    }
+}
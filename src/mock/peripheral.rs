@@ -0,0 +1,410 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::SystemTime;
+
+#[cfg(feature = "l2cap")]
+use futures_core::Stream;
+use tokio::sync::broadcast;
+
+use super::gatt::VirtualService;
+#[cfg(feature = "l2cap")]
+use super::l2cap_channel::Listeners;
+use super::radio::{RadioEvent, RadioInner};
+use super::{DeviceId, Faults, Operation};
+use crate::error::ErrorKind;
+use crate::pairing::Passkey;
+#[cfg(feature = "l2cap")]
+use crate::L2capChannel;
+use crate::{AddressType, AdvertisementData, ConnectionEvent, Error, Result};
+
+/// The default ATT MTU of a virtual peripheral.
+const DEFAULT_MTU: u16 = 23;
+
+/// A virtual Bluetooth LE peripheral.
+///
+/// `VirtualPeripheral` is a cheaply cloneable handle: all clones refer to the same peripheral. Add it to a
+/// [`VirtualRadio`][super::VirtualRadio] to make it visible to mock adapters.
+#[derive(Debug, Clone)]
+pub struct VirtualPeripheral {
+    pub(super) inner: Arc<PeripheralInner>,
+}
+
+#[derive(Debug)]
+pub(super) struct PeripheralInner {
+    id: DeviceId,
+    state: Mutex<PeripheralState>,
+    pub(super) events: broadcast::Sender<PeripheralEvent>,
+    pub(super) faults: Faults,
+    #[cfg(feature = "l2cap")]
+    pub(super) l2cap_listeners: Listeners<L2capChannel>,
+}
+
+#[derive(Debug)]
+struct PeripheralState {
+    radio: Weak<RadioInner>,
+    name: Option<String>,
+    address_type: AddressType,
+    adv_data: Option<AdvertisementData>,
+    scan_response: Option<AdvertisementData>,
+    rssi: Option<i16>,
+    connected: bool,
+    paired: bool,
+    /// Whether the peripheral is advertised by a mock adapter, and so serves the adapter's GATT servers
+    local: bool,
+    passkey: Option<Passkey>,
+    mtu: u16,
+    services: Vec<VirtualService>,
+    next_handle: u16,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum PeripheralEvent {
+    Connection(ConnectionEvent),
+    ServicesChanged(RangeInclusive<u16>),
+}
+
+impl Default for VirtualPeripheral {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualPeripheral {
+    /// Creates a new peripheral with a unique random static address.
+    pub fn new() -> Self {
+        static NEXT_ADDRESS: AtomicU32 = AtomicU32::new(1);
+        let [a, b, c, d] = NEXT_ADDRESS.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+        Self::with_address([0xc0, 0x00, a, b, c, d]).with_address_type(AddressType::Random)
+    }
+
+    /// Creates a new peripheral with the given public Bluetooth address (most significant byte first).
+    pub fn with_address(address: [u8; 6]) -> Self {
+        VirtualPeripheral {
+            inner: Arc::new(PeripheralInner {
+                id: DeviceId(address),
+                state: Mutex::new(PeripheralState {
+                    radio: Weak::new(),
+                    name: None,
+                    address_type: AddressType::Public,
+                    adv_data: None,
+                    scan_response: None,
+                    rssi: None,
+                    connected: false,
+                    paired: false,
+                    local: false,
+                    passkey: None,
+                    mtu: DEFAULT_MTU,
+                    services: Vec::new(),
+                    next_handle: 1,
+                }),
+                events: broadcast::channel(64).0,
+                faults: Faults::default(),
+                #[cfg(feature = "l2cap")]
+                l2cap_listeners: Listeners::default(),
+            }),
+        }
+    }
+
+    /// Sets the GAP device name of this peripheral.
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        self.inner.state.lock().unwrap().name = Some(name.into());
+        self
+    }
+
+    /// Sets the type of this peripheral's address.
+    pub fn with_address_type(self, address_type: AddressType) -> Self {
+        self.inner.state.lock().unwrap().address_type = address_type;
+        self
+    }
+
+    /// Sets the advertisement data broadcast by this peripheral.
+    pub fn with_advertisement(self, adv_data: AdvertisementData) -> Self {
+        self.inner.state.lock().unwrap().adv_data = Some(adv_data);
+        self
+    }
+
+    /// Sets the data this peripheral sends in response to scan requests.
+    pub fn with_scan_response(self, scan_response: AdvertisementData) -> Self {
+        self.inner.state.lock().unwrap().scan_response = Some(scan_response);
+        self
+    }
+
+    /// Sets the signal strength, in dBm, at which this peripheral is received.
+    pub fn with_rssi(self, rssi: i16) -> Self {
+        self.inner.state.lock().unwrap().rssi = Some(rssi);
+        self
+    }
+
+    /// Sets the negotiated ATT MTU used for connections to this peripheral.
+    pub fn with_mtu(self, mtu: u16) -> Self {
+        self.inner.state.lock().unwrap().mtu = mtu;
+        self
+    }
+
+    /// Requires `passkey` to be confirmed or entered when pairing with a custom pairing agent.
+    pub fn with_passkey(self, passkey: Passkey) -> Self {
+        self.inner.state.lock().unwrap().passkey = Some(passkey);
+        self
+    }
+
+    /// Adds a service to the GATT database of this peripheral.
+    pub fn with_service(self, service: VirtualService) -> Self {
+        self.add_service_inner(service);
+        self
+    }
+
+    /// This peripheral's unique identifier.
+    pub fn id(&self) -> DeviceId {
+        self.inner.id
+    }
+
+    /// The GAP device name of this peripheral.
+    pub fn name(&self) -> Option<String> {
+        self.inner.state.lock().unwrap().name.clone()
+    }
+
+    /// The advertisement data broadcast by this peripheral.
+    pub fn advertisement(&self) -> Option<AdvertisementData> {
+        self.inner.state.lock().unwrap().adv_data.clone()
+    }
+
+    /// Replaces the advertisement data of this peripheral and broadcasts it to any running scans.
+    ///
+    /// Setting the advertisement data to `None` stops the peripheral from advertising.
+    pub fn set_advertisement(&self, adv_data: Option<AdvertisementData>) {
+        self.inner.state.lock().unwrap().adv_data = adv_data;
+        self.advertise();
+    }
+
+    /// The data this peripheral sends in response to scan requests.
+    pub fn scan_response(&self) -> Option<AdvertisementData> {
+        self.inner.state.lock().unwrap().scan_response.clone()
+    }
+
+    /// Replaces the scan response data of this peripheral.
+    ///
+    /// The new scan response is sent with the next advertisement. Scan responses are only sent while the peripheral
+    /// is advertising.
+    pub fn set_scan_response(&self, scan_response: Option<AdvertisementData>) {
+        self.inner.state.lock().unwrap().scan_response = scan_response;
+    }
+
+    /// The signal strength in dBm at which this peripheral is received.
+    pub fn rssi(&self) -> Option<i16> {
+        self.inner.state.lock().unwrap().rssi
+    }
+
+    /// Changes the signal strength at which this peripheral is received.
+    ///
+    /// The new value is reported with the next advertisement.
+    pub fn set_rssi(&self, rssi: Option<i16>) {
+        self.inner.state.lock().unwrap().rssi = rssi;
+    }
+
+    /// Broadcasts the current advertisement data, followed by the scan response if there is one, to any running
+    /// scans.
+    ///
+    /// Does nothing if the peripheral is not advertising or has not been added to a radio.
+    pub fn advertise(&self) {
+        let radio = self.inner.state.lock().unwrap().radio.upgrade();
+        if let Some(radio) = radio {
+            if radio.is_powered() {
+                for event in self.advertising_events(SystemTime::now()) {
+                    let _ = radio.events.send(event);
+                }
+            }
+        }
+    }
+
+    /// The advertisement and scan response packets this peripheral currently sends
+    pub(super) fn advertising_events(&self, timestamp: SystemTime) -> Vec<RadioEvent> {
+        let state = self.inner.state.lock().unwrap();
+        let packets = state.adv_data.iter().map(|x| (x, false)).chain(
+            state
+                .adv_data
+                .as_ref()
+                .and(state.scan_response.as_ref())
+                .map(|x| (x, true)),
+        );
+        packets
+            .map(|(adv_data, is_scan_response)| RadioEvent::Advertisement {
+                peripheral: self.clone(),
+                adv_data: Box::new(adv_data.clone()),
+                is_scan_response,
+                rssi: state.rssi,
+                timestamp,
+            })
+            .collect()
+    }
+
+    /// The services in the GATT database of this peripheral.
+    pub fn services(&self) -> Vec<VirtualService> {
+        self.inner.state.lock().unwrap().services.clone()
+    }
+
+    /// Adds a service to the GATT database of this peripheral and sends a service changed indication.
+    pub fn add_service(&self, service: VirtualService) {
+        let range = self.add_service_inner(service);
+        let _ = self.inner.events.send(PeripheralEvent::ServicesChanged(range));
+    }
+
+    /// Removes a service from the GATT database of this peripheral and sends a service changed indication.
+    pub fn remove_service(&self, service: &VirtualService) {
+        let removed = {
+            let mut state = self.inner.state.lock().unwrap();
+            let len = state.services.len();
+            state.services.retain(|x| x != service);
+            len != state.services.len()
+        };
+
+        if removed {
+            let _ = self
+                .inner
+                .events
+                .send(PeripheralEvent::ServicesChanged(service.handle_range()));
+        }
+    }
+
+    /// Whether a central is currently connected to this peripheral.
+    pub fn is_connected(&self) -> bool {
+        self.inner.state.lock().unwrap().connected
+    }
+
+    /// Whether this peripheral has been paired.
+    pub fn is_paired(&self) -> bool {
+        self.inner.state.lock().unwrap().paired
+    }
+
+    /// Terminates the connection to this peripheral from the peripheral side.
+    pub fn disconnect(&self) {
+        if self.set_connected(false) {
+            for service in self.services() {
+                service.disconnected();
+            }
+        }
+    }
+
+    /// Listens for L2CAP channels opened to this peripheral with
+    /// [`Device::open_l2cap_channel`][crate::Device::open_l2cap_channel], returning the PSM listened on and a stream
+    /// of the peripheral's ends of the opened channels.
+    ///
+    /// If `psm` is `None`, a free PSM in the dynamic range is assigned. Secure channels require the peripheral to be
+    /// paired. Virtual channels have an MTU of 512 bytes in both directions. The peripheral stops listening when the
+    /// stream is dropped.
+    #[cfg(feature = "l2cap")]
+    pub fn listen_l2cap(
+        &self,
+        psm: Option<u16>,
+        secure: bool,
+    ) -> Result<(u16, impl Stream<Item = L2capChannel> + Send + Unpin)> {
+        self.inner.l2cap_listeners.listen(psm, secure)
+    }
+
+    /// Opens an L2CAP channel from this peripheral to a listener of the adapters of its radio, returning the
+    /// peripheral's end of the channel.
+    ///
+    /// The peripheral must be connected. The listener receives the other end of the channel together with the
+    /// [`Device`][crate::Device] of this peripheral. See [`Self::listen_l2cap`] for the properties of the channel.
+    #[cfg(feature = "l2cap")]
+    pub fn open_l2cap_channel(&self, psm: u16, secure: bool) -> Result<L2capChannel> {
+        let radio = self.radio().ok_or_else(|| Error::from(ErrorKind::NotConnected))?;
+        self.check_connected()?;
+        let device = super::device::DeviceImpl::device(self.clone());
+        let (reader, writer) = radio
+            .l2cap_listeners
+            .connect(psm, secure, self.is_paired(), |channel| (device, channel))?;
+        Ok(L2capChannel::new(reader, writer))
+    }
+
+    /// Causes the next `op` performed on this peripheral to fail with `kind`.
+    pub fn fail_next(&self, op: Operation, kind: ErrorKind) {
+        self.inner.faults.push(op, kind);
+    }
+
+    fn add_service_inner(&self, service: VirtualService) -> RangeInclusive<u16> {
+        let mut state = self.inner.state.lock().unwrap();
+        let range = service.assign_handles(state.next_handle);
+        state.next_handle = range.end().saturating_add(1);
+        state.services.push(service);
+        range
+    }
+
+    /// Marks this peripheral as advertised by a mock adapter.
+    pub(super) fn into_local(self) -> Self {
+        self.inner.state.lock().unwrap().local = true;
+        self
+    }
+
+    pub(super) fn is_local(&self) -> bool {
+        self.inner.state.lock().unwrap().local
+    }
+
+    pub(super) fn attach(&self, radio: Weak<RadioInner>) {
+        self.inner.state.lock().unwrap().radio = radio;
+    }
+
+    pub(super) fn detach(&self) {
+        self.disconnect();
+        self.inner.state.lock().unwrap().radio = Weak::new();
+    }
+
+    pub(super) fn radio(&self) -> Option<Arc<RadioInner>> {
+        self.inner.state.lock().unwrap().radio.upgrade()
+    }
+
+    pub(super) fn address_type(&self) -> AddressType {
+        self.inner.state.lock().unwrap().address_type
+    }
+
+    pub(super) fn mtu(&self) -> u16 {
+        self.inner.state.lock().unwrap().mtu
+    }
+
+    pub(super) fn passkey(&self) -> Option<Passkey> {
+        self.inner.state.lock().unwrap().passkey
+    }
+
+    pub(super) fn set_paired(&self, paired: bool) {
+        self.inner.state.lock().unwrap().paired = paired;
+    }
+
+    /// Updates the connection state, returning `true` if it changed.
+    ///
+    /// The GATT servers of the radio are told about connections to local peripherals.
+    pub(super) fn set_connected(&self, connected: bool) -> bool {
+        let (changed, servers) = {
+            let mut state = self.inner.state.lock().unwrap();
+            let changed = std::mem::replace(&mut state.connected, connected) != connected;
+            let servers = match state.radio.upgrade() {
+                Some(radio) if changed && state.local => radio.servers(),
+                _ => Vec::new(),
+            };
+            (changed, servers)
+        };
+
+        for server in servers {
+            server.connection_changed(connected, self.mtu());
+        }
+
+        if changed {
+            let event = if connected {
+                ConnectionEvent::Connected
+            } else {
+                ConnectionEvent::Disconnected
+            };
+            let _ = self.inner.events.send(PeripheralEvent::Connection(event));
+        }
+
+        changed
+    }
+
+    /// Returns an error if GATT operations cannot currently be performed on this peripheral.
+    pub(super) fn check_connected(&self) -> Result<()> {
+        if self.is_connected() {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::NotConnected))
+        }
+    }
+}
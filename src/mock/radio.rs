@@ -0,0 +1,263 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::SystemTime;
+
+use tokio::sync::broadcast;
+
+use super::adapter::AdapterImpl;
+use super::gatt::VirtualService;
+#[cfg(feature = "l2cap")]
+use super::l2cap_channel::Listeners;
+use super::peripheral::VirtualPeripheral;
+use super::server::State as ServerState;
+use super::DeviceId;
+use crate::{Adapter, AdvertisementData};
+
+/// The default number of advertising sets a virtual radio supports.
+const DEFAULT_ADVERTISING_SETS: usize = 4;
+
+/// A virtual radio environment shared by mock adapters and virtual peripherals.
+///
+/// Every [`Adapter`] created from the same radio sees the same set of peripherals.
+#[derive(Debug, Clone)]
+pub struct VirtualRadio {
+    pub(super) inner: Arc<RadioInner>,
+}
+
+#[derive(Debug)]
+pub(super) struct RadioInner {
+    id: DeviceId,
+    state: Mutex<RadioState>,
+    pub(super) events: broadcast::Sender<RadioEvent>,
+    /// The L2CAP listeners of the radio's adapters
+    #[cfg(feature = "l2cap")]
+    pub(super) l2cap_listeners: Listeners<(crate::Device, crate::L2capChannel)>,
+}
+
+#[derive(Debug)]
+struct RadioState {
+    powered: bool,
+    peripherals: Vec<VirtualPeripheral>,
+    max_advertising_sets: usize,
+    advertising_sets: usize,
+    servers: Vec<Weak<ServerState>>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum RadioEvent {
+    Powered(bool),
+    Removed(DeviceId),
+    Advertisement {
+        peripheral: VirtualPeripheral,
+        adv_data: Box<AdvertisementData>,
+        is_scan_response: bool,
+        rssi: Option<i16>,
+        timestamp: SystemTime,
+    },
+}
+
+impl Default for VirtualRadio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualRadio {
+    /// Creates a new, powered on, radio environment with no peripherals.
+    pub fn new() -> Self {
+        static NEXT_ADDRESS: AtomicU32 = AtomicU32::new(1);
+        let [a, b, c, d] = NEXT_ADDRESS.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+        VirtualRadio {
+            inner: Arc::new(RadioInner {
+                id: DeviceId([0xc0, 0xff, a, b, c, d]),
+                state: Mutex::new(RadioState {
+                    powered: true,
+                    peripherals: Vec::new(),
+                    max_advertising_sets: DEFAULT_ADVERTISING_SETS,
+                    advertising_sets: 0,
+                    servers: Vec::new(),
+                }),
+                events: broadcast::channel(256).0,
+                #[cfg(feature = "l2cap")]
+                l2cap_listeners: Listeners::default(),
+            }),
+        }
+    }
+
+    /// The process-wide radio used by [`Adapter::default()`].
+    pub fn global() -> VirtualRadio {
+        static GLOBAL: OnceLock<VirtualRadio> = OnceLock::new();
+        GLOBAL.get_or_init(VirtualRadio::new).clone()
+    }
+
+    /// This radio's unique identifier, which GATT servers started on the radio report as the identifier of the
+    /// centrals connected to them.
+    pub fn id(&self) -> DeviceId {
+        self.inner.id
+    }
+
+    /// Creates an [`Adapter`] attached to this radio.
+    pub fn adapter(&self) -> Adapter {
+        Adapter(AdapterImpl::new(self.clone()))
+    }
+
+    /// Adds `peripheral` to this radio. If the peripheral has advertisement data, running scans will receive it.
+    pub fn add_peripheral(&self, peripheral: &VirtualPeripheral) {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.peripherals.iter().any(|x| x.id() == peripheral.id()) {
+                return;
+            }
+            state.peripherals.push(peripheral.clone());
+        }
+        peripheral.attach(Arc::downgrade(&self.inner));
+        peripheral.advertise();
+    }
+
+    /// Removes `peripheral` from this radio, disconnecting it if it is connected. Running scans report it lost.
+    pub fn remove_peripheral(&self, peripheral: &VirtualPeripheral) {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .peripherals
+            .retain(|x| x.id() != peripheral.id());
+        peripheral.detach();
+        let _ = self.inner.events.send(RadioEvent::Removed(peripheral.id()));
+    }
+
+    /// All peripherals currently present on this radio.
+    pub fn peripherals(&self) -> Vec<VirtualPeripheral> {
+        self.inner.state.lock().unwrap().peripherals.clone()
+    }
+
+    /// Finds the peripheral identified by `id`.
+    pub fn peripheral(&self, id: &DeviceId) -> Option<VirtualPeripheral> {
+        self.inner.peripheral(id)
+    }
+
+    /// Sets how many advertising sets mock adapters on this radio can have active at the same time. The default is 4.
+    ///
+    /// Advertising sets that are already active keep advertising.
+    pub fn set_max_advertising_sets(&self, max: usize) {
+        self.inner.state.lock().unwrap().max_advertising_sets = max;
+    }
+
+    /// The number of further advertising sets mock adapters on this radio can start.
+    pub fn available_advertising_sets(&self) -> usize {
+        let state = self.inner.state.lock().unwrap();
+        state.max_advertising_sets.saturating_sub(state.advertising_sets)
+    }
+
+    /// Claims an advertising set, returning `false` if none are available.
+    pub(super) fn acquire_advertising_set(&self) -> bool {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.advertising_sets < state.max_advertising_sets {
+            state.advertising_sets += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns an advertising set claimed with [`Self::acquire_advertising_set`].
+    pub(super) fn release_advertising_set(&self) {
+        self.inner.state.lock().unwrap().advertising_sets -= 1;
+    }
+
+    /// Registers a GATT server, adding its services to the peripherals advertised by this radio's adapters.
+    pub(super) fn add_server(&self, server: &Arc<ServerState>) {
+        self.inner.state.lock().unwrap().servers.push(Arc::downgrade(server));
+        for peripheral in self.local_peripherals() {
+            for service in server.services() {
+                peripheral.add_service(service.clone());
+            }
+        }
+    }
+
+    /// Unregisters a GATT server, removing its services from the peripherals advertised by this radio's adapters.
+    pub(super) fn remove_server(&self, server: &ServerState) {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .servers
+            .retain(|x| !std::ptr::eq(x.as_ptr(), server));
+        for peripheral in self.local_peripherals() {
+            for service in server.services() {
+                peripheral.remove_service(service);
+            }
+        }
+    }
+
+    /// The services of the GATT servers running on this radio.
+    pub(super) fn local_services(&self) -> Vec<VirtualService> {
+        self.inner
+            .servers()
+            .iter()
+            .flat_map(|server| server.services().iter().cloned())
+            .collect()
+    }
+
+    /// The peripherals advertised by this radio's adapters.
+    pub(super) fn local_peripherals(&self) -> Vec<VirtualPeripheral> {
+        self.peripherals()
+            .into_iter()
+            .filter(VirtualPeripheral::is_local)
+            .collect()
+    }
+
+    /// Whether the radio is powered on.
+    pub fn is_powered(&self) -> bool {
+        self.inner.is_powered()
+    }
+
+    /// Powers the radio on or off.
+    ///
+    /// Powering the radio off disconnects all peripherals and ends any running scans.
+    pub fn set_powered(&self, powered: bool) {
+        let peripherals = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.powered == powered {
+                return;
+            }
+            state.powered = powered;
+            state.peripherals.clone()
+        };
+
+        if !powered {
+            for peripheral in peripherals {
+                peripheral.disconnect();
+            }
+        }
+
+        let _ = self.inner.events.send(RadioEvent::Powered(powered));
+    }
+}
+
+impl RadioInner {
+    pub(super) fn is_powered(&self) -> bool {
+        self.state.lock().unwrap().powered
+    }
+
+    /// The GATT servers running on this radio.
+    pub(super) fn servers(&self) -> Vec<Arc<ServerState>> {
+        self.state
+            .lock()
+            .unwrap()
+            .servers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    pub(super) fn peripheral(&self, id: &DeviceId) -> Option<VirtualPeripheral> {
+        self.state
+            .lock()
+            .unwrap()
+            .peripherals
+            .iter()
+            .find(|x| x.id() == *id)
+            .cloned()
+    }
+}
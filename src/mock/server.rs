@@ -0,0 +1,299 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use futures_core::Stream;
+use tokio::sync::broadcast;
+
+use super::broadcast_stream;
+use super::gatt::{VirtualCharacteristic, VirtualDescriptor, VirtualService};
+use super::peripheral::VirtualPeripheral;
+use super::radio::VirtualRadio;
+use crate::error::{AttError, ErrorKind};
+use crate::server::{
+    AttributeId, AttributePermissions, Central, ReadRequest, RequestHandler, SecurityLevel, ServerEvent,
+    ServiceDefinition, Subscription, WriteKind, WriteRequest,
+};
+use crate::{Error, Result, Uuid};
+
+/// A GATT server whose services are served by the peripherals advertised by the adapters of a virtual radio.
+///
+/// Every central connected to one of these peripherals is the radio itself, identified by
+/// [`VirtualRadio::id`].
+#[derive(Debug)]
+pub struct ServerImpl {
+    state: Arc<State>,
+}
+
+pub(crate) struct State {
+    radio: VirtualRadio,
+    services: Vec<VirtualService>,
+    handler: Arc<dyn RequestHandler>,
+    connections: Mutex<Connections>,
+    subscriptions: Mutex<Vec<Subscription>>,
+    events: broadcast::Sender<ServerEvent>,
+}
+
+#[derive(Debug, Default)]
+struct Connections {
+    /// The number of connected local peripherals
+    count: usize,
+    mtu: u16,
+}
+
+/// Ties a virtual characteristic or descriptor to the server which answers requests for it.
+#[derive(Debug)]
+pub(crate) struct Binding {
+    server: Weak<State>,
+    attribute: AttributeId,
+    permissions: AttributePermissions,
+}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("services", &self.services)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ServerImpl {
+    fn drop(&mut self) {
+        self.state.radio.remove_server(&self.state);
+        for service in &self.state.services {
+            service.disconnected();
+        }
+    }
+}
+
+impl ServerImpl {
+    /// Starts serving `services` from the peripherals advertised by the adapters of `radio`.
+    pub(super) fn start(
+        radio: &VirtualRadio,
+        services: Vec<ServiceDefinition>,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Self {
+        let state = Arc::new_cyclic(|server: &Weak<State>| {
+            let bind = |attribute, permissions| Binding {
+                server: server.clone(),
+                attribute,
+                permissions,
+            };
+            let services = services
+                .iter()
+                .map(|definition| {
+                    let mut service = VirtualService::new(definition.uuid);
+                    if !definition.primary {
+                        service = service.secondary();
+                    }
+                    for characteristic_definition in &definition.characteristics {
+                        let mut attribute = AttributeId {
+                            service: definition.uuid,
+                            characteristic: characteristic_definition.uuid,
+                            descriptor: None,
+                        };
+                        let mut characteristic = VirtualCharacteristic::new(
+                            characteristic_definition.uuid,
+                            characteristic_definition.properties,
+                        );
+                        let _ = characteristic
+                            .inner
+                            .binding
+                            .set(bind(attribute, characteristic_definition.permissions));
+                        for descriptor_definition in &characteristic_definition.descriptors {
+                            attribute.descriptor = Some(descriptor_definition.uuid);
+                            let descriptor = VirtualDescriptor::new(descriptor_definition.uuid);
+                            let _ = descriptor
+                                .inner
+                                .binding
+                                .set(bind(attribute, descriptor_definition.permissions));
+                            characteristic = characteristic.with_descriptor(descriptor);
+                        }
+                        service = service.with_characteristic(characteristic);
+                    }
+                    service
+                })
+                .collect();
+
+            // Centrals may already be connected to the peripherals the radio advertises
+            let connected = radio.local_peripherals().into_iter().filter(|x| x.is_connected());
+            let (count, mtu) = connected.fold((0, 0), |(count, _), x| (count + 1, x.mtu()));
+            State {
+                radio: radio.clone(),
+                services,
+                handler,
+                connections: Mutex::new(Connections { count, mtu }),
+                subscriptions: Mutex::new(Vec::new()),
+                events: broadcast::channel(64).0,
+            }
+        });
+
+        radio.add_server(&state);
+        ServerImpl { state }
+    }
+
+    pub fn centrals(&self) -> Vec<Central> {
+        self.state.central().into_iter().collect()
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.state.subscriptions.lock().unwrap().clone()
+    }
+
+    pub fn events(&self) -> impl Stream<Item = ServerEvent> + Send + Unpin {
+        broadcast_stream(self.state.events.subscribe())
+    }
+
+    pub async fn notify(&self, service: Uuid, characteristic: Uuid, value: &[u8]) -> Result<bool> {
+        let characteristic = self
+            .state
+            .services
+            .iter()
+            .filter(|x| x.uuid() == service)
+            .flat_map(|x| x.characteristics())
+            .find(|x| x.uuid() == characteristic)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, None, "the server has no such characteristic"))?;
+
+        let properties = characteristic.properties();
+        if !(properties.notify || properties.indicate) {
+            return Err(Error::new(
+                ErrorKind::NotSupported,
+                None,
+                "characteristic does not support notifications or indications",
+            ));
+        }
+
+        Ok(characteristic.notify(value) > 0)
+    }
+}
+
+impl State {
+    pub(super) fn services(&self) -> &[VirtualService] {
+        &self.services
+    }
+
+    /// Updates the connections to the local peripherals of the radio.
+    pub(super) fn connection_changed(&self, connected: bool, mtu: u16) {
+        let event = {
+            let mut connections = self.connections.lock().unwrap();
+            if connected {
+                connections.count += 1;
+                connections.mtu = mtu;
+                (connections.count == 1).then(|| ServerEvent::Connected(self.central_with_mtu(mtu)))
+            } else {
+                connections.count = connections.count.saturating_sub(1);
+                (connections.count == 0).then(|| ServerEvent::Disconnected(self.radio.id()))
+            }
+        };
+
+        if let Some(event) = event {
+            let _ = self.events.send(event);
+        }
+    }
+
+    fn central(&self) -> Option<Central> {
+        let connections = self.connections.lock().unwrap();
+        (connections.count > 0).then(|| self.central_with_mtu(connections.mtu))
+    }
+
+    fn central_with_mtu(&self, mtu: u16) -> Central {
+        Central {
+            id: self.radio.id(),
+            mtu,
+        }
+    }
+}
+
+impl Binding {
+    /// Reads the attribute from the handler, continuing with increasing offsets like a long read while the
+    /// responses fill the MTU.
+    pub(super) async fn read(&self, peripheral: &VirtualPeripheral) -> Result<Vec<u8>> {
+        let server = self.check(peripheral, self.permissions.read, AttError::READ_NOT_PERMITTED)?;
+        let mtu = peripheral.mtu();
+        // A read response has 1 byte of overhead (opcode)
+        let max_len = usize::from(mtu) - 1;
+        let mut value = Vec::new();
+        loop {
+            let request = ReadRequest {
+                central: server.radio.id(),
+                attribute: self.attribute,
+                offset: value.len() as u16,
+                mtu,
+            };
+            let mut chunk = server.handler.read(request).await.map_err(protocol_error)?;
+            let full = chunk.len() >= max_len;
+            chunk.truncate(max_len);
+            value.append(&mut chunk);
+            if !full || value.len() >= usize::from(u16::MAX) {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Writes the attribute with the handler. The errors of writes without response are not returned.
+    pub(super) async fn write(&self, peripheral: &VirtualPeripheral, value: &[u8], kind: WriteKind) -> Result<()> {
+        let server = self.check(peripheral, self.permissions.write, AttError::WRITE_NOT_PERMITTED)?;
+        let request = WriteRequest {
+            central: server.radio.id(),
+            attribute: self.attribute,
+            offset: 0,
+            mtu: peripheral.mtu(),
+            value: value.to_vec(),
+            kind,
+        };
+        match server.handler.write(request).await {
+            Err(err) if kind != WriteKind::WithoutResponse => Err(protocol_error(err)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the first subscription of the central to the characteristic.
+    pub(super) fn subscribed(&self, indications: bool) {
+        if let Some(server) = self.server.upgrade() {
+            let subscription = Subscription {
+                attribute: self.attribute,
+                central: Some(server.radio.id()),
+                indications,
+            };
+            server.subscriptions.lock().unwrap().push(subscription.clone());
+            let _ = server.events.send(ServerEvent::Subscribed(subscription));
+        }
+    }
+
+    /// Records the end of the last subscription of the central to the characteristic.
+    pub(super) fn unsubscribed(&self) {
+        if let Some(server) = self.server.upgrade() {
+            let subscription = {
+                let mut subscriptions = server.subscriptions.lock().unwrap();
+                let index = subscriptions.iter().position(|x| x.attribute == self.attribute);
+                index.map(|index| subscriptions.remove(index))
+            };
+            if let Some(subscription) = subscription {
+                let _ = server.events.send(ServerEvent::Unsubscribed(subscription));
+            }
+        }
+    }
+
+    /// Checks that the attribute permits the access with the security of the connection to `peripheral`, which is
+    /// encrypted and authenticated once the peripheral is paired.
+    fn check(
+        &self,
+        peripheral: &VirtualPeripheral,
+        permission: Option<SecurityLevel>,
+        err: AttError,
+    ) -> Result<Arc<State>> {
+        let server = self
+            .server
+            .upgrade()
+            .ok_or_else(|| protocol_error(AttError::INVALID_HANDLE))?;
+        match permission {
+            None => Err(protocol_error(err)),
+            Some(level) if level > SecurityLevel::None && !peripheral.is_paired() => {
+                Err(protocol_error(AttError::INSUFFICIENT_AUTHENTICATION))
+            }
+            Some(_) => Ok(server),
+        }
+    }
+}
+
+fn protocol_error(err: AttError) -> Error {
+    ErrorKind::Protocol(err).into()
+}
@@ -0,0 +1,106 @@
+use super::gatt::VirtualService;
+use super::peripheral::VirtualPeripheral;
+use super::Operation;
+use crate::{Characteristic, Result, Service, Uuid};
+
+/// A Bluetooth GATT service
+#[derive(Debug, Clone)]
+pub struct ServiceImpl {
+    pub(super) inner: VirtualService,
+    peripheral: VirtualPeripheral,
+}
+
+impl PartialEq for ServiceImpl {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for ServiceImpl {}
+
+impl std::hash::Hash for ServiceImpl {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::sync::Arc::as_ptr(&self.inner.inner).hash(state);
+    }
+}
+
+impl Service {
+    pub(super) fn new(peripheral: VirtualPeripheral, inner: VirtualService) -> Service {
+        Service(ServiceImpl { inner, peripheral })
+    }
+}
+
+impl ServiceImpl {
+    /// The [`Uuid`] identifying the type of this GATT service
+    pub fn uuid(&self) -> Uuid {
+        self.inner.uuid()
+    }
+
+    /// The [`Uuid`] identifying the type of this GATT service
+    pub async fn uuid_async(&self) -> Result<Uuid> {
+        Ok(self.inner.uuid())
+    }
+
+    /// Whether this is a primary service of the device.
+    pub async fn is_primary(&self) -> Result<bool> {
+        Ok(self.inner.is_primary())
+    }
+
+    /// Discover all characteristics associated with this service.
+    pub async fn discover_characteristics(&self) -> Result<Vec<Characteristic>> {
+        self.characteristics().await
+    }
+
+    /// Discover the characteristic(s) with the given [`Uuid`].
+    pub async fn discover_characteristics_with_uuid(&self, uuid: Uuid) -> Result<Vec<Characteristic>> {
+        Ok(self
+            .characteristics()
+            .await?
+            .into_iter()
+            .filter(|x| x.uuid() == uuid)
+            .collect())
+    }
+
+    /// Get previously discovered characteristics.
+    ///
+    /// If no characteristics have been discovered yet, this method will perform characteristic discovery.
+    pub async fn characteristics(&self) -> Result<Vec<Characteristic>> {
+        self.peripheral.check_connected()?;
+        self.peripheral.inner.faults.check(Operation::DiscoverServices)?;
+        Ok(self
+            .inner
+            .characteristics()
+            .into_iter()
+            .map(|x| Characteristic::new(self.peripheral.clone(), x))
+            .collect())
+    }
+
+    /// Discover the included services of this service.
+    pub async fn discover_included_services(&self) -> Result<Vec<Service>> {
+        self.included_services().await
+    }
+
+    /// Discover the included service(s) with the given [`Uuid`].
+    pub async fn discover_included_services_with_uuid(&self, uuid: Uuid) -> Result<Vec<Service>> {
+        Ok(self
+            .included_services()
+            .await?
+            .into_iter()
+            .filter(|x| x.uuid() == uuid)
+            .collect())
+    }
+
+    /// Get previously discovered included services.
+    ///
+    /// If no included services have been discovered yet, this method will perform included service discovery.
+    pub async fn included_services(&self) -> Result<Vec<Service>> {
+        self.peripheral.check_connected()?;
+        self.peripheral.inner.faults.check(Operation::DiscoverServices)?;
+        Ok(self
+            .inner
+            .included_services()
+            .into_iter()
+            .map(|x| Service::new(self.peripheral.clone(), x))
+            .collect())
+    }
+}
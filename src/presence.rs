@@ -0,0 +1,589 @@
+//! Presence and region monitoring
+//!
+//! A [`PresenceMonitor`] watches advertisements for devices in a set of [`Region`]s, and reports when each device
+//! enters or exits a region and when its signal strength changes while it is inside. A region is a set of devices, an
+//! iBeacon UUID with an optional major and minor number, or any predicate on advertisements.
+//!
+//! A device enters a region once it has been seen in the region with a signal strength of at least the enter threshold
+//! for the region's dwell time, and exits once it has not been seen with a signal strength of at least the exit
+//! threshold for the region's exit timeout. Setting the exit threshold below the enter threshold stops devices at the
+//! edge of the range from repeatedly entering and exiting.
+//!
+//! The monitor takes the time from a [`Clock`], so it can be tested with a [`VirtualClock`] and advertisements passed
+//! to [`PresenceMonitor::process`] instead of a real scan.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use bluest::presence::{PresenceEvent, PresenceMonitor, Region};
+//! use bluest::{Adapter, Uuid};
+//! use futures_lite::StreamExt;
+//!
+//! # async fn example(adapter: Adapter, uuid: Uuid) -> bluest::Result<()> {
+//! let monitor = PresenceMonitor::new();
+//! let lobby = monitor.add_region(
+//!     Region::ibeacon(uuid, Some(1), None)
+//!         .with_rssi_thresholds(-70, -80)
+//!         .with_exit_timeout(Duration::from_secs(30)),
+//! );
+//! let scan = adapter.scan(&[]).await?;
+//! let mut events = monitor.events(scan);
+//! while let Some(event) = events.next().await {
+//!     match event {
+//!         PresenceEvent::Entered { region, device, .. } if region == lobby => println!("{device:?} arrived"),
+//!         PresenceEvent::Exited { region, device } if region == lobby => println!("{device:?} left"),
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+
+use crate::beacon::IBeacon;
+use crate::util::Timer;
+use crate::{AdvertisingDevice, Device, DeviceId, ScanFilter, Uuid};
+
+/// The default time after which a device which is no longer seen exits a region
+pub const DEFAULT_EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default change in signal strength, in dB, which is reported as [`PresenceEvent::RangeChanged`]
+pub const DEFAULT_RANGE_THRESHOLD: u16 = 5;
+
+/// A source of the current time for a [`PresenceMonitor`]
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// The current time
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves forward when it is advanced, for testing
+///
+/// Clones of a virtual clock share the same time.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl VirtualClock {
+    /// Creates a clock which starts at the current time.
+    pub fn new() -> Self {
+        VirtualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Identifies a region added to a [`PresenceMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionId(u64);
+
+/// A change in the presence of a device in a region
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresenceEvent {
+    /// The device entered the region
+    Entered {
+        /// The region the device entered
+        region: RegionId,
+        /// The device
+        device: Device,
+        /// The signal strength in dBm of the advertisement which completed the dwell time
+        rssi: Option<i16>,
+    },
+    /// The device exited the region
+    Exited {
+        /// The region the device exited
+        region: RegionId,
+        /// The device
+        device: Device,
+    },
+    /// The signal strength of a device inside the region changed by at least the region's range threshold
+    RangeChanged {
+        /// The region the device is in
+        region: RegionId,
+        /// The device
+        device: Device,
+        /// The new signal strength in dBm
+        rssi: i16,
+    },
+}
+
+/// A set of devices whose presence is monitored by a [`PresenceMonitor`]
+#[derive(Clone)]
+pub struct Region {
+    matcher: Matcher,
+    enter_rssi: Option<i16>,
+    exit_rssi: Option<i16>,
+    dwell_time: Duration,
+    exit_timeout: Duration,
+    range_threshold: u16,
+}
+
+#[derive(Clone)]
+enum Matcher {
+    Devices(HashSet<DeviceId>),
+    IBeacon {
+        uuid: Uuid,
+        major: Option<u16>,
+        minor: Option<u16>,
+    },
+    Filter(ScanFilter),
+    Predicate(Arc<dyn Fn(&AdvertisingDevice) -> bool + Send + Sync>),
+}
+
+impl Region {
+    fn new(matcher: Matcher) -> Self {
+        Region {
+            matcher,
+            enter_rssi: None,
+            exit_rssi: None,
+            dwell_time: Duration::ZERO,
+            exit_timeout: DEFAULT_EXIT_TIMEOUT,
+            range_threshold: DEFAULT_RANGE_THRESHOLD,
+        }
+    }
+
+    /// A region containing the devices with the given identifiers.
+    pub fn devices(ids: impl IntoIterator<Item = DeviceId>) -> Self {
+        Self::new(Matcher::Devices(ids.into_iter().collect()))
+    }
+
+    /// A region containing the iBeacons with proximity UUID `uuid`, and the given major and minor numbers if they are
+    /// `Some`.
+    pub fn ibeacon(uuid: Uuid, major: Option<u16>, minor: Option<u16>) -> Self {
+        Self::new(Matcher::IBeacon { uuid, major, minor })
+    }
+
+    /// A region containing the devices whose manufacturer specific data for `company_id` satisfies `predicate`.
+    pub fn manufacturer_data(company_id: u16, predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        Self::matching(move |device| device.adv_data.manufacturer_data.get_all(company_id).any(&predicate))
+    }
+
+    /// A region containing the devices whose advertisements match `filter`.
+    pub fn filter(filter: ScanFilter) -> Self {
+        Self::new(Matcher::Filter(filter))
+    }
+
+    /// A region containing the devices whose advertisements satisfy `predicate`.
+    pub fn matching(predicate: impl Fn(&AdvertisingDevice) -> bool + Send + Sync + 'static) -> Self {
+        Self::new(Matcher::Predicate(Arc::new(predicate)))
+    }
+
+    /// Sets the signal strengths in dBm a device must be seen with to enter the region, and to stay in it.
+    ///
+    /// If `exit` is greater than `enter`, `enter` is used for both. Advertisements without a signal strength never
+    /// reach a threshold.
+    pub fn with_rssi_thresholds(mut self, enter: i16, exit: i16) -> Self {
+        self.enter_rssi = Some(enter);
+        self.exit_rssi = Some(exit.min(enter));
+        self
+    }
+
+    /// Sets how long a device must be seen before it enters the region. The default is zero, so a device enters the
+    /// region with its first advertisement.
+    ///
+    /// The dwell time restarts if the device is seen with a signal strength below the enter threshold, or is not seen
+    /// for the exit timeout.
+    pub fn with_dwell_time(mut self, dwell_time: Duration) -> Self {
+        self.dwell_time = dwell_time;
+        self
+    }
+
+    /// Sets how long a device must not be seen before it exits the region. The default is
+    /// [`DEFAULT_EXIT_TIMEOUT`].
+    pub fn with_exit_timeout(mut self, timeout: Duration) -> Self {
+        self.exit_timeout = timeout;
+        self
+    }
+
+    /// Sets the change in signal strength, in dB, which is reported as [`PresenceEvent::RangeChanged`]. The default
+    /// is [`DEFAULT_RANGE_THRESHOLD`]. Zero reports every advertisement.
+    pub fn with_range_threshold(mut self, threshold: u16) -> Self {
+        self.range_threshold = threshold;
+        self
+    }
+
+    /// Returns `true` if `device` belongs to this region, regardless of its signal strength.
+    pub fn matches(&self, device: &AdvertisingDevice) -> bool {
+        match &self.matcher {
+            Matcher::Devices(ids) => ids.contains(&device.device.id()),
+            Matcher::IBeacon { uuid, major, minor } => IBeacon::from_advertisement(&device.adv_data).is_some_and(|x| {
+                x.uuid == *uuid
+                    && major.is_none_or(|major| x.major == major)
+                    && minor.is_none_or(|minor| x.minor == minor)
+            }),
+            Matcher::Filter(filter) => filter.matches(device),
+            Matcher::Predicate(predicate) => predicate(device),
+        }
+    }
+
+    fn reaches(threshold: Option<i16>, rssi: Option<i16>) -> bool {
+        match threshold {
+            Some(threshold) => rssi.is_some_and(|rssi| rssi >= threshold),
+            None => true,
+        }
+    }
+}
+
+impl std::fmt::Debug for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("Region");
+        match &self.matcher {
+            Matcher::Devices(ids) => f.field("devices", ids),
+            Matcher::IBeacon { uuid, major, minor } => {
+                f.field("uuid", uuid).field("major", major).field("minor", minor)
+            }
+            Matcher::Filter(filter) => f.field("filter", filter),
+            Matcher::Predicate(_) => f.field("predicate", &format_args!("..")),
+        };
+        f.field("enter_rssi", &self.enter_rssi)
+            .field("exit_rssi", &self.exit_rssi)
+            .field("dwell_time", &self.dwell_time)
+            .field("exit_timeout", &self.exit_timeout)
+            .field("range_threshold", &self.range_threshold)
+            .finish()
+    }
+}
+
+/// Monitors the presence of devices in a set of regions.
+///
+/// Clones of a monitor share the same regions and state, so regions can be added and removed while another task
+/// consumes [`PresenceMonitor::events`].
+#[derive(Debug, Clone)]
+pub struct PresenceMonitor {
+    inner: Arc<Mutex<MonitorState>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct MonitorState {
+    next_id: u64,
+    regions: BTreeMap<RegionId, RegionState>,
+}
+
+#[derive(Debug)]
+struct RegionState {
+    region: Region,
+    devices: HashMap<DeviceId, Presence>,
+}
+
+#[derive(Debug)]
+struct Presence {
+    device: Device,
+    last_seen: Instant,
+    state: PresenceState,
+}
+
+#[derive(Debug)]
+enum PresenceState {
+    /// The device has been seen for less than the dwell time
+    Pending { since: Instant },
+    /// The device is in the region
+    Inside { reported_rssi: Option<i16> },
+}
+
+impl PresenceMonitor {
+    /// Creates a monitor with no regions, which uses the [`SystemClock`].
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+
+    /// Creates a monitor with no regions, which takes the time from `clock`.
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        PresenceMonitor {
+            inner: Arc::new(Mutex::new(MonitorState::default())),
+            clock: Arc::new(clock),
+        }
+    }
+
+    /// Starts monitoring `region`.
+    pub fn add_region(&self, region: Region) -> RegionId {
+        let mut state = self.inner.lock().unwrap();
+        let id = RegionId(state.next_id);
+        state.next_id += 1;
+        state.regions.insert(
+            id,
+            RegionState {
+                region,
+                devices: HashMap::new(),
+            },
+        );
+        id
+    }
+
+    /// Stops monitoring the region with `id`, without reporting the devices inside it as exited.
+    ///
+    /// Returns `false` if there is no such region.
+    pub fn remove_region(&self, id: RegionId) -> bool {
+        self.inner.lock().unwrap().regions.remove(&id).is_some()
+    }
+
+    /// The devices currently inside the region with `id`.
+    pub fn present(&self, id: RegionId) -> Vec<Device> {
+        let state = self.inner.lock().unwrap();
+        let Some(region) = state.regions.get(&id) else {
+            return Vec::new();
+        };
+        region
+            .devices
+            .values()
+            .filter(|x| matches!(x.state, PresenceState::Inside { .. }))
+            .map(|x| x.device.clone())
+            .collect()
+    }
+
+    /// Updates the regions with one advertisement, received at the clock's current time.
+    ///
+    /// Returns the resulting [`PresenceEvent::Entered`] and [`PresenceEvent::RangeChanged`] events.
+    pub fn process(&self, adv: &AdvertisingDevice) -> Vec<PresenceEvent> {
+        let now = self.clock.now();
+        let mut state = self.inner.lock().unwrap();
+        let mut events = Vec::new();
+        for (&id, region_state) in &mut state.regions {
+            let region = &region_state.region;
+            if !region.matches(adv) {
+                continue;
+            }
+
+            let device_id = adv.device.id();
+            let enters = Region::reaches(region.enter_rssi, adv.rssi);
+            let Some(presence) = region_state.devices.get_mut(&device_id) else {
+                if enters {
+                    let presence = Presence {
+                        device: adv.device.clone(),
+                        last_seen: now,
+                        state: PresenceState::Pending { since: now },
+                    };
+                    region_state.devices.insert(adv.device.id(), presence);
+                    events.extend(region_state.update_pending(id, &device_id, adv.rssi, now));
+                }
+                continue;
+            };
+
+            match presence.state {
+                PresenceState::Pending { .. } if !enters => {
+                    region_state.devices.remove(&device_id);
+                }
+                PresenceState::Pending { ref mut since } => {
+                    if now.saturating_duration_since(presence.last_seen) > region.exit_timeout {
+                        *since = now;
+                    }
+                    presence.device = adv.device.clone();
+                    presence.last_seen = now;
+                    events.extend(region_state.update_pending(id, &device_id, adv.rssi, now));
+                }
+                PresenceState::Inside { ref mut reported_rssi } => {
+                    if !Region::reaches(region.exit_rssi, adv.rssi) {
+                        continue;
+                    }
+                    presence.device = adv.device.clone();
+                    presence.last_seen = now;
+                    let Some(rssi) = adv.rssi else { continue };
+                    if reported_rssi.is_none_or(|x| rssi.abs_diff(x) >= region.range_threshold) {
+                        *reported_rssi = Some(rssi);
+                        events.push(PresenceEvent::RangeChanged {
+                            region: id,
+                            device: presence.device.clone(),
+                            rssi,
+                        });
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// Removes the devices which have not been seen for their region's exit timeout at the clock's current time.
+    ///
+    /// Returns a [`PresenceEvent::Exited`] event for each device which was inside its region.
+    /// [`PresenceMonitor::events`] calls this whenever a device is due to exit, but it must be called directly after
+    /// advancing a [`VirtualClock`].
+    pub fn tick(&self) -> Vec<PresenceEvent> {
+        let now = self.clock.now();
+        let mut state = self.inner.lock().unwrap();
+        let mut events = Vec::new();
+        for (&id, region_state) in &mut state.regions {
+            let timeout = region_state.region.exit_timeout;
+            region_state.devices.retain(|_, presence| {
+                if now.saturating_duration_since(presence.last_seen) < timeout {
+                    return true;
+                }
+                if let PresenceState::Inside { .. } = presence.state {
+                    events.push(PresenceEvent::Exited {
+                        region: id,
+                        device: presence.device.clone(),
+                    });
+                }
+                false
+            });
+        }
+        events
+    }
+
+    /// The next time at which a device is due to exit a region, if any.
+    fn next_deadline(&self) -> Option<Instant> {
+        let state = self.inner.lock().unwrap();
+        state
+            .regions
+            .values()
+            .flat_map(|region_state| {
+                let timeout = region_state.region.exit_timeout;
+                region_state.devices.values().map(move |x| x.last_seen + timeout)
+            })
+            .min()
+    }
+
+    /// Processes every advertisement from `scan`, and returns a stream of the resulting events.
+    ///
+    /// The stream ends when `scan` ends.
+    pub fn events<S>(&self, scan: S) -> PresenceEvents<S>
+    where
+        S: Stream<Item = AdvertisingDevice> + Unpin,
+    {
+        PresenceEvents {
+            monitor: self.clone(),
+            scan,
+            pending: Vec::new(),
+            timer: None,
+        }
+    }
+}
+
+impl Default for PresenceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegionState {
+    /// Moves a pending device inside the region if it has been seen for the dwell time.
+    fn update_pending(
+        &mut self,
+        region: RegionId,
+        id: &DeviceId,
+        rssi: Option<i16>,
+        now: Instant,
+    ) -> Option<PresenceEvent> {
+        let presence = self.devices.get_mut(id)?;
+        let PresenceState::Pending { since } = presence.state else {
+            return None;
+        };
+        if now.saturating_duration_since(since) < self.region.dwell_time {
+            return None;
+        }
+        presence.state = PresenceState::Inside { reported_rssi: rssi };
+        Some(PresenceEvent::Entered {
+            region,
+            device: presence.device.clone(),
+            rssi,
+        })
+    }
+}
+
+/// The stream of events returned by [`PresenceMonitor::events`]
+pub struct PresenceEvents<S> {
+    monitor: PresenceMonitor,
+    scan: S,
+    /// Events not yet returned, in reverse order
+    pending: Vec<PresenceEvent>,
+    timer: Option<(Instant, Timer)>,
+}
+
+impl<S> PresenceEvents<S> {
+    /// Starts a timer for the next exit deadline, unless one is already running for an earlier time.
+    fn schedule(&mut self) {
+        let Some(deadline) = self.monitor.next_deadline() else {
+            self.timer = None;
+            return;
+        };
+        if self.timer.as_ref().is_some_and(|(x, _)| *x <= deadline) {
+            return;
+        }
+        let delay = deadline.saturating_duration_since(self.monitor.clock.now());
+        self.timer = Some((deadline, Timer::after(delay)));
+    }
+
+    fn push(&mut self, events: Vec<PresenceEvent>) {
+        self.pending.splice(0..0, events.into_iter().rev());
+    }
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for PresenceEvents<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PresenceEvents")
+            .field("monitor", &self.monitor)
+            .field("scan", &self.scan)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl<S> Stream for PresenceEvents<S>
+where
+    S: Stream<Item = AdvertisingDevice> + Unpin,
+{
+    type Item = PresenceEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.pending.pop() {
+                return Poll::Ready(Some(event));
+            }
+
+            if let Some((_, timer)) = &mut this.timer {
+                if Pin::new(timer).poll(cx).is_ready() {
+                    this.timer = None;
+                    let events = this.monitor.tick();
+                    this.push(events);
+                    this.schedule();
+                    continue;
+                }
+            }
+
+            match Pin::new(&mut this.scan).poll_next(cx) {
+                Poll::Ready(Some(adv)) => {
+                    let events = this.monitor.process(&adv);
+                    this.push(events);
+                    this.schedule();
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
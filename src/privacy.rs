@@ -0,0 +1,287 @@
+//! Resolution of resolvable private addresses
+//!
+//! Devices using LE privacy advertise with a resolvable private address (RPA) which changes periodically, typically
+//! every 15 minutes, so one physical device appears as many [`DeviceId`]s over time. Each RPA is generated from the
+//! device's identity resolving key (IRK), which the device shares with its peers when it bonds. A [`Keyring`] holds the
+//! known IRKs, and recognises the identity of the device behind an RPA (see the Bluetooth Core Specification, Vol 6,
+//! Part B, §1.3.2.3).
+//!
+//! The address of a device is only available on Linux, Windows and Android. CoreBluetooth resolves the addresses of
+//! bonded devices itself and hides them from applications, so on macOS and iOS nothing is resolved.
+//!
+//! ```rust,no_run
+//! use bluest::privacy::{Identity, Irk, Keyring};
+//! use bluest::{Adapter, AddressType};
+//! use futures_lite::StreamExt;
+//!
+//! # async fn example(adapter: Adapter) -> bluest::Result<()> {
+//! let mut keyring = Keyring::new();
+//! keyring.add(
+//!     Irk::from_u128(0xec0234a3_57c8ad05_341010a6_0a397d9b),
+//!     Identity::new([0xc0, 0x11, 0x22, 0x33, 0x44, 0x55], AddressType::Random),
+//! );
+//! let scan = adapter.scan(&[]).await?;
+//! let mut scan = keyring.resolve_scan(scan);
+//! while let Some(device) = scan.next().await {
+//!     if let Some(identity) = device.identity {
+//!         println!("{identity} is advertising as {:?}", device.adv.device);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes128, Block};
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+use crate::{AddressType, AdvertisingDevice, DeviceId};
+
+/// An identity resolving key
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Irk([u8; 16]);
+
+impl Irk {
+    /// Creates a key from its bytes, most significant byte first as in the Core Specification.
+    pub const fn from_be_bytes(bytes: [u8; 16]) -> Self {
+        Irk(bytes)
+    }
+
+    /// Creates a key from its bytes, least significant byte first as sent over the air by the Security Manager.
+    pub fn from_le_bytes(mut bytes: [u8; 16]) -> Self {
+        bytes.reverse();
+        Irk(bytes)
+    }
+
+    /// Creates a key from its 128-bit value.
+    pub const fn from_u128(value: u128) -> Self {
+        Irk(value.to_be_bytes())
+    }
+
+    /// The bytes of the key, most significant byte first.
+    pub const fn to_be_bytes(&self) -> [u8; 16] {
+        self.0
+    }
+
+    /// Returns `true` if `address` is a resolvable private address generated from this key.
+    ///
+    /// `address` is most significant byte first, as displayed.
+    pub fn resolves(&self, address: [u8; 6]) -> bool {
+        let [p0, p1, p2, h0, h1, h2] = address;
+        p0 >> 6 == 0b01 && ah(self, u32::from_be_bytes([0, p0, p1, p2])) == u32::from_be_bytes([0, h0, h1, h2])
+    }
+
+    /// Generates the resolvable private address for `prand`, of which the least significant 22 bits are used.
+    ///
+    /// The random part of the address should be new each time the address changes, and not all 0 or all 1 bits.
+    pub fn resolvable_private_address(&self, prand: u32) -> [u8; 6] {
+        let prand = (prand & 0x3f_ffff) | 0x40_0000;
+        let [_, p0, p1, p2] = prand.to_be_bytes();
+        let [_, h0, h1, h2] = ah(self, prand).to_be_bytes();
+        [p0, p1, p2, h0, h1, h2]
+    }
+}
+
+impl std::fmt::Debug for Irk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keys are secrets, so they are kept out of logs
+        f.write_str("Irk(..)")
+    }
+}
+
+/// The random address hash function `ah` (Core Specification, Vol 3, Part H, §2.2.2)
+///
+/// Returns the 24-bit hash of the 24-bit value `r`, which is the least significant 24 bits of the AES-128 encryption
+/// of `r` under `k`.
+pub fn ah(k: &Irk, r: u32) -> u32 {
+    let cipher = Aes128::new(&k.0.into());
+    let mut block = Block::default();
+    block[13..].copy_from_slice(&r.to_be_bytes()[1..]);
+    cipher.encrypt_block(&mut block);
+    u32::from_be_bytes([0, block[13], block[14], block[15]])
+}
+
+/// The kind of a Bluetooth LE device address (Core Specification, Vol 6, Part B, §1.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressKind {
+    /// A public address registered with the IEEE
+    Public,
+    /// A random address which does not change while the device is powered
+    RandomStatic,
+    /// A random address which can be resolved with the device's identity resolving key
+    ResolvablePrivate,
+    /// A random address which cannot be resolved
+    NonResolvablePrivate,
+}
+
+impl AddressKind {
+    /// Classifies `address`, most significant byte first, of type `address_type`.
+    ///
+    /// Returns `None` for random addresses with the reserved most significant bits `0b10`.
+    pub fn classify(address: [u8; 6], address_type: AddressType) -> Option<Self> {
+        match address_type {
+            AddressType::Public => Some(AddressKind::Public),
+            AddressType::Random => match address[0] >> 6 {
+                0b11 => Some(AddressKind::RandomStatic),
+                0b01 => Some(AddressKind::ResolvablePrivate),
+                0b00 => Some(AddressKind::NonResolvablePrivate),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The identity address of a device, which it shares with its IRK when it bonds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Identity {
+    /// The address, most significant byte first
+    pub address: [u8; 6],
+    /// The type of the address, which is public or a random static address
+    pub address_type: AddressType,
+}
+
+impl Identity {
+    /// Creates an identity from its address, most significant byte first, and address type.
+    pub const fn new(address: [u8; 6], address_type: AddressType) -> Self {
+        Identity { address, address_type }
+    }
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.address;
+        write!(f, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{g:02X}")?;
+        match self.address_type {
+            AddressType::Public => f.write_str(" (public)"),
+            AddressType::Random => f.write_str(" (random)"),
+        }
+    }
+}
+
+/// An advertisement tagged with the identity of its device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedDevice {
+    /// The advertisement
+    pub adv: AdvertisingDevice,
+    /// The address the device advertised with, if the platform reports it
+    pub address: Option<[u8; 6]>,
+    /// The kind of the address, if the address and its type are known
+    pub address_kind: Option<AddressKind>,
+    /// The identity of the device, if the address is a resolvable private address generated by a key in the keyring or
+    /// is itself the identity address of a key in the keyring
+    pub identity: Option<Identity>,
+}
+
+/// A set of identity resolving keys and the identities they belong to
+#[derive(Debug, Default, Clone)]
+pub struct Keyring {
+    keys: Vec<(Irk, Identity)>,
+}
+
+impl Keyring {
+    /// Creates an empty keyring.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the key of the device with `identity`, replacing any previous key for that identity.
+    pub fn add(&mut self, irk: Irk, identity: Identity) {
+        self.remove(&identity);
+        self.keys.push((irk, identity));
+    }
+
+    /// Removes the key of the device with `identity`. Returns `false` if there was none.
+    pub fn remove(&mut self, identity: &Identity) -> bool {
+        let len = self.keys.len();
+        self.keys.retain(|(_, x)| x != identity);
+        self.keys.len() != len
+    }
+
+    /// The number of keys in the keyring.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the keyring has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The identity whose key generated the resolvable private address `address`, or whose identity address is
+    /// `address`.
+    pub fn resolve(&self, address: [u8; 6]) -> Option<Identity> {
+        self.keys
+            .iter()
+            .find(|(irk, identity)| identity.address == address || irk.resolves(address))
+            .map(|(_, identity)| *identity)
+    }
+
+    /// Tags `adv` with the kind of its address and the identity of its device.
+    pub fn resolve_device(&self, adv: AdvertisingDevice) -> ResolvedDevice {
+        let address = device_address(&adv.device.id());
+        let address_kind = address
+            .zip(adv.address_type)
+            .and_then(|(a, t)| AddressKind::classify(a, t));
+        let identity = address.and_then(|address| {
+            let identity = self.resolve(address)?;
+            // A random address cannot be the public identity address of a device, or the other way around
+            match adv.address_type {
+                Some(address_type) if identity.address == address && identity.address_type != address_type => None,
+                _ => Some(identity),
+            }
+        });
+        ResolvedDevice {
+            adv,
+            address,
+            address_kind,
+            identity,
+        }
+    }
+
+    /// Tags every advertisement from `scan` with the kind of its address and the identity of its device.
+    pub fn resolve_scan<S>(self, scan: S) -> impl Stream<Item = ResolvedDevice> + Send + Unpin
+    where
+        S: Stream<Item = AdvertisingDevice> + Send + Unpin,
+    {
+        scan.map(move |adv| self.resolve_device(adv))
+    }
+}
+
+/// The Bluetooth address of the device with `id`, most significant byte first, if the platform reports it.
+#[cfg(any(feature = "mock", target_os = "linux"))]
+fn device_address(id: &DeviceId) -> Option<[u8; 6]> {
+    Some(id.address())
+}
+
+#[cfg(all(target_os = "android", not(feature = "mock")))]
+fn device_address(id: &DeviceId) -> Option<[u8; 6]> {
+    parse_address(&id.0)
+}
+
+/// Windows device identifiers end with the device address, as in `BluetoothLE#BluetoothLE<adapter>-<device>`.
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
+fn device_address(id: &DeviceId) -> Option<[u8; 6]> {
+    let id = id.0.to_str()?;
+    parse_address(id.get(id.len().checked_sub(17)?..)?)
+}
+
+#[cfg(all(any(target_os = "macos", target_os = "ios"), not(feature = "mock")))]
+fn device_address(_id: &DeviceId) -> Option<[u8; 6]> {
+    None
+}
+
+/// Parses an address in the format `AB:CD:EF:01:23:45`.
+#[cfg(all(any(target_os = "android", target_os = "windows"), not(feature = "mock")))]
+fn parse_address(s: &str) -> Option<[u8; 6]> {
+    let mut address = [0; 6];
+    let mut parts = s.split(':');
+    for byte in &mut address {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(address)
+}
@@ -0,0 +1,335 @@
+//! Distance estimation from signal strength
+//!
+//! A [`ProximityEstimator`] turns advertisements into [`DistanceEstimate`]s with the log-distance path loss model:
+//!
+//! ```text
+//! distance = 10 ^ ((rssi_at_1m - rssi) / (10 * exponent))
+//! ```
+//!
+//! where `rssi_at_1m` is the signal strength measured 1 m from the device, and `exponent` describes the environment:
+//! 2 in free space, and typically between 2.5 and 4 indoors. The signal strength of each device is smoothed by an
+//! [`RssiFilter`] before the distance is calculated, since individual measurements vary by several dB.
+//!
+//! Signal strength is a poor measure of distance, as it depends on the orientation of both antennas and everything
+//! between them, so the coarse [`Zone`] of an estimate is often more useful than its distance.
+//!
+//! ```rust,no_run
+//! use bluest::proximity::{Kalman, ProximityEstimator, Zone};
+//! use bluest::Adapter;
+//! use futures_lite::StreamExt;
+//!
+//! # async fn example(adapter: Adapter) -> bluest::Result<()> {
+//! let estimator = ProximityEstimator::new()
+//!     .with_path_loss_exponent(2.5)
+//!     .with_filter(Kalman::default);
+//! let scan = adapter.scan(&[]).await?;
+//! let mut estimates = estimator.estimates(scan);
+//! while let Some(estimate) = estimates.next().await {
+//!     if estimate.zone == Zone::Immediate {
+//!         println!("{:?} is within reach", estimate.device);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+use crate::beacon::{AltBeacon, Beacon, Eddystone, IBeacon};
+use crate::{AdvertisingDevice, Device, DeviceId};
+
+/// The path loss in dB over the first metre, used to convert a transmit power to a signal strength at 1 m
+pub const PATH_LOSS_AT_1M: i16 = 41;
+
+/// The default path loss exponent, for free space
+pub const DEFAULT_PATH_LOSS_EXPONENT: f64 = 2.0;
+
+/// The default upper limit in metres of [`Zone::Immediate`]
+pub const DEFAULT_IMMEDIATE_LIMIT: f64 = 0.5;
+
+/// The default upper limit in metres of [`Zone::Near`]
+pub const DEFAULT_NEAR_LIMIT: f64 = 3.0;
+
+/// Estimates the distance in metres at which a signal strength of `rssi` is received from a device, given the signal
+/// strength `rssi_at_1m` measured at 1 m from it, with the log-distance path loss model.
+pub fn distance(rssi: f64, rssi_at_1m: f64, path_loss_exponent: f64) -> f64 {
+    10f64.powf((rssi_at_1m - rssi) / (10.0 * path_loss_exponent))
+}
+
+/// A coarse distance range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Zone {
+    /// Within the immediate limit, 0.5 m by default
+    Immediate,
+    /// Within the near limit, 3 m by default
+    Near,
+    /// Beyond the near limit
+    Far,
+    /// The distance is unknown because the device's signal strength at 1 m is unknown
+    Unknown,
+}
+
+/// Smooths the signal strength measurements of one device
+///
+/// A [`ProximityEstimator`] creates a filter for each device it sees.
+pub trait RssiFilter: Send {
+    /// Adds a measurement in dBm, and returns the smoothed signal strength.
+    fn update(&mut self, rssi: f64) -> f64;
+}
+
+/// The mean of the most recent measurements
+#[derive(Debug, Clone)]
+pub struct MovingAverage {
+    window: usize,
+    samples: VecDeque<f64>,
+}
+
+impl MovingAverage {
+    /// Creates a filter averaging the latest `window` measurements, at least one.
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        MovingAverage {
+            window,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+}
+
+impl RssiFilter for MovingAverage {
+    fn update(&mut self, rssi: f64) -> f64 {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rssi);
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+}
+
+/// An exponential moving average
+#[derive(Debug, Clone)]
+pub struct Exponential {
+    weight: f64,
+    value: Option<f64>,
+}
+
+impl Exponential {
+    /// Creates a filter which gives each new measurement `weight`, between 0 and 1.
+    pub fn new(weight: f64) -> Self {
+        Exponential {
+            weight: weight.clamp(0.0, 1.0),
+            value: None,
+        }
+    }
+}
+
+impl Default for Exponential {
+    /// Creates a filter which gives each new measurement a weight of 0.25.
+    fn default() -> Self {
+        Self::new(0.25)
+    }
+}
+
+impl RssiFilter for Exponential {
+    fn update(&mut self, rssi: f64) -> f64 {
+        let value = self.value.map_or(rssi, |x| x + self.weight * (rssi - x));
+        self.value = Some(value);
+        value
+    }
+}
+
+/// A one-dimensional Kalman filter, which models the signal strength as constant with random drift
+#[derive(Debug, Clone)]
+pub struct Kalman {
+    process_noise: f64,
+    measurement_noise: f64,
+    estimate: Option<(f64, f64)>,
+}
+
+impl Kalman {
+    /// Creates a filter where the signal strength drifts with variance `process_noise` between measurements, and
+    /// measurements have variance `measurement_noise`, both in dB².
+    ///
+    /// The smaller the process noise is relative to the measurement noise, the more the filter smooths.
+    pub fn new(process_noise: f64, measurement_noise: f64) -> Self {
+        Kalman {
+            process_noise,
+            measurement_noise,
+            estimate: None,
+        }
+    }
+}
+
+impl Default for Kalman {
+    /// Creates a filter with a process noise of 0.5 dB² and a measurement noise of 16 dB².
+    fn default() -> Self {
+        Self::new(0.5, 16.0)
+    }
+}
+
+impl RssiFilter for Kalman {
+    fn update(&mut self, rssi: f64) -> f64 {
+        let (value, variance) = match self.estimate {
+            Some((value, variance)) => {
+                let variance = variance + self.process_noise;
+                let gain = variance / (variance + self.measurement_noise);
+                (value + gain * (rssi - value), (1.0 - gain) * variance)
+            }
+            None => (rssi, self.measurement_noise),
+        };
+        self.estimate = Some((value, variance));
+        value
+    }
+}
+
+/// The estimated distance of a device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceEstimate {
+    /// The device
+    pub device: Device,
+    /// The signal strength in dBm of the advertisement
+    pub rssi: i16,
+    /// The signal strength in dBm after smoothing
+    pub filtered_rssi: f64,
+    /// The signal strength in dBm at 1 m from the device, if known
+    pub rssi_at_1m: Option<i16>,
+    /// The estimated distance in metres, if the signal strength at 1 m is known
+    pub distance: Option<f64>,
+    /// The coarse range of the distance
+    pub zone: Zone,
+    /// The time at which the advertisement was received
+    pub timestamp: Option<SystemTime>,
+}
+
+type FilterFactory = Box<dyn Fn() -> Box<dyn RssiFilter> + Send + Sync>;
+
+/// Estimates the distance of devices from their advertisements.
+pub struct ProximityEstimator {
+    rssi_at_1m: Option<i16>,
+    path_loss_exponent: f64,
+    immediate_limit: f64,
+    near_limit: f64,
+    new_filter: FilterFactory,
+    filters: HashMap<DeviceId, Box<dyn RssiFilter>>,
+}
+
+impl ProximityEstimator {
+    /// Creates an estimator with the default path loss exponent, zone limits and [`Exponential`] filter.
+    pub fn new() -> Self {
+        ProximityEstimator {
+            rssi_at_1m: None,
+            path_loss_exponent: DEFAULT_PATH_LOSS_EXPONENT,
+            immediate_limit: DEFAULT_IMMEDIATE_LIMIT,
+            near_limit: DEFAULT_NEAR_LIMIT,
+            new_filter: Box::new(|| Box::new(Exponential::default())),
+            filters: HashMap::new(),
+        }
+    }
+
+    /// Uses `rssi` as the signal strength in dBm at 1 m from every device.
+    ///
+    /// Otherwise the signal strength at 1 m is taken from the advertisement: the measured power of an iBeacon or
+    /// AltBeacon, or the transmit power of an Eddystone frame or of the advertisement's
+    /// [`tx_power_level`][crate::AdvertisementData::tx_power_level] less [`PATH_LOSS_AT_1M`].
+    pub fn with_rssi_at_1m(mut self, rssi: i16) -> Self {
+        self.rssi_at_1m = Some(rssi);
+        self
+    }
+
+    /// Sets the path loss exponent. The default is [`DEFAULT_PATH_LOSS_EXPONENT`].
+    pub fn with_path_loss_exponent(mut self, exponent: f64) -> Self {
+        self.path_loss_exponent = exponent;
+        self
+    }
+
+    /// Sets the upper limits in metres of [`Zone::Immediate`] and [`Zone::Near`].
+    pub fn with_zone_limits(mut self, immediate: f64, near: f64) -> Self {
+        self.immediate_limit = immediate;
+        self.near_limit = near.max(immediate);
+        self
+    }
+
+    /// Smooths the signal strength of each device with a filter created by `new_filter`, such as
+    /// `|| MovingAverage::new(5)` or `Kalman::default`.
+    pub fn with_filter<F: RssiFilter + 'static>(mut self, new_filter: impl Fn() -> F + Send + Sync + 'static) -> Self {
+        self.new_filter = Box::new(move || Box::new(new_filter()));
+        self.filters.clear();
+        self
+    }
+
+    /// Updates the filter of the advertising device and estimates its distance.
+    ///
+    /// Returns `None` if the advertisement has no signal strength.
+    pub fn estimate(&mut self, adv: &AdvertisingDevice) -> Option<DistanceEstimate> {
+        let rssi = adv.rssi?;
+        let filtered_rssi = self
+            .filters
+            .entry(adv.device.id())
+            .or_insert_with(|| (self.new_filter)())
+            .update(f64::from(rssi));
+        let rssi_at_1m = self.rssi_at_1m.or_else(|| advertised_rssi_at_1m(adv));
+        let distance = rssi_at_1m.map(|x| distance(filtered_rssi, f64::from(x), self.path_loss_exponent));
+        let zone = match distance {
+            Some(x) if x <= self.immediate_limit => Zone::Immediate,
+            Some(x) if x <= self.near_limit => Zone::Near,
+            Some(_) => Zone::Far,
+            None => Zone::Unknown,
+        };
+        Some(DistanceEstimate {
+            device: adv.device.clone(),
+            rssi,
+            filtered_rssi,
+            rssi_at_1m,
+            distance,
+            zone,
+            timestamp: adv.timestamp,
+        })
+    }
+
+    /// Discards the filter state of the device with `id`, so its next estimate starts afresh.
+    pub fn reset(&mut self, id: &DeviceId) {
+        self.filters.remove(id);
+    }
+
+    /// Estimates the distance of the device of each advertisement from `scan` with a signal strength.
+    pub fn estimates<S>(mut self, scan: S) -> impl Stream<Item = DistanceEstimate> + Send + Unpin
+    where
+        S: Stream<Item = AdvertisingDevice> + Send + Unpin,
+    {
+        scan.filter_map(move |adv| self.estimate(&adv))
+    }
+}
+
+impl Default for ProximityEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ProximityEstimator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProximityEstimator")
+            .field("rssi_at_1m", &self.rssi_at_1m)
+            .field("path_loss_exponent", &self.path_loss_exponent)
+            .field("immediate_limit", &self.immediate_limit)
+            .field("near_limit", &self.near_limit)
+            .field("devices", &self.filters.len())
+            .finish()
+    }
+}
+
+/// The signal strength at 1 m from the device according to its advertisement.
+fn advertised_rssi_at_1m(adv: &AdvertisingDevice) -> Option<i16> {
+    let from_tx_power = |tx_power: i16| tx_power - PATH_LOSS_AT_1M;
+    match Beacon::from_device(adv) {
+        Some(Beacon::IBeacon(IBeacon { measured_power, .. })) => Some(measured_power.into()),
+        Some(Beacon::AltBeacon(AltBeacon { reference_rssi, .. })) => Some(reference_rssi.into()),
+        Some(Beacon::Eddystone(
+            Eddystone::Uid { tx_power, .. } | Eddystone::Url { tx_power, .. } | Eddystone::Eid { tx_power, .. },
+        )) => Some(from_tx_power(tx_power.into())),
+        _ => adv.adv_data.tx_power_level.map(from_tx_power),
+    }
+}
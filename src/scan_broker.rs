@@ -0,0 +1,363 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+
+use futures_core::Stream;
+use futures_lite::{future, StreamExt};
+
+use crate::error::ErrorKind;
+use crate::{Adapter, AdvertisingDevice, Error, Result, ScanEvent, ScanFilter, ScanOptions};
+
+/// The number of advertisements queued for a subscription which is not polled before the oldest are dropped.
+const QUEUE_CAPACITY: usize = 256;
+
+/// Shares one scan between any number of subscribers.
+///
+/// Most platforms only allow one scan at a time, which [`Adapter::scan`] reports with
+/// [`ErrorKind::AlreadyScanning`]. A broker runs a single scan for the combination of the filters of all its
+/// subscriptions, and sends each advertisement to the subscriptions whose filter it matches. The scan is restarted
+/// with the new combined filter whenever a subscription is added or dropped, and stopped when there are no
+/// subscriptions left.
+///
+/// Clones of a broker share the same scan.
+///
+/// If the scan fails or ends, for example because the adapter is turned off, every subscription ends. After a failure,
+/// [`ScanSubscription::error`] returns the error which ended the scan.
+///
+/// ```rust,no_run
+/// use bluest::btuuid::services;
+/// use bluest::{Adapter, ScanBroker, ScanFilter};
+/// use futures_lite::StreamExt;
+///
+/// # async fn example(adapter: Adapter) -> bluest::Result<()> {
+/// let broker = ScanBroker::new(adapter);
+/// let mut heart_rate = broker
+///     .subscribe(ScanFilter::new().with_services([services::HEART_RATE]))
+///     .await?;
+/// let mut beacons = broker
+///     .subscribe(ScanFilter::new().with_manufacturer_data(0x004c, &[0x02, 0x15], None))
+///     .await?;
+/// while let Some(device) = heart_rate.next().await {
+///     println!("{:?}", device.device);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ScanBroker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    adapter: Adapter,
+    options: ScanOptions,
+    state: Arc<Mutex<State>>,
+    /// The running scan, which sends its advertisements to the subscriptions as it is polled
+    ///
+    /// Subscriptions poll the scan when they are polled themselves, so the broker works without spawning a task. When
+    /// both locks are needed, this one is locked first.
+    scan: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    /// Set when the scan needs to be polled
+    poll_requested: AtomicBool,
+    /// Wakes every subscription when the scan can make progress
+    waker: Waker,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+    status: Status,
+}
+
+struct Subscriber {
+    filter: ScanFilter,
+    queue: VecDeque<AdvertisingDevice>,
+    waker: Option<Waker>,
+    ended: bool,
+    /// The failure which ended the subscription
+    error: Option<(ErrorKind, String)>,
+}
+
+#[derive(Default)]
+enum Status {
+    #[default]
+    Starting,
+    Running,
+    Ended,
+    Failed(ErrorKind, String),
+}
+
+impl ScanBroker {
+    /// Creates a broker for scans on `adapter`.
+    pub fn new(adapter: Adapter) -> Self {
+        Self::with_options(adapter, ScanOptions::default())
+    }
+
+    /// Creates a broker for scans on `adapter` with the given scan `options`.
+    ///
+    /// A [`ScanOptions::duration`] or [`ScanOptions::max_results`] applies to each scan the broker starts, so it ends
+    /// every subscription when it is reached.
+    pub fn with_options(adapter: Adapter, options: ScanOptions) -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let waker = Waker::from(Arc::new(WakeAll(Arc::downgrade(&state))));
+        ScanBroker {
+            inner: Arc::new(Inner {
+                adapter,
+                options,
+                state,
+                scan: Mutex::new(None),
+                poll_requested: AtomicBool::new(false),
+                waker,
+            }),
+        }
+    }
+
+    /// Subscribes to the advertisements matching `filter`.
+    ///
+    /// Returns once the scan including `filter` has started. Each advertisement the scan receives is sent to every
+    /// subscription it matches. A subscription which is not polled keeps the most recent 256 advertisements.
+    pub async fn subscribe(&self, filter: ScanFilter) -> Result<ScanSubscription> {
+        let id = {
+            let mut state = self.inner.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.subscribers.insert(
+                id,
+                Subscriber {
+                    filter,
+                    queue: VecDeque::new(),
+                    waker: None,
+                    ended: false,
+                    error: None,
+                },
+            );
+            id
+        };
+        let subscription = ScanSubscription {
+            inner: self.inner.clone(),
+            id,
+        };
+
+        self.inner.restart();
+        future::poll_fn(|cx| subscription.poll_started(cx)).await?;
+        Ok(subscription)
+    }
+
+    /// The number of subscriptions which have not ended.
+    pub fn subscribers(&self) -> usize {
+        let state = self.inner.state.lock().unwrap();
+        state.subscribers.values().filter(|x| !x.ended).count()
+    }
+}
+
+impl std::fmt::Debug for ScanBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanBroker")
+            .field("adapter", &self.inner.adapter)
+            .field("options", &self.inner.options)
+            .field("subscribers", &self.subscribers())
+            .finish()
+    }
+}
+
+impl Inner {
+    /// Replaces the running scan with one for the current subscriptions.
+    fn restart(&self) {
+        let mut scan = self.scan.lock().unwrap();
+        // The old scan must stop before the new one starts, since most platforms only allow one
+        *scan = None;
+
+        let mut state = self.state.lock().unwrap();
+        let filter = state
+            .subscribers
+            .values()
+            .filter(|x| !x.ended)
+            .map(|x| x.filter.clone())
+            .reduce(ScanFilter::or);
+        if let Some(filter) = filter {
+            state.status = Status::Starting;
+            *scan = Some(Box::pin(run_scan(
+                self.adapter.clone(),
+                filter,
+                self.options.clone(),
+                self.state.clone(),
+            )));
+        }
+    }
+
+    /// Registers `waker` for subscription `id` and polls the scan, unless another subscription is already polling it.
+    fn poll_scan(&self, id: u64, waker: &Waker) {
+        if let Some(subscriber) = self.state.lock().unwrap().subscribers.get_mut(&id) {
+            subscriber.waker = Some(waker.clone());
+        }
+
+        // If another subscription is polling the scan, it polls it again on behalf of this one
+        self.poll_requested.store(true, Ordering::SeqCst);
+        while self.poll_requested.load(Ordering::SeqCst) {
+            let Ok(mut scan) = self.scan.try_lock() else { return };
+            while self.poll_requested.swap(false, Ordering::SeqCst) {
+                if let Some(future) = scan.as_mut() {
+                    if future.as_mut().poll(&mut Context::from_waker(&self.waker)).is_ready() {
+                        *scan = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs a scan, sending each advertisement to the matching subscriptions.
+async fn run_scan(adapter: Adapter, filter: ScanFilter, options: ScanOptions, state: Arc<Mutex<State>>) {
+    let mut scan = match adapter.try_scan_events(filter, options).await {
+        Ok(scan) => {
+            set_status(&state, Status::Running);
+            scan
+        }
+        Err(err) => {
+            set_status(&state, Status::Failed(err.kind(), err.message().to_owned()));
+            return;
+        }
+    };
+
+    while let Some(event) = scan.next().await {
+        let device = match event {
+            Ok(ScanEvent::Advertisement(device)) => device,
+            Ok(ScanEvent::DeviceLost(_)) => continue,
+            Err(err) => {
+                set_status(&state, Status::Failed(err.kind(), err.message().to_owned()));
+                return;
+            }
+        };
+        let wakers = {
+            let mut state = state.lock().unwrap();
+            let mut wakers = Vec::new();
+            for subscriber in state.subscribers.values_mut() {
+                if !subscriber.ended && subscriber.filter.matches(&device) {
+                    if subscriber.queue.len() == QUEUE_CAPACITY {
+                        subscriber.queue.pop_front();
+                    }
+                    subscriber.queue.push_back(device.clone());
+                    wakers.extend(subscriber.waker.take());
+                }
+            }
+            wakers
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    set_status(&state, Status::Ended);
+}
+
+/// Updates the status of the scan and wakes every subscription, ending them if the scan has stopped.
+fn set_status(state: &Mutex<State>, status: Status) {
+    let wakers = {
+        let mut state = state.lock().unwrap();
+        let stopped = matches!(status, Status::Ended | Status::Failed(..));
+        let error = match &status {
+            Status::Failed(kind, message) => Some((*kind, message.clone())),
+            _ => None,
+        };
+        state.status = status;
+        state
+            .subscribers
+            .values_mut()
+            .filter_map(|subscriber| {
+                if stopped && !subscriber.ended {
+                    subscriber.ended = true;
+                    subscriber.error.clone_from(&error);
+                }
+                subscriber.waker.take()
+            })
+            .collect::<Vec<_>>()
+    };
+    wakers.into_iter().for_each(Waker::wake);
+}
+
+struct WakeAll(Weak<Mutex<State>>);
+
+impl Wake for WakeAll {
+    fn wake(self: Arc<Self>) {
+        let Some(state) = self.0.upgrade() else { return };
+        let wakers = {
+            let mut state = state.lock().unwrap();
+            state
+                .subscribers
+                .values_mut()
+                .filter_map(|x| x.waker.take())
+                .collect::<Vec<_>>()
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// A stream of the advertisements matching the filter of a [`ScanBroker`] subscription.
+///
+/// Dropping the subscription removes its filter from the scan.
+pub struct ScanSubscription {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl ScanSubscription {
+    /// The error which ended this subscription, if the scan failed.
+    ///
+    /// Returns `None` while the subscription is running, and if the scan ended without an error.
+    pub fn error(&self) -> Option<Error> {
+        let state = self.inner.state.lock().unwrap();
+        let (kind, message) = state.subscribers.get(&self.id)?.error.as_ref()?;
+        Some(Error::new(*kind, None, message))
+    }
+
+    fn poll_started(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_scan(self.id, cx.waker());
+        let state = self.inner.state.lock().unwrap();
+        match &state.status {
+            Status::Starting => Poll::Pending,
+            Status::Running | Status::Ended => Poll::Ready(Ok(())),
+            Status::Failed(kind, message) => Poll::Ready(Err(Error::new(*kind, None, message))),
+        }
+    }
+}
+
+impl Stream for ScanSubscription {
+    type Item = AdvertisingDevice;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_scan(self.id, cx.waker());
+        let mut state = self.inner.state.lock().unwrap();
+        let Some(subscriber) = state.subscribers.get_mut(&self.id) else {
+            return Poll::Ready(None);
+        };
+        match subscriber.queue.pop_front() {
+            Some(device) => Poll::Ready(Some(device)),
+            None if subscriber.ended => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for ScanSubscription {
+    fn drop(&mut self) {
+        let ended = self
+            .inner
+            .state
+            .lock()
+            .unwrap()
+            .subscribers
+            .remove(&self.id)
+            .map(|x| x.ended);
+        if ended == Some(false) {
+            self.inner.restart();
+        }
+    }
+}
+
+impl std::fmt::Debug for ScanSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanSubscription").field("id", &self.id).finish()
+    }
+}
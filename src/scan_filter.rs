@@ -0,0 +1,258 @@
+use crate::{AdvertisingDevice, DeviceId, Uuid};
+
+/// A filter selecting the advertisements returned by [`Adapter::scan_with_filter`][crate::Adapter::scan_with_filter].
+///
+/// A filter is built from predicates with the `with_*` methods. An advertisement matches the filter if it matches all
+/// of its predicates, so [`ScanFilter::new()`] matches every advertisement. Filters can be combined with
+/// [`ScanFilter::and`] and [`ScanFilter::or`].
+///
+/// Each platform applies the parts of the filter it supports while scanning, and the rest of the filter is applied
+/// to the received advertisements. The results are the same on every platform, but a filter the platform can apply
+/// may save power.
+///
+/// ```rust
+/// use bluest::btuuid::services;
+/// use bluest::ScanFilter;
+///
+/// // Heart rate monitors close by, and any device from a particular manufacturer
+/// let filter = ScanFilter::new()
+///     .with_services([services::HEART_RATE])
+///     .with_min_rssi(-70)
+///     .or(ScanFilter::new().with_manufacturer_data(0xffff, &[0x01], None));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Services(Vec<Uuid>),
+    MinRssi(i16),
+    NamePrefix(String),
+    #[cfg(feature = "regex")]
+    NameRegex(regex::Regex),
+    ManufacturerData {
+        company_id: u16,
+        data: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    ServiceData {
+        uuid: Uuid,
+        data: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    Connectable,
+    AllowedDevices(Vec<DeviceId>),
+    DeniedDevices(Vec<DeviceId>),
+    AnyOf(Vec<ScanFilter>),
+}
+
+impl ScanFilter {
+    /// Creates a filter which matches every advertisement.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a filter matching advertisements which include at least one of `services`, or every advertisement if
+    /// `services` is empty.
+    pub(crate) fn from_services(services: &[Uuid]) -> Self {
+        if services.is_empty() {
+            Self::new()
+        } else {
+            Self::new().with_services(services.iter().copied())
+        }
+    }
+
+    fn with(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// Matches advertisements which include at least one GATT service with a UUID in `services`.
+    pub fn with_services(self, services: impl IntoIterator<Item = Uuid>) -> Self {
+        self.with(Predicate::Services(services.into_iter().collect()))
+    }
+
+    /// Matches advertisements received with a signal strength of at least `rssi` dBm.
+    ///
+    /// Advertisements received without a signal strength do not match.
+    pub fn with_min_rssi(self, rssi: i16) -> Self {
+        self.with(Predicate::MinRssi(rssi))
+    }
+
+    /// Matches advertisements with a local name starting with `prefix`.
+    pub fn with_name_prefix(self, prefix: impl Into<String>) -> Self {
+        self.with(Predicate::NamePrefix(prefix.into()))
+    }
+
+    /// Matches advertisements with a local name matching `regex`.
+    ///
+    /// Requires the `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn with_name_regex(self, regex: regex::Regex) -> Self {
+        self.with(Predicate::NameRegex(regex))
+    }
+
+    /// Matches advertisements with a manufacturer data entry for `company_id` which starts with `data`.
+    ///
+    /// If `mask` is given, only the bits set in `mask` are compared. Octets of `data` beyond the end of `mask` are
+    /// compared in full. An empty `data` matches any manufacturer data for `company_id`.
+    pub fn with_manufacturer_data(self, company_id: u16, data: &[u8], mask: Option<&[u8]>) -> Self {
+        self.with(Predicate::ManufacturerData {
+            company_id,
+            data: data.to_vec(),
+            mask: mask.map(<[u8]>::to_vec),
+        })
+    }
+
+    /// Matches advertisements with service data for `uuid` which starts with `data`.
+    ///
+    /// `data` and `mask` are compared as for [`ScanFilter::with_manufacturer_data`].
+    pub fn with_service_data(self, uuid: Uuid, data: &[u8], mask: Option<&[u8]>) -> Self {
+        self.with(Predicate::ServiceData {
+            uuid,
+            data: data.to_vec(),
+            mask: mask.map(<[u8]>::to_vec),
+        })
+    }
+
+    /// Matches advertisements unless they are known not to be connectable.
+    ///
+    /// Advertisements whose connectability is unknown are matched. This includes every advertisement on Linux, where
+    /// [`AdvertisementData::is_connectable`][crate::AdvertisementData::is_connectable] is always `None`.
+    pub fn with_connectable(self) -> Self {
+        self.with(Predicate::Connectable)
+    }
+
+    /// Matches advertisements from the devices in `devices` only.
+    pub fn with_allowed_devices(self, devices: impl IntoIterator<Item = DeviceId>) -> Self {
+        self.with(Predicate::AllowedDevices(devices.into_iter().collect()))
+    }
+
+    /// Matches advertisements from devices which are not in `devices`.
+    pub fn with_denied_devices(self, devices: impl IntoIterator<Item = DeviceId>) -> Self {
+        self.with(Predicate::DeniedDevices(devices.into_iter().collect()))
+    }
+
+    /// Returns a filter matching advertisements which match both this filter and `other`.
+    pub fn and(mut self, other: ScanFilter) -> Self {
+        self.predicates.extend(other.predicates);
+        self
+    }
+
+    /// Returns a filter matching advertisements which match this filter, `other`, or both.
+    pub fn or(self, other: ScanFilter) -> Self {
+        let mut filters = Vec::new();
+        for mut filter in [self, other] {
+            match filter.predicates.as_mut_slice() {
+                [Predicate::AnyOf(x)] => filters.append(x),
+                _ => filters.push(filter),
+            }
+        }
+        ScanFilter::new().with(Predicate::AnyOf(filters))
+    }
+
+    /// Returns `true` if `device` matches this filter.
+    pub fn matches(&self, device: &AdvertisingDevice) -> bool {
+        self.predicates.iter().all(|x| x.matches(device))
+    }
+}
+
+/// The parts of a filter a platform can apply while scanning. Each backend uses the ones its platform supports.
+#[allow(dead_code)]
+impl ScanFilter {
+    /// Service UUIDs of which every matching advertisement includes at least one, or an empty list if the filter
+    /// does not require any service.
+    pub(crate) fn services(&self) -> Vec<Uuid> {
+        self.predicates
+            .iter()
+            .map(|x| match x {
+                Predicate::Services(services) => services.clone(),
+                Predicate::AnyOf(filters) => {
+                    let mut services = Vec::new();
+                    for filter in filters {
+                        let required = filter.services();
+                        if required.is_empty() {
+                            return Vec::new();
+                        }
+                        services.extend(required);
+                    }
+                    services
+                }
+                _ => Vec::new(),
+            })
+            .find(|x| !x.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// The lowest signal strength in dBm a matching advertisement can be received with.
+    pub(crate) fn min_rssi(&self) -> Option<i16> {
+        self.predicates
+            .iter()
+            .filter_map(|x| match x {
+                Predicate::MinRssi(rssi) => Some(*rssi),
+                Predicate::AnyOf(filters) => filters
+                    .iter()
+                    .map(ScanFilter::min_rssi)
+                    .try_fold(i16::MAX, |acc, x| x.map(|x| acc.min(x))),
+                _ => None,
+            })
+            .max()
+    }
+
+    /// A prefix of the local name of every matching advertisement.
+    pub(crate) fn name_prefix(&self) -> Option<&str> {
+        self.predicates.iter().find_map(|x| match x {
+            Predicate::NamePrefix(prefix) => Some(prefix.as_str()),
+            _ => None,
+        })
+    }
+
+    /// A company ID and the unmasked prefix of manufacturer data which every matching advertisement includes.
+    pub(crate) fn manufacturer_data(&self) -> Option<(u16, &[u8])> {
+        self.predicates.iter().find_map(|x| match x {
+            Predicate::ManufacturerData { company_id, data, mask } => {
+                let len = mask
+                    .as_ref()
+                    .map_or(data.len(), |mask| mask.iter().take_while(|x| **x == 0xff).count());
+                Some((*company_id, &data[..len.min(data.len())]))
+            }
+            _ => None,
+        })
+    }
+}
+
+impl Predicate {
+    fn matches(&self, device: &AdvertisingDevice) -> bool {
+        let adv_data = &device.adv_data;
+        match self {
+            Predicate::Services(services) => adv_data.services.iter().any(|x| services.contains(x)),
+            Predicate::MinRssi(min) => device.rssi.is_some_and(|x| x >= *min),
+            Predicate::NamePrefix(prefix) => adv_data.local_name.as_ref().is_some_and(|x| x.starts_with(prefix)),
+            #[cfg(feature = "regex")]
+            Predicate::NameRegex(regex) => adv_data.local_name.as_ref().is_some_and(|x| regex.is_match(x)),
+            Predicate::ManufacturerData { company_id, data, mask } => adv_data
+                .manufacturer_data
+                .get_all(*company_id)
+                .any(|value| masked_prefix(value, data, mask.as_deref())),
+            Predicate::ServiceData { uuid, data, mask } => adv_data
+                .service_data
+                .get(uuid)
+                .is_some_and(|value| masked_prefix(value, data, mask.as_deref())),
+            Predicate::Connectable => adv_data.is_connectable != Some(false),
+            Predicate::AllowedDevices(devices) => devices.contains(&device.device.id()),
+            Predicate::DeniedDevices(devices) => !devices.contains(&device.device.id()),
+            Predicate::AnyOf(filters) => filters.iter().any(|x| x.matches(device)),
+        }
+    }
+}
+
+/// Returns `true` if `value` starts with `data`, comparing only the bits set in `mask`.
+fn masked_prefix(value: &[u8], data: &[u8], mask: Option<&[u8]>) -> bool {
+    value.len() >= data.len()
+        && data.iter().zip(value).enumerate().all(|(i, (x, y))| {
+            let mask = mask.and_then(|mask| mask.get(i)).copied().unwrap_or(0xff);
+            x & mask == y & mask
+        })
+}
@@ -0,0 +1,409 @@
+//! GATT server (peripheral role)
+//!
+//! A [`Server`] publishes a local GATT database which connected centrals can discover and access. The database is
+//! declared with [`ServiceDefinition`]s, [`CharacteristicDefinition`]s and [`DescriptorDefinition`]s and started with
+//! [`Adapter::start_server`][crate::Adapter::start_server]. Reads and writes from centrals are answered by a
+//! [`RequestHandler`], and notifications and indications are sent with [`Server::notify`]. The server keeps running
+//! until it is dropped.
+//!
+//! To let centrals find and connect to the server, start a connectable advertisement with
+//! [`Adapter::start_advertising`][crate::Adapter::start_advertising].
+//!
+//! Only Linux and the `mock` backend support GATT servers, other platforms return an error with
+//! [`ErrorKind::NotSupported`].
+//!
+//! ```rust,no_run
+//! use bluest::btuuid::{characteristics, services};
+//! use bluest::error::AttError;
+//! use bluest::server::{CharacteristicDefinition, ReadRequest, RequestHandler, ServiceDefinition};
+//! use bluest::{Adapter, CharacteristicProperties};
+//!
+//! struct Battery;
+//!
+//! #[async_trait::async_trait]
+//! impl RequestHandler for Battery {
+//!     async fn read(&self, request: ReadRequest) -> Result<Vec<u8>, AttError> {
+//!         match request.offset {
+//!             0 => Ok(vec![87]),
+//!             _ => Err(AttError::INVALID_OFFSET),
+//!         }
+//!     }
+//! }
+//!
+//! # async fn example(adapter: Adapter) -> bluest::Result<()> {
+//! let battery_level = CharacteristicDefinition::new(
+//!     characteristics::BATTERY_LEVEL,
+//!     CharacteristicProperties::from_bits(0x12), // read + notify
+//! );
+//! let service = ServiceDefinition::new(services::BATTERY).with_characteristic(battery_level);
+//! let server = adapter.start_server(vec![service], Battery).await?;
+//!
+//! // Sent to the centrals which have subscribed to the battery level
+//! server.notify(services::BATTERY, characteristics::BATTERY_LEVEL, &[86]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_trait::async_trait;
+use futures_core::Stream;
+
+use crate::btuuid::descriptors;
+use crate::error::{AttError, ErrorKind};
+use crate::{sys, CharacteristicProperties, DeviceId, Error, Result, Uuid};
+
+/// The security a connection needs for an attribute to be accessed.
+#[non_exhaustive]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SecurityLevel {
+    /// No security is needed
+    #[default]
+    None,
+    /// The connection must be encrypted
+    Encrypted,
+    /// The connection must be encrypted with an authenticated (MITM protected) key
+    Authenticated,
+    /// The connection must be encrypted with an authenticated key from LE Secure Connections pairing
+    Secure,
+}
+
+/// Which accesses to an attribute are permitted, and the security each needs.
+///
+/// The default permits no access.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttributePermissions {
+    /// The security needed for reads, or `None` if the attribute cannot be read
+    pub read: Option<SecurityLevel>,
+    /// The security needed for writes, or `None` if the attribute cannot be written
+    pub write: Option<SecurityLevel>,
+}
+
+impl AttributePermissions {
+    /// Permits reads with the given security.
+    pub const fn read(level: SecurityLevel) -> Self {
+        AttributePermissions {
+            read: Some(level),
+            write: None,
+        }
+    }
+
+    /// Permits writes with the given security.
+    pub const fn write(level: SecurityLevel) -> Self {
+        AttributePermissions {
+            read: None,
+            write: Some(level),
+        }
+    }
+
+    /// Permits reads and writes with the given security.
+    pub const fn read_write(level: SecurityLevel) -> Self {
+        AttributePermissions {
+            read: Some(level),
+            write: Some(level),
+        }
+    }
+}
+
+/// A service of a [`Server`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDefinition {
+    pub(crate) uuid: Uuid,
+    pub(crate) primary: bool,
+    pub(crate) characteristics: Vec<CharacteristicDefinition>,
+}
+
+impl ServiceDefinition {
+    /// Creates a primary service without characteristics.
+    pub fn new(uuid: Uuid) -> Self {
+        ServiceDefinition {
+            uuid,
+            primary: true,
+            characteristics: Vec::new(),
+        }
+    }
+
+    /// Makes this a secondary service.
+    pub fn secondary(mut self) -> Self {
+        self.primary = false;
+        self
+    }
+
+    /// Adds `characteristic` to the service.
+    pub fn with_characteristic(mut self, characteristic: CharacteristicDefinition) -> Self {
+        self.characteristics.push(characteristic);
+        self
+    }
+
+    /// The UUID of the service
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
+/// A characteristic of a [`ServiceDefinition`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharacteristicDefinition {
+    pub(crate) uuid: Uuid,
+    pub(crate) properties: CharacteristicProperties,
+    pub(crate) permissions: AttributePermissions,
+    pub(crate) descriptors: Vec<DescriptorDefinition>,
+}
+
+impl CharacteristicDefinition {
+    /// Creates a characteristic with `properties`.
+    ///
+    /// The characteristic can be read without security if it has the `read` property, and written without security
+    /// if it has any of the write properties. Use [`with_permissions`][Self::with_permissions] to require security.
+    ///
+    /// The client characteristic configuration descriptor of a characteristic with the `notify` or `indicate`
+    /// property is added by the platform.
+    pub fn new(uuid: Uuid, properties: CharacteristicProperties) -> Self {
+        CharacteristicDefinition {
+            uuid,
+            properties,
+            permissions: AttributePermissions {
+                read: properties.read.then_some(SecurityLevel::None),
+                write: is_writable(properties).then_some(SecurityLevel::None),
+            },
+            descriptors: Vec::new(),
+        }
+    }
+
+    /// Sets the permissions of the characteristic value, which must permit reads if and only if the characteristic
+    /// has the `read` property, and writes if and only if it has a write property.
+    pub fn with_permissions(mut self, permissions: AttributePermissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Adds `descriptor` to the characteristic.
+    pub fn with_descriptor(mut self, descriptor: DescriptorDefinition) -> Self {
+        self.descriptors.push(descriptor);
+        self
+    }
+
+    /// The UUID of the characteristic
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    /// The properties of the characteristic
+    pub fn properties(&self) -> CharacteristicProperties {
+        self.properties
+    }
+}
+
+/// A descriptor of a [`CharacteristicDefinition`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorDefinition {
+    pub(crate) uuid: Uuid,
+    pub(crate) permissions: AttributePermissions,
+}
+
+impl DescriptorDefinition {
+    /// Creates a descriptor with `permissions`.
+    pub fn new(uuid: Uuid, permissions: AttributePermissions) -> Self {
+        DescriptorDefinition { uuid, permissions }
+    }
+
+    /// The UUID of the descriptor
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
+/// Identifies the characteristic value or descriptor a request is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttributeId {
+    /// The UUID of the service
+    pub service: Uuid,
+    /// The UUID of the characteristic
+    pub characteristic: Uuid,
+    /// The UUID of the descriptor, or `None` for the characteristic value
+    pub descriptor: Option<Uuid>,
+}
+
+/// A read of an attribute by a central
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRequest {
+    /// The central reading the attribute
+    pub central: DeviceId,
+    /// The attribute which is read
+    pub attribute: AttributeId,
+    /// The offset into the value the read starts at
+    pub offset: u16,
+    /// The ATT MTU of the connection
+    pub mtu: u16,
+}
+
+/// How a central writes an attribute
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WriteKind {
+    /// A write request, which the central receives a response to
+    WithResponse,
+    /// A write command, which the central receives no response to
+    WithoutResponse,
+    /// Part of a reliable write, which is only applied when the central executes it
+    Reliable,
+}
+
+/// A write of an attribute by a central
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteRequest {
+    /// The central writing the attribute
+    pub central: DeviceId,
+    /// The attribute which is written
+    pub attribute: AttributeId,
+    /// The offset into the value the write starts at
+    pub offset: u16,
+    /// The ATT MTU of the connection
+    pub mtu: u16,
+    /// The value written at `offset`
+    pub value: Vec<u8>,
+    /// How the value is written
+    pub kind: WriteKind,
+}
+
+/// Answers the reads and writes of centrals accessing a [`Server`].
+///
+/// Requests are only passed to the handler if the attribute permits them and the connection has the security the
+/// permissions need. The default implementations reject every request.
+#[async_trait]
+pub trait RequestHandler: Send + Sync + 'static {
+    /// Returns the value of `request.attribute`, starting at `request.offset`.
+    ///
+    /// Values longer than fit in one response are read in several requests with increasing offsets.
+    async fn read(&self, _request: ReadRequest) -> Result<Vec<u8>, AttError> {
+        Err(AttError::READ_NOT_PERMITTED)
+    }
+
+    /// Writes `request.value` to `request.attribute`.
+    ///
+    /// The error of a [`WriteKind::WithoutResponse`] write is not sent to the central.
+    async fn write(&self, _request: WriteRequest) -> Result<(), AttError> {
+        Err(AttError::WRITE_NOT_PERMITTED)
+    }
+}
+
+/// A central connected to a [`Server`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Central {
+    /// The identifier of the central
+    pub id: DeviceId,
+    /// The ATT MTU of the connection
+    pub mtu: u16,
+}
+
+/// A subscription to the notifications or indications of a characteristic
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    /// The characteristic subscribed to
+    pub attribute: AttributeId,
+    /// The subscribed central, if the platform reports it
+    pub central: Option<DeviceId>,
+    /// Whether values are sent as indications, which the central confirms, rather than notifications
+    pub indications: bool,
+}
+
+/// Events generated by [`Server::events`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    /// A central has started using the server
+    Connected(Central),
+    /// The ATT MTU of the connection to a central has changed
+    MtuChanged(Central),
+    /// A central has disconnected
+    Disconnected(DeviceId),
+    /// A central has subscribed to a characteristic
+    Subscribed(Subscription),
+    /// A central has unsubscribed from a characteristic
+    Unsubscribed(Subscription),
+}
+
+/// A GATT server started with [`Adapter::start_server`].
+///
+/// The services of the server are removed when it is dropped.
+///
+/// # Platform specifics
+///
+/// ## Linux
+///
+/// BlueZ does not report connections to the server itself, so a central is only known once it has read or written an
+/// attribute. BlueZ also manages the subscriptions of each central and only tells the server whether any central is
+/// subscribed to a characteristic. Each characteristic therefore has at most one [`Subscription`], without a
+/// central, which ends when the last central unsubscribes. Descriptor requests do not carry the MTU, so they report
+/// the MTU of the last characteristic request of the central.
+///
+/// ## Mock
+///
+/// The services are served by the peripherals advertised by the adapters of the `VirtualRadio`, and the only central
+/// is the radio itself, identified by `VirtualRadio::id`. Attributes which need security can only be
+/// accessed once the peripheral is paired.
+///
+/// [`Adapter::start_server`]: crate::Adapter::start_server
+#[derive(Debug)]
+pub struct Server(pub(crate) sys::server::ServerImpl);
+
+impl Server {
+    /// The centrals using the server.
+    pub fn centrals(&self) -> Vec<Central> {
+        self.0.centrals()
+    }
+
+    /// The active subscriptions to the characteristics of the server.
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        self.0.subscriptions()
+    }
+
+    /// A stream of the [`ServerEvent`]s which occur from now on.
+    pub fn events(&self) -> impl Stream<Item = ServerEvent> + Send + Unpin {
+        self.0.events()
+    }
+
+    /// Sends `value` to the centrals subscribed to `characteristic` of `service`.
+    ///
+    /// For indications, waits until the value is confirmed. Returns `false` if no central is subscribed, and an error
+    /// with [`ErrorKind::NotFound`] if the server has no such characteristic.
+    pub async fn notify(&self, service: Uuid, characteristic: Uuid, value: &[u8]) -> Result<bool> {
+        self.0.notify(service, characteristic, value).await
+    }
+}
+
+/// Checks that the properties and permissions of the characteristics of `services` agree.
+pub(crate) fn validate(services: &[ServiceDefinition]) -> Result<()> {
+    let invalid = |message: String| Err(Error::new(ErrorKind::InvalidParameter, None, message));
+    for service in services {
+        for characteristic in &service.characteristics {
+            let properties = characteristic.properties;
+            if properties.read != characteristic.permissions.read.is_some()
+                || is_writable(properties) != characteristic.permissions.write.is_some()
+            {
+                return invalid(format!(
+                    "the permissions of characteristic {} do not match its properties",
+                    characteristic.uuid
+                ));
+            }
+            if characteristic
+                .descriptors
+                .iter()
+                .any(|x| x.uuid == descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION)
+            {
+                return invalid(format!(
+                    "the client characteristic configuration descriptor of characteristic {} is managed by the platform",
+                    characteristic.uuid
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a characteristic with `properties` can be written in any way.
+fn is_writable(properties: CharacteristicProperties) -> bool {
+    properties.write
+        || properties.write_without_response
+        || properties.reliable_write
+        || properties.authenticated_signed_writes
+}
@@ -1,6 +1,13 @@
 #![allow(unused)] // used depending on the target.
 
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::future::Future;
 use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 pub struct ScopeGuard<F: FnOnce()> {
     dropfn: ManuallyDrop<F>,
@@ -19,3 +26,142 @@ pub fn defer<F: FnOnce()>(dropfn: F) -> ScopeGuard<F> {
         dropfn: ManuallyDrop::new(dropfn),
     }
 }
+
+/// A future which completes after a duration.
+///
+/// All timers are waited for by one shared thread, so they work with any async runtime. Code which is not specific to
+/// Linux uses this rather than `tokio::time`, since Bluest does not require a Tokio runtime on other platforms.
+pub struct Timer {
+    id: u64,
+    state: Arc<Mutex<TimerState>>,
+}
+
+#[derive(Default)]
+struct TimerState {
+    expired: bool,
+    waker: Option<Waker>,
+}
+
+/// The timers which have not expired yet, ordered by their deadlines
+#[derive(Default)]
+struct PendingTimers {
+    next_id: u64,
+    heap: BinaryHeap<Reverse<PendingTimer>>,
+}
+
+struct PendingTimer {
+    deadline: Instant,
+    id: u64,
+    state: Arc<Mutex<TimerState>>,
+}
+
+impl PendingTimer {
+    fn key(&self) -> (Instant, u64) {
+        (self.deadline, self.id)
+    }
+}
+
+impl PartialEq for PendingTimer {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PendingTimer {}
+
+impl PartialOrd for PendingTimer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingTimer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Returns the pending timers, starting the timer thread the first time.
+fn pending_timers() -> &'static (Mutex<PendingTimers>, Condvar) {
+    static PENDING: OnceLock<(Mutex<PendingTimers>, Condvar)> = OnceLock::new();
+    PENDING.get_or_init(|| {
+        std::thread::Builder::new()
+            .name("bluest-timer".to_string())
+            .spawn(run_timers)
+            .expect("failed to start the timer thread");
+        (Mutex::default(), Condvar::new())
+    })
+}
+
+/// Expires the pending timers as their deadlines pass.
+fn run_timers() {
+    let (pending, condvar) = pending_timers();
+    let mut timers = pending.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        while timers.heap.peek().is_some_and(|Reverse(x)| x.deadline <= now) {
+            expired.push(timers.heap.pop().unwrap().0.state);
+        }
+        if !expired.is_empty() {
+            drop(timers);
+            for state in expired {
+                let mut state = state.lock().unwrap();
+                state.expired = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+            timers = pending.lock().unwrap();
+            continue;
+        }
+
+        timers = match timers.heap.peek().map(|Reverse(x)| x.deadline - now) {
+            Some(timeout) => condvar.wait_timeout(timers, timeout).unwrap().0,
+            None => condvar.wait(timers).unwrap(),
+        };
+    }
+}
+
+impl Timer {
+    pub fn after(duration: Duration) -> Self {
+        let state = Arc::new(Mutex::new(TimerState::default()));
+        let (pending, condvar) = pending_timers();
+        let mut timers = pending.lock().unwrap();
+        let id = timers.next_id;
+        timers.next_id += 1;
+        timers.heap.push(Reverse(PendingTimer {
+            deadline: Instant::now() + duration,
+            id,
+            state: state.clone(),
+        }));
+        // The timer thread only needs waking when it would otherwise sleep past the new deadline
+        if timers.heap.peek().is_some_and(|Reverse(x)| x.id == id) {
+            condvar.notify_one();
+        }
+        Timer { id, state }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.expired {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if !self.state.lock().unwrap().expired {
+            let id = self.id;
+            pending_timers().0.lock().unwrap().heap.retain(|Reverse(x)| x.id != id);
+        }
+    }
+}
@@ -1,15 +1,17 @@
 pub mod adapter;
+pub mod advertisement;
 pub mod characteristic;
 pub mod descriptor;
 pub mod device;
 pub mod error;
+pub mod server;
 pub mod service;
 mod types;
 
 /// A platform-specific device identifier.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct DeviceId(std::ffi::OsString);
+pub struct DeviceId(pub(crate) std::ffi::OsString);
 
 impl std::fmt::Display for DeviceId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,34 +1,37 @@
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use super::types::StringVec;
-use crate::error::{Error, ErrorKind};
-use crate::util::defer;
-use crate::windows_advertisement::AdvertisementImpl;
-use crate::{
-    AdapterEvent, AdvertisementData, AdvertisingDevice, AdvertisingGuard, BluetoothUuidExt, ConnectionEvent, Device, DeviceId, ManufacturerData, Result, Uuid
-};
 use futures_core::Stream;
 use futures_lite::{stream, StreamExt};
 use tracing::{debug, error, trace, warn};
-use windows::core::HSTRING;
-use windows::Devices::Bluetooth::Advertisement::*;
+use windows::core::{ComInterface, HSTRING};
 use windows::Devices::Bluetooth::Advertisement::{
-    BluetoothLEAdvertisement, BluetoothLEAdvertisementDataSection, BluetoothLEAdvertisementFilter,
-    BluetoothLEAdvertisementFlags, BluetoothLEAdvertisementPublisher, BluetoothLEAdvertisementReceivedEventArgs,
+    BluetoothLEAdvertisementDataSection, BluetoothLEAdvertisementFilter, BluetoothLEAdvertisementReceivedEventArgs,
     BluetoothLEAdvertisementType, BluetoothLEAdvertisementWatcher, BluetoothLEAdvertisementWatcherStoppedEventArgs,
-    BluetoothLEManufacturerData, BluetoothLEScanningMode,
+    BluetoothLEManufacturerData, BluetoothLEScanningMode, *,
+};
+use windows::Devices::Bluetooth::{
+    BluetoothAdapter, BluetoothAddressType, BluetoothConnectionStatus, BluetoothLEDevice, BluetoothSignalStrengthFilter,
 };
-use windows::Devices::Bluetooth::{BluetoothAdapter, BluetoothConnectionStatus, BluetoothLEDevice};
 use windows::Devices::Enumeration::{DeviceInformation, DeviceInformationKind};
 use windows::Devices::Radios::{Radio, RadioState};
 use windows::Foundation::Collections::{IIterable, IVector};
-use windows::Foundation::IReference;
-use windows::Foundation::TypedEventHandler;
-use windows::Storage::Streams::DataReader;
-use windows::Storage::Streams::DataWriter;
-use windows::Storage::Streams::IBuffer;
+use windows::Foundation::{DateTime, IReference, PropertyValue, TypedEventHandler};
+use windows::Storage::Streams::{DataReader, DataWriter};
+
+use super::advertisement::AdvertisementImpl;
+use super::types::StringVec;
+use crate::error::{Error, ErrorKind};
+use crate::server::{RequestHandler, Server, ServiceDefinition};
+use crate::util::defer;
+use crate::{
+    adv, AdapterEvent, AddressType, AdvertisementData, AdvertisingDevice, AdvertisingGuard, AdvertisingParameters,
+    BluetoothUuidExt, ConnectionEvent, Device, DeviceId, ManufacturerData, MonitorEvent, MonitorOptions,
+    MonitorPattern, Result, ScanEvent, ScanFilter, ScanMode, ScanOptions, Uuid,
+};
+
 /// The system's Bluetooth adapter interface.
 ///
 /// The default adapter for the system may be created with the [`Adapter::default()`] method.
@@ -239,12 +242,13 @@ impl AdapterImpl {
     /// [`Device`] which sent it. Scanning is automatically stopped when the stream is dropped. Inclusion of duplicate
     /// packets is a platform-specific implementation detail.
     ///
-    /// If `services` is not empty, returns advertisements including at least one GATT service with a UUID in
-    /// `services`. Otherwise returns all advertisements.
-    pub async fn scan<'a>(
-        &'a self,
-        services: &'a [Uuid],
-    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + 'a> {
+    /// Only advertisements matching `filter` are returned. Windows reports every advertisement it receives and does not
+    /// take a scan interval or window.
+    pub async fn scan(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = AdvertisingDevice> + Send + Unpin + '_> {
         let (sender, receiver) = futures_channel::mpsc::channel(16);
         let sender = Arc::new(std::sync::Mutex::new(sender));
 
@@ -278,57 +282,45 @@ impl AdapterImpl {
             },
         );
 
-        let build_watcher = |uuid: Option<Uuid>| {
-            let watcher = BluetoothLEAdvertisementWatcher::new()?;
-            watcher.SetScanningMode(BluetoothLEScanningMode::Active)?;
-            watcher.SetAllowExtendedAdvertisements(true)?;
-            watcher.Received(&received_handler)?;
-            watcher.Stopped(&stopped_handler)?;
-
-            if let Some(uuid) = uuid {
-                // let service_uuids = advertisement.ServiceUuids()?;
-                // service_uuids.Append(windows::core::GUID::from_u128(uuid.as_u128()))?;
-                // let advertisement = BluetoothLEAdvertisement::new()?;
-                // advertisement.GetManufacturerDataByCompanyId(0x69);
-                // advertisement.GetManufacturerDataByCompanyId(0x0059);
-                // let advertisement_filter = BluetoothLEAdvertisementFilter::new()?;
-                // advertisement_filter.SetAdvertisement(&advertisement)?;
-
-                // watcher.SetAdvertisementFilter(&advertisement_filter)?;
-            }
-
-            Ok::<_, windows::core::Error>(watcher)
-        };
-
-        let watchers = if services.is_empty() {
-            vec![build_watcher(None)?]
-        } else {
-            services
-                .iter()
-                .map(|uuid| build_watcher(Some(*uuid)))
-                .collect::<Result<_, _>>()?
-        };
-
-        for watcher in &watchers {
-            watcher.Start()?;
+        let watcher = BluetoothLEAdvertisementWatcher::new()?;
+        watcher.SetScanningMode(match options.mode {
+            ScanMode::Active => BluetoothLEScanningMode::Active,
+            ScanMode::Passive => BluetoothLEScanningMode::Passive,
+        })?;
+        watcher.SetAllowExtendedAdvertisements(true)?;
+        watcher.Received(&received_handler)?;
+        watcher.Stopped(&stopped_handler)?;
+
+        // Let the watcher drop what it can. The whole filter is applied to the received advertisements below.
+        if let Some(rssi) = filter.min_rssi() {
+            let signal_strength_filter = BluetoothSignalStrengthFilter::new()?;
+            let threshold: IReference<i16> = PropertyValue::CreateInt16(rssi)?.cast()?;
+            signal_strength_filter.SetInRangeThresholdInDBm(&threshold)?;
+            watcher.SetSignalStrengthFilter(&signal_strength_filter)?;
+        }
+        if let Some((company_id, data)) = filter.manufacturer_data() {
+            let writer = DataWriter::new()?;
+            writer.WriteBytes(&company_id.to_le_bytes())?;
+            writer.WriteBytes(data)?;
+            let pattern = BluetoothLEAdvertisementBytePattern::Create(
+                adv::ad_types::MANUFACTURER_SPECIFIC_DATA,
+                0,
+                &writer.DetachBuffer()?,
+            )?;
+            let advertisement_filter = BluetoothLEAdvertisementFilter::new()?;
+            advertisement_filter.BytePatterns()?.Append(&pattern)?;
+            watcher.SetAdvertisementFilter(&advertisement_filter)?;
         }
 
+        watcher.Start()?;
+
         let guard = defer(move || {
-            for watcher in watchers {
-                if let Err(err) = watcher.Stop() {
-                    error!("Error stopping scan: {:?}", err);
-                }
+            if let Err(err) = watcher.Stop() {
+                error!("Error stopping scan: {:?}", err);
             }
         });
 
         Ok(receiver
-            .filter(move |event_args| {
-                event_args
-                    .Advertisement()
-                    .ok()
-                    .and_then(|ad| ad.GetManufacturerDataByCompanyId(0x0059).ok())
-                    .map_or(false, |data| data.Size() != Ok(0))
-            })
             .then(move |event_args| {
                 let _guard = &guard;
 
@@ -367,10 +359,27 @@ impl AdapterImpl {
                     let addr = event_args.BluetoothAddress().ok()?;
                     let kind = event_args.BluetoothAddressType().ok()?;
                     let rssi = event_args.RawSignalStrengthInDBm().ok();
+                    let timestamp = event_args.Timestamp().ok().and_then(to_system_time);
+                    let is_scan_response = event_args.IsScanResponse().ok();
+                    let address_type = match kind {
+                        BluetoothAddressType::Public => Some(AddressType::Public),
+                        BluetoothAddressType::Random => Some(AddressType::Random),
+                        _ => None,
+                    };
                     let adv_data = AdvertisementData::from(event_args);
 
                     match Device::from_addr(addr, kind).await {
-                        Ok(device) => Some(AdvertisingDevice { device, rssi, adv_data }),
+                        Ok(device) => Some(AdvertisingDevice {
+                            device,
+                            rssi,
+                            adv_data,
+                            timestamp,
+                            address_type,
+                            primary_phy: None,
+                            secondary_phy: None,
+                            advertising_sid: None,
+                            is_scan_response,
+                        }),
                         Err(err) => {
                             if err.code().is_err() {
                                 warn!("Error creating device: {:?}", err);
@@ -382,7 +391,29 @@ impl AdapterImpl {
                     }
                 })
             })
-            .filter_map(|x| x))
+            .filter_map(|x| x)
+            .filter(move |x| filter.matches(x)))
+    }
+
+    /// Starts scanning for Bluetooth advertising packets.
+    ///
+    /// Windows never reports devices lost, so only advertisements are returned. The stream ends with an error when
+    /// Windows stops the advertisement watcher.
+    pub async fn try_scan_events(
+        &self,
+        filter: ScanFilter,
+        options: &ScanOptions,
+    ) -> Result<impl Stream<Item = Result<ScanEvent>> + Send + Unpin + '_> {
+        // The advertisements only end when the watcher is stopped, which this adapter only does once they are dropped
+        Ok(self
+            .scan(filter, options)
+            .await?
+            .map(|adv| Ok(ScanEvent::Advertisement(adv)))
+            .chain(stream::once(Err(Error::new(
+                ErrorKind::ScanStopped,
+                None,
+                "the advertisement watcher was stopped",
+            )))))
     }
 
     pub async fn discover_devices<'a>(
@@ -395,7 +426,10 @@ impl AdapterImpl {
         let advertising = Box::pin(stream::try_unfold(None, |state| async {
             let mut stream = match state {
                 Some(stream) => stream,
-                None => self.scan(services).await?,
+                None => {
+                    self.scan(ScanFilter::from_services(services), &ScanOptions::default())
+                        .await?
+                }
             };
             Ok(stream.next().await.map(|x| (x.device, Some(stream))))
         }));
@@ -454,12 +488,45 @@ impl AdapterImpl {
         }))
     }
 
-    pub async fn start_advertising(&self, data: AdvertisementData) -> Result<AdvertisingGuard, String> {
-        let mut advertisement_impl = AdvertisementImpl::new();
-        advertisement_impl.start_advertising(data).await?;
+    /// Starts advertising `data` from this adapter with a new publisher
+    pub async fn start_advertising(
+        &self,
+        data: AdvertisementData,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingGuard> {
+        let advertisement = AdvertisementImpl::start(data, parameters)?;
+        Ok(AdvertisingGuard { advertisement })
+    }
 
-        Ok(AdvertisingGuard { advertisement: advertisement_impl })
+    /// Windows does not report the number of advertising sets
+    pub async fn available_advertising_sets(&self) -> Result<usize> {
+        Err(ErrorKind::NotSupported.into())
     }
+
+    /// Advertisement monitors are not supported on Windows
+    pub async fn monitor_advertisements(
+        &self,
+        _patterns: &[MonitorPattern],
+        _options: &MonitorOptions,
+    ) -> Result<stream::Empty<MonitorEvent>> {
+        Err(ErrorKind::NotSupported.into())
+    }
+
+    /// GATT servers are not supported on Windows
+    pub async fn start_server(
+        &self,
+        _services: Vec<ServiceDefinition>,
+        _handler: Arc<dyn RequestHandler>,
+    ) -> Result<Server> {
+        Err(ErrorKind::NotSupported.into())
+    }
+}
+
+/// Converts a WinRT `DateTime`, in 100ns intervals since January 1, 1601 (UTC), to a [`SystemTime`].
+fn to_system_time(time: DateTime) -> Option<SystemTime> {
+    const UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+    let intervals = u64::try_from(time.UniversalTime.checked_sub(UNIX_EPOCH)?).ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_nanos(intervals.checked_mul(100)?))
 }
 
 impl From<BluetoothConnectionStatus> for ConnectionEvent {
@@ -486,8 +553,14 @@ impl TryFrom<BluetoothLEManufacturerData> for ManufacturerData {
 
 impl From<BluetoothLEAdvertisementReceivedEventArgs> for AdvertisementData {
     fn from(event_args: BluetoothLEAdvertisementReceivedEventArgs) -> Self {
-        let is_connectable = event_args.IsConnectable().unwrap_or(false);
+        let is_connectable = event_args.IsConnectable().ok();
         let tx_power_level = event_args.TransmitPowerLevelInDBm().ok().and_then(|x| x.Value().ok());
+        let flags = event_args
+            .Advertisement()
+            .and_then(|x| x.Flags())
+            .and_then(|x| x.Value())
+            .ok()
+            .map(|x| x.0 as u8);
         let (local_name, manufacturer_data, services, service_data) = if let Ok(adv) = event_args.Advertisement() {
             let local_name = adv
                 .LocalName()
@@ -495,9 +568,8 @@ impl From<BluetoothLEAdvertisementReceivedEventArgs> for AdvertisementData {
                 .and_then(|x| (!x.is_empty()).then(|| x.to_string_lossy()));
             let manufacturer_data = adv
                 .ManufacturerData()
-                .and_then(|x| x.GetAt(0))
-                .and_then(|x| x.try_into())
-                .ok();
+                .map(|x| x.into_iter().filter_map(|x| x.try_into().ok()).collect())
+                .unwrap_or_default();
 
             let services = adv
                 .ServiceUuids()
@@ -512,7 +584,7 @@ impl From<BluetoothLEAdvertisementReceivedEventArgs> for AdvertisementData {
 
             (local_name, manufacturer_data, services, service_data)
         } else {
-            (None, None, Vec::new(), HashMap::new())
+            (None, Default::default(), Vec::new(), HashMap::new())
         };
 
         AdvertisementData {
@@ -522,6 +594,8 @@ impl From<BluetoothLEAdvertisementReceivedEventArgs> for AdvertisementData {
             tx_power_level,
             is_connectable,
             service_data,
+            flags,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,26 @@
+use futures_lite::stream;
+
+use crate::server::{Central, ServerEvent, Subscription};
+use crate::{Result, Uuid};
+
+/// GATT servers are not supported on Windows, so there are no servers.
+#[derive(Debug)]
+pub enum ServerImpl {}
+
+impl ServerImpl {
+    pub fn centrals(&self) -> Vec<Central> {
+        match *self {}
+    }
+
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        match *self {}
+    }
+
+    pub fn events(&self) -> stream::Empty<ServerEvent> {
+        match *self {}
+    }
+
+    pub async fn notify(&self, _service: Uuid, _characteristic: Uuid, _value: &[u8]) -> Result<bool> {
+        match *self {}
+    }
+}
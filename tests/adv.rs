@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bluest::adv::{self, flags, AdStructure, PayloadFormat};
+use bluest::btuuid::services;
+use bluest::error::ErrorKind;
+use bluest::{AdvertisementData, BluetoothUuidExt, ManufacturerData, ManufacturerDataList, Uuid};
+
+const CUSTOM_SERVICE: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+
+#[test]
+fn decode_payload() {
+    let payload = [
+        0x02, 0x01, 0x06, // Flags
+        0x05, 0x03, 0x0f, 0x18, 0x0a, 0x18, // Complete list of 16-bit service UUIDs
+        0x05, 0x16, 0x0f, 0x18, 0x57, 0x01, // 16-bit service data
+        0x07, 0xff, 0x4c, 0x00, 0x02, 0x15, 0xaa, 0xbb, // Manufacturer specific data
+        0x04, 0xff, 0x59, 0x00, 0x01, // Manufacturer specific data
+        0x02, 0x0a, 0xf4, // TX power level
+        0x03, 0x08, b'B', b'a', // Shortened local name
+        0x02, 0x42, 0x01, // Unknown AD type
+        0x00, 0x00, 0x00, // Padding
+    ];
+
+    let data = adv::decode(&payload).unwrap();
+    assert_eq!(
+        data.flags,
+        Some(flags::LE_GENERAL_DISCOVERABLE | flags::BR_EDR_NOT_SUPPORTED)
+    );
+    assert_eq!(data.services, vec![services::BATTERY, services::DEVICE_INFORMATION]);
+    assert_eq!(
+        data.service_data,
+        HashMap::from([(services::BATTERY, vec![0x57, 0x01])])
+    );
+    assert_eq!(
+        data.manufacturer_data,
+        ManufacturerDataList::from(vec![
+            ManufacturerData {
+                company_id: 0x004c,
+                data: vec![0x02, 0x15, 0xaa, 0xbb],
+            },
+            ManufacturerData {
+                company_id: 0x0059,
+                data: vec![0x01],
+            },
+        ])
+    );
+    assert_eq!(data.manufacturer_data.get(0x0059), Some(&[0x01][..]));
+    assert_eq!(data.tx_power_level, Some(-12));
+    assert_eq!(data.local_name.as_deref(), Some("Ba"));
+    assert_eq!(
+        data.other_structures,
+        vec![AdStructure {
+            ad_type: 0x42,
+            data: vec![0x01]
+        }]
+    );
+}
+
+#[test]
+fn decode_uuid_sizes() {
+    let payload = [
+        0x05, 0x05, 0x78, 0x56, 0x34, 0x12, // Complete list of 32-bit service UUIDs
+        0x11, 0x06, 0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0, 0x93, 0xf3, 0xa3, 0xb5, 0x01, 0x00, 0x40,
+        0x6e, // Incomplete list of 128-bit service UUIDs
+        0x07, 0x20, 0x78, 0x56, 0x34, 0x12, 0x01, 0x02, // 32-bit service data
+        0x03, 0x14, 0x0f, 0x18, // 16-bit service solicitation UUIDs
+    ];
+
+    let data = adv::decode(&payload).unwrap();
+    assert_eq!(data.services, vec![Uuid::from_u32(0x12345678), CUSTOM_SERVICE]);
+    assert_eq!(data.service_data.get(&Uuid::from_u32(0x12345678)), Some(&vec![1, 2]));
+    assert_eq!(data.solicited_services, vec![services::BATTERY]);
+}
+
+#[test]
+fn decode_rejects_truncated_structures() {
+    let err = adv::decode(&[0x02, 0x01, 0x06, 0x05, 0x09, b'a']).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+}
+
+#[test]
+fn malformed_structures_are_kept_raw() {
+    // A 16-bit UUID list with an odd length and a one octet appearance
+    let data = adv::decode(&[0x04, 0x03, 0x0f, 0x18, 0x0a, 0x02, 0x19, 0x40]).unwrap();
+    assert!(data.services.is_empty());
+    assert_eq!(data.appearance, None);
+    assert_eq!(
+        data.other_structures,
+        vec![
+            AdStructure {
+                ad_type: 0x03,
+                data: vec![0x0f, 0x18, 0x0a]
+            },
+            AdStructure {
+                ad_type: 0x19,
+                data: vec![0x40]
+            },
+        ]
+    );
+}
+
+#[test]
+fn round_trip() {
+    let data = AdvertisementData {
+        local_name: Some("Thermometer".to_string()),
+        manufacturer_data: ManufacturerDataList::from(vec![
+            ManufacturerData {
+                company_id: 0xffff,
+                data: vec![1, 2, 3],
+            },
+            ManufacturerData {
+                company_id: 0xffff,
+                data: vec![4],
+            },
+        ]),
+        services: vec![services::HEALTH_THERMOMETER, Uuid::from_u32(0x12345678), CUSTOM_SERVICE],
+        service_data: HashMap::from([
+            (services::HEALTH_THERMOMETER, vec![1]),
+            (Uuid::from_u32(0x12345678), vec![2]),
+            (CUSTOM_SERVICE, vec![3]),
+        ]),
+        tx_power_level: Some(-8),
+        is_connectable: None,
+        flags: Some(flags::LE_LIMITED_DISCOVERABLE),
+        solicited_services: vec![services::BATTERY, CUSTOM_SERVICE],
+        appearance: Some(0x0300),
+        advertising_interval: Some(Duration::from_millis(100)),
+        uri: Some("https://example.com/sensor".to_string()),
+        le_supported_features: Some(vec![0x01, 0x40]),
+        other_structures: vec![AdStructure {
+            ad_type: 0x3d,
+            data: vec![0x01, 0x02],
+        }],
+    };
+
+    let payload = adv::encode(&data, PayloadFormat::Extended).unwrap();
+    assert_eq!(adv::decode(&payload).unwrap(), data);
+}
+
+#[test]
+fn encode_uris_and_intervals() {
+    let data = AdvertisementData {
+        uri: Some("https://a.b".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(adv::encode(&data, PayloadFormat::Legacy).unwrap(), b"\x07\x24\x17//a.b");
+
+    let data = AdvertisementData {
+        uri: Some("urn:x".to_string()),
+        ..Default::default()
+    };
+    let payload = adv::encode(&data, PayloadFormat::Legacy).unwrap();
+    assert_eq!(payload, b"\x07\x24\x01urn:x");
+    assert_eq!(adv::decode(&payload).unwrap(), data);
+
+    let data = AdvertisementData {
+        advertising_interval: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let payload = adv::encode(&data, PayloadFormat::Legacy).unwrap();
+    assert_eq!(payload, [0x04, 0x2f, 0x00, 0x77, 0x01]);
+    assert_eq!(adv::decode(&payload).unwrap(), data);
+}
+
+#[test]
+fn encode_reports_overflow() {
+    let data = AdvertisementData {
+        local_name: Some("A rather long device name".to_string()),
+        flags: Some(flags::LE_GENERAL_DISCOVERABLE),
+        tx_power_level: Some(0),
+        ..Default::default()
+    };
+
+    let err = adv::encode(&data, PayloadFormat::Legacy).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+    assert_eq!(adv::encode(&data, PayloadFormat::Extended).unwrap().len(), 33);
+
+    let data = AdvertisementData {
+        manufacturer_data: ManufacturerData {
+            company_id: 0xffff,
+            data: vec![0; 300],
+        }
+        .into(),
+        ..Default::default()
+    };
+    let err = adv::encode(&data, PayloadFormat::Extended).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+
+    let data = AdvertisementData {
+        tx_power_level: Some(200),
+        ..Default::default()
+    };
+    assert!(adv::encode(&data, PayloadFormat::Legacy).is_err());
+}
+
+#[test]
+fn manufacturer_data_lookup() {
+    let list = ManufacturerDataList::from_iter([
+        ManufacturerData {
+            company_id: 0x004c,
+            data: vec![1],
+        },
+        ManufacturerData {
+            company_id: 0x0059,
+            data: vec![2],
+        },
+        ManufacturerData {
+            company_id: 0x004c,
+            data: vec![3],
+        },
+    ]);
+
+    assert_eq!(list.len(), 3);
+    assert_eq!(list.first().map(|x| x.company_id), Some(0x004c));
+    assert_eq!(list.get(0x004c), Some(&[1][..]));
+    assert_eq!(list.get_all(0x004c).collect::<Vec<_>>(), [&[1][..], &[3][..]]);
+    assert_eq!(list.get(0xffff), None);
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bluest::beacon::{AltBeacon, Beacon, Eddystone, EddystoneTlm, IBeacon, EDDYSTONE_SERVICE};
+use bluest::btuuid::services;
+use bluest::error::ErrorKind;
+use bluest::{AdvertisementData, ManufacturerData, ManufacturerDataList, Uuid};
+
+const PROXIMITY_UUID: Uuid = Uuid::from_u128(0xe2c56db5_dffb_48d2_b060_d0f5a71096e0);
+
+fn eddystone(frame: &[u8]) -> AdvertisementData {
+    AdvertisementData {
+        services: vec![EDDYSTONE_SERVICE],
+        service_data: HashMap::from([(EDDYSTONE_SERVICE, frame.to_vec())]),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn ibeacon() {
+    let data = AdvertisementData {
+        manufacturer_data: ManufacturerData {
+            company_id: 0x004c,
+            data: vec![
+                0x02, 0x15, 0xe2, 0xc5, 0x6d, 0xb5, 0xdf, 0xfb, 0x48, 0xd2, 0xb0, 0x60, 0xd0, 0xf5, 0xa7, 0x10, 0x96,
+                0xe0, 0x00, 0x01, 0x01, 0x02, 0xc5,
+            ],
+        }
+        .into(),
+        ..Default::default()
+    };
+
+    let beacon = IBeacon {
+        uuid: PROXIMITY_UUID,
+        major: 1,
+        minor: 258,
+        measured_power: -59,
+    };
+    assert_eq!(Beacon::from_advertisement(&data), Some(Beacon::IBeacon(beacon)));
+    assert_eq!(beacon.to_advertisement(), data);
+
+    // Other Apple manufacturer data is not an iBeacon
+    let data = AdvertisementData {
+        manufacturer_data: ManufacturerData {
+            company_id: 0x004c,
+            data: vec![0x10, 0x05, 0x01],
+        }
+        .into(),
+        ..Default::default()
+    };
+    assert_eq!(Beacon::from_advertisement(&data), None);
+}
+
+#[test]
+fn altbeacon() {
+    let mut value = vec![0xbe, 0xac];
+    value.extend(1..=20);
+    value.extend([0xc4, 0x7f]);
+    let data = AdvertisementData {
+        manufacturer_data: ManufacturerDataList::from(vec![
+            ManufacturerData {
+                company_id: 0x0059,
+                data: vec![0x01],
+            },
+            ManufacturerData {
+                company_id: 0x0118,
+                data: value,
+            },
+        ]),
+        ..Default::default()
+    };
+
+    let beacon = AltBeacon::from_advertisement(&data).unwrap();
+    assert_eq!(beacon.company_id, 0x0118);
+    assert_eq!(beacon.beacon_id[..3], [1, 2, 3]);
+    assert_eq!(beacon.reference_rssi, -60);
+    assert_eq!(beacon.mfg_reserved, 0x7f);
+    assert_eq!(
+        beacon.to_advertisement().manufacturer_data[..],
+        data.manufacturer_data[1..]
+    );
+}
+
+#[test]
+fn eddystone_uid_and_eid() {
+    let mut frame = vec![0x00, 0xee];
+    frame.extend(0..16);
+    frame.extend([0, 0]);
+    let uid = Eddystone::Uid {
+        tx_power: -18,
+        namespace: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        instance: [10, 11, 12, 13, 14, 15],
+    };
+    assert_eq!(Eddystone::from_advertisement(&eddystone(&frame)), Some(uid.clone()));
+    assert_eq!(uid.to_advertisement().unwrap(), eddystone(&frame));
+
+    // The reserved octets are optional
+    assert!(Eddystone::from_advertisement(&eddystone(&frame[..18])).is_some());
+
+    let eid = Eddystone::Eid {
+        tx_power: 0,
+        eid: [8, 7, 6, 5, 4, 3, 2, 1],
+    };
+    let frame = [0x30, 0x00, 8, 7, 6, 5, 4, 3, 2, 1];
+    assert_eq!(Eddystone::from_advertisement(&eddystone(&frame)), Some(eid.clone()));
+    assert_eq!(eid.to_advertisement().unwrap(), eddystone(&frame));
+}
+
+#[test]
+fn eddystone_url() {
+    let frame = [0x10, 0xec, 0x01, b'g', b'o', b'o', b'g', b'l', b'e', 0x00, b'x'];
+    let url = Eddystone::Url {
+        tx_power: -20,
+        url: "https://www.google.com/x".to_string(),
+    };
+    assert_eq!(Eddystone::from_advertisement(&eddystone(&frame)), Some(url.clone()));
+    assert_eq!(url.to_advertisement().unwrap(), eddystone(&frame));
+
+    let url = Eddystone::Url {
+        tx_power: 0,
+        url: "http://a.info".to_string(),
+    };
+    let data = url.to_advertisement().unwrap();
+    assert_eq!(data.service_data[&EDDYSTONE_SERVICE], [0x10, 0x00, 0x02, b'a', 0x0b]);
+
+    for url in [
+        "ftp://example.com",
+        "https://ex ample.com",
+        "https://a-very-long-domain-name.com/",
+    ] {
+        let err = Eddystone::Url {
+            tx_power: 0,
+            url: url.to_string(),
+        }
+        .to_advertisement()
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidParameter, "{url}");
+    }
+
+    // Reserved codes are rejected
+    assert_eq!(
+        Eddystone::from_advertisement(&eddystone(&[0x10, 0x00, 0x00, 0x0e])),
+        None
+    );
+}
+
+#[test]
+fn eddystone_tlm() {
+    let frame = [
+        0x20, 0x00, 0x0b, 0xb8, 0x18, 0x80, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x58,
+    ];
+    let tlm = EddystoneTlm {
+        battery_voltage: Some(3000),
+        temperature: Some(24.5),
+        advertising_count: 256,
+        uptime: Duration::from_secs(60),
+    };
+    assert_eq!(
+        Eddystone::from_advertisement(&eddystone(&frame)),
+        Some(Eddystone::Tlm(tlm))
+    );
+    assert_eq!(Eddystone::Tlm(tlm).to_advertisement().unwrap(), eddystone(&frame));
+
+    // Negative temperatures and missing sensors
+    let frame = [
+        0x20, 0x00, 0x00, 0x00, 0xff, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let Some(Eddystone::Tlm(tlm)) = Eddystone::from_advertisement(&eddystone(&frame)) else {
+        panic!("not a TLM frame");
+    };
+    assert_eq!(tlm.battery_voltage, None);
+    assert_eq!(tlm.temperature, Some(-0.5));
+    let frame = [
+        0x20, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let Some(Eddystone::Tlm(tlm)) = Eddystone::from_advertisement(&eddystone(&frame)) else {
+        panic!("not a TLM frame");
+    };
+    assert_eq!(tlm.temperature, None);
+
+    // Encrypted TLM frames are ignored
+    let mut frame = frame;
+    frame[1] = 0x01;
+    assert_eq!(Eddystone::from_advertisement(&eddystone(&frame)), None);
+
+    let err = Eddystone::Tlm(EddystoneTlm {
+        temperature: Some(300.0),
+        ..tlm
+    })
+    .to_advertisement()
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+}
+
+#[test]
+fn beacons_in_other_advertisements() {
+    let data = AdvertisementData {
+        services: vec![services::BATTERY],
+        service_data: HashMap::from([(services::BATTERY, vec![0x20, 0x00])]),
+        ..Default::default()
+    };
+    assert_eq!(Beacon::from_advertisement(&data), None);
+}
@@ -0,0 +1,1230 @@
+//! End-to-end tests of the Linux backend against a fake BlueZ daemon.
+
+#![cfg(all(target_os = "linux", not(feature = "mock")))]
+
+mod fake_bluez;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bluest::adv::{self, AdStructure};
+use bluest::btuuid::{characteristics, descriptors, services};
+use bluest::emulator::{Battery, Emulator, ValueSource};
+use bluest::error::{AttError, ErrorKind};
+use bluest::pairing::{IoCapability, PairingAgent, PairingRejected, Passkey};
+#[cfg(feature = "privacy")]
+use bluest::privacy::{AddressKind, Identity, Irk, Keyring};
+use bluest::server::{
+    AttributeId, AttributePermissions, CharacteristicDefinition, DescriptorDefinition, ReadRequest, RequestHandler,
+    SecurityLevel, ServerEvent, ServiceDefinition, Subscription, WriteKind, WriteRequest,
+};
+use bluest::{
+    Adapter, AdapterEvent, AddressType, AdvertisementData, AdvertisingParameters, CharacteristicProperties,
+    ConnectionEvent, Device, DuplicatePolicy, ManufacturerData, ManufacturerDataList, MonitorEvent, MonitorOptions,
+    MonitorPattern, Phy, ScanBroker, ScanEvent, ScanFilter, ScanMode, ScanOptions,
+};
+use fake_bluez::{FakeBluez, FakeCharacteristic, FakeDescriptor, FakeDevice, FakeService, FakeWrite};
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn next<S: Stream + Unpin>(stream: &mut S) -> S::Item {
+    tokio::time::timeout(TIMEOUT, stream.next())
+        .await
+        .expect("timed out waiting for the next item")
+        .expect("stream ended unexpectedly")
+}
+
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    tokio::time::timeout(TIMEOUT, async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("timed out waiting for condition");
+}
+
+/// Scans until the fake `device` is reported.
+async fn discover(adapter: &Adapter, device: &FakeDevice) -> Device {
+    let id = device.address().map(|x| format!("{x:02X}")).join(":");
+    let mut scan = adapter.scan(&[]).await.unwrap();
+    loop {
+        let adv = next(&mut scan).await;
+        if adv.device.id().to_string() == id {
+            return adv.device;
+        }
+    }
+}
+
+struct KeyboardAgent(Passkey);
+
+#[async_trait]
+impl PairingAgent for KeyboardAgent {
+    fn io_capability(&self) -> IoCapability {
+        IoCapability::KeyboardOnly
+    }
+
+    async fn request_passkey(&self, _device: &Device) -> Result<Passkey, PairingRejected> {
+        Ok(self.0)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adapter_events() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let adapter = Adapter::default().await.unwrap();
+    adapter.wait_available().await.unwrap();
+
+    let mut events = adapter.events().await.unwrap();
+    bluez.adapter().set_powered(false);
+    assert!(matches!(next(&mut events).await, Ok(AdapterEvent::Unavailable)));
+    bluez.adapter().set_powered(true);
+    assert!(matches!(next(&mut events).await, Ok(AdapterEvent::Available)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_reports_advertisement_data() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let thermometer = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Thermometer")
+        .with_rssi(-60)
+        .with_tx_power(4)
+        .with_manufacturer_data(0x004c, &[1, 2, 3])
+        .with_manufacturer_data(0x0059, &[4])
+        .with_uuid(services::HEALTH_THERMOMETER)
+        .with_service_data(services::HEALTH_THERMOMETER, &[9]);
+    bluez.adapter().add_device(&thermometer);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut scan = adapter.scan(&[]).await.unwrap();
+    assert!(bluez.adapter().is_discovering());
+
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.device.id().to_string(), "12:34:56:78:9A:BC");
+    assert_eq!(adv.adv_data.local_name.as_deref(), Some("Thermometer"));
+    assert_eq!(
+        adv.adv_data.manufacturer_data,
+        ManufacturerDataList::from(vec![
+            ManufacturerData {
+                company_id: 0x004c,
+                data: vec![1, 2, 3]
+            },
+            ManufacturerData {
+                company_id: 0x0059,
+                data: vec![4]
+            },
+        ])
+    );
+    assert_eq!(adv.adv_data.manufacturer_data.get(0x0059), Some(&[4][..]));
+    assert_eq!(adv.adv_data.services, vec![services::HEALTH_THERMOMETER]);
+    assert_eq!(
+        adv.adv_data.service_data.get(&services::HEALTH_THERMOMETER),
+        Some(&vec![9])
+    );
+    assert_eq!(adv.adv_data.tx_power_level, Some(4));
+
+    // Devices found while the scan is running are reported as well
+    let sensor = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]).with_name("Sensor");
+    bluez.adapter().add_device(&sensor);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.device.name_async().await.unwrap(), "Sensor");
+
+    drop(scan);
+    eventually(|| !bluez.adapter().is_discovering()).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_reports_packet_metadata() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let peripheral = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Peripheral")
+        .with_advertising_flags(0x06);
+    let beacon = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd])
+        .with_random_address()
+        .with_advertising_flags(0x04)
+        .with_manufacturer_data(0x004c, &[2, 0x15]);
+    bluez.adapter().add_device(&peripheral);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut scan = adapter.scan(&[]).await.unwrap();
+    let before = SystemTime::now();
+    bluez.adapter().add_device(&beacon);
+    let mut found = Vec::new();
+    while found.len() < 2 {
+        found.push(next(&mut scan).await);
+    }
+    found.sort_by_key(|x| x.device.id().to_string());
+
+    assert_eq!(found[0].address_type, Some(AddressType::Public));
+    assert_eq!(found[1].address_type, Some(AddressType::Random));
+    assert!(found[1].timestamp.unwrap() >= before);
+    // BlueZ does not report these
+    assert_eq!(found[0].adv_data.is_connectable, None);
+    assert_eq!(found[1].adv_data.is_connectable, None);
+    assert_eq!(found[1].primary_phy, None);
+    assert_eq!(found[1].advertising_sid, None);
+    assert_eq!(found[1].is_scan_response, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_follows_property_changes() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let beacon = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Beacon")
+        .with_rssi(-70);
+    bluez.adapter().add_device(&beacon);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut scan = adapter.scan(&[]).await.unwrap();
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.rssi, Some(-70));
+    assert_eq!(adv.device.rssi().await.unwrap(), -70);
+
+    beacon.set_rssi(-50);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.rssi, Some(-50));
+    assert_eq!(adv.device.rssi().await.unwrap(), -50);
+
+    beacon.set_manufacturer_data(0x004c, &[4, 5]);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.adv_data.manufacturer_data.get(0x004c), Some(&[4, 5][..]));
+
+    // Changes to other properties are not advertisements
+    beacon.set_name("Renamed");
+    assert!(tokio::time::timeout(Duration::from_millis(500), scan.next())
+        .await
+        .is_err());
+    beacon.set_tx_power(-4);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.adv_data.tx_power_level, Some(-4));
+
+    // Devices are still followed after being removed and found again
+    bluez.adapter().remove_device(&beacon);
+    bluez.adapter().add_device(&beacon);
+    next(&mut scan).await;
+    beacon.set_rssi(-40);
+    assert_eq!(next(&mut scan).await.rssi, Some(-40));
+    beacon.set_rssi(-45);
+    assert_eq!(next(&mut scan).await.rssi, Some(-45));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_filters_by_service() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let sensor = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]).with_name("Sensor");
+    let thermometer = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd])
+        .with_name("Thermometer")
+        .with_uuid(services::HEALTH_THERMOMETER);
+    bluez.adapter().add_device(&sensor);
+    bluez.adapter().add_device(&thermometer);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut scan = adapter.scan(&[services::HEALTH_THERMOMETER]).await.unwrap();
+    let filter = bluez.adapter().discovery_filter().unwrap();
+    assert_eq!(filter.uuids, vec![services::HEALTH_THERMOMETER]);
+    assert_eq!(filter.transport.as_deref(), Some("le"));
+
+    // The fake daemon ignores the filter, so the sensor must be dropped by the scan itself
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.device.name_async().await.unwrap(), "Thermometer");
+    bluez
+        .adapter()
+        .add_device(&FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbe]).with_name("Other"));
+    let other = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbf])
+        .with_name("Other thermometer")
+        .with_uuid(services::HEALTH_THERMOMETER);
+    bluez.adapter().add_device(&other);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.device.name_async().await.unwrap(), "Other thermometer");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_with_filter() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let far = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Sensor 1")
+        .with_rssi(-90)
+        .with_uuid(services::HEALTH_THERMOMETER);
+    let other = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd])
+        .with_name("Scale")
+        .with_rssi(-50)
+        .with_uuid(services::HEALTH_THERMOMETER);
+    let beacon = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbe])
+        .with_name("Beacon")
+        .with_rssi(-80)
+        .with_manufacturer_data(0x004c, &[0x02, 0x15, 0x01]);
+    let near = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbf])
+        .with_name("Sensor 2")
+        .with_rssi(-40)
+        .with_uuid(services::HEALTH_THERMOMETER);
+    for device in [&far, &other, &beacon] {
+        bluez.adapter().add_device(device);
+    }
+
+    let adapter = Adapter::default().await.unwrap();
+    let filter = ScanFilter::new()
+        .with_services([services::HEALTH_THERMOMETER])
+        .with_min_rssi(-60)
+        .with_name_prefix("Sensor");
+    let mut scan = adapter.scan_with_filter(filter).await.unwrap();
+    let discovery_filter = bluez.adapter().discovery_filter().unwrap();
+    assert_eq!(discovery_filter.uuids, vec![services::HEALTH_THERMOMETER]);
+    assert_eq!(discovery_filter.rssi, Some(-60));
+    assert_eq!(discovery_filter.pattern.as_deref(), Some("Sensor"));
+
+    // The fake daemon ignores the filter, so the scan drops the far sensor, the scale and the beacon itself
+    bluez.adapter().add_device(&near);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.device.id().to_string(), "12:34:56:78:9A:BF");
+    drop(scan);
+    eventually(|| !bluez.adapter().is_discovering()).await;
+
+    // Only what every alternative requires is pushed down
+    let filter = ScanFilter::new()
+        .with_min_rssi(-60)
+        .or(ScanFilter::new().with_manufacturer_data(0x004c, &[0x02, 0x15], None));
+    let mut scan = adapter.scan_with_filter(filter).await.unwrap();
+    let discovery_filter = bluez.adapter().discovery_filter().unwrap();
+    assert!(discovery_filter.uuids.is_empty());
+    assert_eq!(discovery_filter.rssi, None);
+    let mut found = Vec::new();
+    while found.len() < 3 {
+        found.push(next(&mut scan).await.device.id().to_string());
+    }
+    found.sort();
+    assert_eq!(found, ["12:34:56:78:9A:BD", "12:34:56:78:9A:BE", "12:34:56:78:9A:BF"]);
+}
+
+// The next scan waits for the discovery session of a dropped scan to stop, even when no other task can run meanwhile
+#[tokio::test]
+async fn scan_restarts_with_a_new_filter() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let adapter = Adapter::default().await.unwrap();
+    let scan = adapter.scan(&[]).await.unwrap();
+    assert!(bluez.adapter().discovery_filter().unwrap().uuids.is_empty());
+
+    drop(scan);
+    let _scan = adapter.scan(&[services::HEALTH_THERMOMETER]).await.unwrap();
+    let filter = bluez.adapter().discovery_filter().unwrap();
+    assert_eq!(filter.uuids, vec![services::HEALTH_THERMOMETER]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_with_connectable_filter() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    // Non-connectable beacons commonly advertise the LE General Discoverable and BR/EDR Not Supported flags
+    let beacon = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_advertising_flags(0x06)
+        .with_manufacturer_data(0x004c, &[0x02, 0x15, 0x01]);
+    let sentinel = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]).with_name("Sentinel");
+
+    let adapter = Adapter::default().await.unwrap();
+    let filter = ScanFilter::new()
+        .with_connectable()
+        .or(ScanFilter::new().with_name_prefix("Sentinel"));
+    let mut scan = adapter.scan_with_filter(filter).await.unwrap();
+    bluez.adapter().add_device(&beacon);
+    bluez.adapter().add_device(&sentinel);
+
+    // BlueZ does not report whether an advertisement is connectable, so the beacon is not assumed not to be
+    let mut found = Vec::new();
+    while found.len() < 2 {
+        found.push(next(&mut scan).await.device.id().to_string());
+    }
+    found.sort();
+    assert_eq!(found, ["12:34:56:78:9A:BC", "12:34:56:78:9A:BD"]);
+    drop(scan);
+    eventually(|| !bluez.adapter().is_discovering()).await;
+
+    let mut scan = adapter.scan(&[]).await.unwrap();
+    let mut found = Vec::new();
+    while found.len() < 2 {
+        found.push(next(&mut scan).await);
+    }
+    found.sort_by_key(|x| x.device.id().to_string());
+    assert_eq!(found[0].adv_data.flags, Some(0x06));
+    assert_eq!(found[0].adv_data.is_connectable, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_with_options() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let sensor = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_rssi(-60)
+        .with_manufacturer_data(0xffff, &[0x01]);
+    bluez.adapter().add_device(&sensor);
+
+    let adapter = Adapter::default().await.unwrap();
+    let options = ScanOptions {
+        mode: ScanMode::Passive,
+        ..Default::default()
+    };
+    let err = adapter
+        .scan_with_options(ScanFilter::new(), options)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+
+    // BlueZ only signals changed data, and signal strength updates with the same data are dropped
+    let options = ScanOptions {
+        duplicates: DuplicatePolicy::ReportChanges,
+        max_results: Some(2),
+        ..Default::default()
+    };
+    let mut scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    assert_eq!(bluez.adapter().discovery_filter().unwrap().duplicate_data, Some(false));
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.adv_data.manufacturer_data.get(0xffff), Some(&[0x01][..]));
+    sensor.set_rssi(-50);
+    sensor.set_manufacturer_data(0xffff, &[0x02]);
+    let adv = next(&mut scan).await;
+    assert_eq!(adv.adv_data.manufacturer_data.get(0xffff), Some(&[0x02][..]));
+    assert_eq!(adv.rssi, Some(-50));
+
+    // The stream ends after `max_results` advertisements
+    sensor.set_manufacturer_data(0xffff, &[0x03]);
+    assert!(scan.next().await.is_none());
+    drop(scan);
+    eventually(|| !bluez.adapter().is_discovering()).await;
+
+    let options = ScanOptions {
+        duration: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let mut scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    assert_eq!(bluez.adapter().discovery_filter().unwrap().duplicate_data, Some(true));
+    let _ = next(&mut scan).await;
+    tokio::time::timeout(TIMEOUT, async { while scan.next().await.is_some() {} })
+        .await
+        .expect("the scan did not end after its duration");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_reports_removed_devices() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let sensor = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]).with_name("Sensor");
+    let other = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]).with_name("Other");
+    bluez.adapter().add_device(&sensor);
+    bluez.adapter().add_device(&other);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut events = adapter
+        .scan_events(ScanFilter::new().with_name_prefix("Sensor"), ScanOptions::default())
+        .await
+        .unwrap();
+    match next(&mut events).await {
+        ScanEvent::Advertisement(adv) => assert_eq!(adv.device.id().address(), sensor.address()),
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    // Only devices which were reported are lost
+    bluez.adapter().remove_device(&other);
+    bluez.adapter().remove_device(&sensor);
+    match next(&mut events).await {
+        ScanEvent::DeviceLost(id) => assert_eq!(id.address(), sensor.address()),
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_errors() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let sensor = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]).with_name("Sensor");
+    bluez.adapter().add_device(&sensor);
+    let adapter = Adapter::default().await.unwrap();
+
+    // Powering the adapter off ends the scan
+    let mut events = adapter
+        .try_scan_events(ScanFilter::new(), ScanOptions::default())
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut events).await, Ok(ScanEvent::Advertisement(_))));
+    bluez.adapter().set_powered(false);
+    let err = next(&mut events).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+    assert!(tokio::time::timeout(TIMEOUT, events.next()).await.unwrap().is_none());
+    drop(events);
+
+    // The scan survives BlueZ stopping discovery for a while, but ends when discovery does not restart
+    bluez.adapter().set_powered(true);
+    let mut events = adapter
+        .try_scan_events(ScanFilter::new(), ScanOptions::default())
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut events).await, Ok(ScanEvent::Advertisement(_))));
+    bluez.adapter().set_discovery_paused(true);
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    bluez.adapter().set_discovery_paused(false);
+    bluez.adapter().set_discovery_paused(true);
+    let paused = std::time::Instant::now();
+    let err = tokio::time::timeout(Duration::from_secs(15), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ScanStopped);
+    assert!(paused.elapsed() >= Duration::from_secs(9));
+    assert!(tokio::time::timeout(TIMEOUT, events.next()).await.unwrap().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_broker() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let thermometer = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]).with_uuid(services::HEALTH_THERMOMETER);
+    let scale = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]).with_uuid(services::WEIGHT_SCALE);
+
+    let adapter = Adapter::default().await.unwrap();
+    let broker = ScanBroker::new(adapter);
+    let uuids = || {
+        let mut uuids = bluez.adapter().discovery_filter().unwrap().uuids;
+        uuids.sort();
+        uuids
+    };
+
+    let mut thermometers = broker
+        .subscribe(ScanFilter::new().with_services([services::HEALTH_THERMOMETER]))
+        .await
+        .unwrap();
+    assert_eq!(uuids(), [services::HEALTH_THERMOMETER]);
+
+    // The scan widens to cover both subscriptions, which each get their own devices
+    let mut scales = broker
+        .subscribe(ScanFilter::new().with_services([services::WEIGHT_SCALE]))
+        .await
+        .unwrap();
+    assert_eq!(uuids(), [services::HEALTH_THERMOMETER, services::WEIGHT_SCALE]);
+    bluez.adapter().add_device(&thermometer);
+    bluez.adapter().add_device(&scale);
+    assert_eq!(
+        next(&mut thermometers).await.device.id().to_string(),
+        "12:34:56:78:9A:BC"
+    );
+    assert_eq!(next(&mut scales).await.device.id().to_string(), "12:34:56:78:9A:BD");
+
+    // And narrows again when one is dropped
+    drop(thermometers);
+    scale.set_rssi(-40);
+    assert_eq!(next(&mut scales).await.rssi, Some(-40));
+    assert_eq!(uuids(), [services::WEIGHT_SCALE]);
+
+    drop(scales);
+    eventually(|| !bluez.adapter().is_discovering()).await;
+    assert_eq!(broker.subscribers(), 0);
+}
+
+#[cfg(feature = "privacy")]
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_resolves_private_addresses() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let irk = Irk::from_u128(0xec0234a3_57c8ad05_341010a6_0a397d9b);
+    let identity = Identity::new([0xc0, 0x11, 0x22, 0x33, 0x44, 0x55], AddressType::Random);
+    let private = FakeDevice::new([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xaa]).with_random_address();
+    bluez.adapter().add_device(&private);
+
+    let adapter = Adapter::default().await.unwrap();
+    let mut keyring = Keyring::new();
+    keyring.add(irk, identity);
+    let mut scan = keyring.resolve_scan(adapter.scan(&[]).await.unwrap());
+    let found = next(&mut scan).await;
+    assert_eq!(found.adv.device.id().address(), private.address());
+    assert_eq!(found.address, Some(private.address()));
+    assert_eq!(found.address_kind, Some(AddressKind::ResolvablePrivate));
+    assert_eq!(found.identity, Some(identity));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn monitor_advertisements() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let sensor = FakeDevice::new([0x00, 0x1a, 0x7d, 0x10, 0x20, 0x30])
+        .with_name("Sensor")
+        .with_rssi(-55)
+        .with_manufacturer_data(0xffff, &[0x01, 0x02]);
+    bluez.adapter().add_device(&sensor);
+
+    let adapter = Adapter::default().await.unwrap();
+    let patterns = [MonitorPattern::new(
+        adv::ad_types::MANUFACTURER_SPECIFIC_DATA,
+        0,
+        [0xff, 0xff],
+    )];
+    let options = MonitorOptions {
+        rssi_high_threshold: Some(-60),
+        rssi_low_threshold: Some(-80),
+        rssi_high_timeout: Some(Duration::from_secs(1)),
+        rssi_low_timeout: Some(Duration::from_secs(5)),
+        rssi_sampling_period: Some(Duration::ZERO),
+    };
+    let mut events = adapter
+        .monitor_advertisements_with_options(&patterns, options)
+        .await
+        .unwrap();
+
+    let monitors = bluez.adapter().monitors();
+    assert_eq!(monitors.len(), 1);
+    let monitor = &monitors[0];
+    assert_eq!(monitor.monitor_type, "or_patterns");
+    assert_eq!(monitor.rssi_high_threshold, Some(-60));
+    assert_eq!(monitor.rssi_low_threshold, Some(-80));
+    assert_eq!(monitor.rssi_high_timeout, Some(1));
+    assert_eq!(monitor.rssi_low_timeout, Some(5));
+    assert_eq!(monitor.rssi_sampling_period, Some(0));
+    assert_eq!(monitor.patterns, [(0, 0xff, vec![0xff, 0xff])]);
+
+    monitor.device_found(&sensor).await;
+    match next(&mut events).await {
+        MonitorEvent::DeviceFound(adv) => {
+            assert_eq!(adv.device.id().address(), sensor.address());
+            assert_eq!(adv.rssi, Some(-55));
+            assert_eq!(adv.adv_data.local_name.as_deref(), Some("Sensor"));
+            assert_eq!(adv.adv_data.manufacturer_data.get(0xffff), Some(&[0x01, 0x02][..]));
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+    monitor.device_lost(&sensor).await;
+    match next(&mut events).await {
+        MonitorEvent::DeviceLost(adv) => assert_eq!(adv.device.id().address(), sensor.address()),
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    // Dropping the stream unregisters the monitor
+    drop(events);
+    eventually(|| bluez.adapter().monitors().is_empty()).await;
+
+    let Err(err) = adapter.monitor_advertisements(&[]).await else {
+        panic!("a monitor without patterns was registered");
+    };
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+    let too_long = [MonitorPattern::new(adv::ad_types::COMPLETE_LOCAL_NAME, 0, [b'x'; 32])];
+    let Err(err) = adapter.monitor_advertisements(&too_long).await else {
+        panic!("BlueZ accepted a pattern longer than an advertisement");
+    };
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gatt_operations() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let battery_level = FakeCharacteristic::new(characteristics::BATTERY_LEVEL, &["read", "notify"])
+        .with_value(&[87])
+        .with_descriptor(FakeDescriptor::new(descriptors::CHARACTERISTIC_USER_DESCRIPTION).with_value(b"Battery"));
+    let control_point = FakeCharacteristic::new(
+        characteristics::ALERT_NOTIFICATION_CONTROL_POINT,
+        &["write", "write-without-response"],
+    );
+    let peripheral = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Battery")
+        .with_mtu(185)
+        .with_service(
+            FakeService::new(services::BATTERY)
+                .with_characteristic(battery_level.clone())
+                .with_characteristic(control_point.clone()),
+        );
+    bluez.adapter().add_device(&peripheral);
+
+    let adapter = Adapter::default().await.unwrap();
+    let device = discover(&adapter, &peripheral).await;
+    adapter.connect_device(&device).await.unwrap();
+    assert!(peripheral.is_connected());
+    assert!(device.is_connected().await);
+
+    let services = device.discover_services().await.unwrap();
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].uuid_async().await.unwrap(), services::BATTERY);
+    assert!(services[0].is_primary().await.unwrap());
+
+    let level = &services[0]
+        .discover_characteristics_with_uuid(characteristics::BATTERY_LEVEL)
+        .await
+        .unwrap()[0];
+    let props = level.properties().await.unwrap();
+    assert!(props.read && props.notify && !props.write);
+    assert_eq!(level.read().await.unwrap(), vec![87]);
+    assert!(level.write(&[1]).await.is_err());
+
+    let descriptors = level.descriptors().await.unwrap();
+    assert_eq!(descriptors.len(), 1);
+    assert_eq!(descriptors[0].read().await.unwrap(), b"Battery");
+    descriptors[0].write(b"Main battery").await.unwrap();
+    assert_eq!(battery_level.descriptors()[0].value(), b"Main battery");
+
+    let control = &services[0]
+        .discover_characteristics_with_uuid(characteristics::ALERT_NOTIFICATION_CONTROL_POINT)
+        .await
+        .unwrap()[0];
+    control.write(&[1]).await.unwrap();
+    control.write_without_response(&[2]).await.unwrap();
+    assert_eq!(
+        control_point.writes(),
+        vec![
+            FakeWrite {
+                value: vec![1],
+                op: "request".into()
+            },
+            FakeWrite {
+                value: vec![2],
+                op: "command".into()
+            },
+        ]
+    );
+    // bluer reserves a few bytes of the ATT MTU to work around a BlueZ bug
+    let max_write_len = control.max_write_len_async().await.unwrap();
+    assert!(max_write_len > 20 && max_write_len <= 182);
+
+    let mut notifications = level.notify().await.unwrap();
+    assert!(battery_level.is_notifying());
+    assert!(battery_level.notify(&[86]));
+    assert_eq!(next(&mut notifications).await.unwrap(), vec![86]);
+    drop(notifications);
+    eventually(|| !battery_level.is_notifying()).await;
+
+    let mut events = adapter.device_connection_events(&device).await.unwrap();
+    peripheral.disconnect();
+    assert_eq!(next(&mut events).await, ConnectionEvent::Disconnected);
+    assert!(!device.is_connected().await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn service_changed_indications() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let service_changed = FakeCharacteristic::new(characteristics::SERVICE_CHANGED, &["indicate"]);
+    let peripheral = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Changing")
+        .with_service(FakeService::new(services::GENERIC_ATTRIBUTE).with_characteristic(service_changed.clone()));
+    bluez.adapter().add_device(&peripheral);
+
+    let adapter = Adapter::default().await.unwrap();
+    let device = discover(&adapter, &peripheral).await;
+    adapter.connect_device(&device).await.unwrap();
+    let mut indications = device.service_changed_indications().await.unwrap();
+
+    let battery = FakeService::new(services::BATTERY)
+        .with_characteristic(FakeCharacteristic::new(characteristics::BATTERY_LEVEL, &["read"]));
+    peripheral.add_service(&battery);
+    let start = battery.handle();
+    let end = start + 2;
+    assert!(service_changed.notify(&[start.to_le_bytes(), end.to_le_bytes()].concat()));
+
+    let changed = next(&mut indications).await.unwrap();
+    let services = device.services().await.unwrap();
+    assert_eq!(services.len(), 2);
+    for service in services {
+        let invalidated = service.uuid_async().await.unwrap() == services::BATTERY;
+        assert_eq!(changed.was_invalidated(&service), invalidated);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pair_with_agent() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let peripheral = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc])
+        .with_name("Keyboard")
+        .with_passkey(123456);
+    bluez.adapter().add_device(&peripheral);
+
+    let adapter = Adapter::default().await.unwrap();
+    let device = discover(&adapter, &peripheral).await;
+
+    assert!(device
+        .pair_with_agent(&KeyboardAgent(Passkey::new(654321)))
+        .await
+        .is_err());
+    assert!(!peripheral.is_paired());
+
+    device
+        .pair_with_agent(&KeyboardAgent(Passkey::new(123456)))
+        .await
+        .unwrap();
+    assert!(peripheral.is_paired());
+    assert!(device.is_paired().await.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advertising() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let adapter = Adapter::default().await.unwrap();
+
+    let guard = adapter
+        .start_advertising(AdvertisementData {
+            local_name: Some("Beacon".to_string()),
+            manufacturer_data: ManufacturerDataList::from(vec![
+                ManufacturerData {
+                    company_id: 0xffff,
+                    data: vec![1, 2],
+                },
+                ManufacturerData {
+                    company_id: 0x004c,
+                    data: vec![3],
+                },
+            ]),
+            services: vec![services::BATTERY],
+            service_data: HashMap::from([(services::BATTERY, vec![87])]),
+            tx_power_level: Some(-4),
+            solicited_services: vec![services::HEART_RATE],
+            appearance: Some(0x0040),
+            advertising_interval: Some(Duration::from_millis(100)),
+            uri: Some("https://example.com".to_string()),
+            other_structures: vec![AdStructure {
+                ad_type: 0x3d,
+                data: vec![5],
+            }],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let advertisements = bluez.adapter().advertisements();
+    assert_eq!(advertisements.len(), 1);
+    let advertisement = &advertisements[0];
+    assert_eq!(advertisement.advertisement_type, "broadcast");
+    assert_eq!(advertisement.discoverable, None);
+    assert_eq!(advertisement.local_name.as_deref(), Some("Beacon"));
+    assert_eq!(
+        advertisement.manufacturer_data,
+        HashMap::from([(0xffff, vec![1, 2]), (0x004c, vec![3])])
+    );
+    assert_eq!(advertisement.service_uuids, vec![services::BATTERY]);
+    assert_eq!(
+        advertisement.service_data,
+        HashMap::from([(services::BATTERY, vec![87])])
+    );
+    assert_eq!(advertisement.solicit_uuids, vec![services::HEART_RATE]);
+    assert_eq!(advertisement.tx_power, Some(-4));
+    assert_eq!(advertisement.includes, vec!["tx-power".to_string()]);
+    assert_eq!(advertisement.appearance, Some(0x0040));
+    assert_eq!(advertisement.min_interval, Some(100));
+    assert_eq!(advertisement.max_interval, Some(100));
+    assert_eq!(
+        advertisement.data,
+        HashMap::from([(0x24, b"\x17//example.com".to_vec()), (0x3d, vec![5])])
+    );
+
+    drop(guard);
+    eventually(|| bluez.adapter().advertisements().is_empty()).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advertising_modes() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let adapter = Adapter::default().await.unwrap();
+
+    let connectable = adapter
+        .start_advertising(AdvertisementData {
+            is_connectable: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let advertisements = bluez.adapter().advertisements();
+    assert_eq!(advertisements[0].advertisement_type, "peripheral");
+    assert_eq!(advertisements[0].discoverable, Some(true));
+    drop(connectable);
+    eventually(|| bluez.adapter().advertisements().is_empty()).await;
+
+    let hidden = adapter
+        .start_advertising(AdvertisementData {
+            is_connectable: Some(true),
+            flags: Some(adv::flags::BR_EDR_NOT_SUPPORTED),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let advertisements = bluez.adapter().advertisements();
+    assert_eq!(advertisements[0].advertisement_type, "peripheral");
+    assert_eq!(advertisements[0].discoverable, Some(false));
+    drop(hidden);
+
+    let err = adapter
+        .start_advertising(AdvertisementData {
+            is_connectable: Some(true),
+            flags: Some(adv::flags::LE_LIMITED_DISCOVERABLE),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+
+    let err = adapter
+        .start_advertising(AdvertisementData {
+            flags: Some(adv::flags::LE_GENERAL_DISCOVERABLE),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+
+    let err = adapter
+        .start_advertising(AdvertisementData {
+            manufacturer_data: ManufacturerDataList::from(vec![
+                ManufacturerData {
+                    company_id: 0xffff,
+                    data: vec![1],
+                },
+                ManufacturerData {
+                    company_id: 0xffff,
+                    data: vec![2],
+                },
+            ]),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+
+    // Failures reported by BlueZ are returned as errors
+    bluez.adapter().set_supported_instances(0);
+    let err = adapter
+        .start_advertising(AdvertisementData::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advertising_sets() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let adapter = Adapter::default().await.unwrap();
+    assert_eq!(adapter.available_advertising_sets().await.unwrap(), 4);
+
+    let beacon = adapter
+        .start_advertising_with_parameters(
+            AdvertisementData {
+                manufacturer_data: ManufacturerData {
+                    company_id: 0x004c,
+                    data: vec![0x02, 0x15],
+                }
+                .into(),
+                tx_power_level: Some(-59),
+                advertising_interval: Some(Duration::from_millis(1000)),
+                ..Default::default()
+            },
+            AdvertisingParameters {
+                interval: Some(Duration::from_millis(100)..=Duration::from_millis(150)),
+                tx_power: Some(4),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let service = adapter
+        .start_advertising_with_parameters(
+            AdvertisementData {
+                is_connectable: Some(true),
+                services: vec![services::BATTERY],
+                ..Default::default()
+            },
+            AdvertisingParameters {
+                duration: Some(Duration::from_secs(2)),
+                timeout: Some(Duration::from_secs(60)),
+                secondary_phy: Some(Phy::LeCoded),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(adapter.available_advertising_sets().await.unwrap(), 2);
+
+    let advertisements = bluez.adapter().advertisements();
+    assert_eq!(advertisements.len(), 2);
+    let broadcast = advertisements
+        .iter()
+        .find(|x| x.advertisement_type == "broadcast")
+        .unwrap();
+    assert_eq!(broadcast.min_interval, Some(100));
+    assert_eq!(broadcast.max_interval, Some(150));
+    assert_eq!(broadcast.tx_power, Some(4));
+    assert_eq!(broadcast.includes, ["tx-power"]);
+    assert_eq!(broadcast.duration, None);
+    let peripheral = advertisements
+        .iter()
+        .find(|x| x.advertisement_type == "peripheral")
+        .unwrap();
+    assert_eq!(peripheral.service_uuids, [services::BATTERY]);
+    assert_eq!(peripheral.duration, Some(2));
+    assert_eq!(peripheral.timeout, Some(60));
+    assert_eq!(peripheral.secondary_channel.as_deref(), Some("Coded"));
+    assert_eq!(peripheral.min_interval, None);
+
+    // Each guard stops only its own set
+    drop(beacon);
+    eventually(|| bluez.adapter().advertisements().len() == 1).await;
+    assert_eq!(bluez.adapter().advertisements()[0].advertisement_type, "peripheral");
+    assert_eq!(adapter.available_advertising_sets().await.unwrap(), 3);
+    drop(service);
+    eventually(|| bluez.adapter().advertisements().is_empty()).await;
+
+    let err = adapter
+        .start_advertising_with_parameters(
+            AdvertisementData::default(),
+            AdvertisingParameters {
+                scan_response: Some(AdvertisementData {
+                    local_name: Some("Sensor".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+}
+
+/// Answers requests to the attributes of the `gatt_server` test and records the writes.
+#[derive(Default)]
+struct TestHandler {
+    writes: Arc<Mutex<Vec<WriteRequest>>>,
+}
+
+#[async_trait]
+impl RequestHandler for TestHandler {
+    async fn read(&self, request: ReadRequest) -> Result<Vec<u8>, AttError> {
+        let value: &[u8] = match (request.attribute.characteristic, request.attribute.descriptor) {
+            (characteristics::BATTERY_LEVEL, None) => &[87],
+            (characteristics::BATTERY_LEVEL, Some(descriptors::CHARACTERISTIC_USER_DESCRIPTION)) => b"Battery",
+            _ => return Err(AttError::INSUFFICIENT_AUTHORIZATION),
+        };
+        value
+            .get(usize::from(request.offset)..)
+            .map(<[u8]>::to_vec)
+            .ok_or(AttError::INVALID_OFFSET)
+    }
+
+    async fn write(&self, request: WriteRequest) -> Result<(), AttError> {
+        self.writes.lock().unwrap().push(request);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gatt_server() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let central = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]).with_mtu(185);
+    bluez.adapter().add_device(&central);
+    central.connect();
+    let adapter = Adapter::default().await.unwrap();
+
+    // Permissions must match the properties, and the platform adds the CCCD itself
+    let invalid = [
+        CharacteristicDefinition::new(
+            characteristics::BATTERY_LEVEL,
+            CharacteristicProperties::from_bits(0x02),
+        )
+        .with_permissions(AttributePermissions::write(SecurityLevel::None)),
+        CharacteristicDefinition::new(
+            characteristics::BATTERY_LEVEL,
+            CharacteristicProperties::from_bits(0x12),
+        )
+        .with_descriptor(DescriptorDefinition::new(
+            descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION,
+            AttributePermissions::read_write(SecurityLevel::None),
+        )),
+    ];
+    for characteristic in invalid {
+        let service = ServiceDefinition::new(services::BATTERY).with_characteristic(characteristic);
+        let err = adapter
+            .start_server(vec![service], TestHandler::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+    }
+    assert!(bluez.adapter().applications().is_empty());
+
+    let services = vec![
+        ServiceDefinition::new(services::BATTERY).with_characteristic(
+            CharacteristicDefinition::new(
+                characteristics::BATTERY_LEVEL,
+                CharacteristicProperties::from_bits(0x12),
+            )
+            .with_descriptor(DescriptorDefinition::new(
+                descriptors::CHARACTERISTIC_USER_DESCRIPTION,
+                AttributePermissions::read(SecurityLevel::None),
+            )),
+        ),
+        ServiceDefinition::new(services::ALERT_NOTIFICATION).with_characteristic(
+            CharacteristicDefinition::new(
+                characteristics::ALERT_NOTIFICATION_CONTROL_POINT,
+                CharacteristicProperties::from_bits(0x0c),
+            )
+            .with_permissions(AttributePermissions::write(SecurityLevel::Encrypted)),
+        ),
+        ServiceDefinition::new(services::HEALTH_THERMOMETER).with_characteristic(CharacteristicDefinition::new(
+            characteristics::TEMPERATURE_MEASUREMENT,
+            CharacteristicProperties::from_bits(0x22),
+        )),
+    ];
+    let handler = TestHandler::default();
+    let writes = handler.writes.clone();
+    let server = adapter.start_server(services, handler).await.unwrap();
+    let mut events = server.events();
+
+    let applications = bluez.adapter().applications();
+    assert_eq!(applications.len(), 1);
+    let app = &applications[0];
+    assert_eq!(app.services.len(), 3);
+    assert!(app.services.iter().all(|x| x.primary));
+    let flags = |service, characteristic| {
+        let mut flags = app.characteristic(service, characteristic).flags.clone();
+        flags.sort();
+        flags
+    };
+    assert_eq!(
+        flags(services::BATTERY, characteristics::BATTERY_LEVEL),
+        ["notify", "read"]
+    );
+    assert_eq!(
+        flags(
+            services::ALERT_NOTIFICATION,
+            characteristics::ALERT_NOTIFICATION_CONTROL_POINT
+        ),
+        ["encrypt-write", "write", "write-without-response"]
+    );
+    assert_eq!(
+        flags(services::HEALTH_THERMOMETER, characteristics::TEMPERATURE_MEASUREMENT),
+        ["indicate", "read"]
+    );
+    let level = app.characteristic(services::BATTERY, characteristics::BATTERY_LEVEL);
+    let control = app.characteristic(
+        services::ALERT_NOTIFICATION,
+        characteristics::ALERT_NOTIFICATION_CONTROL_POINT,
+    );
+    let temperature = app.characteristic(services::HEALTH_THERMOMETER, characteristics::TEMPERATURE_MEASUREMENT);
+    assert_eq!(level.descriptors.len(), 1);
+    assert_eq!(level.descriptors[0].flags, ["read"]);
+
+    // The central becomes known with its first request
+    assert_eq!(level.read(&central, 0).await.unwrap(), [87]);
+    let ServerEvent::Connected(connected) = next(&mut events).await else {
+        panic!("expected a connection event")
+    };
+    assert_eq!(connected.id.to_string(), "12:34:56:78:9A:BC");
+    assert_eq!(connected.mtu, 185);
+    assert_eq!(server.centrals(), std::slice::from_ref(&connected));
+
+    assert_eq!(level.descriptors[0].read(&central, 3).await.unwrap(), b"tery");
+    let err = level.read(&central, 2).await.unwrap_err();
+    assert_eq!(err.name(), Some("org.bluez.Error.InvalidOffset"));
+    let err = temperature.read(&central, 0).await.unwrap_err();
+    assert_eq!(err.name(), Some("org.bluez.Error.NotAuthorized"));
+
+    control.write(&central, &[1], "request").await.unwrap();
+    control.write(&central, &[2], "command").await.unwrap();
+    let writes = writes.lock().unwrap().clone();
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[0].central, connected.id);
+    assert_eq!(
+        writes[0].attribute.characteristic,
+        characteristics::ALERT_NOTIFICATION_CONTROL_POINT
+    );
+    assert_eq!(writes[0].mtu, 185);
+    assert_eq!(
+        (&writes[0].value[..], writes[0].kind),
+        (&[1][..], WriteKind::WithResponse)
+    );
+    assert_eq!(
+        (&writes[1].value[..], writes[1].kind),
+        (&[2][..], WriteKind::WithoutResponse)
+    );
+
+    // Notifications are only sent while a central is subscribed
+    let notify = |characteristic, value| server.notify(services::BATTERY, characteristic, value);
+    assert!(!notify(characteristics::BATTERY_LEVEL, &[86]).await.unwrap());
+    let err = notify(characteristics::TEMPERATURE_MEASUREMENT, &[0])
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    let err = server
+        .notify(
+            services::ALERT_NOTIFICATION,
+            characteristics::ALERT_NOTIFICATION_CONTROL_POINT,
+            &[0],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+
+    let mut notifications = level.subscribe().await.unwrap();
+    let subscription = Subscription {
+        attribute: AttributeId {
+            service: services::BATTERY,
+            characteristic: characteristics::BATTERY_LEVEL,
+            descriptor: None,
+        },
+        central: None,
+        indications: false,
+    };
+    assert_eq!(next(&mut events).await, ServerEvent::Subscribed(subscription.clone()));
+    assert_eq!(server.subscriptions(), std::slice::from_ref(&subscription));
+    assert!(notify(characteristics::BATTERY_LEVEL, &[86]).await.unwrap());
+    assert_eq!(notifications.next().await.unwrap(), [86]);
+
+    // Indications wait for the confirmation of the central
+    let mut indications = temperature.subscribe().await.unwrap();
+    let ServerEvent::Subscribed(subscribed) = next(&mut events).await else {
+        panic!("expected a subscription event")
+    };
+    assert!(subscribed.indications);
+    assert!(server
+        .notify(
+            services::HEALTH_THERMOMETER,
+            characteristics::TEMPERATURE_MEASUREMENT,
+            &[1, 2]
+        )
+        .await
+        .unwrap());
+    assert_eq!(indications.next().await.unwrap(), [1, 2]);
+
+    level.unsubscribe().await.unwrap();
+    assert_eq!(next(&mut events).await, ServerEvent::Unsubscribed(subscription));
+    assert_eq!(server.subscriptions(), [subscribed]);
+    assert!(!notify(characteristics::BATTERY_LEVEL, &[85]).await.unwrap());
+
+    central.disconnect();
+    assert_eq!(next(&mut events).await, ServerEvent::Disconnected(connected.id));
+    assert!(server.centrals().is_empty());
+
+    drop(server);
+    eventually(|| bluez.adapter().applications().is_empty()).await;
+}
+
+#[tokio::test]
+async fn emulator() {
+    let Some(bluez) = FakeBluez::start() else { return };
+    let central = FakeDevice::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]);
+    bluez.adapter().add_device(&central);
+    central.connect();
+    let adapter = Adapter::default().await.unwrap();
+
+    let (levels, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let updates = futures_lite::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|x| (x, receiver))
+    });
+    let emulator = Emulator::new("Emulated battery")
+        .with_profile(Battery::new(ValueSource::new(87, updates)))
+        .start(&adapter)
+        .await
+        .unwrap();
+
+    let advertisements = bluez.adapter().advertisements();
+    assert_eq!(advertisements.len(), 1);
+    assert_eq!(advertisements[0].advertisement_type, "peripheral");
+    assert_eq!(advertisements[0].local_name.as_deref(), Some("Emulated battery"));
+    assert_eq!(advertisements[0].service_uuids, vec![services::BATTERY]);
+
+    let applications = bluez.adapter().applications();
+    let level = applications[0].characteristic(services::BATTERY, characteristics::BATTERY_LEVEL);
+    assert_eq!(level.read(&central, 0).await.unwrap(), [87]);
+    let mut notifications = level.subscribe().await.unwrap();
+    levels.send(86).unwrap();
+    assert_eq!(notifications.next().await.unwrap(), [86]);
+    assert_eq!(
+        emulator.value(services::BATTERY, characteristics::BATTERY_LEVEL),
+        Some(vec![86])
+    );
+
+    drop(emulator);
+    eventually(|| bluez.adapter().applications().is_empty() && bluez.adapter().advertisements().is_empty()).await;
+}
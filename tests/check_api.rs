@@ -7,6 +7,34 @@ fn assert_send<T: Send>(t: T) -> T {
     t
 }
 
+#[cfg(feature = "l2cap")]
+fn check_l2cap_reader<T>(_: &T)
+where
+    T: futures_io::AsyncRead + futures_core::Stream<Item = Result<Vec<u8>>> + Send + Unpin,
+{
+}
+
+#[cfg(feature = "l2cap")]
+fn check_l2cap_writer<T>(_: &T)
+where
+    T: futures_io::AsyncWrite
+        + futures_sink::Sink<Vec<u8>, Error = Error>
+        + for<'a> futures_sink::Sink<&'a [u8], Error = Error>
+        + Send
+        + Unpin,
+{
+}
+
+#[cfg(all(feature = "l2cap", feature = "tokio"))]
+fn check_tokio_reader<T: tokio::io::AsyncRead>(_: &T) {}
+
+#[cfg(all(feature = "l2cap", feature = "tokio"))]
+fn check_tokio_writer<T: tokio::io::AsyncWrite>(_: &T) {}
+
+struct NoopHandler;
+
+impl server::RequestHandler for NoopHandler {}
+
 async fn check_adapter_apis(adapter: Adapter) -> Result<Device> {
     let events: Result<_> = assert_send(adapter.events()).await;
     let _event: Option<Result<AdapterEvent>> = assert_send(events?.next()).await;
@@ -19,6 +47,40 @@ async fn check_adapter_apis(adapter: Adapter) -> Result<Device> {
     let scan: Result<_> = assert_send(adapter.scan(&[btuuid::services::GENERIC_ACCESS])).await;
     let _adv: Option<AdvertisingDevice> = assert_send(scan?.next()).await;
 
+    let scan: Result<_> = assert_send(adapter.scan_with_filter(ScanFilter::new())).await;
+    let _adv: Option<AdvertisingDevice> = assert_send(scan?.next()).await;
+    let scan: Result<_> = assert_send(adapter.scan_with_options(ScanFilter::new(), ScanOptions::default())).await;
+    let _adv: Option<AdvertisingDevice> = assert_send(scan?.next()).await;
+    let scan: Result<_> = assert_send(adapter.scan_events(ScanFilter::new(), ScanOptions::default())).await;
+    let _event: Option<ScanEvent> = assert_send(scan?.next()).await;
+    let scan: Result<_> = assert_send(adapter.try_scan_events(ScanFilter::new(), ScanOptions::default())).await;
+    let _event: Option<Result<ScanEvent>> = assert_send(scan?.next()).await;
+    let broker = ScanBroker::new(adapter.clone());
+    let mut subscription: ScanSubscription = assert_send(broker.subscribe(ScanFilter::new())).await?;
+    let _adv: Option<AdvertisingDevice> = assert_send(subscription.next()).await;
+    let _error: Option<Error> = subscription.error();
+    let table = DeviceTable::new();
+    let scan: Result<_> = assert_send(adapter.scan(&[])).await;
+    assert_send(table.track(scan?)).await;
+    let monitor = presence::PresenceMonitor::new();
+    let scan: Result<_> = assert_send(adapter.scan(&[])).await;
+    let _event: Option<presence::PresenceEvent> = assert_send(monitor.events(scan?).next()).await;
+    let scan: Result<_> = assert_send(adapter.scan(&[])).await;
+    let mut estimates = proximity::ProximityEstimator::new().estimates(scan?);
+    let _estimate: Option<proximity::DistanceEstimate> = assert_send(estimates.next()).await;
+    #[cfg(feature = "privacy")]
+    {
+        let scan: Result<_> = assert_send(adapter.scan(&[])).await;
+        let mut resolved = privacy::Keyring::new().resolve_scan(scan?);
+        let _device: Option<privacy::ResolvedDevice> = assert_send(resolved.next()).await;
+    }
+    let patterns = [MonitorPattern::new(adv::ad_types::COMPLETE_LOCAL_NAME, 0, *b"Sensor")];
+    let monitor: Result<_> = assert_send(adapter.monitor_advertisements(&patterns)).await;
+    let _event: Option<MonitorEvent> = assert_send(monitor?.next()).await;
+    let monitor: Result<_> =
+        assert_send(adapter.monitor_advertisements_with_options(&patterns, MonitorOptions::default())).await;
+    let _event: Option<MonitorEvent> = assert_send(monitor?.next()).await;
+
     let discovery: Result<_> = assert_send(adapter.discover_devices(&[btuuid::services::GENERIC_ACCESS])).await;
     let _device: Option<Result<Device>> = assert_send(discovery?.next()).await;
 
@@ -31,6 +93,34 @@ async fn check_adapter_apis(adapter: Adapter) -> Result<Device> {
     let events: Result<_> = assert_send(adapter.device_connection_events(&device)).await;
     let _event: Option<ConnectionEvent> = assert_send(events?.next()).await;
 
+    let _guard: Result<AdvertisingGuard> = assert_send(adapter.start_advertising(AdvertisementData::default())).await;
+    let _guard: Result<AdvertisingGuard> = assert_send(
+        adapter.start_advertising_with_parameters(AdvertisementData::default(), AdvertisingParameters::default()),
+    )
+    .await;
+    let _sets: Result<usize> = assert_send(adapter.available_advertising_sets()).await;
+
+    let server: Result<server::Server> = assert_send(adapter.start_server(Vec::new(), NoopHandler)).await;
+    let server = server?;
+    let _centrals: Vec<server::Central> = server.centrals();
+    let _subscriptions: Vec<server::Subscription> = server.subscriptions();
+    let _event: Option<server::ServerEvent> = assert_send(server.events().next()).await;
+    let _sent: Result<bool> =
+        assert_send(server.notify(btuuid::services::BATTERY, btuuid::characteristics::BATTERY_LEVEL, &[])).await;
+
+    #[cfg(feature = "l2cap")]
+    {
+        let listener: Result<_> = assert_send(adapter.listen_l2cap(None, true)).await;
+        let (_psm, mut channels): (u16, _) = listener?;
+        let _channel: Option<Result<(Device, L2capChannel)>> = assert_send(channels.next()).await;
+    }
+
+    let emulator = emulator::Emulator::new("emulator")
+        .with_profile(emulator::Battery::new(emulator::ValueSource::constant(100)))
+        .with_profile(emulator::CurrentTime::system_clock());
+    let emulator: Result<emulator::EmulatorHandle> = assert_send(emulator.start(&adapter)).await;
+    let _value: Option<Vec<u8>> = emulator?.value(btuuid::services::BATTERY, btuuid::characteristics::BATTERY_LEVEL);
+
     Ok(device)
 }
 
@@ -54,6 +144,36 @@ async fn check_device_apis(device: Device) -> Result<Service> {
 
     let _rssi: Result<i16> = assert_send(device.rssi()).await;
 
+    #[cfg(feature = "l2cap")]
+    {
+        let channel: Result<L2capChannel> = assert_send(device.open_l2cap_channel(0x80, true)).await;
+        let mut channel = channel?;
+        check_l2cap_reader(&channel);
+        check_l2cap_writer(&channel);
+        #[cfg(feature = "tokio")]
+        {
+            check_tokio_reader(&channel);
+            check_tokio_writer(&channel);
+        }
+        let _mtu: Result<usize> = channel.read_mtu();
+        let _mtu: Result<usize> = channel.write_mtu();
+        let _res: Result<()> = assert_send(channel.write(&[0u8])).await;
+        let mut buf = [0u8; 64];
+        let _len: Result<usize> = assert_send(channel.read(&mut buf)).await;
+        let (mut reader, mut writer) = channel.split();
+        check_l2cap_reader(&reader);
+        check_l2cap_writer(&writer);
+        #[cfg(feature = "tokio")]
+        {
+            check_tokio_reader(&reader);
+            check_tokio_writer(&writer);
+        }
+        let _len: Result<usize> = reader.try_read(&mut buf);
+        let _res: Result<()> = writer.try_write(&[0u8]);
+        let _res: Result<()> = assert_send(reader.close()).await;
+        let _res: Result<()> = assert_send(writer.close()).await;
+    }
+
     Ok(services?.into_iter().next().unwrap())
 }
 
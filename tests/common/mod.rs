@@ -0,0 +1,37 @@
+//! Helpers shared by the tests against the mock backend.
+
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{AdvertisingDevice, Device, ScanFilter, ScanOptions};
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+/// Receives the next item from `stream`, which must arrive within a second.
+pub async fn next<T>(stream: &mut (impl Stream<Item = T> + Unpin)) -> T {
+    tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .expect("timed out")
+        .expect("stream ended")
+}
+
+/// Receives one advertisement from each peripheral on `radio`.
+pub async fn advertisements(radio: &VirtualRadio) -> Vec<AdvertisingDevice> {
+    let adapter = radio.adapter();
+    let options = ScanOptions {
+        max_results: Some(radio.peripherals().len()),
+        ..Default::default()
+    };
+    let scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    scan.collect().await
+}
+
+/// Connects an adapter of `radio` to `peripheral`.
+pub async fn connect(radio: &VirtualRadio, peripheral: &VirtualPeripheral) -> Device {
+    let adapter = radio.adapter();
+    let device = adapter.open_device(&peripheral.id()).await.unwrap();
+    adapter.connect_device(&device).await.unwrap();
+    device
+}
@@ -0,0 +1,133 @@
+//! Tests of the device table against the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::time::{Duration, SystemTime};
+
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{AdvertisementData, AdvertisingDevice, DeviceTable, ManufacturerData, ScanFilter, ScanOptions};
+use common::advertisements;
+
+mod common;
+
+fn peripheral(name: &str, rssi: i16) -> VirtualPeripheral {
+    VirtualPeripheral::new()
+        .with_rssi(rssi)
+        .with_advertisement(AdvertisementData {
+            local_name: Some(name.to_string()),
+            manufacturer_data: ManufacturerData {
+                company_id: 0xffff,
+                data: vec![0x01],
+            }
+            .into(),
+            ..Default::default()
+        })
+}
+
+#[tokio::test]
+async fn statistics() {
+    let radio = VirtualRadio::new();
+    radio.add_peripheral(&peripheral("Sensor", -60));
+    let adv = advertisements(&radio).await.remove(0);
+    let start = SystemTime::now() - Duration::from_secs(10);
+    let at = |millis, rssi| AdvertisingDevice {
+        timestamp: Some(start + Duration::from_millis(millis)),
+        rssi: Some(rssi),
+        ..adv.clone()
+    };
+
+    let table = DeviceTable::new();
+    table.record(&at(0, -60));
+    table.record(&at(100, -70));
+    // One advertisement is missed here
+    table.record(&at(300, -50));
+    table.record(&AdvertisingDevice {
+        adv_data: AdvertisementData {
+            local_name: Some("Sensor 2".to_string()),
+            ..Default::default()
+        },
+        rssi: None,
+        is_scan_response: Some(true),
+        ..at(301, 0)
+    });
+
+    let entry = table.get(&adv.device.id()).unwrap();
+    assert_eq!(entry.packets, 4);
+    assert_eq!(entry.first_seen, start);
+    assert_eq!(entry.last_seen, start + Duration::from_millis(301));
+    assert_eq!(entry.rssi, Some(-50));
+    assert_eq!(entry.min_rssi, Some(-70));
+    assert_eq!(entry.max_rssi, Some(-50));
+    assert_eq!(entry.mean_rssi, Some(-60.0));
+    assert_eq!(entry.smoothed_rssi, Some(-59.375));
+    assert_eq!(entry.min_interval, Some(Duration::from_millis(100)));
+    assert_eq!(entry.mean_interval, Some(Duration::from_millis(150)));
+
+    // The scan response updates the name and keeps the rest of the advertisement
+    assert_eq!(entry.adv_data.local_name.as_deref(), Some("Sensor 2"));
+    assert_eq!(entry.adv_data.manufacturer_data.get(0xffff), Some(&[0x01][..]));
+}
+
+#[tokio::test]
+async fn snapshots_and_eviction() {
+    let radio = VirtualRadio::new();
+    radio.add_peripheral(&peripheral("Far", -80));
+    radio.add_peripheral(&peripheral("Near", -40));
+    radio.add_peripheral(&peripheral("Old", -30));
+    let advertisements = advertisements(&radio).await;
+    let find = |name: &str| {
+        advertisements
+            .iter()
+            .find(|x| x.adv_data.local_name.as_deref() == Some(name))
+            .unwrap()
+            .clone()
+    };
+
+    let table = DeviceTable::new().with_eviction_timeout(Duration::from_secs(60));
+    let now = SystemTime::now();
+    let mut old = find("Old");
+    old.timestamp = Some(now - Duration::from_secs(120));
+    table.record(&old);
+    assert_eq!(table.len(), 1);
+
+    // Recording newer advertisements evicts the device which has been silent too long
+    let mut far = find("Far");
+    far.timestamp = Some(now - Duration::from_secs(1));
+    table.record(&far);
+    table.record(&find("Near"));
+    assert_eq!(table.len(), 2);
+    assert!(table.get(&old.device.id()).is_none());
+
+    let names = |entries: Vec<bluest::DeviceEntry>| {
+        entries
+            .into_iter()
+            .map(|x| x.adv_data.local_name.unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(table.sorted_by_rssi()), ["Near", "Far"]);
+    assert_eq!(names(table.sorted_by_recency()), ["Near", "Far"]);
+    assert!(table.evict().is_empty());
+}
+
+#[tokio::test]
+async fn track() {
+    let radio = VirtualRadio::new();
+    let sensor = peripheral("Sensor", -60);
+    radio.add_peripheral(&sensor);
+    let adapter = radio.adapter();
+
+    let table = DeviceTable::new();
+    let options = ScanOptions {
+        max_results: Some(3),
+        ..Default::default()
+    };
+    let scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    sensor.advertise();
+    sensor.set_rssi(Some(-50));
+    sensor.advertise();
+    table.track(scan).await;
+
+    let entry = table.get(&sensor.id()).unwrap();
+    assert_eq!(entry.packets, 3);
+    assert_eq!(entry.max_rssi, Some(-50));
+}
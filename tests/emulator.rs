@@ -0,0 +1,169 @@
+//! Tests of the peripheral emulators with the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::future::Future;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, UNIX_EPOCH};
+
+use bluest::btuuid::{characteristics, services};
+use bluest::emulator::{
+    Battery, CurrentTime, DeviceInformation, Emulator, HeartRate, LedButton, ValueSource,
+    BLINKY_BUTTON_STATE_CHARACTERISTIC, BLINKY_LED_STATE_CHARACTERISTIC, NORDIC_LED_AND_BUTTON_SERVICE,
+};
+use bluest::error::{AttError, ErrorKind};
+use bluest::mock::VirtualRadio;
+use bluest::{Characteristic, Device, Uuid};
+use common::{advertisements, next};
+use futures_lite::stream;
+
+mod common;
+
+/// Connects to the emulator on `radio`, which must advertise `service`.
+async fn connect(radio: &VirtualRadio, service: Uuid) -> Device {
+    let adv = advertisements(radio).await.remove(0);
+    assert!(adv.adv_data.services.contains(&service));
+    common::connect(radio, &radio.peripherals()[0]).await
+}
+
+async fn characteristic(device: &Device, service: Uuid, characteristic: Uuid) -> Characteristic {
+    let service = device
+        .discover_services_with_uuid(service)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    service
+        .discover_characteristics_with_uuid(characteristic)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn led_button() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let (presses, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let button = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|x| (x, receiver))
+    });
+    let emulator = Emulator::new("Blinky")
+        .with_profile(LedButton::new(ValueSource::new(false, button)))
+        .start(&adapter)
+        .await
+        .unwrap();
+
+    let device = connect(&radio, NORDIC_LED_AND_BUTTON_SERVICE).await;
+    assert_eq!(device.name().unwrap(), "Blinky");
+    assert_eq!(emulator.server().centrals().len(), 1);
+
+    let button = characteristic(
+        &device,
+        NORDIC_LED_AND_BUTTON_SERVICE,
+        BLINKY_BUTTON_STATE_CHARACTERISTIC,
+    )
+    .await;
+    assert_eq!(button.read().await.unwrap(), [0]);
+    let mut updates = button.notify().await.unwrap();
+    presses.send(true).unwrap();
+    assert_eq!(next(&mut updates).await.unwrap(), [1]);
+    assert_eq!(button.read().await.unwrap(), [1]);
+
+    let led = characteristic(&device, NORDIC_LED_AND_BUTTON_SERVICE, BLINKY_LED_STATE_CHARACTERISTIC).await;
+    assert_eq!(led.read().await.unwrap(), [0]);
+    led.write(&[1]).await.unwrap();
+    assert_eq!(
+        emulator.value(NORDIC_LED_AND_BUTTON_SERVICE, BLINKY_LED_STATE_CHARACTERISTIC),
+        Some(vec![1])
+    );
+    let err = led.write(&[1, 0]).await.unwrap_err();
+    assert_eq!(
+        err.kind(),
+        ErrorKind::Protocol(AttError::INVALID_ATTRIBUTE_VALUE_LENGTH)
+    );
+
+    // Stopping the emulator stops its advertisement
+    drop(emulator);
+    assert!(radio.peripherals().is_empty());
+    assert!(!device.is_connected().await);
+}
+
+#[tokio::test]
+async fn standard_profiles() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let interval = Duration::from_millis(100);
+    let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_500);
+    let emulator = Emulator::new("Sensor")
+        .with_profile(Battery::new(ValueSource::cycle([100, 50], interval)))
+        .with_profile(
+            DeviceInformation::new()
+                .with_manufacturer_name("Bluest")
+                .with_model_number("Emulator"),
+        )
+        .with_profile(HeartRate::new(ValueSource::steps(70, interval, [80, 300])).with_body_sensor_location(1))
+        .with_profile(CurrentTime::new(ValueSource::constant(time)))
+        .start(&adapter)
+        .await
+        .unwrap();
+    let device = connect(&radio, services::HEART_RATE).await;
+
+    let measurement = characteristic(&device, services::HEART_RATE, characteristics::HEART_RATE_MEASUREMENT).await;
+    let mut measurements = measurement.notify().await.unwrap();
+    assert_eq!(next(&mut measurements).await.unwrap(), [0x00, 80]);
+    assert_eq!(next(&mut measurements).await.unwrap(), [0x01, 0x2c, 0x01]);
+    let err = measurement.read().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::READ_NOT_PERMITTED));
+    let location = characteristic(&device, services::HEART_RATE, characteristics::BODY_SENSOR_LOCATION).await;
+    assert_eq!(location.read().await.unwrap(), [1]);
+
+    // The battery level alternates between the values of the cycle
+    let level = characteristic(&device, services::BATTERY, characteristics::BATTERY_LEVEL).await;
+    let mut levels = level.notify().await.unwrap();
+    let first = next(&mut levels).await.unwrap();
+    let second = next(&mut levels).await.unwrap();
+    assert_ne!(first, second);
+    assert!([first, second].iter().all(|x| *x == [100] || *x == [50]));
+
+    let manufacturer = characteristic(
+        &device,
+        services::DEVICE_INFORMATION,
+        characteristics::MANUFACTURER_NAME_STRING,
+    )
+    .await;
+    assert_eq!(manufacturer.read().await.unwrap(), b"Bluest");
+    let serial = device
+        .discover_services_with_uuid(services::DEVICE_INFORMATION)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+        .discover_characteristics_with_uuid(characteristics::SERIAL_NUMBER_STRING)
+        .await
+        .unwrap();
+    assert!(serial.is_empty());
+
+    // 2024-02-29 12:34:56.5 UTC was a Thursday
+    let current_time = characteristic(&device, services::CURRENT_TIME, characteristics::CURRENT_TIME).await;
+    let value = current_time.read().await.unwrap();
+    assert_eq!(value, [0xe8, 0x07, 2, 29, 12, 34, 56, 4, 128, 0]);
+    assert_eq!(
+        emulator.value(services::CURRENT_TIME, characteristics::CURRENT_TIME),
+        Some(value)
+    );
+}
+
+#[test]
+fn start_outside_tokio_runtime() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let emulator = Emulator::new("Sensor").with_profile(Battery::new(ValueSource::constant(100)));
+    let start = std::pin::pin!(emulator.start(&adapter));
+    let res = start.poll(&mut Context::from_waker(Waker::noop()));
+    let Poll::Ready(Err(err)) = res else {
+        panic!("the emulator started without a Tokio runtime")
+    };
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+}
@@ -0,0 +1,2158 @@
+//! A fake `bluetoothd` serving the BlueZ D-Bus API on a private bus.
+//!
+//! The fake registers `org.bluez` on a private `dbus-daemon` and points `DBUS_SYSTEM_BUS_ADDRESS` at it, so the
+//! Linux backend talks to it exactly as it would to the real daemon. The bus and the fake live for the whole test
+//! process; [`FakeBluez::start()`] resets the fake to a single powered adapter and serializes the tests using it.
+
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+use bluest::Uuid;
+use dbus::arg::{prop_cast, PropMap, RefArg, Variant};
+use dbus::channel::{Channel, MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::nonblock::stdintf::org_freedesktop_dbus::{ObjectManager, Properties, PropertiesPropertiesChanged};
+use dbus::nonblock::{MsgMatch, Proxy, SyncConnection};
+use dbus::Path;
+use dbus_crossroads::{Crossroads, IfaceToken, MethodErr};
+
+const SERVICE_NAME: &str = "org.bluez";
+const MANAGER_PATH: &str = "/org/bluez";
+const ADAPTER_PATH: &str = "/org/bluez/hci0";
+
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const ADV_MANAGER_INTERFACE: &str = "org.bluez.LEAdvertisingManager1";
+const ADVERTISEMENT_INTERFACE: &str = "org.bluez.LEAdvertisement1";
+const MONITOR_MANAGER_INTERFACE: &str = "org.bluez.AdvertisementMonitorManager1";
+const MONITOR_INTERFACE: &str = "org.bluez.AdvertisementMonitor1";
+const GATT_MANAGER_INTERFACE: &str = "org.bluez.GattManager1";
+const AGENT_MANAGER_INTERFACE: &str = "org.bluez.AgentManager1";
+const AGENT_INTERFACE: &str = "org.bluez.Agent1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const SERVICE_INTERFACE: &str = "org.bluez.GattService1";
+const CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+const DESCRIPTOR_INTERFACE: &str = "org.bluez.GattDescriptor1";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:tmpdir=/tmp</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+static BUS: OnceLock<Option<Bus>> = OnceLock::new();
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+fn bus() -> &'static Bus {
+    BUS.get()
+        .and_then(Option::as_ref)
+        .expect("the fake BlueZ daemon is not running")
+}
+
+/// Exclusive access to the fake BlueZ daemon for the duration of a test.
+pub struct FakeBluez {
+    adapter: FakeAdapter,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl FakeBluez {
+    /// Starts the fake daemon, or resets it if it is already running.
+    ///
+    /// Returns `None` if no `dbus-daemon` is available, in which case the calling test should be skipped. Set
+    /// `BLUEST_REQUIRE_DBUS=1` to fail instead, so that a CI runner without `dbus-daemon` cannot pass silently.
+    pub fn start() -> Option<FakeBluez> {
+        let guard = TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(bus) = BUS.get_or_init(Bus::launch).as_ref() else {
+            let test = std::thread::current().name().unwrap_or("<unknown>").to_owned();
+            assert!(
+                !std::env::var("BLUEST_REQUIRE_DBUS").is_ok_and(|v| !v.is_empty() && v != "0"),
+                "{test}: no dbus-daemon available and BLUEST_REQUIRE_DBUS is set"
+            );
+            // Bypass the test harness' output capture, which hides the output of passing tests
+            let _ = writeln!(std::io::stderr(), "SKIPPED {test}: no dbus-daemon available");
+            return None;
+        };
+        let adapter = bus.reset();
+        Some(FakeBluez { adapter, _guard: guard })
+    }
+
+    /// The fake `hci0` adapter.
+    pub fn adapter(&self) -> &FakeAdapter {
+        &self.adapter
+    }
+}
+
+struct Bus {
+    conn: Arc<SyncConnection>,
+    cr: Arc<Mutex<Crossroads>>,
+    tokens: Tokens,
+    agents: Mutex<Agents>,
+    adapter: Mutex<Option<FakeAdapter>>,
+    _runtime: tokio::runtime::Runtime,
+    // The daemon is killed when this pipe is closed at process exit
+    _daemon: ChildStdin,
+}
+
+#[derive(Clone, Copy)]
+struct Tokens {
+    adapter: IfaceToken<FakeAdapter>,
+    adv_manager: IfaceToken<FakeAdapter>,
+    monitor_manager: IfaceToken<FakeAdapter>,
+    gatt_manager: IfaceToken<FakeAdapter>,
+    agent_manager: IfaceToken<()>,
+    device: IfaceToken<FakeDevice>,
+    service: IfaceToken<FakeService>,
+    characteristic: IfaceToken<FakeCharacteristic>,
+    descriptor: IfaceToken<FakeDescriptor>,
+}
+
+#[derive(Debug, Clone)]
+struct Agent {
+    owner: String,
+    path: Path<'static>,
+    capability: String,
+}
+
+#[derive(Debug, Default)]
+struct Agents {
+    registered: Vec<Agent>,
+    default: Option<Agent>,
+}
+
+impl Bus {
+    fn launch() -> Option<Bus> {
+        // The runtime cannot be created from within the runtime of the calling test
+        match std::thread::spawn(Bus::try_launch).join().unwrap() {
+            Ok(bus) => Some(bus),
+            Err(err) => {
+                eprintln!("unable to start a private D-Bus daemon: {err}");
+                None
+            }
+        }
+    }
+
+    fn try_launch() -> Result<Bus, Box<dyn std::error::Error + Send + Sync>> {
+        let config = std::env::temp_dir().join(format!("bluest-fake-bluez-{}.conf", std::process::id()));
+        std::fs::write(&config, BUS_CONFIG)?;
+
+        let mut daemon = Command::new("sh")
+            .arg("-c")
+            .arg(r#"command -v dbus-daemon >/dev/null || exit; dbus-daemon --config-file="$0" --nofork --print-address=1 & read _; kill $!"#)
+            .arg(&config)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = daemon.stdin.take().unwrap();
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address)?;
+        let address = address.trim().to_owned();
+        if address.is_empty() {
+            return Err("dbus-daemon is not installed or did not report its address".into());
+        }
+        std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &address);
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()?;
+
+        let (conn, cr, tokens) = runtime.block_on(async {
+            let mut channel = Channel::open_private(&address)?;
+            channel.register()?;
+            let (resource, conn) = dbus_tokio::connection::from_channel::<SyncConnection>(channel)?;
+            tokio::spawn(async move {
+                let err = resource.await;
+                panic!("lost connection to the private D-Bus daemon: {err}");
+            });
+            conn.request_name(SERVICE_NAME, false, true, true).await?;
+
+            let mut cr = Crossroads::new();
+            cr.set_async_support(Some((
+                conn.clone(),
+                Box::new(|x| {
+                    tokio::spawn(x);
+                }),
+            )));
+            cr.set_object_manager_support(Some(conn.clone()));
+
+            let tokens = Tokens::register(&mut cr);
+            let object_manager = cr.object_manager();
+            cr.insert("/", &[object_manager], ());
+            cr.insert(MANAGER_PATH, &[tokens.agent_manager], ());
+
+            let cr = Arc::new(Mutex::new(cr));
+            let handler = cr.clone();
+            conn.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    let _ = handler.lock().unwrap().handle_message(msg, conn);
+                    true
+                }),
+            );
+
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((conn, cr, tokens))
+        })?;
+
+        Ok(Bus {
+            conn,
+            cr,
+            tokens,
+            agents: Mutex::new(Agents::default()),
+            adapter: Mutex::new(None),
+            _runtime: runtime,
+            _daemon: stdin,
+        })
+    }
+
+    fn reset(&self) -> FakeAdapter {
+        let mut cr = self.cr.lock().unwrap();
+        if let Some(adapter) = self.adapter.lock().unwrap().take() {
+            adapter.unpublish(&mut cr);
+        }
+        *self.agents.lock().unwrap() = Agents::default();
+
+        let adapter = FakeAdapter::new();
+        cr.insert(
+            ADAPTER_PATH,
+            &[
+                self.tokens.adapter,
+                self.tokens.adv_manager,
+                self.tokens.monitor_manager,
+                self.tokens.gatt_manager,
+            ],
+            adapter.clone(),
+        );
+        *self.adapter.lock().unwrap() = Some(adapter.clone());
+        adapter
+    }
+
+    fn agent_for(&self, sender: Option<&str>) -> Option<Agent> {
+        let agents = self.agents.lock().unwrap();
+        agents
+            .registered
+            .iter()
+            .find(|x| Some(x.owner.as_str()) == sender)
+            .or(agents.default.as_ref())
+            .cloned()
+    }
+}
+
+fn emit_changed(path: &Path<'static>, interface: &str, changed: PropMap) {
+    let msg = PropertiesPropertiesChanged {
+        interface_name: interface.to_owned(),
+        changed_properties: changed,
+        invalidated_properties: Vec::new(),
+    }
+    .to_emit_message(path);
+    let _ = bus().conn.send(msg);
+}
+
+fn prop(value: impl RefArg + 'static) -> Variant<Box<dyn RefArg>> {
+    Variant(Box::new(value))
+}
+
+fn bluez_error(name: &str, message: &str) -> MethodErr {
+    (format!("org.bluez.Error.{name}"), message.to_owned()).into()
+}
+
+fn absent(name: &str) -> MethodErr {
+    MethodErr::invalid_arg(name)
+}
+
+fn format_address(address: [u8; 6]) -> String {
+    address.map(|x| format!("{x:02X}")).join(":")
+}
+
+impl Tokens {
+    fn register(cr: &mut Crossroads) -> Tokens {
+        Tokens {
+            adapter: register_adapter(cr),
+            adv_manager: register_adv_manager(cr),
+            monitor_manager: register_monitor_manager(cr),
+            gatt_manager: register_gatt_manager(cr),
+            agent_manager: register_agent_manager(cr),
+            device: register_device(cr),
+            service: register_service(cr),
+            characteristic: register_characteristic(cr),
+            descriptor: register_descriptor(cr),
+        }
+    }
+}
+
+/// A discovery filter set with `org.bluez.Adapter1.SetDiscoveryFilter`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoveryFilter {
+    pub uuids: Vec<Uuid>,
+    pub rssi: Option<i16>,
+    pub pathloss: Option<u16>,
+    pub transport: Option<String>,
+    pub duplicate_data: Option<bool>,
+    pub discoverable: Option<bool>,
+    pub pattern: Option<String>,
+}
+
+impl DiscoveryFilter {
+    fn from_dict(dict: &PropMap) -> Self {
+        DiscoveryFilter {
+            uuids: prop_cast::<Vec<String>>(dict, "UUIDs")
+                .map(|x| x.iter().filter_map(|x| x.parse().ok()).collect())
+                .unwrap_or_default(),
+            rssi: prop_cast(dict, "RSSI").copied(),
+            pathloss: prop_cast(dict, "Pathloss").copied(),
+            transport: prop_cast(dict, "Transport").cloned(),
+            duplicate_data: prop_cast(dict, "DuplicateData").copied(),
+            discoverable: prop_cast(dict, "Discoverable").copied(),
+            pattern: prop_cast(dict, "Pattern").cloned(),
+        }
+    }
+}
+
+/// An advertisement registered with `org.bluez.LEAdvertisingManager1.RegisterAdvertisement`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeAdvertisement {
+    pub owner: String,
+    pub path: String,
+    pub advertisement_type: String,
+    pub service_uuids: Vec<Uuid>,
+    pub solicit_uuids: Vec<Uuid>,
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    pub service_data: HashMap<Uuid, Vec<u8>>,
+    pub data: HashMap<u8, Vec<u8>>,
+    pub discoverable: Option<bool>,
+    pub includes: Vec<String>,
+    pub local_name: Option<String>,
+    pub appearance: Option<u16>,
+    pub duration: Option<u16>,
+    pub timeout: Option<u16>,
+    pub secondary_channel: Option<String>,
+    pub min_interval: Option<u32>,
+    pub max_interval: Option<u32>,
+    pub tx_power: Option<i16>,
+}
+
+impl FakeAdvertisement {
+    /// Reads the properties of the advertisement object published by `owner` at `path`.
+    async fn fetch(owner: String, path: Path<'static>) -> Result<Self, MethodErr> {
+        let proxy = Proxy::new(owner.clone(), path.clone(), TIMEOUT, bus().conn.clone());
+
+        async fn get<T>(proxy: &Proxy<'_, Arc<SyncConnection>>, name: &str) -> Option<T>
+        where
+            T: for<'b> dbus::arg::Get<'b> + Send + 'static,
+        {
+            proxy.get(ADVERTISEMENT_INTERFACE, name).await.ok()
+        }
+
+        fn uuids(x: Option<Vec<String>>) -> Vec<Uuid> {
+            x.unwrap_or_default().iter().filter_map(|x| x.parse().ok()).collect()
+        }
+
+        let advertisement_type = get::<String>(&proxy, "Type")
+            .await
+            .ok_or_else(|| MethodErr::invalid_arg("Type"))?;
+
+        Ok(FakeAdvertisement {
+            owner,
+            path: path.to_string(),
+            advertisement_type,
+            service_uuids: uuids(get(&proxy, "ServiceUUIDs").await),
+            solicit_uuids: uuids(get(&proxy, "SolicitUUIDs").await),
+            manufacturer_data: get::<HashMap<u16, Variant<Vec<u8>>>>(&proxy, "ManufacturerData")
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(k, v)| (k, v.0))
+                .collect(),
+            service_data: get::<HashMap<String, Variant<Vec<u8>>>>(&proxy, "ServiceData")
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(k, v)| Some((k.parse().ok()?, v.0)))
+                .collect(),
+            data: get::<HashMap<u8, Variant<Vec<u8>>>>(&proxy, "Data")
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(k, v)| (k, v.0))
+                .collect(),
+            discoverable: get(&proxy, "Discoverable").await,
+            includes: get(&proxy, "Includes").await.unwrap_or_default(),
+            local_name: get(&proxy, "LocalName").await,
+            appearance: get(&proxy, "Appearance").await,
+            duration: get(&proxy, "Duration").await,
+            timeout: get(&proxy, "Timeout").await,
+            secondary_channel: get(&proxy, "SecondaryChannel").await,
+            min_interval: get(&proxy, "MinInterval").await,
+            max_interval: get(&proxy, "MaxInterval").await,
+            tx_power: get(&proxy, "TxPower").await,
+        })
+    }
+}
+
+/// An advertisement monitor registered with `org.bluez.AdvertisementMonitorManager1.RegisterMonitor`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeMonitor {
+    pub owner: String,
+    pub path: String,
+    pub monitor_type: String,
+    pub rssi_low_threshold: Option<i16>,
+    pub rssi_high_threshold: Option<i16>,
+    pub rssi_low_timeout: Option<u16>,
+    pub rssi_high_timeout: Option<u16>,
+    pub rssi_sampling_period: Option<u16>,
+    /// The `(start_position, ad_type, content)` of each pattern
+    pub patterns: Vec<(u8, u8, Vec<u8>)>,
+}
+
+impl FakeMonitor {
+    fn from_props(owner: String, path: &Path<'static>, props: &PropMap) -> Self {
+        let int = |name: &str| props.get(name).and_then(|x| x.0.as_i64());
+        let patterns = props
+            .get("Patterns")
+            .and_then(|x| x.0.as_iter())
+            .map(|patterns| {
+                patterns
+                    .filter_map(|pattern| {
+                        let mut fields = pattern.as_iter()?;
+                        let start_position = fields.next()?.as_u64()? as u8;
+                        let ad_type = fields.next()?.as_u64()? as u8;
+                        let content = fields.next()?.as_iter()?.filter_map(|x| x.as_u64()).map(|x| x as u8);
+                        Some((start_position, ad_type, content.collect()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        FakeMonitor {
+            owner,
+            path: path.to_string(),
+            monitor_type: prop_cast::<String>(props, "Type").cloned().unwrap_or_default(),
+            rssi_low_threshold: int("RSSILowThreshold").map(|x| x as i16),
+            rssi_high_threshold: int("RSSIHighThreshold").map(|x| x as i16),
+            rssi_low_timeout: int("RSSILowTimeout").map(|x| x as u16),
+            rssi_high_timeout: int("RSSIHighTimeout").map(|x| x as u16),
+            rssi_sampling_period: int("RSSISamplingPeriod").map(|x| x as u16),
+            patterns,
+        }
+    }
+
+    /// Whether BlueZ would accept the monitor: it must match `or_patterns` which each fit in an advertisement.
+    fn is_valid(&self) -> bool {
+        self.monitor_type == "or_patterns"
+            && !self.patterns.is_empty()
+            && self
+                .patterns
+                .iter()
+                .all(|(start, _, content)| !content.is_empty() && usize::from(*start) + content.len() <= 31)
+    }
+
+    /// Tells the client that `device` has come into range.
+    pub async fn device_found(&self, device: &FakeDevice) {
+        self.call("DeviceFound", device).await;
+    }
+
+    /// Tells the client that `device` has gone out of range.
+    pub async fn device_lost(&self, device: &FakeDevice) {
+        self.call("DeviceLost", device).await;
+    }
+
+    async fn call(&self, method: &str, device: &FakeDevice) {
+        let proxy = Proxy::new(self.owner.clone(), self.path.clone(), TIMEOUT, bus().conn.clone());
+        let res: Result<(), _> = proxy.method_call(MONITOR_INTERFACE, method, (device.path(),)).await;
+        res.unwrap_or_else(|err| panic!("{method} failed: {err}"));
+    }
+}
+
+/// A GATT application registered with `org.bluez.GattManager1.RegisterApplication`.
+///
+/// The attributes of the application can be accessed through it as a connected central would.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeApplication {
+    pub owner: String,
+    pub path: String,
+    pub services: Vec<FakeLocalService>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeLocalService {
+    pub path: String,
+    pub uuid: Uuid,
+    pub primary: bool,
+    pub characteristics: Vec<FakeLocalCharacteristic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeLocalCharacteristic {
+    pub owner: String,
+    pub path: String,
+    pub uuid: Uuid,
+    pub flags: Vec<String>,
+    pub descriptors: Vec<FakeLocalDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeLocalDescriptor {
+    pub owner: String,
+    pub path: String,
+    pub uuid: Uuid,
+    pub flags: Vec<String>,
+}
+
+/// The values a local characteristic sends while a central is subscribed to it.
+pub struct FakeNotifications {
+    values: tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>,
+    // The signals are only received while the match is alive
+    _match: MsgMatch,
+}
+
+impl FakeApplication {
+    /// Reads the objects of the application published by `owner` under `root`.
+    async fn fetch(owner: String, root: Path<'static>) -> Result<Self, MethodErr> {
+        let proxy = Proxy::new(owner.clone(), root.clone(), TIMEOUT, bus().conn.clone());
+        let objects = proxy
+            .get_managed_objects()
+            .await
+            .map_err(|err| MethodErr::failed(&err))?;
+
+        let uuid = |props: &PropMap| {
+            prop_cast::<String>(props, "UUID")
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(|| absent("UUID"))
+        };
+        let flags = |props: &PropMap| -> Vec<String> {
+            props
+                .get("Flags")
+                .and_then(|x| x.0.as_iter())
+                .map(|x| x.filter_map(|x| x.as_str().map(str::to_owned)).collect())
+                .unwrap_or_default()
+        };
+        let parent = |props: &PropMap, name: &str| {
+            props
+                .get(name)
+                .and_then(|x| x.0.as_str().map(str::to_owned))
+                .ok_or_else(|| absent(name))
+        };
+
+        let mut paths = objects.keys().collect::<Vec<_>>();
+        paths.sort();
+        let (mut services, mut characteristics, mut descriptors) = (Vec::new(), Vec::new(), Vec::new());
+        for path in paths {
+            let interfaces = &objects[path];
+            if let Some(props) = interfaces.get(SERVICE_INTERFACE) {
+                services.push(FakeLocalService {
+                    path: path.to_string(),
+                    uuid: uuid(props)?,
+                    primary: prop_cast::<bool>(props, "Primary").copied().unwrap_or(true),
+                    characteristics: Vec::new(),
+                });
+            } else if let Some(props) = interfaces.get(CHARACTERISTIC_INTERFACE) {
+                let characteristic = FakeLocalCharacteristic {
+                    owner: owner.clone(),
+                    path: path.to_string(),
+                    uuid: uuid(props)?,
+                    flags: flags(props),
+                    descriptors: Vec::new(),
+                };
+                characteristics.push((parent(props, "Service")?, characteristic));
+            } else if let Some(props) = interfaces.get(DESCRIPTOR_INTERFACE) {
+                let descriptor = FakeLocalDescriptor {
+                    owner: owner.clone(),
+                    path: path.to_string(),
+                    uuid: uuid(props)?,
+                    flags: flags(props),
+                };
+                descriptors.push((parent(props, "Characteristic")?, descriptor));
+            }
+        }
+
+        for (parent, descriptor) in descriptors {
+            let (_, characteristic) = characteristics
+                .iter_mut()
+                .find(|(_, x)| x.path == parent)
+                .ok_or_else(|| bluez_error("Failed", "Descriptor without characteristic"))?;
+            characteristic.descriptors.push(descriptor);
+        }
+        for (parent, characteristic) in characteristics {
+            let service = services
+                .iter_mut()
+                .find(|x| x.path == parent)
+                .ok_or_else(|| bluez_error("Failed", "Characteristic without service"))?;
+            service.characteristics.push(characteristic);
+        }
+        if services.is_empty() {
+            return Err(bluez_error("Failed", "No object received"));
+        }
+
+        Ok(FakeApplication {
+            owner,
+            path: root.to_string(),
+            services,
+        })
+    }
+
+    /// The characteristic with `uuid` in the service with `service`.
+    pub fn characteristic(&self, service: Uuid, uuid: Uuid) -> &FakeLocalCharacteristic {
+        self.services
+            .iter()
+            .filter(|x| x.uuid == service)
+            .flat_map(|x| &x.characteristics)
+            .find(|x| x.uuid == uuid)
+            .expect("no such characteristic")
+    }
+}
+
+/// The options BlueZ passes with a request from `central` at `offset`.
+fn request_options(central: &FakeDevice, offset: u16) -> PropMap {
+    let mut options = PropMap::new();
+    options.insert("device".into(), prop(central.path()));
+    options.insert("offset".into(), prop(offset));
+    options.insert("mtu".into(), prop(central.state().mtu));
+    options.insert("link".into(), prop("LE".to_owned()));
+    options
+}
+
+impl FakeLocalCharacteristic {
+    fn proxy(&self) -> Proxy<'static, Arc<SyncConnection>> {
+        Proxy::new(self.owner.clone(), self.path.clone(), TIMEOUT, bus().conn.clone())
+    }
+
+    /// Reads the value as `central`, starting at `offset`.
+    pub async fn read(&self, central: &FakeDevice, offset: u16) -> Result<Vec<u8>, dbus::Error> {
+        let (value,): (Vec<u8>,) = self
+            .proxy()
+            .method_call(
+                CHARACTERISTIC_INTERFACE,
+                "ReadValue",
+                (request_options(central, offset),),
+            )
+            .await?;
+        Ok(value)
+    }
+
+    /// Writes `value` as `central` with the BlueZ write `kind`, which is `request`, `command` or `reliable`.
+    pub async fn write(&self, central: &FakeDevice, value: &[u8], kind: &str) -> Result<(), dbus::Error> {
+        let mut options = request_options(central, 0);
+        options.insert("type".into(), prop(kind.to_owned()));
+        self.proxy()
+            .method_call(CHARACTERISTIC_INTERFACE, "WriteValue", (value.to_vec(), options))
+            .await
+    }
+
+    /// Enables notifications, or indications which are confirmed as they are received if the characteristic cannot
+    /// notify.
+    pub async fn subscribe(&self) -> Result<FakeNotifications, dbus::Error> {
+        let confirm = self.flags.iter().any(|x| x == "indicate") && !self.flags.iter().any(|x| x == "notify");
+        let (tx, values) = tokio::sync::mpsc::unbounded_channel();
+        let owner = dbus::strings::BusName::from(self.owner.clone());
+        let path = Path::from(self.path.clone());
+        let rule = PropertiesPropertiesChanged::match_rule(Some(&owner), Some(&path)).static_clone();
+        let proxy = self.proxy();
+        let signals = bus()
+            .conn
+            .add_match(rule)
+            .await?
+            .cb(move |_, changed: PropertiesPropertiesChanged| {
+                let Some(value) = changed.changed_properties.get("Value").and_then(|x| x.0.as_iter()) else {
+                    return true;
+                };
+                let value = value.filter_map(|x| x.as_u64()).map(|x| x as u8).collect();
+                if confirm {
+                    let proxy = proxy.clone();
+                    tokio::spawn(async move {
+                        let _: Result<(), _> = proxy.method_call(CHARACTERISTIC_INTERFACE, "Confirm", ()).await;
+                    });
+                }
+                tx.send(value).is_ok()
+            });
+        let () = self
+            .proxy()
+            .method_call(CHARACTERISTIC_INTERFACE, "StartNotify", ())
+            .await?;
+        Ok(FakeNotifications {
+            values,
+            _match: signals,
+        })
+    }
+
+    /// Disables notifications, as BlueZ does once the last central unsubscribes.
+    pub async fn unsubscribe(&self) -> Result<(), dbus::Error> {
+        self.proxy()
+            .method_call(CHARACTERISTIC_INTERFACE, "StopNotify", ())
+            .await
+    }
+}
+
+impl FakeLocalDescriptor {
+    fn proxy(&self) -> Proxy<'static, Arc<SyncConnection>> {
+        Proxy::new(self.owner.clone(), self.path.clone(), TIMEOUT, bus().conn.clone())
+    }
+
+    /// Reads the value as `central`, starting at `offset`.
+    pub async fn read(&self, central: &FakeDevice, offset: u16) -> Result<Vec<u8>, dbus::Error> {
+        let mut options = request_options(central, offset);
+        options.remove("mtu");
+        let (value,): (Vec<u8>,) = self
+            .proxy()
+            .method_call(DESCRIPTOR_INTERFACE, "ReadValue", (options,))
+            .await?;
+        Ok(value)
+    }
+
+    /// Writes `value` as `central`.
+    pub async fn write(&self, central: &FakeDevice, value: &[u8]) -> Result<(), dbus::Error> {
+        let mut options = request_options(central, 0);
+        options.remove("mtu");
+        options.insert("prepare_authorize".into(), prop(false));
+        self.proxy()
+            .method_call(DESCRIPTOR_INTERFACE, "WriteValue", (value.to_vec(), options))
+            .await
+    }
+}
+
+impl FakeNotifications {
+    /// The next value sent by the characteristic.
+    pub async fn next(&mut self) -> Option<Vec<u8>> {
+        tokio::time::timeout(TIMEOUT, self.values.recv()).await.ok().flatten()
+    }
+}
+
+/// The fake `hci0` adapter.
+#[derive(Debug, Clone)]
+pub struct FakeAdapter {
+    state: Arc<Mutex<AdapterState>>,
+}
+
+#[derive(Debug)]
+struct AdapterState {
+    address: [u8; 6],
+    alias: String,
+    powered: bool,
+    discovery_sessions: usize,
+    discovery_filter: Option<DiscoveryFilter>,
+    devices: Vec<FakeDevice>,
+    advertisements: Vec<FakeAdvertisement>,
+    supported_instances: u8,
+    /// The owner and path of the object managers registered with `RegisterMonitor`
+    monitor_roots: Vec<(String, Path<'static>)>,
+    monitors: Vec<FakeMonitor>,
+    applications: Vec<FakeApplication>,
+}
+
+impl FakeAdapter {
+    fn new() -> Self {
+        FakeAdapter {
+            state: Arc::new(Mutex::new(AdapterState {
+                address: [0x00, 0x1a, 0x7d, 0xda, 0x71, 0x00],
+                alias: "fake-bluez".to_owned(),
+                powered: true,
+                discovery_sessions: 0,
+                discovery_filter: None,
+                devices: Vec::new(),
+                advertisements: Vec::new(),
+                supported_instances: 4,
+                monitor_roots: Vec::new(),
+                monitors: Vec::new(),
+                applications: Vec::new(),
+            })),
+        }
+    }
+
+    fn path() -> Path<'static> {
+        Path::from(ADAPTER_PATH)
+    }
+
+    fn state(&self) -> MutexGuard<'_, AdapterState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Adds `device` to the adapter as if it had just been discovered.
+    pub fn add_device(&self, device: &FakeDevice) {
+        let mut cr = bus().cr.lock().unwrap();
+        self.state().devices.push(device.clone());
+        cr.insert(device.path(), &[bus().tokens.device], device.clone());
+    }
+
+    /// Removes `device` from the adapter, disconnecting it first if necessary.
+    pub fn remove_device(&self, device: &FakeDevice) {
+        let mut cr = bus().cr.lock().unwrap();
+        self.remove_device_in(&mut cr, device);
+    }
+
+    fn remove_device_in(&self, cr: &mut Crossroads, device: &FakeDevice) -> bool {
+        let removed = {
+            let mut state = self.state();
+            let len = state.devices.len();
+            state.devices.retain(|x| x != device);
+            len != state.devices.len()
+        };
+
+        if removed {
+            device.disconnect_in(cr);
+            let _: Option<FakeDevice> = cr.remove(&device.path());
+        }
+        removed
+    }
+
+    fn unpublish(&self, cr: &mut Crossroads) {
+        let devices = self.state().devices.clone();
+        for device in devices {
+            self.remove_device_in(cr, &device);
+        }
+        // Stops the monitor watchers
+        self.state().monitor_roots.clear();
+        let _: Option<FakeAdapter> = cr.remove(&Self::path());
+    }
+
+    pub fn is_powered(&self) -> bool {
+        self.state().powered
+    }
+
+    /// Powers the adapter on or off. Powering off stops discovery and disconnects all devices.
+    pub fn set_powered(&self, powered: bool) {
+        let mut cr = bus().cr.lock().unwrap();
+        let (devices, was_discovering) = {
+            let mut state = self.state();
+            if state.powered == powered {
+                return;
+            }
+            state.powered = powered;
+            let was_discovering = state.discovery_sessions > 0;
+            if !powered {
+                state.discovery_sessions = 0;
+            }
+            (state.devices.clone(), was_discovering)
+        };
+
+        if !powered {
+            for device in devices {
+                device.disconnect_in(&mut cr);
+            }
+        }
+
+        let mut changed = PropMap::new();
+        changed.insert("Powered".into(), prop(powered));
+        if !powered && was_discovering {
+            changed.insert("Discovering".into(), prop(false));
+        }
+        emit_changed(&Self::path(), ADAPTER_INTERFACE, changed);
+    }
+
+    /// Whether any client has an active discovery session.
+    pub fn is_discovering(&self) -> bool {
+        self.state().discovery_sessions > 0
+    }
+
+    /// Stops or restarts discovery without ending the discovery sessions of clients, as BlueZ does while the
+    /// controller is busy.
+    pub fn set_discovery_paused(&self, paused: bool) {
+        let mut changed = PropMap::new();
+        changed.insert("Discovering".into(), prop(!paused));
+        emit_changed(&Self::path(), ADAPTER_INTERFACE, changed);
+    }
+
+    /// The discovery filter most recently set by a client.
+    pub fn discovery_filter(&self) -> Option<DiscoveryFilter> {
+        self.state().discovery_filter.clone()
+    }
+
+    /// The advertisements currently registered by clients.
+    pub fn advertisements(&self) -> Vec<FakeAdvertisement> {
+        self.state().advertisements.clone()
+    }
+
+    /// The advertisement monitors currently activated for clients.
+    pub fn monitors(&self) -> Vec<FakeMonitor> {
+        self.state().monitors.clone()
+    }
+
+    /// Sets the number of advertisements the adapter can broadcast concurrently.
+    pub fn applications(&self) -> Vec<FakeApplication> {
+        self.state().applications.clone()
+    }
+
+    pub fn set_supported_instances(&self, instances: u8) {
+        self.state().supported_instances = instances;
+    }
+
+    fn start_discovery(&self) -> Result<(), MethodErr> {
+        let started = {
+            let mut state = self.state();
+            if !state.powered {
+                return Err(bluez_error("NotReady", "Resource Not Ready"));
+            }
+            state.discovery_sessions += 1;
+            state.discovery_sessions == 1
+        };
+
+        if started {
+            let mut changed = PropMap::new();
+            changed.insert("Discovering".into(), prop(true));
+            emit_changed(&Self::path(), ADAPTER_INTERFACE, changed);
+        }
+        Ok(())
+    }
+
+    fn stop_discovery(&self) -> Result<(), MethodErr> {
+        let stopped = {
+            let mut state = self.state();
+            if state.discovery_sessions == 0 {
+                return Err(bluez_error("Failed", "No discovery started"));
+            }
+            state.discovery_sessions -= 1;
+            state.discovery_sessions == 0
+        };
+
+        if stopped {
+            let mut changed = PropMap::new();
+            changed.insert("Discovering".into(), prop(false));
+            emit_changed(&Self::path(), ADAPTER_INTERFACE, changed);
+        }
+        Ok(())
+    }
+
+    fn register_advertisement(&self, advertisement: FakeAdvertisement) -> Result<(), MethodErr> {
+        let active = {
+            let mut state = self.state();
+            if state
+                .advertisements
+                .iter()
+                .any(|x| x.owner == advertisement.owner && x.path == advertisement.path)
+            {
+                return Err(bluez_error("AlreadyExists", "Already Exists"));
+            }
+            if state.advertisements.len() >= usize::from(state.supported_instances) {
+                return Err(bluez_error("NotPermitted", "Maximum advertisements reached"));
+            }
+            state.advertisements.push(advertisement);
+            state.advertisements.len() as u8
+        };
+
+        let mut changed = PropMap::new();
+        changed.insert("ActiveInstances".into(), prop(active));
+        emit_changed(&Self::path(), ADV_MANAGER_INTERFACE, changed);
+        Ok(())
+    }
+
+    fn unregister_advertisement(&self, owner: &str, path: &Path<'static>) -> Result<(), MethodErr> {
+        let active = {
+            let mut state = self.state();
+            let len = state.advertisements.len();
+            state.advertisements.retain(|x| x.owner != owner || x.path != **path);
+            if len == state.advertisements.len() {
+                return Err(bluez_error("DoesNotExist", "Does Not Exist"));
+            }
+            state.advertisements.len() as u8
+        };
+
+        let mut changed = PropMap::new();
+        changed.insert("ActiveInstances".into(), prop(active));
+        emit_changed(&Self::path(), ADV_MANAGER_INTERFACE, changed);
+        Ok(())
+    }
+
+    fn register_monitor(&self, owner: String, root: Path<'static>) -> Result<(), MethodErr> {
+        {
+            let mut state = self.state();
+            if state.monitor_roots.iter().any(|(o, r)| *o == owner && *r == root) {
+                return Err(bluez_error("AlreadyExists", "Already Exists"));
+            }
+            state.monitor_roots.push((owner.clone(), root.clone()));
+        }
+        tokio::spawn(self.clone().watch_monitors(owner, root));
+        Ok(())
+    }
+
+    fn unregister_monitor(&self, owner: &str, root: &Path<'static>) -> Result<(), MethodErr> {
+        let mut state = self.state();
+        let len = state.monitor_roots.len();
+        state.monitor_roots.retain(|(o, r)| o != owner || r != root);
+        if len == state.monitor_roots.len() {
+            return Err(bluez_error("DoesNotExist", "Does Not Exist"));
+        }
+        let prefix = format!("{root}/");
+        state
+            .monitors
+            .retain(|x| x.owner != owner || !x.path.starts_with(&prefix));
+        Ok(())
+    }
+
+    fn register_application(&self, application: FakeApplication) -> Result<(), MethodErr> {
+        let mut state = self.state();
+        if state
+            .applications
+            .iter()
+            .any(|x| x.owner == application.owner && x.path == application.path)
+        {
+            return Err(bluez_error("AlreadyExists", "Already Exists"));
+        }
+        state.applications.push(application);
+        Ok(())
+    }
+
+    fn unregister_application(&self, owner: &str, path: &Path<'static>) -> Result<(), MethodErr> {
+        let mut state = self.state();
+        let len = state.applications.len();
+        state.applications.retain(|x| x.owner != owner || x.path != **path);
+        if len == state.applications.len() {
+            return Err(bluez_error("DoesNotExist", "Does Not Exist"));
+        }
+        Ok(())
+    }
+
+    /// Activates the monitors the client adds under `root`, and forgets those it removes, until it is unregistered.
+    ///
+    /// BlueZ follows the object manager's signals instead, but polling is simpler and just as good for tests.
+    async fn watch_monitors(self, owner: String, root: Path<'static>) {
+        let proxy = Proxy::new(owner.clone(), root.clone(), TIMEOUT, bus().conn.clone());
+        let prefix = format!("{root}/");
+        let mut seen = HashSet::new();
+        loop {
+            let Ok(objects) = proxy.get_managed_objects().await else {
+                return;
+            };
+            {
+                let mut state = self.state();
+                if !state.monitor_roots.iter().any(|(o, r)| *o == owner && *r == root) {
+                    return;
+                }
+                state.monitors.retain(|x| {
+                    x.owner != owner
+                        || !x.path.starts_with(&prefix)
+                        || objects.contains_key(&Path::from(x.path.clone()))
+                });
+            }
+
+            for (path, interfaces) in objects {
+                let Some(props) = interfaces.get(MONITOR_INTERFACE) else {
+                    continue;
+                };
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+                let monitor = FakeMonitor::from_props(owner.clone(), &path, props);
+                let method = if monitor.is_valid() {
+                    self.state().monitors.push(monitor);
+                    "Activate"
+                } else {
+                    "Release"
+                };
+                let target = Proxy::new(owner.clone(), path, TIMEOUT, bus().conn.clone());
+                let _: Result<(), _> = target.method_call(MONITOR_INTERFACE, method, ()).await;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+fn register_adapter(cr: &mut Crossroads) -> IfaceToken<FakeAdapter> {
+    cr.register(ADAPTER_INTERFACE, |b| {
+        b.property("Address")
+            .get(|_, a: &mut FakeAdapter| Ok(format_address(a.state().address)));
+        b.property("AddressType").get(|_, _| Ok("public".to_owned()));
+        b.property("Name")
+            .get(|_, a: &mut FakeAdapter| Ok(a.state().alias.clone()));
+        b.property("Alias")
+            .get(|_, a: &mut FakeAdapter| Ok(a.state().alias.clone()));
+        b.property("Class").get(|_, _| Ok(0u32));
+        b.property("Powered")
+            .get(|_, a: &mut FakeAdapter| Ok(a.state().powered));
+        b.property("Discoverable").get(|_, _| Ok(false));
+        b.property("DiscoverableTimeout").get(|_, _| Ok(180u32));
+        b.property("Pairable").get(|_, _| Ok(true));
+        b.property("PairableTimeout").get(|_, _| Ok(0u32));
+        b.property("Discovering")
+            .get(|_, a: &mut FakeAdapter| Ok(a.state().discovery_sessions > 0));
+        b.property("UUIDs").get(|_, _| Ok(Vec::<String>::new()));
+
+        b.method("StartDiscovery", (), (), |_, a: &mut FakeAdapter, ()| {
+            a.start_discovery()
+        });
+        b.method("StopDiscovery", (), (), |_, a: &mut FakeAdapter, ()| a.stop_discovery());
+        b.method(
+            "SetDiscoveryFilter",
+            ("properties",),
+            (),
+            |_, a: &mut FakeAdapter, (dict,): (PropMap,)| {
+                a.state().discovery_filter = Some(DiscoveryFilter::from_dict(&dict));
+                Ok(())
+            },
+        );
+        b.method("GetDiscoveryFilters", (), ("filters",), |_, _, ()| {
+            let filters = [
+                "UUIDs",
+                "RSSI",
+                "Pathloss",
+                "Transport",
+                "DuplicateData",
+                "Discoverable",
+                "Pattern",
+            ];
+            Ok((filters.map(String::from).to_vec(),))
+        });
+        b.method_with_cr(
+            "RemoveDevice",
+            ("device",),
+            (),
+            |ctx, cr, (device,): (Path<'static>,)| {
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+                let device = adapter
+                    .state()
+                    .devices
+                    .iter()
+                    .find(|x| x.path() == device)
+                    .cloned()
+                    .ok_or_else(|| bluez_error("DoesNotExist", "Does Not Exist"))?;
+                adapter.remove_device_in(cr, &device);
+                Ok(())
+            },
+        );
+    })
+}
+
+fn register_adv_manager(cr: &mut Crossroads) -> IfaceToken<FakeAdapter> {
+    cr.register(ADV_MANAGER_INTERFACE, |b| {
+        b.property("ActiveInstances")
+            .get(|_, a: &mut FakeAdapter| Ok(a.state().advertisements.len() as u8));
+        b.property("SupportedInstances").get(|_, a: &mut FakeAdapter| {
+            let state = a.state();
+            Ok(state
+                .supported_instances
+                .saturating_sub(state.advertisements.len() as u8))
+        });
+        b.property("SupportedIncludes").get(|_, _| {
+            Ok(vec![
+                "tx-power".to_owned(),
+                "appearance".to_owned(),
+                "local-name".to_owned(),
+            ])
+        });
+        b.property("SupportedSecondaryChannels")
+            .get(|_, _| Ok(vec!["1M".to_owned(), "2M".to_owned(), "Coded".to_owned()]));
+
+        b.method_with_cr_async(
+            "RegisterAdvertisement",
+            ("advertisement", "options"),
+            (),
+            |mut ctx, cr, (path, _options): (Path<'static>, PropMap)| {
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()));
+                let owner = ctx.message().sender().map(|x| x.to_string());
+                async move {
+                    let res = async {
+                        let adapter = adapter?;
+                        let owner = owner.ok_or_else(|| MethodErr::failed("missing sender"))?;
+                        let advertisement = FakeAdvertisement::fetch(owner, path).await?;
+                        adapter.register_advertisement(advertisement)
+                    }
+                    .await;
+                    ctx.reply(res)
+                }
+            },
+        );
+        b.method_with_cr(
+            "UnregisterAdvertisement",
+            ("advertisement",),
+            (),
+            |ctx, cr, (path,): (Path<'static>,)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+                adapter.unregister_advertisement(&owner, &path)
+            },
+        );
+    })
+}
+
+fn register_monitor_manager(cr: &mut Crossroads) -> IfaceToken<FakeAdapter> {
+    cr.register(MONITOR_MANAGER_INTERFACE, |b| {
+        b.property("SupportedMonitorTypes")
+            .get(|_, _| Ok(vec!["or_patterns".to_owned()]));
+        b.property("SupportedFeatures").get(|_, _| Ok(Vec::<String>::new()));
+
+        b.method_with_cr(
+            "RegisterMonitor",
+            ("application",),
+            (),
+            |ctx, cr, (root,): (Path<'static>,)| {
+                let owner = ctx
+                    .message()
+                    .sender()
+                    .map(|x| x.to_string())
+                    .ok_or_else(|| MethodErr::failed("missing sender"))?;
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+                adapter.register_monitor(owner, root)
+            },
+        );
+        b.method_with_cr(
+            "UnregisterMonitor",
+            ("application",),
+            (),
+            |ctx, cr, (root,): (Path<'static>,)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+                adapter.unregister_monitor(&owner, &root)
+            },
+        );
+    })
+}
+
+fn register_gatt_manager(cr: &mut Crossroads) -> IfaceToken<FakeAdapter> {
+    cr.register(GATT_MANAGER_INTERFACE, |b| {
+        b.method_with_cr_async(
+            "RegisterApplication",
+            ("application", "options"),
+            (),
+            |mut ctx, cr, (path, _options): (Path<'static>, PropMap)| {
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()));
+                let owner = ctx.message().sender().map(|x| x.to_string());
+                async move {
+                    let res = async {
+                        let adapter = adapter?;
+                        let owner = owner.ok_or_else(|| MethodErr::failed("missing sender"))?;
+                        let application = FakeApplication::fetch(owner, path).await?;
+                        adapter.register_application(application)
+                    }
+                    .await;
+                    ctx.reply(res)
+                }
+            },
+        );
+        b.method_with_cr(
+            "UnregisterApplication",
+            ("application",),
+            (),
+            |ctx, cr, (path,): (Path<'static>,)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let adapter = cr
+                    .data_mut::<FakeAdapter>(ctx.path())
+                    .cloned()
+                    .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+                adapter.unregister_application(&owner, &path)
+            },
+        );
+    })
+}
+
+fn register_agent_manager(cr: &mut Crossroads) -> IfaceToken<()> {
+    cr.register(AGENT_MANAGER_INTERFACE, |b| {
+        b.method(
+            "RegisterAgent",
+            ("agent", "capability"),
+            (),
+            |ctx, _, (path, capability): (Path<'static>, String)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let mut agents = bus().agents.lock().unwrap();
+                if agents.registered.iter().any(|x| x.owner == owner) {
+                    return Err(bluez_error("AlreadyExists", "Already Exists"));
+                }
+                agents.registered.push(Agent {
+                    owner,
+                    path,
+                    capability,
+                });
+                Ok(())
+            },
+        );
+        b.method(
+            "UnregisterAgent",
+            ("agent",),
+            (),
+            |ctx, _, (path,): (Path<'static>,)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let mut agents = bus().agents.lock().unwrap();
+                agents.registered.retain(|x| x.owner != owner || x.path != path);
+                if agents
+                    .default
+                    .as_ref()
+                    .is_some_and(|x| x.owner == owner && x.path == path)
+                {
+                    agents.default = None;
+                }
+                Ok(())
+            },
+        );
+        b.method(
+            "RequestDefaultAgent",
+            ("agent",),
+            (),
+            |ctx, _, (path,): (Path<'static>,)| {
+                let owner = ctx.message().sender().map(|x| x.to_string()).unwrap_or_default();
+                let mut agents = bus().agents.lock().unwrap();
+                let agent = agents
+                    .registered
+                    .iter()
+                    .find(|x| x.owner == owner && x.path == path)
+                    .cloned()
+                    .ok_or_else(|| bluez_error("DoesNotExist", "Does Not Exist"))?;
+                agents.default = Some(agent);
+                Ok(())
+            },
+        );
+    })
+}
+
+/// A fake remote Bluetooth LE device.
+#[derive(Debug, Clone)]
+pub struct FakeDevice {
+    inner: Arc<DeviceInner>,
+}
+
+#[derive(Debug)]
+struct DeviceInner {
+    address: [u8; 6],
+    path: Path<'static>,
+    state: Mutex<DeviceState>,
+}
+
+#[derive(Debug)]
+struct DeviceState {
+    address_type: &'static str,
+    name: Option<String>,
+    rssi: Option<i16>,
+    tx_power: Option<i16>,
+    advertising_flags: Option<u8>,
+    manufacturer_data: HashMap<u16, Vec<u8>>,
+    service_data: HashMap<Uuid, Vec<u8>>,
+    uuids: Vec<Uuid>,
+    connected: bool,
+    services_resolved: bool,
+    paired: bool,
+    passkey: Option<u32>,
+    mtu: u16,
+    services: Vec<FakeService>,
+    next_handle: u16,
+}
+
+impl PartialEq for FakeDevice {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl FakeDevice {
+    /// Creates a device with the given address (most significant byte first).
+    pub fn new(address: [u8; 6]) -> Self {
+        let path = format!("{ADAPTER_PATH}/dev_{}", format_address(address).replace(':', "_"));
+        FakeDevice {
+            inner: Arc::new(DeviceInner {
+                address,
+                path: Path::from(path),
+                state: Mutex::new(DeviceState {
+                    address_type: "public",
+                    name: None,
+                    rssi: None,
+                    tx_power: None,
+                    advertising_flags: None,
+                    manufacturer_data: HashMap::new(),
+                    service_data: HashMap::new(),
+                    uuids: Vec::new(),
+                    connected: false,
+                    services_resolved: false,
+                    paired: false,
+                    passkey: None,
+                    mtu: 23,
+                    services: Vec::new(),
+                    next_handle: 1,
+                }),
+            }),
+        }
+    }
+
+    pub fn with_random_address(self) -> Self {
+        self.state().address_type = "random";
+        self
+    }
+
+    pub fn with_name(self, name: &str) -> Self {
+        self.state().name = Some(name.to_owned());
+        self
+    }
+
+    pub fn with_rssi(self, rssi: i16) -> Self {
+        self.state().rssi = Some(rssi);
+        self
+    }
+
+    pub fn with_tx_power(self, tx_power: i16) -> Self {
+        self.state().tx_power = Some(tx_power);
+        self
+    }
+
+    /// Sets the value of the Flags AD structure (CSS §A.1.3) of the device's advertisement.
+    pub fn with_advertising_flags(self, flags: u8) -> Self {
+        self.state().advertising_flags = Some(flags);
+        self
+    }
+
+    pub fn with_manufacturer_data(self, company_id: u16, data: &[u8]) -> Self {
+        self.state().manufacturer_data.insert(company_id, data.to_vec());
+        self
+    }
+
+    pub fn with_service_data(self, uuid: Uuid, data: &[u8]) -> Self {
+        self.state().service_data.insert(uuid, data.to_vec());
+        self
+    }
+
+    pub fn with_uuid(self, uuid: Uuid) -> Self {
+        self.state().uuids.push(uuid);
+        self
+    }
+
+    /// Requires `passkey` to be confirmed or entered by the pairing agent.
+    pub fn with_passkey(self, passkey: u32) -> Self {
+        self.state().passkey = Some(passkey);
+        self
+    }
+
+    pub fn with_mtu(self, mtu: u16) -> Self {
+        self.state().mtu = mtu;
+        self
+    }
+
+    /// Adds `service` to the GATT database of this device.
+    pub fn with_service(self, service: FakeService) -> Self {
+        self.attach_service(&service);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, DeviceState> {
+        self.inner.state.lock().unwrap()
+    }
+
+    pub fn path(&self) -> Path<'static> {
+        self.inner.path.clone()
+    }
+
+    pub fn address(&self) -> [u8; 6] {
+        self.inner.address
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state().connected
+    }
+
+    pub fn is_paired(&self) -> bool {
+        self.state().paired
+    }
+
+    pub fn services(&self) -> Vec<FakeService> {
+        self.state().services.clone()
+    }
+
+    pub fn set_rssi(&self, rssi: i16) {
+        self.state().rssi = Some(rssi);
+        self.emit("RSSI", prop(rssi));
+    }
+
+    pub fn set_tx_power(&self, tx_power: i16) {
+        self.state().tx_power = Some(tx_power);
+        self.emit("TxPower", prop(tx_power));
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.state().name = Some(name.to_owned());
+        self.emit("Name", prop(name.to_owned()));
+        self.emit("Alias", prop(name.to_owned()));
+    }
+
+    pub fn set_manufacturer_data(&self, company_id: u16, data: &[u8]) {
+        let value = {
+            let mut state = self.state();
+            state.manufacturer_data.insert(company_id, data.to_vec());
+            manufacturer_data_prop(&state.manufacturer_data)
+        };
+        self.emit("ManufacturerData", prop(value));
+    }
+
+    pub fn set_service_data(&self, uuid: Uuid, data: &[u8]) {
+        let value = {
+            let mut state = self.state();
+            state.service_data.insert(uuid, data.to_vec());
+            service_data_prop(&state.service_data)
+        };
+        self.emit("ServiceData", prop(value));
+    }
+
+    /// Adds `service` to the GATT database, publishing it immediately if the device is connected.
+    pub fn add_service(&self, service: &FakeService) {
+        let mut cr = bus().cr.lock().unwrap();
+        self.attach_service(service);
+        if self.is_connected() {
+            service.publish(&mut cr);
+        }
+    }
+
+    /// Removes `service` from the GATT database.
+    pub fn remove_service(&self, service: &FakeService) {
+        let mut cr = bus().cr.lock().unwrap();
+        let removed = {
+            let mut state = self.state();
+            let len = state.services.len();
+            state.services.retain(|x| x != service);
+            len != state.services.len()
+        };
+        if removed && self.is_connected() {
+            service.unpublish(&mut cr);
+        }
+    }
+
+    /// Connects the device to the adapter from the device side, as a central connecting to a local GATT server does.
+    pub fn connect(&self) {
+        let mut cr = bus().cr.lock().unwrap();
+        self.connect_in(&mut cr).expect("the adapter is powered off");
+    }
+
+    /// Terminates the connection from the device side.
+    pub fn disconnect(&self) {
+        let mut cr = bus().cr.lock().unwrap();
+        self.disconnect_in(&mut cr);
+    }
+
+    fn emit(&self, name: &str, value: Variant<Box<dyn RefArg>>) {
+        let mut changed = PropMap::new();
+        changed.insert(name.to_owned(), value);
+        emit_changed(&self.inner.path, DEVICE_INTERFACE, changed);
+    }
+
+    fn attach_service(&self, service: &FakeService) {
+        let mut state = self.state();
+        let next = service.assign(&self.inner.path, state.next_handle, state.mtu);
+        state.next_handle = next;
+        state.services.push(service.clone());
+    }
+
+    fn connect_in(&self, cr: &mut Crossroads) -> Result<(), MethodErr> {
+        if !bus()
+            .adapter
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(FakeAdapter::is_powered)
+        {
+            return Err(bluez_error("NotReady", "Resource Not Ready"));
+        }
+
+        let services = {
+            let mut state = self.state();
+            if state.connected {
+                return Ok(());
+            }
+            state.connected = true;
+            state.services.clone()
+        };
+        self.emit("Connected", prop(true));
+
+        for service in services {
+            service.publish(cr);
+        }
+
+        self.state().services_resolved = true;
+        self.emit("ServicesResolved", prop(true));
+        Ok(())
+    }
+
+    fn disconnect_in(&self, cr: &mut Crossroads) {
+        let services = {
+            let mut state = self.state();
+            if !state.connected {
+                return;
+            }
+            state.connected = false;
+            state.services_resolved = false;
+            state.services.clone()
+        };
+        self.emit("ServicesResolved", prop(false));
+
+        for service in services {
+            service.unpublish(cr);
+        }
+
+        self.emit("Connected", prop(false));
+    }
+
+    async fn pair(&self, sender: Option<String>) -> Result<(), MethodErr> {
+        let passkey = {
+            let state = self.state();
+            if state.paired {
+                return Err(bluez_error("AlreadyExists", "Already Exists"));
+            }
+            state.passkey
+        };
+
+        if let (Some(agent), Some(passkey)) = (bus().agent_for(sender.as_deref()), passkey) {
+            let proxy = Proxy::new(agent.owner, agent.path, TIMEOUT, bus().conn.clone());
+            let device = self.path();
+            let res = match agent.capability.as_str() {
+                "DisplayOnly" => {
+                    proxy
+                        .method_call(AGENT_INTERFACE, "DisplayPasskey", (device, passkey, 0u16))
+                        .await
+                }
+                "DisplayYesNo" => {
+                    proxy
+                        .method_call(AGENT_INTERFACE, "RequestConfirmation", (device, passkey))
+                        .await
+                }
+                "NoInputNoOutput" => Ok(()),
+                _ => proxy
+                    .method_call(AGENT_INTERFACE, "RequestPasskey", (device,))
+                    .await
+                    .and_then(|(entered,): (u32,)| {
+                        if entered == passkey {
+                            Ok(())
+                        } else {
+                            Err(dbus::Error::new_failed("incorrect passkey"))
+                        }
+                    }),
+            };
+            res.map_err(|_| bluez_error("AuthenticationFailed", "Authentication Failed"))?;
+        }
+
+        self.state().paired = true;
+        self.emit("Paired", prop(true));
+        Ok(())
+    }
+}
+
+fn manufacturer_data_prop(data: &HashMap<u16, Vec<u8>>) -> HashMap<u16, Variant<Vec<u8>>> {
+    data.iter().map(|(k, v)| (*k, Variant(v.clone()))).collect()
+}
+
+fn service_data_prop(data: &HashMap<Uuid, Vec<u8>>) -> HashMap<String, Variant<Vec<u8>>> {
+    data.iter().map(|(k, v)| (k.to_string(), Variant(v.clone()))).collect()
+}
+
+fn register_device(cr: &mut Crossroads) -> IfaceToken<FakeDevice> {
+    cr.register(DEVICE_INTERFACE, |b| {
+        b.property("Address")
+            .get(|_, d: &mut FakeDevice| Ok(format_address(d.address())));
+        b.property("AddressType")
+            .get(|_, d: &mut FakeDevice| Ok(d.state().address_type.to_owned()));
+        b.property("Name")
+            .get(|ctx, d: &mut FakeDevice| d.state().name.clone().ok_or_else(|| absent(ctx.name())));
+        b.property("Alias").get(|_, d: &mut FakeDevice| {
+            let state = d.state();
+            Ok(state
+                .name
+                .clone()
+                .unwrap_or_else(|| format_address(d.address()).replace(':', "-")))
+        });
+        b.property("Adapter").get(|_, _| Ok(FakeAdapter::path()));
+        b.property("Paired").get(|_, d: &mut FakeDevice| Ok(d.state().paired));
+        b.property("Bonded").get(|_, d: &mut FakeDevice| Ok(d.state().paired));
+        b.property("Connected")
+            .get(|_, d: &mut FakeDevice| Ok(d.state().connected));
+        b.property("Trusted").get(|_, _| Ok(false));
+        b.property("Blocked").get(|_, _| Ok(false));
+        b.property("LegacyPairing").get(|_, _| Ok(false));
+        b.property("ServicesResolved")
+            .get(|_, d: &mut FakeDevice| Ok(d.state().services_resolved));
+        b.property("RSSI")
+            .get(|ctx, d: &mut FakeDevice| d.state().rssi.ok_or_else(|| absent(ctx.name())));
+        b.property("TxPower")
+            .get(|ctx, d: &mut FakeDevice| d.state().tx_power.ok_or_else(|| absent(ctx.name())));
+        b.property("AdvertisingFlags").get(|ctx, d: &mut FakeDevice| {
+            d.state()
+                .advertising_flags
+                .map(|flags| vec![flags])
+                .ok_or_else(|| absent(ctx.name()))
+        });
+        b.property("UUIDs").get(|ctx, d: &mut FakeDevice| {
+            let state = d.state();
+            if state.uuids.is_empty() {
+                return Err(absent(ctx.name()));
+            }
+            Ok(state.uuids.iter().map(Uuid::to_string).collect::<Vec<_>>())
+        });
+        b.property("ManufacturerData").get(|ctx, d: &mut FakeDevice| {
+            let state = d.state();
+            if state.manufacturer_data.is_empty() {
+                return Err(absent(ctx.name()));
+            }
+            Ok(manufacturer_data_prop(&state.manufacturer_data))
+        });
+        b.property("ServiceData").get(|ctx, d: &mut FakeDevice| {
+            let state = d.state();
+            if state.service_data.is_empty() {
+                return Err(absent(ctx.name()));
+            }
+            Ok(service_data_prop(&state.service_data))
+        });
+
+        b.method_with_cr("Connect", (), (), |ctx, cr, ()| {
+            let device = cr
+                .data_mut::<FakeDevice>(ctx.path())
+                .cloned()
+                .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+            device.connect_in(cr)
+        });
+        b.method_with_cr("Disconnect", (), (), |ctx, cr, ()| {
+            let device = cr
+                .data_mut::<FakeDevice>(ctx.path())
+                .cloned()
+                .ok_or_else(|| MethodErr::no_path(ctx.path()))?;
+            device.disconnect_in(cr);
+            Ok(())
+        });
+        b.method_with_cr_async("Pair", (), (), |mut ctx, cr, ()| {
+            let device = cr
+                .data_mut::<FakeDevice>(ctx.path())
+                .cloned()
+                .ok_or_else(|| MethodErr::no_path(ctx.path()));
+            let sender = ctx.message().sender().map(|x| x.to_string());
+            async move {
+                let res = match device {
+                    Ok(device) => device.pair(sender).await,
+                    Err(err) => Err(err),
+                };
+                ctx.reply(res)
+            }
+        });
+        b.method("CancelPairing", (), (), |_, _: &mut FakeDevice, ()| Ok(()));
+    })
+}
+
+/// A service in the GATT database of a [`FakeDevice`].
+#[derive(Debug, Clone)]
+pub struct FakeService {
+    state: Arc<Mutex<ServiceState>>,
+}
+
+#[derive(Debug)]
+struct ServiceState {
+    uuid: Uuid,
+    primary: bool,
+    handle: u16,
+    path: Path<'static>,
+    device: Path<'static>,
+    characteristics: Vec<FakeCharacteristic>,
+}
+
+impl PartialEq for FakeService {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl FakeService {
+    pub fn new(uuid: Uuid) -> Self {
+        FakeService {
+            state: Arc::new(Mutex::new(ServiceState {
+                uuid,
+                primary: true,
+                handle: 0,
+                path: Path::from("/"),
+                device: Path::from("/"),
+                characteristics: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn secondary(self) -> Self {
+        self.state().primary = false;
+        self
+    }
+
+    pub fn with_characteristic(self, characteristic: FakeCharacteristic) -> Self {
+        self.state().characteristics.push(characteristic);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, ServiceState> {
+        self.state.lock().unwrap()
+    }
+
+    /// The attribute handle of the service declaration, assigned when the service is added to a device.
+    pub fn handle(&self) -> u16 {
+        self.state().handle
+    }
+
+    pub fn characteristics(&self) -> Vec<FakeCharacteristic> {
+        self.state().characteristics.clone()
+    }
+
+    /// Lays out the attributes of this service starting at `handle`, returning the next free handle.
+    fn assign(&self, device: &Path<'static>, handle: u16, mtu: u16) -> u16 {
+        let mut state = self.state();
+        state.handle = handle;
+        state.device = device.clone();
+        state.path = Path::from(format!("{device}/service{handle:04x}"));
+        let mut next = handle + 1;
+        for characteristic in &state.characteristics {
+            next = characteristic.assign(&state.path, next, mtu);
+        }
+        next
+    }
+
+    fn publish(&self, cr: &mut Crossroads) {
+        let tokens = bus().tokens;
+        let path = self.state().path.clone();
+        cr.insert(path, &[tokens.service], self.clone());
+        for characteristic in self.characteristics() {
+            cr.insert(characteristic.path(), &[tokens.characteristic], characteristic.clone());
+            for descriptor in characteristic.descriptors() {
+                cr.insert(descriptor.path(), &[tokens.descriptor], descriptor.clone());
+            }
+        }
+    }
+
+    fn unpublish(&self, cr: &mut Crossroads) {
+        for characteristic in self.characteristics() {
+            for descriptor in characteristic.descriptors() {
+                let _: Option<FakeDescriptor> = cr.remove(&descriptor.path());
+            }
+            characteristic.state().notifying = false;
+            let _: Option<FakeCharacteristic> = cr.remove(&characteristic.path());
+        }
+        let path = self.state().path.clone();
+        let _: Option<FakeService> = cr.remove(&path);
+    }
+}
+
+fn register_service(cr: &mut Crossroads) -> IfaceToken<FakeService> {
+    cr.register(SERVICE_INTERFACE, |b| {
+        b.property("UUID")
+            .get(|_, s: &mut FakeService| Ok(s.state().uuid.to_string()));
+        b.property("Primary")
+            .get(|_, s: &mut FakeService| Ok(s.state().primary));
+        b.property("Device")
+            .get(|_, s: &mut FakeService| Ok(s.state().device.clone()));
+        b.property("Includes").get(|_, _| Ok(Vec::<Path<'static>>::new()));
+        b.property("Handle").get(|_, s: &mut FakeService| Ok(s.state().handle));
+    })
+}
+
+/// A write received by a [`FakeCharacteristic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeWrite {
+    pub value: Vec<u8>,
+    /// The write type requested by the client: `command`, `request` or `reliable`.
+    pub op: String,
+}
+
+/// A characteristic in the GATT database of a [`FakeDevice`].
+#[derive(Debug, Clone)]
+pub struct FakeCharacteristic {
+    state: Arc<Mutex<CharacteristicState>>,
+}
+
+#[derive(Debug)]
+struct CharacteristicState {
+    uuid: Uuid,
+    flags: Vec<String>,
+    value: Vec<u8>,
+    notifying: bool,
+    handle: u16,
+    mtu: u16,
+    path: Path<'static>,
+    service: Path<'static>,
+    writes: Vec<FakeWrite>,
+    descriptors: Vec<FakeDescriptor>,
+}
+
+impl PartialEq for FakeCharacteristic {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl FakeCharacteristic {
+    /// Creates a characteristic with BlueZ `flags` such as `read`, `write`, `write-without-response` or `notify`.
+    pub fn new(uuid: Uuid, flags: &[&str]) -> Self {
+        FakeCharacteristic {
+            state: Arc::new(Mutex::new(CharacteristicState {
+                uuid,
+                flags: flags.iter().map(|x| (*x).to_owned()).collect(),
+                value: Vec::new(),
+                notifying: false,
+                handle: 0,
+                mtu: 23,
+                path: Path::from("/"),
+                service: Path::from("/"),
+                writes: Vec::new(),
+                descriptors: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn with_value(self, value: &[u8]) -> Self {
+        self.state().value = value.to_vec();
+        self
+    }
+
+    pub fn with_descriptor(self, descriptor: FakeDescriptor) -> Self {
+        self.state().descriptors.push(descriptor);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, CharacteristicState> {
+        self.state.lock().unwrap()
+    }
+
+    fn path(&self) -> Path<'static> {
+        self.state().path.clone()
+    }
+
+    pub fn handle(&self) -> u16 {
+        self.state().handle
+    }
+
+    pub fn value(&self) -> Vec<u8> {
+        self.state().value.clone()
+    }
+
+    pub fn descriptors(&self) -> Vec<FakeDescriptor> {
+        self.state().descriptors.clone()
+    }
+
+    /// Whether a client has enabled notifications.
+    pub fn is_notifying(&self) -> bool {
+        self.state().notifying
+    }
+
+    /// The writes received from clients, oldest first.
+    pub fn writes(&self) -> Vec<FakeWrite> {
+        self.state().writes.clone()
+    }
+
+    /// Updates the value and sends it to subscribed clients. Returns `false` if no client is subscribed.
+    pub fn notify(&self, value: &[u8]) -> bool {
+        let (notifying, path) = {
+            let mut state = self.state();
+            state.value = value.to_vec();
+            (state.notifying, state.path.clone())
+        };
+
+        if notifying {
+            let mut changed = PropMap::new();
+            changed.insert("Value".into(), prop(value.to_vec()));
+            emit_changed(&path, CHARACTERISTIC_INTERFACE, changed);
+        }
+        notifying
+    }
+
+    fn assign(&self, service: &Path<'static>, handle: u16, mtu: u16) -> u16 {
+        let mut state = self.state();
+        // The declaration occupies `handle` and the value `handle + 1`; BlueZ names the object after the declaration
+        state.handle = handle;
+        state.mtu = mtu;
+        state.service = service.clone();
+        state.path = Path::from(format!("{service}/char{handle:04x}"));
+        let mut next = handle + 2;
+        for descriptor in &state.descriptors {
+            descriptor.assign(&state.path, next);
+            next += 1;
+        }
+        next
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.state().flags.iter().any(|x| x == flag)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, MethodErr> {
+        if !self.has_flag("read") {
+            return Err(bluez_error("NotPermitted", "Read not permitted"));
+        }
+
+        let (value, path) = {
+            let state = self.state();
+            (state.value.clone(), state.path.clone())
+        };
+
+        let mut changed = PropMap::new();
+        changed.insert("Value".into(), prop(value.clone()));
+        emit_changed(&path, CHARACTERISTIC_INTERFACE, changed);
+        Ok(value)
+    }
+
+    fn write(&self, value: Vec<u8>, options: &PropMap) -> Result<(), MethodErr> {
+        let op = prop_cast::<String>(options, "type")
+            .cloned()
+            .unwrap_or_else(|| "request".to_owned());
+        let flag = if op == "command" {
+            "write-without-response"
+        } else {
+            "write"
+        };
+        if !self.has_flag(flag) {
+            return Err(bluez_error("NotPermitted", "Write not permitted"));
+        }
+
+        let mut state = self.state();
+        state.value = value.clone();
+        state.writes.push(FakeWrite { value, op });
+        Ok(())
+    }
+
+    fn set_notifying(&self, notifying: bool) -> Result<(), MethodErr> {
+        if !self.has_flag("notify") && !self.has_flag("indicate") {
+            return Err(bluez_error("NotSupported", "Operation is not supported"));
+        }
+
+        let (changed, path) = {
+            let mut state = self.state();
+            (
+                std::mem::replace(&mut state.notifying, notifying) != notifying,
+                state.path.clone(),
+            )
+        };
+
+        if changed {
+            let mut changed = PropMap::new();
+            changed.insert("Notifying".into(), prop(notifying));
+            emit_changed(&path, CHARACTERISTIC_INTERFACE, changed);
+        }
+        Ok(())
+    }
+}
+
+fn register_characteristic(cr: &mut Crossroads) -> IfaceToken<FakeCharacteristic> {
+    cr.register(CHARACTERISTIC_INTERFACE, |b| {
+        b.property("UUID")
+            .get(|_, c: &mut FakeCharacteristic| Ok(c.state().uuid.to_string()));
+        b.property("Service")
+            .get(|_, c: &mut FakeCharacteristic| Ok(c.state().service.clone()));
+        b.property("Value").get(|_, c: &mut FakeCharacteristic| Ok(c.value()));
+        b.property("Notifying")
+            .get(|_, c: &mut FakeCharacteristic| Ok(c.state().notifying));
+        b.property("Flags")
+            .get(|_, c: &mut FakeCharacteristic| Ok(c.state().flags.clone()));
+        b.property("MTU").get(|_, c: &mut FakeCharacteristic| Ok(c.state().mtu));
+        b.property("Handle")
+            .get(|_, c: &mut FakeCharacteristic| Ok(c.state().handle + 1));
+
+        b.method(
+            "ReadValue",
+            ("options",),
+            ("value",),
+            |_, c: &mut FakeCharacteristic, (_options,): (PropMap,)| c.read().map(|x| (x,)),
+        );
+        b.method(
+            "WriteValue",
+            ("value", "options"),
+            (),
+            |_, c: &mut FakeCharacteristic, (value, options): (Vec<u8>, PropMap)| c.write(value, &options),
+        );
+        b.method("StartNotify", (), (), |_, c: &mut FakeCharacteristic, ()| {
+            c.set_notifying(true)
+        });
+        b.method("StopNotify", (), (), |_, c: &mut FakeCharacteristic, ()| {
+            c.set_notifying(false)
+        });
+    })
+}
+
+/// A descriptor in the GATT database of a [`FakeDevice`].
+#[derive(Debug, Clone)]
+pub struct FakeDescriptor {
+    state: Arc<Mutex<DescriptorState>>,
+}
+
+#[derive(Debug)]
+struct DescriptorState {
+    uuid: Uuid,
+    value: Vec<u8>,
+    handle: u16,
+    path: Path<'static>,
+    characteristic: Path<'static>,
+}
+
+impl FakeDescriptor {
+    pub fn new(uuid: Uuid) -> Self {
+        FakeDescriptor {
+            state: Arc::new(Mutex::new(DescriptorState {
+                uuid,
+                value: Vec::new(),
+                handle: 0,
+                path: Path::from("/"),
+                characteristic: Path::from("/"),
+            })),
+        }
+    }
+
+    pub fn with_value(self, value: &[u8]) -> Self {
+        self.state().value = value.to_vec();
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, DescriptorState> {
+        self.state.lock().unwrap()
+    }
+
+    fn path(&self) -> Path<'static> {
+        self.state().path.clone()
+    }
+
+    pub fn value(&self) -> Vec<u8> {
+        self.state().value.clone()
+    }
+
+    fn assign(&self, characteristic: &Path<'static>, handle: u16) {
+        let mut state = self.state();
+        state.handle = handle;
+        state.characteristic = characteristic.clone();
+        state.path = Path::from(format!("{characteristic}/desc{handle:04x}"));
+    }
+}
+
+fn register_descriptor(cr: &mut Crossroads) -> IfaceToken<FakeDescriptor> {
+    cr.register(DESCRIPTOR_INTERFACE, |b| {
+        b.property("UUID")
+            .get(|_, d: &mut FakeDescriptor| Ok(d.state().uuid.to_string()));
+        b.property("Characteristic")
+            .get(|_, d: &mut FakeDescriptor| Ok(d.state().characteristic.clone()));
+        b.property("Value").get(|_, d: &mut FakeDescriptor| Ok(d.value()));
+        b.property("Handle")
+            .get(|_, d: &mut FakeDescriptor| Ok(d.state().handle));
+
+        b.method(
+            "ReadValue",
+            ("options",),
+            ("value",),
+            |_, d: &mut FakeDescriptor, (_options,): (PropMap,)| Ok((d.value(),)),
+        );
+        b.method(
+            "WriteValue",
+            ("value", "options"),
+            (),
+            |_, d: &mut FakeDescriptor, (value, _options): (Vec<u8>, PropMap)| {
+                d.state().value = value;
+                Ok(())
+            },
+        );
+    })
+}
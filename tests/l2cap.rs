@@ -0,0 +1,246 @@
+//! Tests of L2CAP channels with the mock backend.
+
+#![cfg(all(feature = "mock", feature = "l2cap"))]
+
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use bluest::error::ErrorKind;
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{Device, L2capChannel};
+use futures_lite::{AsyncReadExt, AsyncWriteExt, StreamExt};
+use futures_sink::Sink;
+
+mod common;
+
+/// The MTU of virtual L2CAP channels
+const MTU: usize = 512;
+
+/// Connects to a new peripheral of `radio`
+async fn connect(radio: &VirtualRadio) -> (VirtualPeripheral, Device) {
+    let peripheral = VirtualPeripheral::new();
+    radio.add_peripheral(&peripheral);
+    let device = common::connect(radio, &peripheral).await;
+    (peripheral, device)
+}
+
+/// Opens a channel to a new peripheral, returning the central's and the peripheral's ends
+async fn open_channel() -> (L2capChannel, L2capChannel) {
+    let radio = VirtualRadio::new();
+    let (peripheral, device) = connect(&radio).await;
+    let (psm, mut listener) = peripheral.listen_l2cap(None, false).unwrap();
+    let central = device.open_l2cap_channel(psm, false).await.unwrap();
+    (central, listener.next().await.unwrap())
+}
+
+async fn start_send(channel: &mut L2capChannel, packet: &[u8]) -> bluest::Result<()> {
+    poll_fn(|cx| Sink::<&[u8]>::poll_ready(Pin::new(&mut *channel), cx)).await?;
+    Pin::new(channel).start_send(packet)
+}
+
+#[tokio::test]
+async fn packets_are_exchanged_both_ways() {
+    let (mut central, mut peripheral) = open_channel().await;
+    assert_eq!(central.read_mtu().unwrap(), MTU);
+    assert_eq!(central.write_mtu().unwrap(), MTU);
+
+    let mut buf = [0; MTU];
+    central.write(b"ping").await.unwrap();
+    assert_eq!(peripheral.read(&mut buf).await.unwrap(), 4);
+    assert_eq!(&buf[..4], b"ping");
+
+    peripheral.write(b"pong").await.unwrap();
+    assert_eq!(central.read(&mut buf).await.unwrap(), 4);
+    assert_eq!(&buf[..4], b"pong");
+}
+
+#[tokio::test]
+async fn open_and_listen_errors() {
+    let radio = VirtualRadio::new();
+    let (peripheral, device) = connect(&radio).await;
+
+    let err = device.open_l2cap_channel(0x80, false).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+
+    let (psm, listener) = peripheral.listen_l2cap(None, false).unwrap();
+    assert_eq!(psm, 0x80);
+    let (next, _next_listener) = peripheral.listen_l2cap(None, false).unwrap();
+    assert_eq!(next, 0x81);
+    let err = peripheral.listen_l2cap(Some(psm), false).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Other);
+
+    // The PSM is free again once the listener is dropped
+    drop(listener);
+    let err = device.open_l2cap_channel(psm, false).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+    let (_psm, _listener) = peripheral.listen_l2cap(Some(psm), true).unwrap();
+
+    let err = device.open_l2cap_channel(psm, false).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+    device.pair().await.unwrap();
+    device.open_l2cap_channel(psm, false).await.unwrap();
+
+    radio.adapter().disconnect_device(&device).await.unwrap();
+    let err = device.open_l2cap_channel(psm, false).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+#[tokio::test]
+async fn async_read_buffers_the_rest_of_a_packet() {
+    let (central, mut peripheral) = open_channel().await;
+    peripheral.write(b"hello world").await.unwrap();
+    peripheral.write(b"again").await.unwrap();
+
+    let (mut central, _writer) = central.split();
+    let mut buf = [0; 5];
+    central.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    // The rest of the first packet is returned before the second packet
+    let mut small = [0; 3];
+    assert_eq!(
+        central.try_read(&mut small).unwrap_err().kind(),
+        ErrorKind::InvalidParameter
+    );
+    let mut packet = [0; MTU];
+    assert_eq!(central.read(&mut packet).await.unwrap(), 6);
+    assert_eq!(&packet[..6], b" world");
+    assert_eq!(central.read(&mut packet).await.unwrap(), 5);
+    assert_eq!(&packet[..5], b"again");
+    assert_eq!(central.try_read(&mut packet).unwrap_err().kind(), ErrorKind::NotReady);
+}
+
+#[tokio::test]
+async fn packet_stream_continues_after_async_read() {
+    let (mut central, mut peripheral) = open_channel().await;
+    peripheral.write(b"first").await.unwrap();
+    peripheral.write(b"second").await.unwrap();
+
+    let mut buf = [0; 2];
+    central.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"fi");
+    assert_eq!(central.next().await.unwrap().unwrap(), b"rst");
+    assert_eq!(central.next().await.unwrap().unwrap(), b"second");
+
+    // The end of the channel ends the stream and reads nothing
+    peripheral.close().await.unwrap();
+    assert!(central.next().await.is_none());
+    assert_eq!(central.read(&mut buf).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn async_write_splits_at_the_mtu() {
+    let (mut central, mut peripheral) = open_channel().await;
+
+    let data: Vec<u8> = (0..1000).map(|x| x as u8).collect();
+    central.write_all(&data).await.unwrap();
+    central.flush().await.unwrap();
+
+    let mut buf = [0; MTU];
+    assert_eq!(peripheral.read(&mut buf).await.unwrap(), MTU);
+    assert_eq!(&buf[..], &data[..MTU]);
+    assert_eq!(peripheral.read(&mut buf).await.unwrap(), 1000 - MTU);
+    assert_eq!(&buf[..1000 - MTU], &data[MTU..]);
+}
+
+#[tokio::test]
+async fn empty_async_write_sends_nothing() {
+    let (mut central, peripheral) = open_channel().await;
+
+    assert_eq!(AsyncWriteExt::write(&mut central, &[]).await.unwrap(), 0);
+    central.flush().await.unwrap();
+    let (mut peripheral, _writer) = peripheral.split();
+    let mut buf = [0; MTU];
+    assert_eq!(peripheral.try_read(&mut buf).unwrap_err().kind(), ErrorKind::NotReady);
+
+    AsyncWriteExt::write(&mut central, b"x").await.unwrap();
+    central.flush().await.unwrap();
+    assert_eq!(peripheral.read(&mut buf).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn sink_rejects_packets_larger_than_the_mtu() {
+    let (mut central, mut peripheral) = open_channel().await;
+
+    let err = start_send(&mut central, &[0; MTU + 1]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+
+    start_send(&mut central, &[1; MTU]).await.unwrap();
+    poll_fn(|cx| Sink::<&[u8]>::poll_flush(Pin::new(&mut central), cx))
+        .await
+        .unwrap();
+    assert_eq!(peripheral.next().await.unwrap().unwrap(), [1; MTU]);
+}
+
+#[tokio::test]
+async fn queued_packets_are_written_first() {
+    let (mut central, mut peripheral) = open_channel().await;
+
+    start_send(&mut central, b"sink").await.unwrap();
+    AsyncWriteExt::write(&mut central, b"bytes").await.unwrap();
+    start_send(&mut central, b"sink again").await.unwrap();
+    central.write(b"packet").await.unwrap();
+
+    assert_eq!(peripheral.next().await.unwrap().unwrap(), b"sink");
+    assert_eq!(peripheral.next().await.unwrap().unwrap(), b"bytes");
+    assert_eq!(peripheral.next().await.unwrap().unwrap(), b"sink again");
+    assert_eq!(peripheral.next().await.unwrap().unwrap(), b"packet");
+}
+
+#[tokio::test]
+async fn closing_ends_both_directions() {
+    let (mut central, mut peripheral) = open_channel().await;
+    central.close().await.unwrap();
+
+    assert!(peripheral.next().await.is_none());
+    let err = peripheral.write(b"late").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+#[tokio::test]
+async fn adapter_accepts_channels_from_peripherals() {
+    let radio = VirtualRadio::new();
+    let (peripheral, device) = connect(&radio).await;
+    let adapter = radio.adapter();
+    let (psm, mut channels) = adapter.listen_l2cap(None, false).await.unwrap();
+    assert_eq!(psm, 0x80);
+
+    let mut outgoing = peripheral.open_l2cap_channel(psm, false).unwrap();
+    let (accepted, mut incoming) = channels.next().await.unwrap().unwrap();
+    assert_eq!(accepted, device);
+
+    outgoing.write(b"hello").await.unwrap();
+    assert_eq!(incoming.next().await.unwrap().unwrap(), b"hello");
+    incoming.write(b"welcome").await.unwrap();
+    assert_eq!(outgoing.next().await.unwrap().unwrap(), b"welcome");
+
+    let err = peripheral.open_l2cap_channel(psm + 1, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+    let err = adapter.listen_l2cap(Some(psm), false).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Other);
+
+    // Channels are refused once the stream is dropped
+    drop(channels);
+    let err = peripheral.open_l2cap_channel(psm, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+}
+
+#[tokio::test]
+async fn adapter_accepts_secure_channels_from_paired_peripherals() {
+    let radio = VirtualRadio::new();
+    let (peripheral, device) = connect(&radio).await;
+    let adapter = radio.adapter();
+    let (psm, mut channels) = adapter.listen_l2cap(Some(0x85), true).await.unwrap();
+    assert_eq!(psm, 0x85);
+
+    let err = peripheral.open_l2cap_channel(psm, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+
+    device.pair().await.unwrap();
+    let _channel = peripheral.open_l2cap_channel(psm, false).unwrap();
+    assert!(channels.next().await.unwrap().is_ok());
+
+    peripheral.disconnect();
+    let err = peripheral.open_l2cap_channel(psm, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
@@ -0,0 +1,455 @@
+//! Tests of the mock backend's virtual radio, peripherals, GATT databases and pairing.
+
+#![cfg(feature = "mock")]
+
+use bluest::error::{AttError, ErrorKind};
+use bluest::mock::{
+    Operation, VirtualCharacteristic, VirtualDescriptor, VirtualPeripheral, VirtualRadio, VirtualService,
+};
+use bluest::pairing::{IoCapability, PairingAgent, PairingRejected, Passkey};
+use bluest::{
+    btuuid, AdapterEvent, AdvertisementData, CharacteristicProperties, ConnectionEvent, Device, ManufacturerData,
+    ScanEvent, Uuid,
+};
+use common::connect;
+use futures_lite::StreamExt;
+
+mod common;
+
+const SERVICE: Uuid = Uuid::from_u128(0x5e1d0000_7a2b_4c3d_8e4f_a1b2c3d4e5f6);
+const INCLUDED: Uuid = Uuid::from_u128(0x5e1d0001_7a2b_4c3d_8e4f_a1b2c3d4e5f6);
+const CHARACTERISTIC: Uuid = Uuid::from_u128(0x5e1d0002_7a2b_4c3d_8e4f_a1b2c3d4e5f6);
+const READ_ONLY: Uuid = Uuid::from_u128(0x5e1d0003_7a2b_4c3d_8e4f_a1b2c3d4e5f6);
+const DESCRIPTOR: Uuid = Uuid::from_u128(0x5e1d0004_7a2b_4c3d_8e4f_a1b2c3d4e5f6);
+
+fn advertisement(name: &str) -> AdvertisementData {
+    AdvertisementData {
+        local_name: Some(name.to_owned()),
+        services: vec![SERVICE],
+        ..Default::default()
+    }
+}
+
+/// A peripheral with one primary service, which has a secondary included service, a readable, writable and notifying
+/// characteristic with a descriptor, and a read only characteristic
+fn sensor() -> (VirtualPeripheral, VirtualCharacteristic, VirtualDescriptor) {
+    let descriptor = VirtualDescriptor::new(DESCRIPTOR).with_value(*b"description");
+    let characteristic = VirtualCharacteristic::new(
+        CHARACTERISTIC,
+        CharacteristicProperties::from_bits(0x1a), // read + write + notify
+    )
+    .with_value([1, 2, 3])
+    .with_descriptor(descriptor.clone());
+    let read_only = VirtualCharacteristic::new(READ_ONLY, CharacteristicProperties::from_bits(0x02)).with_value([9]);
+    let included = VirtualService::new(INCLUDED).secondary();
+    let service = VirtualService::new(SERVICE)
+        .with_characteristic(characteristic.clone())
+        .with_characteristic(read_only)
+        .with_included_service(included.clone());
+
+    let peripheral = VirtualPeripheral::new()
+        .with_name("sensor")
+        .with_rssi(-60)
+        .with_mtu(100)
+        .with_advertisement(advertisement("sensor"))
+        .with_service(service)
+        .with_service(included);
+    (peripheral, characteristic, descriptor)
+}
+
+#[tokio::test]
+async fn radio_power_is_reported_to_adapters() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let mut events = adapter.events().await.unwrap();
+    let mut scan = adapter
+        .try_scan_events(Default::default(), Default::default())
+        .await
+        .unwrap();
+
+    radio.set_powered(false);
+    assert!(matches!(events.next().await, Some(Ok(AdapterEvent::Unavailable))));
+    let err = scan.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+    assert!(scan.next().await.is_none());
+    let err = adapter.scan(&[]).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+
+    let available = tokio::spawn({
+        let adapter = adapter.clone();
+        async move { adapter.wait_available().await }
+    });
+    radio.set_powered(true);
+    assert!(matches!(events.next().await, Some(Ok(AdapterEvent::Available))));
+    available.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn powering_off_disconnects_peripherals() {
+    let radio = VirtualRadio::new();
+    let (peripheral, ..) = sensor();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+
+    radio.set_powered(false);
+    assert!(!peripheral.is_connected());
+    assert!(!device.is_connected().await);
+}
+
+#[tokio::test]
+async fn scans_see_added_and_removed_peripherals() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let known = VirtualPeripheral::new().with_advertisement(advertisement("known"));
+    radio.add_peripheral(&known);
+    let mut scan = adapter
+        .scan_events(Default::default(), Default::default())
+        .await
+        .unwrap();
+
+    // Peripherals already advertising are reported first
+    let Some(ScanEvent::Advertisement(adv)) = scan.next().await else {
+        panic!("expected an advertisement");
+    };
+    assert_eq!(adv.device.id(), known.id());
+
+    let (peripheral, ..) = sensor();
+    let scan_response = AdvertisementData {
+        manufacturer_data: ManufacturerData {
+            company_id: 0x0059,
+            data: vec![7],
+        }
+        .into(),
+        ..Default::default()
+    };
+    peripheral.set_scan_response(Some(scan_response.clone()));
+    radio.add_peripheral(&peripheral);
+    assert_eq!(radio.peripherals().len(), 2);
+
+    let Some(ScanEvent::Advertisement(adv)) = scan.next().await else {
+        panic!("expected an advertisement");
+    };
+    assert_eq!(adv.device.id(), peripheral.id());
+    assert_eq!(adv.adv_data, advertisement("sensor"));
+    assert_eq!(adv.rssi, Some(-60));
+    assert_eq!(adv.is_scan_response, Some(false));
+    let Some(ScanEvent::Advertisement(adv)) = scan.next().await else {
+        panic!("expected a scan response");
+    };
+    assert_eq!(adv.adv_data, scan_response);
+    assert_eq!(adv.is_scan_response, Some(true));
+
+    radio.remove_peripheral(&peripheral);
+    assert!(matches!(scan.next().await, Some(ScanEvent::DeviceLost(id)) if id == peripheral.id()));
+    assert!(radio.peripheral(&peripheral.id()).is_none());
+    let err = adapter.open_device(&peripheral.id()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn peripheral_advertisements_can_change() {
+    let radio = VirtualRadio::new();
+    let peripheral = VirtualPeripheral::new();
+    radio.add_peripheral(&peripheral);
+    let adapter = radio.adapter();
+    let mut scan = adapter.scan(&[]).await.unwrap();
+
+    peripheral.set_rssi(Some(-40));
+    peripheral.set_advertisement(Some(advertisement("first")));
+    let adv = scan.next().await.unwrap();
+    assert_eq!(adv.adv_data.local_name.as_deref(), Some("first"));
+    assert_eq!(adv.rssi, Some(-40));
+
+    peripheral.set_advertisement(None);
+    peripheral.advertise();
+    peripheral.set_advertisement(Some(advertisement("second")));
+    let adv = scan.next().await.unwrap();
+    assert_eq!(adv.adv_data.local_name.as_deref(), Some("second"));
+    assert_eq!(peripheral.advertisement(), Some(advertisement("second")));
+}
+
+#[tokio::test]
+async fn connections() {
+    let radio = VirtualRadio::new();
+    let (peripheral, ..) = sensor();
+    radio.add_peripheral(&peripheral);
+    let adapter = radio.adapter();
+    let device = adapter.open_device(&peripheral.id()).await.unwrap();
+    assert_eq!(device.name().unwrap(), "sensor");
+    assert_eq!(device.rssi().await.unwrap(), -60);
+    let mut events = adapter.device_connection_events(&device).await.unwrap();
+
+    peripheral.fail_next(Operation::Connect, ErrorKind::Timeout);
+    assert_eq!(
+        adapter.connect_device(&device).await.unwrap_err().kind(),
+        ErrorKind::Timeout
+    );
+    assert!(!device.is_connected().await);
+
+    adapter.connect_device(&device).await.unwrap();
+    assert_eq!(events.next().await, Some(ConnectionEvent::Connected));
+    assert!(peripheral.is_connected());
+    assert_eq!(
+        adapter.connected_devices().await.unwrap(),
+        std::slice::from_ref(&device)
+    );
+    assert_eq!(
+        adapter.connected_devices_with_services(&[SERVICE]).await.unwrap(),
+        std::slice::from_ref(&device)
+    );
+    assert!(adapter
+        .connected_devices_with_services(&[btuuid::services::BATTERY])
+        .await
+        .unwrap()
+        .is_empty());
+
+    // The peripheral can end the connection too
+    peripheral.disconnect();
+    assert_eq!(events.next().await, Some(ConnectionEvent::Disconnected));
+    assert!(adapter.connected_devices().await.unwrap().is_empty());
+    let err = device.discover_services().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+
+    // A removed peripheral is out of range
+    radio.remove_peripheral(&peripheral);
+    let err = adapter.connect_device(&device).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+}
+
+#[tokio::test]
+async fn advertising_sets_are_limited() {
+    let radio = VirtualRadio::new();
+    radio.set_max_advertising_sets(1);
+    let adapter = radio.adapter();
+    assert_eq!(adapter.available_advertising_sets().await.unwrap(), 1);
+
+    let guard = adapter.start_advertising(advertisement("local")).await.unwrap();
+    assert_eq!(radio.available_advertising_sets(), 0);
+    assert!(adapter.start_advertising(advertisement("other")).await.is_err());
+
+    drop(guard);
+    assert_eq!(radio.available_advertising_sets(), 1);
+}
+
+#[tokio::test]
+async fn gatt_discovery() {
+    let radio = VirtualRadio::new();
+    let (peripheral, ..) = sensor();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+
+    // Secondary services are only found as included services
+    let services = device.discover_services().await.unwrap();
+    assert_eq!(services.len(), 1);
+    let service = &services[0];
+    assert_eq!(service.uuid(), SERVICE);
+    assert!(service.is_primary().await.unwrap());
+    let included = service.discover_included_services().await.unwrap();
+    assert_eq!(included.len(), 1);
+    assert_eq!(included[0].uuid(), INCLUDED);
+    assert!(!included[0].is_primary().await.unwrap());
+    assert!(device.discover_services_with_uuid(INCLUDED).await.unwrap().is_empty());
+
+    let characteristics = service.discover_characteristics().await.unwrap();
+    let uuids: Vec<_> = characteristics.iter().map(|x| x.uuid()).collect();
+    assert_eq!(uuids, [CHARACTERISTIC, READ_ONLY]);
+    let read_only = service.discover_characteristics_with_uuid(READ_ONLY).await.unwrap();
+    assert_eq!(read_only.len(), 1);
+    assert!(!read_only[0].properties().await.unwrap().write);
+
+    let descriptors = characteristics[0].discover_descriptors().await.unwrap();
+    assert_eq!(descriptors.len(), 1);
+    assert_eq!(descriptors[0].uuid(), DESCRIPTOR);
+
+    peripheral.fail_next(Operation::DiscoverServices, ErrorKind::Timeout);
+    assert_eq!(device.discover_services().await.unwrap_err().kind(), ErrorKind::Timeout);
+}
+
+#[tokio::test]
+async fn gatt_reads_and_writes() {
+    let radio = VirtualRadio::new();
+    let (peripheral, virtual_characteristic, virtual_descriptor) = sensor();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+    let service = &device.discover_services().await.unwrap()[0];
+    let characteristics = service.characteristics().await.unwrap();
+    let (characteristic, read_only) = (&characteristics[0], &characteristics[1]);
+
+    assert_eq!(characteristic.read().await.unwrap(), [1, 2, 3]);
+    virtual_characteristic.set_value([4]);
+    assert_eq!(characteristic.read().await.unwrap(), [4]);
+    characteristic.write(&[5, 6]).await.unwrap();
+    assert_eq!(virtual_characteristic.value(), [5, 6]);
+    assert_eq!(characteristic.max_write_len().unwrap(), 97);
+
+    let err = read_only.write(&[0]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::WRITE_NOT_PERMITTED));
+    let err = characteristic.write_without_response(&[0]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::WRITE_NOT_PERMITTED));
+
+    // Scripted failures fail the next operation of their kind only
+    virtual_characteristic.fail_next(
+        Operation::Read,
+        ErrorKind::Protocol(AttError::INSUFFICIENT_AUTHENTICATION),
+    );
+    let err = characteristic.read().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::INSUFFICIENT_AUTHENTICATION));
+    assert_eq!(characteristic.read().await.unwrap(), [5, 6]);
+
+    let descriptor = &characteristic.descriptors().await.unwrap()[0];
+    assert_eq!(descriptor.read().await.unwrap(), b"description");
+    descriptor.write(b"renamed").await.unwrap();
+    assert_eq!(virtual_descriptor.value(), b"renamed");
+    virtual_descriptor.fail_next(Operation::Write, ErrorKind::Other);
+    assert_eq!(descriptor.write(b"again").await.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(descriptor.value().await.unwrap(), b"renamed");
+}
+
+#[tokio::test]
+async fn gatt_notifications() {
+    let radio = VirtualRadio::new();
+    let (peripheral, virtual_characteristic, _) = sensor();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+    let service = &device.discover_services().await.unwrap()[0];
+    let characteristics = service.characteristics().await.unwrap();
+
+    let err = characteristics[1].notify().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+
+    let characteristic = &characteristics[0];
+    assert_eq!(virtual_characteristic.notify([0]), 0);
+    let mut notifications = characteristic.notify().await.unwrap();
+    assert!(virtual_characteristic.is_notifying());
+    assert_eq!(virtual_characteristic.notify([7]), 1);
+    assert_eq!(notifications.next().await.unwrap().unwrap(), [7]);
+
+    // Notifications end with the connection
+    peripheral.disconnect();
+    assert!(notifications.next().await.is_none());
+    drop(notifications);
+    assert!(!virtual_characteristic.is_notifying());
+}
+
+#[tokio::test]
+async fn services_changed_indications() {
+    let radio = VirtualRadio::new();
+    let (peripheral, ..) = sensor();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+    let service = device.discover_services().await.unwrap().remove(0);
+    let mut indications = device.service_changed_indications().await.unwrap();
+
+    let battery = VirtualService::new(btuuid::services::BATTERY);
+    peripheral.add_service(battery.clone());
+    let changed = indications.next().await.unwrap().unwrap();
+    assert!(!changed.was_invalidated(&service));
+    assert_eq!(device.discover_services().await.unwrap().len(), 2);
+
+    peripheral.remove_service(&battery);
+    let changed = indications.next().await.unwrap().unwrap();
+    assert!(!changed.was_invalidated(&service));
+    assert_eq!(device.discover_services().await.unwrap().len(), 1);
+
+    peripheral.disconnect();
+    assert!(indications.next().await.is_none());
+}
+
+/// Answers pairing requests with a fixed passkey, recording the passkeys it is shown
+struct Agent {
+    io_capability: IoCapability,
+    passkey: Passkey,
+    shown: std::sync::Mutex<Vec<Passkey>>,
+}
+
+impl Agent {
+    fn new(io_capability: IoCapability, passkey: u32) -> Self {
+        Agent {
+            io_capability,
+            passkey: Passkey::new(passkey),
+            shown: Default::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PairingAgent for Agent {
+    fn io_capability(&self) -> IoCapability {
+        self.io_capability
+    }
+
+    async fn confirm(&self, _device: &Device) -> Result<(), PairingRejected> {
+        Ok(())
+    }
+
+    async fn confirm_passkey(&self, _device: &Device, passkey: Passkey) -> Result<(), PairingRejected> {
+        self.shown.lock().unwrap().push(passkey);
+        if passkey == self.passkey {
+            Ok(())
+        } else {
+            Err(PairingRejected::default())
+        }
+    }
+
+    async fn request_passkey(&self, _device: &Device) -> Result<Passkey, PairingRejected> {
+        Ok(self.passkey)
+    }
+
+    fn display_passkey(&self, _device: &Device, passkey: Passkey) {
+        self.shown.lock().unwrap().push(passkey);
+    }
+}
+
+#[tokio::test]
+async fn pairing() {
+    let radio = VirtualRadio::new();
+    let peripheral = VirtualPeripheral::new();
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+
+    peripheral.fail_next(Operation::Pair, ErrorKind::NotAuthorized);
+    assert_eq!(device.pair().await.unwrap_err().kind(), ErrorKind::NotAuthorized);
+    assert!(!device.is_paired().await.unwrap());
+
+    device.pair().await.unwrap();
+    assert!(peripheral.is_paired());
+
+    // Unpairing also disconnects
+    device.unpair().await.unwrap();
+    assert!(!device.is_paired().await.unwrap());
+    assert!(!device.is_connected().await);
+
+    // Without a passkey the agent only confirms the pairing
+    device
+        .pair_with_agent(&Agent::new(IoCapability::KeyboardOnly, 0))
+        .await
+        .unwrap();
+    assert!(device.is_paired().await.unwrap());
+}
+
+#[tokio::test]
+async fn pairing_with_a_passkey() {
+    let radio = VirtualRadio::new();
+    let peripheral = VirtualPeripheral::new().with_passkey(Passkey::new(123456));
+    radio.add_peripheral(&peripheral);
+    let device = connect(&radio, &peripheral).await;
+
+    let wrong = Agent::new(IoCapability::KeyboardDisplay, 654321);
+    let err = device.pair_with_agent(&wrong).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+    let err = device
+        .pair_with_agent(&Agent::new(IoCapability::DisplayYesNo, 654321))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAuthorized);
+    assert!(!peripheral.is_paired());
+
+    let display = Agent::new(IoCapability::DisplayOnly, 0);
+    device.pair_with_agent(&display).await.unwrap();
+    assert_eq!(*display.shown.lock().unwrap(), [Passkey::new(123456)]);
+    device.unpair().await.unwrap();
+
+    let keyboard = Agent::new(IoCapability::KeyboardOnly, 123456);
+    device.pair_with_agent(&keyboard).await.unwrap();
+    assert!(peripheral.is_paired());
+}
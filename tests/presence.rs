@@ -0,0 +1,165 @@
+//! Tests of presence monitoring with injected advertisements and the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::time::Duration;
+
+use bluest::beacon::{IBeacon, APPLE_COMPANY_ID};
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::presence::{PresenceEvent, PresenceMonitor, Region, VirtualClock};
+use bluest::{AdvertisementData, AdvertisingDevice, Uuid};
+use common::advertisements;
+use futures_lite::StreamExt;
+
+mod common;
+
+const UUID: Uuid = Uuid::from_u128(0xe2c56db5_dffb_48d2_b060_d0f5a71096e0);
+
+fn sensor() -> VirtualPeripheral {
+    VirtualPeripheral::new().with_advertisement(AdvertisementData {
+        local_name: Some("Sensor".to_string()),
+        ..Default::default()
+    })
+}
+
+fn ibeacon(major: u16) -> AdvertisementData {
+    IBeacon {
+        uuid: UUID,
+        major,
+        minor: 7,
+        measured_power: -59,
+    }
+    .to_advertisement()
+}
+
+#[tokio::test]
+async fn dwell_time_and_hysteresis() {
+    let radio = VirtualRadio::new();
+    radio.add_peripheral(&sensor());
+    let adv = advertisements(&radio).await.remove(0);
+    let with_rssi = |rssi| AdvertisingDevice {
+        rssi: Some(rssi),
+        ..adv.clone()
+    };
+
+    let clock = VirtualClock::new();
+    let monitor = PresenceMonitor::with_clock(clock.clone());
+    let region = monitor.add_region(
+        Region::devices([adv.device.id()])
+            .with_rssi_thresholds(-70, -80)
+            .with_dwell_time(Duration::from_secs(2))
+            .with_exit_timeout(Duration::from_secs(5)),
+    );
+
+    // Too weak to start the dwell time
+    assert!(monitor.process(&with_rssi(-75)).is_empty());
+    assert!(monitor.process(&with_rssi(-65)).is_empty());
+    clock.advance(Duration::from_secs(1));
+    assert!(monitor.process(&with_rssi(-65)).is_empty());
+    assert!(monitor.present(region).is_empty());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        monitor.process(&with_rssi(-60)),
+        [PresenceEvent::Entered {
+            region,
+            device: adv.device.clone(),
+            rssi: Some(-60),
+        }]
+    );
+    assert_eq!(monitor.present(region).len(), 1);
+
+    // Between the thresholds the device stays inside, and small changes are not reported
+    clock.advance(Duration::from_secs(1));
+    assert!(monitor.process(&with_rssi(-62)).is_empty());
+    assert_eq!(
+        monitor.process(&with_rssi(-78)),
+        [PresenceEvent::RangeChanged {
+            region,
+            device: adv.device.clone(),
+            rssi: -78,
+        }]
+    );
+
+    // Advertisements below the exit threshold do not keep the device inside
+    clock.advance(Duration::from_secs(1));
+    assert!(monitor.process(&with_rssi(-90)).is_empty());
+    clock.advance(Duration::from_secs(3));
+    assert!(monitor.tick().is_empty());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        monitor.tick(),
+        [PresenceEvent::Exited {
+            region,
+            device: adv.device.clone(),
+        }]
+    );
+    assert!(monitor.present(region).is_empty());
+}
+
+#[tokio::test]
+async fn beacon_regions() {
+    let radio = VirtualRadio::new();
+    radio.add_peripheral(&VirtualPeripheral::new().with_advertisement(ibeacon(1)));
+    radio.add_peripheral(&VirtualPeripheral::new().with_advertisement(ibeacon(2)));
+    radio.add_peripheral(&sensor());
+    let advertisements = advertisements(&radio).await;
+
+    let clock = VirtualClock::new();
+    let monitor = PresenceMonitor::with_clock(clock.clone());
+    let major = monitor.add_region(Region::ibeacon(UUID, Some(1), None));
+    let any = monitor.add_region(Region::ibeacon(UUID, None, Some(7)));
+    let apple = monitor.add_region(Region::manufacturer_data(APPLE_COMPANY_ID, |data| data.len() == 23));
+
+    let entered = |region| {
+        let mut majors = monitor
+            .present(region)
+            .iter()
+            .map(|device| {
+                let adv = advertisements.iter().find(|x| x.device == *device).unwrap();
+                IBeacon::from_advertisement(&adv.adv_data).unwrap().major
+            })
+            .collect::<Vec<_>>();
+        majors.sort();
+        majors
+    };
+    for adv in &advertisements {
+        monitor.process(adv);
+    }
+    assert_eq!(entered(major), [1]);
+    assert_eq!(entered(any), [1, 2]);
+    assert_eq!(entered(apple), [1, 2]);
+
+    // Removed regions are forgotten without reporting exits
+    assert!(monitor.remove_region(any));
+    assert!(!monitor.remove_region(any));
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(monitor.tick().len(), 3);
+}
+
+#[tokio::test]
+async fn events() {
+    let radio = VirtualRadio::new();
+    let peripheral = sensor();
+    radio.add_peripheral(&peripheral);
+
+    let monitor = PresenceMonitor::new();
+    let region = monitor.add_region(Region::devices([peripheral.id()]).with_exit_timeout(Duration::from_millis(100)));
+    let adapter = radio.adapter();
+    let scan = adapter.scan(&[]).await.unwrap();
+    let mut events = monitor.events(scan);
+
+    let next = tokio::time::timeout(Duration::from_secs(5), events.next());
+    match next.await.unwrap().unwrap() {
+        PresenceEvent::Entered { region: x, device, .. } => {
+            assert_eq!(x, region);
+            assert_eq!(device.id(), peripheral.id());
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+    let next = tokio::time::timeout(Duration::from_secs(5), events.next());
+    match next.await.unwrap().unwrap() {
+        PresenceEvent::Exited { region: x, .. } => assert_eq!(x, region),
+        event => panic!("unexpected event {event:?}"),
+    }
+}
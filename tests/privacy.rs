@@ -0,0 +1,139 @@
+//! Tests of resolvable private address resolution.
+#![cfg(feature = "privacy")]
+
+use bluest::privacy::{ah, AddressKind, Identity, Irk, Keyring};
+use bluest::AddressType;
+
+/// The identity resolving key of the sample data in the Core Specification, Vol 3, Part H, §D.7
+const IRK: Irk = Irk::from_u128(0xec0234a3_57c8ad05_341010a6_0a397d9b);
+const RPA: [u8; 6] = [0x70, 0x81, 0x94, 0x0d, 0xfb, 0xaa];
+const IDENTITY: Identity = Identity::new([0xc0, 0x11, 0x22, 0x33, 0x44, 0x55], AddressType::Random);
+
+#[test]
+fn spec_sample_data() {
+    assert_eq!(ah(&IRK, 0x708194), 0x0dfbaa);
+    assert!(IRK.resolves(RPA));
+    assert_eq!(IRK.resolvable_private_address(0x708194), RPA);
+
+    let mut bytes = IRK.to_be_bytes();
+    bytes.reverse();
+    assert_eq!(Irk::from_le_bytes(bytes), IRK);
+}
+
+#[test]
+fn resolution() {
+    let other = Irk::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+    assert!(!other.resolves(RPA));
+
+    // Every bit of the hash and the random part matters
+    for i in 0..48 {
+        let mut address = RPA;
+        address[i / 8] ^= 1 << (i % 8);
+        assert!(!IRK.resolves(address), "bit {i}");
+    }
+
+    for prand in [0, 1, 0x12345, 0x3f_fffe, 0xffff_ffff] {
+        let address = other.resolvable_private_address(prand);
+        assert_eq!(
+            AddressKind::classify(address, AddressType::Random),
+            Some(AddressKind::ResolvablePrivate)
+        );
+        assert!(other.resolves(address));
+        assert!(!IRK.resolves(address));
+    }
+}
+
+#[test]
+fn classification() {
+    let classify = AddressKind::classify;
+    assert_eq!(classify(RPA, AddressType::Public), Some(AddressKind::Public));
+    assert_eq!(classify(RPA, AddressType::Random), Some(AddressKind::ResolvablePrivate));
+    assert_eq!(
+        classify(IDENTITY.address, AddressType::Random),
+        Some(AddressKind::RandomStatic)
+    );
+    assert_eq!(
+        classify([0x3f, 0, 0, 0, 0, 1], AddressType::Random),
+        Some(AddressKind::NonResolvablePrivate)
+    );
+    assert_eq!(classify([0x80, 0, 0, 0, 0, 1], AddressType::Random), None);
+}
+
+#[test]
+fn keyring() {
+    let mut keyring = Keyring::new();
+    assert_eq!(keyring.resolve(RPA), None);
+    keyring.add(IRK, IDENTITY);
+    assert_eq!(keyring.len(), 1);
+    assert_eq!(keyring.resolve(RPA), Some(IDENTITY));
+    assert_eq!(keyring.resolve(IDENTITY.address), Some(IDENTITY));
+    assert_eq!(keyring.resolve([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xab]), None);
+
+    // Adding a key for the same identity replaces the old one
+    keyring.add(Irk::from_u128(1), IDENTITY);
+    assert_eq!(keyring.len(), 1);
+    assert_eq!(keyring.resolve(RPA), None);
+    assert!(keyring.remove(&IDENTITY));
+    assert!(!keyring.remove(&IDENTITY));
+    assert!(keyring.is_empty());
+
+    assert_eq!(format!("{IDENTITY}"), "C0:11:22:33:44:55 (random)");
+    assert_eq!(format!("{IRK:?}"), "Irk(..)");
+}
+
+#[cfg(feature = "mock")]
+#[tokio::test]
+async fn scan_results() {
+    use bluest::mock::{VirtualPeripheral, VirtualRadio};
+    use bluest::{AdvertisementData, ScanFilter, ScanOptions};
+    use futures_lite::StreamExt;
+
+    let radio = VirtualRadio::new();
+    let advertisement = |name: &str| AdvertisementData {
+        local_name: Some(name.to_string()),
+        ..Default::default()
+    };
+    for (name, address, address_type) in [
+        ("private", RPA, AddressType::Random),
+        ("identity", IDENTITY.address, AddressType::Random),
+        ("public", [0x00, 0x1b, 0xdc, 0x01, 0x02, 0x03], AddressType::Public),
+        ("other", IRK.resolvable_private_address(0x1234), AddressType::Random),
+    ] {
+        let peripheral = VirtualPeripheral::with_address(address)
+            .with_address_type(address_type)
+            .with_advertisement(advertisement(name));
+        radio.add_peripheral(&peripheral);
+    }
+
+    let mut keyring = Keyring::new();
+    keyring.add(IRK, IDENTITY);
+    let adapter = radio.adapter();
+    let options = ScanOptions {
+        max_results: Some(4),
+        ..Default::default()
+    };
+    let scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    let mut found = keyring
+        .resolve_scan(scan)
+        .map(|x| (x.adv.adv_data.local_name.unwrap(), x.address_kind, x.identity))
+        .collect::<Vec<_>>()
+        .await;
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            ("identity".to_string(), Some(AddressKind::RandomStatic), Some(IDENTITY)),
+            (
+                "other".to_string(),
+                Some(AddressKind::ResolvablePrivate),
+                Some(IDENTITY)
+            ),
+            (
+                "private".to_string(),
+                Some(AddressKind::ResolvablePrivate),
+                Some(IDENTITY)
+            ),
+            ("public".to_string(), Some(AddressKind::Public), None),
+        ]
+    );
+}
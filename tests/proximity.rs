@@ -0,0 +1,144 @@
+//! Tests of distance estimation.
+
+use bluest::proximity::{distance, Exponential, Kalman, MovingAverage, RssiFilter};
+
+#[test]
+fn path_loss() {
+    assert_eq!(distance(-59.0, -59.0, 2.0), 1.0);
+    assert!((distance(-79.0, -59.0, 2.0) - 10.0).abs() < 1e-9);
+    assert!((distance(-89.0, -59.0, 3.0) - 10.0).abs() < 1e-9);
+    assert!((distance(-39.0, -59.0, 2.0) - 0.1).abs() < 1e-9);
+}
+
+#[test]
+fn filters() {
+    let mut average = MovingAverage::new(3);
+    assert_eq!(average.update(-60.0), -60.0);
+    assert_eq!(average.update(-70.0), -65.0);
+    assert_eq!(average.update(-80.0), -70.0);
+    assert_eq!(average.update(-90.0), -80.0);
+
+    let mut exponential = Exponential::new(0.5);
+    assert_eq!(exponential.update(-60.0), -60.0);
+    assert_eq!(exponential.update(-80.0), -70.0);
+    assert_eq!(exponential.update(-80.0), -75.0);
+
+    // The Kalman filter settles on a steady signal and resists a single outlier
+    let mut kalman = Kalman::default();
+    assert_eq!(kalman.update(-60.0), -60.0);
+    for _ in 0..20 {
+        kalman.update(-60.0);
+    }
+    let outlier = kalman.update(-80.0);
+    assert!(outlier < -60.0 && outlier > -65.0, "{outlier}");
+    let mut value = outlier;
+    for _ in 0..100 {
+        value = kalman.update(-80.0);
+    }
+    assert!((value + 80.0).abs() < 0.5, "{value}");
+}
+
+#[cfg(feature = "mock")]
+mod estimator {
+    use bluest::beacon::IBeacon;
+    use bluest::mock::{VirtualPeripheral, VirtualRadio};
+    use bluest::proximity::{MovingAverage, ProximityEstimator, Zone};
+    use bluest::{AdvertisementData, AdvertisingDevice, ScanFilter, ScanOptions, Uuid};
+    use futures_lite::StreamExt;
+
+    async fn advertisement(adv_data: AdvertisementData) -> AdvertisingDevice {
+        let radio = VirtualRadio::new();
+        radio.add_peripheral(&VirtualPeripheral::new().with_advertisement(adv_data));
+        let adapter = radio.adapter();
+        let options = ScanOptions {
+            max_results: Some(1),
+            ..Default::default()
+        };
+        let mut scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+        scan.next().await.unwrap()
+    }
+
+    fn with_rssi(adv: &AdvertisingDevice, rssi: i16) -> AdvertisingDevice {
+        AdvertisingDevice {
+            rssi: Some(rssi),
+            ..adv.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn calibration_and_zones() {
+        let beacon = IBeacon {
+            uuid: Uuid::from_u128(1),
+            major: 1,
+            minor: 1,
+            measured_power: -59,
+        };
+        let beacon = advertisement(beacon.to_advertisement()).await;
+        let tx_power = advertisement(AdvertisementData {
+            tx_power_level: Some(0),
+            ..Default::default()
+        })
+        .await;
+        let unknown = advertisement(AdvertisementData {
+            local_name: Some("Sensor".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+        let mut estimator = ProximityEstimator::new().with_filter(|| MovingAverage::new(1));
+        let estimate = estimator.estimate(&with_rssi(&beacon, -59)).unwrap();
+        assert_eq!(estimate.rssi_at_1m, Some(-59));
+        assert_eq!(estimate.distance, Some(1.0));
+        assert_eq!(estimate.zone, Zone::Near);
+        assert_eq!(
+            estimator.estimate(&with_rssi(&beacon, -50)).unwrap().zone,
+            Zone::Immediate
+        );
+        assert_eq!(estimator.estimate(&with_rssi(&beacon, -75)).unwrap().zone, Zone::Far);
+
+        let estimate = estimator.estimate(&with_rssi(&tx_power, -41)).unwrap();
+        assert_eq!(estimate.rssi_at_1m, Some(-41));
+        assert_eq!(estimate.distance, Some(1.0));
+
+        let estimate = estimator.estimate(&with_rssi(&unknown, -41)).unwrap();
+        assert_eq!(estimate.distance, None);
+        assert_eq!(estimate.zone, Zone::Unknown);
+        assert!(estimator
+            .estimate(&AdvertisingDevice {
+                rssi: None,
+                ..unknown.clone()
+            })
+            .is_none());
+
+        // A calibrated signal strength overrides the advertisement
+        let mut estimator = ProximityEstimator::new()
+            .with_rssi_at_1m(-65)
+            .with_path_loss_exponent(2.0)
+            .with_zone_limits(1.0, 5.0);
+        let estimate = estimator.estimate(&with_rssi(&beacon, -71)).unwrap();
+        assert_eq!(estimate.rssi_at_1m, Some(-65));
+        assert_eq!(estimate.zone, Zone::Near);
+    }
+
+    #[tokio::test]
+    async fn smoothing_is_per_device() {
+        let a = advertisement(AdvertisementData {
+            tx_power_level: Some(0),
+            ..Default::default()
+        })
+        .await;
+        let b = advertisement(AdvertisementData {
+            tx_power_level: Some(0),
+            ..Default::default()
+        })
+        .await;
+
+        let mut estimator = ProximityEstimator::new().with_filter(|| MovingAverage::new(2));
+        estimator.estimate(&with_rssi(&a, -50));
+        assert_eq!(estimator.estimate(&with_rssi(&b, -70)).unwrap().filtered_rssi, -70.0);
+        assert_eq!(estimator.estimate(&with_rssi(&a, -60)).unwrap().filtered_rssi, -55.0);
+
+        estimator.reset(&a.device.id());
+        assert_eq!(estimator.estimate(&with_rssi(&a, -60)).unwrap().filtered_rssi, -60.0);
+    }
+}
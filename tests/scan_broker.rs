@@ -0,0 +1,99 @@
+//! Tests of the scan broker against the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::time::Duration;
+
+use bluest::error::ErrorKind;
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{AdvertisementData, ScanBroker, ScanFilter, ScanSubscription};
+use futures_lite::StreamExt;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn peripheral(name: &str) -> VirtualPeripheral {
+    VirtualPeripheral::new().with_advertisement(AdvertisementData {
+        local_name: Some(name.to_string()),
+        ..Default::default()
+    })
+}
+
+async fn next_name(subscription: &mut ScanSubscription) -> Option<String> {
+    tokio::time::timeout(TIMEOUT, subscription.next())
+        .await
+        .expect("timed out waiting for an advertisement")
+        .map(|x| x.adv_data.local_name.unwrap())
+}
+
+#[tokio::test]
+async fn subscriptions_share_one_scan() {
+    let radio = VirtualRadio::new();
+    let thermometer = peripheral("Thermometer");
+    let scale = peripheral("Scale");
+    let broker = ScanBroker::new(radio.adapter());
+
+    let mut all = broker.subscribe(ScanFilter::new()).await.unwrap();
+    let mut thermometers = broker
+        .subscribe(ScanFilter::new().with_name_prefix("Thermo"))
+        .await
+        .unwrap();
+    assert_eq!(broker.subscribers(), 2);
+
+    radio.add_peripheral(&thermometer);
+    radio.add_peripheral(&scale);
+    thermometer.advertise();
+    scale.advertise();
+    assert_eq!(next_name(&mut thermometers).await.unwrap(), "Thermometer");
+    assert_eq!(next_name(&mut all).await.unwrap(), "Thermometer");
+    assert_eq!(next_name(&mut all).await.unwrap(), "Scale");
+
+    // The remaining subscription keeps receiving advertisements after another is dropped
+    drop(all);
+    assert_eq!(broker.subscribers(), 1);
+    scale.advertise();
+    thermometer.advertise();
+    assert_eq!(next_name(&mut thermometers).await.unwrap(), "Thermometer");
+}
+
+#[tokio::test]
+async fn subscriptions_are_polled_from_separate_tasks() {
+    let radio = VirtualRadio::new();
+    let sensor = peripheral("Sensor");
+    radio.add_peripheral(&sensor);
+    let broker = ScanBroker::new(radio.adapter());
+
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let mut subscription = broker.subscribe(ScanFilter::new()).await.unwrap();
+        tasks.push(tokio::spawn(async move {
+            let mut count = 0;
+            while count < 3 {
+                next_name(&mut subscription).await.unwrap();
+                count += 1;
+            }
+        }));
+    }
+    for _ in 0..3 {
+        sensor.advertise();
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn subscriptions_end_with_the_scan() {
+    let radio = VirtualRadio::new();
+    let broker = ScanBroker::new(radio.adapter());
+    let mut subscription = broker.subscribe(ScanFilter::new()).await.unwrap();
+    assert!(subscription.error().is_none());
+
+    // The error which ended the scan is kept for the subscription
+    radio.set_powered(false);
+    assert_eq!(next_name(&mut subscription).await, None);
+    assert_eq!(broker.subscribers(), 0);
+    assert_eq!(subscription.error().unwrap().kind(), ErrorKind::AdapterUnavailable);
+
+    let err = broker.subscribe(ScanFilter::new()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+}
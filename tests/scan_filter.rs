@@ -0,0 +1,190 @@
+//! Tests of scan filters against the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bluest::btuuid::services;
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{Adapter, AdvertisementData, AdvertisingDevice, ManufacturerData, ScanFilter};
+use futures_lite::StreamExt;
+
+/// Returns the names of the peripherals on `radio` whose advertisements match `filter`.
+async fn scan(adapter: &Adapter, filter: ScanFilter) -> Vec<String> {
+    let mut scan = adapter.scan_with_filter(filter).await.unwrap();
+    let mut names = Vec::new();
+    while let Ok(Some(device)) = tokio::time::timeout(Duration::from_millis(50), scan.next()).await {
+        names.push(name(&device));
+    }
+    names.sort();
+    names
+}
+
+fn name(device: &AdvertisingDevice) -> String {
+    device.adv_data.local_name.clone().unwrap_or_default()
+}
+
+fn peripheral(name: &str, rssi: i16, adv_data: AdvertisementData) -> VirtualPeripheral {
+    VirtualPeripheral::new()
+        .with_rssi(rssi)
+        .with_advertisement(AdvertisementData {
+            local_name: Some(name.to_string()),
+            ..adv_data
+        })
+}
+
+fn radio() -> (VirtualRadio, Vec<VirtualPeripheral>) {
+    let radio = VirtualRadio::new();
+    let peripherals = vec![
+        peripheral(
+            "Thermometer",
+            -50,
+            AdvertisementData {
+                services: vec![services::HEALTH_THERMOMETER],
+                service_data: HashMap::from([(services::HEALTH_THERMOMETER, vec![0x12, 0x34])]),
+                is_connectable: Some(true),
+                ..Default::default()
+            },
+        ),
+        peripheral(
+            "Beacon",
+            -80,
+            AdvertisementData {
+                manufacturer_data: ManufacturerData {
+                    company_id: 0x004c,
+                    data: vec![0x02, 0x15, 0xab],
+                }
+                .into(),
+                is_connectable: Some(false),
+                ..Default::default()
+            },
+        ),
+        peripheral(
+            "Tracker",
+            -65,
+            AdvertisementData {
+                manufacturer_data: ManufacturerData {
+                    company_id: 0x004c,
+                    data: vec![0x12, 0x19],
+                }
+                .into(),
+                ..Default::default()
+            },
+        ),
+    ];
+    for peripheral in &peripherals {
+        radio.add_peripheral(peripheral);
+    }
+    (radio, peripherals)
+}
+
+#[tokio::test]
+async fn predicates() {
+    let (radio, peripherals) = radio();
+    let adapter = radio.adapter();
+
+    assert_eq!(
+        scan(&adapter, ScanFilter::new()).await,
+        ["Beacon", "Thermometer", "Tracker"]
+    );
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_min_rssi(-65)).await,
+        ["Thermometer", "Tracker"]
+    );
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_name_prefix("T")).await,
+        ["Thermometer", "Tracker"]
+    );
+    // The tracker does not say whether it is connectable, which is not held against it
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_connectable()).await,
+        ["Thermometer", "Tracker"]
+    );
+    assert_eq!(
+        scan(
+            &adapter,
+            ScanFilter::new().with_services([services::HEALTH_THERMOMETER])
+        )
+        .await,
+        ["Thermometer"]
+    );
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_manufacturer_data(0x004c, &[], None)).await,
+        ["Beacon", "Tracker"]
+    );
+    assert_eq!(
+        scan(
+            &adapter,
+            ScanFilter::new().with_manufacturer_data(0x004c, &[0x02, 0x15], None)
+        )
+        .await,
+        ["Beacon"]
+    );
+    assert_eq!(
+        scan(
+            &adapter,
+            ScanFilter::new().with_manufacturer_data(0x004c, &[0x02, 0x10], Some(&[0x0f, 0xf0]))
+        )
+        .await,
+        ["Beacon", "Tracker"]
+    );
+    assert_eq!(
+        scan(
+            &adapter,
+            ScanFilter::new().with_service_data(services::HEALTH_THERMOMETER, &[0x00, 0x34], Some(&[0x00]))
+        )
+        .await,
+        ["Thermometer"]
+    );
+    assert_eq!(
+        scan(
+            &adapter,
+            ScanFilter::new().with_service_data(services::HEALTH_THERMOMETER, &[0x12, 0x34, 0x56], None)
+        )
+        .await,
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_allowed_devices([peripherals[1].id()])).await,
+        ["Beacon"]
+    );
+    assert_eq!(
+        scan(&adapter, ScanFilter::new().with_denied_devices([peripherals[1].id()])).await,
+        ["Thermometer", "Tracker"]
+    );
+}
+
+#[tokio::test]
+async fn combinations() {
+    let (radio, _peripherals) = radio();
+    let adapter = radio.adapter();
+
+    let filter = ScanFilter::new()
+        .with_connectable()
+        .and(ScanFilter::new().with_min_rssi(-60));
+    assert_eq!(scan(&adapter, filter).await, ["Thermometer"]);
+
+    let filter = ScanFilter::new()
+        .with_services([services::HEALTH_THERMOMETER])
+        .or(ScanFilter::new().with_manufacturer_data(0x004c, &[0x02, 0x15], None))
+        .or(ScanFilter::new().with_name_prefix("Nothing"));
+    assert_eq!(scan(&adapter, filter).await, ["Beacon", "Thermometer"]);
+
+    // AND binds each alternative of an OR
+    let filter = ScanFilter::new()
+        .with_name_prefix("Thermometer")
+        .or(ScanFilter::new().with_name_prefix("Tracker"))
+        .and(ScanFilter::new().with_min_rssi(-60));
+    assert_eq!(scan(&adapter, filter).await, ["Thermometer"]);
+}
+
+#[cfg(feature = "regex")]
+#[tokio::test]
+async fn name_regex() {
+    let (radio, _peripherals) = radio();
+    let adapter = radio.adapter();
+
+    let filter = ScanFilter::new().with_name_regex(regex::Regex::new("^T.*r$").unwrap());
+    assert_eq!(scan(&adapter, filter).await, ["Thermometer", "Tracker"]);
+}
@@ -0,0 +1,200 @@
+//! Tests of scan options against the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::time::{Duration, Instant};
+
+use bluest::error::ErrorKind;
+use bluest::mock::{VirtualPeripheral, VirtualRadio};
+use bluest::{AdvertisementData, AdvertisingDevice, DuplicatePolicy, ScanEvent, ScanFilter, ScanMode, ScanOptions};
+use futures_core::Stream;
+use futures_lite::StreamExt;
+
+/// Collects the advertisements `scan` returns until it ends or nothing arrives for a while.
+async fn collect(mut scan: impl Stream<Item = AdvertisingDevice> + Unpin) -> Vec<AdvertisingDevice> {
+    let mut advertisements = Vec::new();
+    while let Ok(Some(adv)) = tokio::time::timeout(Duration::from_millis(50), scan.next()).await {
+        advertisements.push(adv);
+    }
+    advertisements
+}
+
+/// Receives the next event from `events`, which must arrive within a few seconds.
+async fn next<T>(events: &mut (impl Stream<Item = T> + Unpin)) -> Option<T> {
+    tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+}
+
+fn advertisement(name: &str) -> AdvertisementData {
+    AdvertisementData {
+        local_name: Some(name.to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn passive_scans_receive_no_scan_responses() {
+    let radio = VirtualRadio::new();
+    let peripheral = VirtualPeripheral::new()
+        .with_advertisement(advertisement("Sensor"))
+        .with_scan_response(advertisement("Sensor scan response"));
+    radio.add_peripheral(&peripheral);
+    let adapter = radio.adapter();
+
+    let scan = adapter
+        .scan_with_options(ScanFilter::new(), ScanOptions::default())
+        .await
+        .unwrap();
+    let found = collect(scan).await;
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[1].is_scan_response, Some(true));
+
+    let options = ScanOptions {
+        mode: ScanMode::Passive,
+        ..Default::default()
+    };
+    let scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    let found = collect(scan).await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].is_scan_response, Some(false));
+}
+
+#[tokio::test]
+async fn duplicates() {
+    let radio = VirtualRadio::new();
+    let peripheral = VirtualPeripheral::new()
+        .with_advertisement(advertisement("Sensor"))
+        .with_scan_response(advertisement("Sensor scan response"));
+    radio.add_peripheral(&peripheral);
+    let adapter = radio.adapter();
+
+    let options = |duplicates| ScanOptions {
+        duplicates,
+        ..Default::default()
+    };
+    let all = adapter
+        .scan_with_options(ScanFilter::new(), options(DuplicatePolicy::ReportAll))
+        .await
+        .unwrap();
+    let changes = adapter
+        .scan_with_options(ScanFilter::new(), options(DuplicatePolicy::ReportChanges))
+        .await
+        .unwrap();
+
+    peripheral.advertise();
+    peripheral.set_rssi(Some(-40));
+    peripheral.advertise();
+    peripheral.set_advertisement(Some(advertisement("Sensor 2")));
+
+    let names = |found: Vec<AdvertisingDevice>| {
+        found
+            .into_iter()
+            .filter(|x| x.is_scan_response == Some(false))
+            .map(|x| x.adv_data.local_name.unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(collect(all).await), ["Sensor", "Sensor", "Sensor", "Sensor 2"]);
+
+    // The unchanged scan response is only reported once, even though it alternates with the advertisement
+    let found = collect(changes).await;
+    assert_eq!(found.iter().filter(|x| x.is_scan_response == Some(true)).count(), 1);
+    assert_eq!(names(found), ["Sensor", "Sensor 2"]);
+}
+
+#[tokio::test]
+async fn limits() {
+    let radio = VirtualRadio::new();
+    let peripherals = (0..3)
+        .map(|i| VirtualPeripheral::new().with_advertisement(advertisement(&format!("Sensor {i}"))))
+        .collect::<Vec<_>>();
+    for peripheral in &peripherals {
+        radio.add_peripheral(peripheral);
+    }
+    let adapter = radio.adapter();
+
+    let options = ScanOptions {
+        max_results: Some(2),
+        ..Default::default()
+    };
+    let mut scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    assert!(scan.next().await.is_some());
+    assert!(scan.next().await.is_some());
+    assert!(scan.next().await.is_none());
+
+    let options = ScanOptions {
+        duration: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let mut scan = adapter.scan_with_options(ScanFilter::new(), options).await.unwrap();
+    let ended = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut count = 0;
+        while scan.next().await.is_some() {
+            count += 1;
+        }
+        count
+    })
+    .await
+    .expect("the scan did not end after its duration");
+    assert_eq!(ended, 3);
+}
+
+#[tokio::test]
+async fn lost_devices() {
+    let radio = VirtualRadio::new();
+    let sensor = VirtualPeripheral::new().with_advertisement(advertisement("Sensor"));
+    let beacon = VirtualPeripheral::new().with_advertisement(advertisement("Beacon"));
+    radio.add_peripheral(&sensor);
+    radio.add_peripheral(&beacon);
+    let adapter = radio.adapter();
+
+    let options = ScanOptions {
+        duplicates: DuplicatePolicy::ReportChanges,
+        lost_timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let mut events = adapter.scan_events(ScanFilter::new(), options).await.unwrap();
+    for _ in 0..2 {
+        assert!(matches!(next(&mut events).await, Some(ScanEvent::Advertisement(_))));
+    }
+
+    // Peripherals removed from the radio are lost immediately
+    radio.remove_peripheral(&beacon);
+    assert_eq!(next(&mut events).await, Some(ScanEvent::DeviceLost(beacon.id())));
+
+    // Repeated advertisements are not reported, but they keep the device from being lost
+    let start = Instant::now();
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sensor.advertise();
+    }
+    assert_eq!(next(&mut events).await, Some(ScanEvent::DeviceLost(sensor.id())));
+    assert!(start.elapsed() >= Duration::from_millis(550));
+
+    // Once lost, the device is reported again even though its advertisement has not changed
+    sensor.advertise();
+    match next(&mut events).await {
+        Some(ScanEvent::Advertisement(adv)) => assert_eq!(adv.device.id(), sensor.id()),
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[tokio::test]
+async fn errors() {
+    let radio = VirtualRadio::new();
+    let sensor = VirtualPeripheral::new().with_advertisement(advertisement("Sensor"));
+    radio.add_peripheral(&sensor);
+    let adapter = radio.adapter();
+
+    let mut events = adapter
+        .try_scan_events(ScanFilter::new(), ScanOptions::default())
+        .await
+        .unwrap();
+    assert!(matches!(next(&mut events).await, Some(Ok(ScanEvent::Advertisement(_)))));
+
+    // The scan ends with the reason it stopped
+    radio.set_powered(false);
+    let err = next(&mut events).await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+    assert!(next(&mut events).await.is_none());
+}
@@ -0,0 +1,173 @@
+//! Tests of GATT servers with the mock backend.
+
+#![cfg(feature = "mock")]
+
+use std::sync::{Arc, Mutex};
+
+use bluest::error::{AttError, ErrorKind};
+use bluest::mock::VirtualRadio;
+use bluest::server::{
+    AttributeId, AttributePermissions, Central, CharacteristicDefinition, DescriptorDefinition, ReadRequest,
+    RequestHandler, SecurityLevel, ServerEvent, ServiceDefinition, Subscription, WriteKind, WriteRequest,
+};
+use bluest::{AdvertisementData, CharacteristicProperties, Uuid};
+use common::next;
+use futures_lite::StreamExt;
+
+mod common;
+
+const SERVICE: Uuid = Uuid::from_u128(0x8d4a0000_2f3c_4f8d_9a39_5e2b8c6d7f10);
+const CHARACTERISTIC: Uuid = Uuid::from_u128(0x8d4a0001_2f3c_4f8d_9a39_5e2b8c6d7f10);
+const DESCRIPTOR: Uuid = Uuid::from_u128(0x8d4a0002_2f3c_4f8d_9a39_5e2b8c6d7f10);
+
+/// A 30 byte value, which takes two reads at the default MTU
+const VALUE: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123";
+
+/// Serves `VALUE` and records the requests
+#[derive(Default, Clone)]
+struct Handler {
+    reads: Arc<Mutex<Vec<ReadRequest>>>,
+    writes: Arc<Mutex<Vec<WriteRequest>>>,
+}
+
+#[async_trait::async_trait]
+impl RequestHandler for Handler {
+    async fn read(&self, request: ReadRequest) -> Result<Vec<u8>, AttError> {
+        let offset = usize::from(request.offset);
+        self.reads.lock().unwrap().push(request);
+        VALUE.get(offset..).map(<[u8]>::to_vec).ok_or(AttError::INVALID_OFFSET)
+    }
+
+    async fn write(&self, request: WriteRequest) -> Result<(), AttError> {
+        self.writes.lock().unwrap().push(request);
+        Ok(())
+    }
+}
+
+fn services() -> Vec<ServiceDefinition> {
+    let characteristic =
+        CharacteristicDefinition::new(CHARACTERISTIC, CharacteristicProperties::from_bits(0x12)).with_descriptor(
+            DescriptorDefinition::new(DESCRIPTOR, AttributePermissions::write(SecurityLevel::Encrypted)),
+        );
+    vec![ServiceDefinition::new(SERVICE).with_characteristic(characteristic)]
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let central = radio.id();
+
+    // Centrals connected to an advertisement before the server starts are told about the new services
+    let advertising = adapter
+        .start_advertising(AdvertisementData {
+            local_name: Some("server".to_string()),
+            services: vec![SERVICE],
+            is_connectable: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let device = adapter
+        .discover_devices(&[SERVICE])
+        .await
+        .unwrap()
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+    adapter.connect_device(&device).await.unwrap();
+    assert!(device.discover_services().await.unwrap().is_empty());
+    let mut changes = device.service_changed_indications().await.unwrap();
+
+    let handler = Handler::default();
+    let server = adapter.start_server(services(), handler.clone()).await.unwrap();
+    next(&mut changes).await.unwrap();
+    assert_eq!(server.centrals(), vec![Central { id: central, mtu: 23 }]);
+    let mut events = server.events();
+
+    let service = device.discover_services().await.unwrap().pop().unwrap();
+    assert_eq!(service.uuid(), SERVICE);
+    let characteristic = service.characteristics().await.unwrap().pop().unwrap();
+    let attribute = AttributeId {
+        service: SERVICE,
+        characteristic: CHARACTERISTIC,
+        descriptor: None,
+    };
+
+    // Values longer than the MTU are read in several requests
+    assert_eq!(characteristic.read().await.unwrap(), VALUE);
+    let reads = handler.reads.lock().unwrap().clone();
+    assert_eq!(reads.iter().map(|x| x.offset).collect::<Vec<_>>(), [0, 22]);
+    assert!(reads
+        .iter()
+        .all(|x| x.central == central && x.attribute == attribute && x.mtu == 23));
+    let err = characteristic.write(b"x").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::WRITE_NOT_PERMITTED));
+
+    // The descriptor needs an encrypted connection
+    let descriptor = characteristic.descriptors().await.unwrap().pop().unwrap();
+    let err = descriptor.write(b"x").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::INSUFFICIENT_AUTHENTICATION));
+    let err = descriptor.read().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Protocol(AttError::READ_NOT_PERMITTED));
+    device.pair().await.unwrap();
+    descriptor.write(b"x").await.unwrap();
+    let write = handler.writes.lock().unwrap().pop().unwrap();
+    assert_eq!(write.value, b"x");
+    assert_eq!(write.kind, WriteKind::WithResponse);
+    assert_eq!(write.attribute.descriptor, Some(DESCRIPTOR));
+
+    // The streams of the central share one subscription
+    assert!(!server.notify(SERVICE, CHARACTERISTIC, b"1").await.unwrap());
+    let mut first = characteristic.notify().await.unwrap();
+    let second = characteristic.notify().await.unwrap();
+    let subscription = Subscription {
+        attribute,
+        central: Some(central),
+        indications: false,
+    };
+    assert_eq!(next(&mut events).await, ServerEvent::Subscribed(subscription.clone()));
+    assert_eq!(server.subscriptions(), vec![subscription.clone()]);
+    assert!(server.notify(SERVICE, CHARACTERISTIC, b"2").await.unwrap());
+    assert_eq!(next(&mut first).await.unwrap(), b"2");
+    drop(second);
+    drop(first);
+    assert_eq!(next(&mut events).await, ServerEvent::Unsubscribed(subscription));
+    assert!(server.subscriptions().is_empty());
+
+    let err = server.notify(SERVICE, DESCRIPTOR, b"3").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    adapter.disconnect_device(&device).await.unwrap();
+    assert_eq!(next(&mut events).await, ServerEvent::Disconnected(central));
+    assert!(server.centrals().is_empty());
+    adapter.connect_device(&device).await.unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        ServerEvent::Connected(Central { id: central, mtu: 23 })
+    );
+
+    // Stopping the server removes its services
+    let mut changes = device.service_changed_indications().await.unwrap();
+    drop(server);
+    assert!(next(&mut changes).await.unwrap().was_invalidated(&service));
+    assert!(device.discover_services().await.unwrap().is_empty());
+    drop(advertising);
+}
+
+#[tokio::test]
+async fn unsupported_notifications() {
+    let radio = VirtualRadio::new();
+    let adapter = radio.adapter();
+    let characteristic = CharacteristicDefinition::new(CHARACTERISTIC, CharacteristicProperties::from_bits(0x02));
+    let services = vec![ServiceDefinition::new(SERVICE).with_characteristic(characteristic)];
+    let server = adapter.start_server(services, Handler::default()).await.unwrap();
+    let err = server.notify(SERVICE, CHARACTERISTIC, b"1").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+    assert!(server.centrals().is_empty());
+
+    radio.set_powered(false);
+    let err = adapter.start_server(Vec::new(), Handler::default()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AdapterUnavailable);
+}